@@ -1,17 +1,23 @@
 use {
     super::{
         level::{Level, PrimaryLevel, SecondaryLevel},
+        shadow::PipelineRequirements,
         state::RecordingState,
         submit::Submittable,
         usage::RenderPassContinue,
         CommandBuffer,
     },
     crate::{
-        capability::{Capability, Compute, Graphics, Supports, Transfer},
+        capability::{Capability, Compute, Execute, Graphics, Supports, Transfer},
         family::FamilyId,
+        query::{QueryKind, QueryPool, ScopedQueryKind, Timestamp},
+        stream::StreamCapture,
     },
 };
 
+#[cfg(not(feature = "no-slow-safety-checks"))]
+use super::shadow::ShadowState;
+
 /// Draw command for [`draw_indirect`].
 ///
 /// [`draw_indirect`]: ../struct.RenderPassEncoder.html#method.draw_indirect
@@ -67,18 +73,73 @@ pub struct DispatchCommand {
     pub z: u32,
 }
 
+/// Items passed to encoder method.
+/// Collected only if commands are captured,
+/// so that they can be recorded into capture before encoding.
+enum Tee<I: Iterator> {
+    Direct(I),
+    Collected(std::vec::IntoIter<I::Item>),
+}
+
+impl<I> Tee<I>
+where
+    I: Iterator,
+{
+    /// Get collected items.
+    /// Empty if commands are not captured.
+    fn items(&self) -> &[I::Item] {
+        match self {
+            Tee::Direct(_) => &[],
+            Tee::Collected(items) => items.as_slice(),
+        }
+    }
+}
+
+impl<I> Iterator for Tee<I>
+where
+    I: Iterator,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        match self {
+            Tee::Direct(items) => items.next(),
+            Tee::Collected(items) => items.next(),
+        }
+    }
+}
+
 /// Encoder for recording commands inside or outside renderpass.
 #[derive(Debug)]
 pub struct EncoderCommon<'a, B: rendy_core::hal::Backend, C> {
     raw: &'a mut B::CommandBuffer,
     capability: C,
     family: FamilyId,
+    #[cfg(not(feature = "no-slow-safety-checks"))]
+    shadow: &'a mut ShadowState,
+    capture: Option<&'a mut StreamCapture>,
 }
 
 impl<'a, B, C> EncoderCommon<'a, B, C>
 where
     B: rendy_core::hal::Backend,
 {
+    /// Record command into capture if commands are captured.
+    fn capture(&mut self, record: impl FnOnce(&mut StreamCapture)) {
+        if let Some(capture) = &mut self.capture {
+            record(&mut **capture);
+        }
+    }
+
+    /// Prepare items to be both captured and encoded.
+    fn tee<I: IntoIterator>(&self, items: I) -> Tee<I::IntoIter> {
+        if self.capture.is_some() {
+            Tee::Collected(items.into_iter().collect::<Vec<_>>().into_iter())
+        } else {
+            Tee::Direct(items.into_iter())
+        }
+    }
+
     /// Bind index buffer.
     /// Last bound index buffer is used in [`draw_indexed`] command.
     ///
@@ -102,6 +163,14 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.shadow.bind_index_buffer();
+        self.capture(|capture| {
+            let buffer = capture.buffer_ref::<B>(buffer);
+            capture
+                .encoder()
+                .bind_index_buffer(buffer, offset, index_type)
+        });
         rendy_core::hal::command::CommandBuffer::bind_index_buffer(
             self.raw,
             rendy_core::hal::buffer::IndexBufferView {
@@ -134,11 +203,31 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        let buffers = self.tee(buffers);
+        self.capture(|capture| {
+            let buffers = buffers
+                .items()
+                .iter()
+                .map(|&(buffer, offset)| (capture.buffer_ref::<B>(buffer), offset))
+                .collect::<Vec<_>>();
+            capture
+                .encoder()
+                .bind_vertex_buffers(first_binding, buffers)
+        });
+
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        let mut count = 0;
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        let buffers = buffers.inspect(|_| count += 1);
+
         rendy_core::hal::command::CommandBuffer::bind_vertex_buffers(
             self.raw,
             first_binding,
             buffers,
-        )
+        );
+
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.shadow.bind_vertex_buffers(first_binding, count);
     }
 
     /// Bind graphics pipeline.
@@ -148,10 +237,47 @@ where
     /// [`draw_indexed_indirect`]: ../struct.RenderPassEncoder.html#method.draw_indexed_indirect
     /// [`draw_indirect`]: ../struct.RenderPassEncoder.html#method.draw_indirect
     pub fn bind_graphics_pipeline(&mut self, pipeline: &B::GraphicsPipeline)
+    where
+        C: Supports<Graphics>,
+    {
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.shadow.bind_graphics_pipeline(None);
+        self.bind_graphics_pipeline_raw(pipeline);
+    }
+
+    /// Bind graphics pipeline along with its requirements.
+    ///
+    /// Unless `"no-slow-safety-checks"` feature is enabled
+    /// draw commands check that descriptor sets and vertex buffers in `requirements` are bound,
+    /// and [`push_constants`] checks that constants fit ranges in `requirements`.
+    /// Otherwise same as [`bind_graphics_pipeline`].
+    ///
+    /// [`bind_graphics_pipeline`]: #method.bind_graphics_pipeline
+    /// [`push_constants`]: #method.push_constants
+    pub fn bind_graphics_pipeline_with_requirements(
+        &mut self,
+        pipeline: &B::GraphicsPipeline,
+        requirements: &PipelineRequirements,
+    ) where
+        C: Supports<Graphics>,
+    {
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.shadow
+            .bind_graphics_pipeline(Some(requirements.clone()));
+        #[cfg(feature = "no-slow-safety-checks")]
+        let _ = requirements;
+        self.bind_graphics_pipeline_raw(pipeline);
+    }
+
+    fn bind_graphics_pipeline_raw(&mut self, pipeline: &B::GraphicsPipeline)
     where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        self.capture(|capture| {
+            let pipeline = capture.graphics_pipeline_ref::<B>(pipeline);
+            capture.encoder().bind_graphics_pipeline(pipeline)
+        });
 
         unsafe {
             rendy_core::hal::command::CommandBuffer::bind_graphics_pipeline(self.raw, pipeline);
@@ -173,6 +299,27 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        let sets = self.tee(sets);
+        let offsets = self.tee(offsets);
+        self.capture(|capture| {
+            let layout = capture.pipeline_layout_ref::<B>(layout);
+            let sets = sets
+                .items()
+                .iter()
+                .map(|&set| capture.descriptor_set_ref::<B>(set))
+                .collect::<Vec<_>>();
+            capture.encoder().bind_graphics_descriptor_sets(
+                layout,
+                first_set,
+                sets,
+                offsets.items().iter().cloned(),
+            )
+        });
+
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        let mut count = 0;
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        let sets = sets.inspect(|_| count += 1);
 
         rendy_core::hal::command::CommandBuffer::bind_graphics_descriptor_sets(
             self.raw,
@@ -181,14 +328,54 @@ where
             sets,
             offsets,
         );
+
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.shadow.bind_graphics_sets(first_set, count);
     }
 
     /// Bind compute pipeline.
     pub fn bind_compute_pipeline(&mut self, pipeline: &B::ComputePipeline)
+    where
+        C: Supports<Compute>,
+    {
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.shadow.bind_compute_pipeline(None);
+        self.bind_compute_pipeline_raw(pipeline);
+    }
+
+    /// Bind compute pipeline along with its requirements.
+    ///
+    /// Unless `"no-slow-safety-checks"` feature is enabled
+    /// dispatch commands check that descriptor sets in `requirements` are bound,
+    /// and [`push_compute_constants`] checks that constants fit ranges in `requirements`.
+    /// Otherwise same as [`bind_compute_pipeline`].
+    ///
+    /// [`bind_compute_pipeline`]: #method.bind_compute_pipeline
+    /// [`push_compute_constants`]: #method.push_compute_constants
+    pub fn bind_compute_pipeline_with_requirements(
+        &mut self,
+        pipeline: &B::ComputePipeline,
+        requirements: &PipelineRequirements,
+    ) where
+        C: Supports<Compute>,
+    {
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.shadow
+            .bind_compute_pipeline(Some(requirements.clone()));
+        #[cfg(feature = "no-slow-safety-checks")]
+        let _ = requirements;
+        self.bind_compute_pipeline_raw(pipeline);
+    }
+
+    fn bind_compute_pipeline_raw(&mut self, pipeline: &B::ComputePipeline)
     where
         C: Supports<Compute>,
     {
         self.capability.assert();
+        self.capture(|capture| {
+            let pipeline = capture.compute_pipeline_ref::<B>(pipeline);
+            capture.encoder().bind_compute_pipeline(pipeline)
+        });
 
         unsafe {
             rendy_core::hal::command::CommandBuffer::bind_compute_pipeline(self.raw, pipeline);
@@ -210,6 +397,27 @@ where
         C: Supports<Compute>,
     {
         self.capability.assert();
+        let sets = self.tee(sets);
+        let offsets = self.tee(offsets);
+        self.capture(|capture| {
+            let layout = capture.pipeline_layout_ref::<B>(layout);
+            let sets = sets
+                .items()
+                .iter()
+                .map(|&set| capture.descriptor_set_ref::<B>(set))
+                .collect::<Vec<_>>();
+            capture.encoder().bind_compute_descriptor_sets(
+                layout,
+                first_set,
+                sets,
+                offsets.items().iter().cloned(),
+            )
+        });
+
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        let mut count = 0;
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        let sets = sets.inspect(|_| count += 1);
 
         rendy_core::hal::command::CommandBuffer::bind_compute_descriptor_sets(
             self.raw,
@@ -218,6 +426,9 @@ where
             sets,
             offsets,
         );
+
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.shadow.bind_compute_sets(first_set, count);
     }
 
     /// Insert pipeline barrier.
@@ -231,6 +442,17 @@ where
         dependencies: rendy_core::hal::memory::Dependencies,
         barriers: impl IntoIterator<Item = rendy_core::hal::memory::Barrier<'b, B>>,
     ) {
+        let barriers = self.tee(barriers);
+        self.capture(|capture| {
+            let barriers = barriers
+                .items()
+                .iter()
+                .map(|barrier| capture.barrier(barrier))
+                .collect::<Vec<_>>();
+            capture
+                .encoder()
+                .pipeline_barrier(stages.clone(), dependencies, barriers)
+        });
         rendy_core::hal::command::CommandBuffer::pipeline_barrier(
             self.raw,
             stages,
@@ -255,11 +477,52 @@ where
         offset: u32,
         constants: &[u32],
     ) {
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.shadow
+            .push_graphics_constants(stages, offset, constants.len() as u32);
+        self.capture(|capture| {
+            let layout = capture.pipeline_layout_ref::<B>(layout);
+            capture
+                .encoder()
+                .push_constants(layout, stages, offset, constants)
+        });
         rendy_core::hal::command::CommandBuffer::push_graphics_constants(
             self.raw, layout, stages, offset, constants,
         );
     }
 
+    /// Push compute constants.
+    ///
+    /// # Safety
+    ///
+    /// `offset` must be multiple of 4.
+    /// `constants.len() + offset`, must be less than or equal to the
+    /// `maxPushConstantsSize` device limit.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdPushConstants.html
+    pub unsafe fn push_compute_constants(
+        &mut self,
+        layout: &B::PipelineLayout,
+        offset: u32,
+        constants: &[u32],
+    ) where
+        C: Supports<Compute>,
+    {
+        self.capability.assert();
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.shadow
+            .push_compute_constants(offset, constants.len() as u32);
+        self.capture(|capture| {
+            let layout = capture.pipeline_layout_ref::<B>(layout);
+            capture
+                .encoder()
+                .push_compute_constants(layout, offset, constants)
+        });
+        rendy_core::hal::command::CommandBuffer::push_compute_constants(
+            self.raw, layout, offset, constants,
+        );
+    }
+
     /// Set viewports
     ///
     /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdSetViewport.html
@@ -271,6 +534,13 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        let viewports = self.tee(viewports);
+        self.capture(|capture| {
+            capture.encoder().set_viewports(
+                first_viewport,
+                viewports.items().iter().map(|&viewport| viewport.clone()),
+            )
+        });
         rendy_core::hal::command::CommandBuffer::set_viewports(self.raw, first_viewport, viewports)
     }
 
@@ -290,6 +560,12 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        let rects = self.tee(rects);
+        self.capture(|capture| {
+            capture
+                .encoder()
+                .set_scissors(first_scissor, rects.items().iter().map(|&&rect| rect))
+        });
         rendy_core::hal::command::CommandBuffer::set_scissors(self.raw, first_scissor, rects)
     }
 
@@ -304,6 +580,7 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        self.capture(|capture| capture.encoder().set_stencil_reference(faces, value));
         rendy_core::hal::command::CommandBuffer::set_stencil_reference(self.raw, faces, value);
     }
 
@@ -318,6 +595,7 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        self.capture(|capture| capture.encoder().set_stencil_read_mask(faces, value));
         rendy_core::hal::command::CommandBuffer::set_stencil_read_mask(self.raw, faces, value);
     }
 
@@ -332,6 +610,7 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        self.capture(|capture| capture.encoder().set_stencil_write_mask(faces, value));
         rendy_core::hal::command::CommandBuffer::set_stencil_write_mask(self.raw, faces, value);
     }
 
@@ -343,6 +622,7 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        self.capture(|capture| capture.encoder().set_blend_constants(color));
         rendy_core::hal::command::CommandBuffer::set_blend_constants(self.raw, color);
     }
 
@@ -354,6 +634,7 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        self.capture(|capture| capture.encoder().set_depth_bounds(bounds.clone()));
         rendy_core::hal::command::CommandBuffer::set_depth_bounds(self.raw, bounds);
     }
 
@@ -365,6 +646,7 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        self.capture(|capture| capture.encoder().set_line_width(width));
         rendy_core::hal::command::CommandBuffer::set_line_width(self.raw, width);
     }
 
@@ -376,9 +658,124 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        self.capture(|capture| capture.encoder().set_depth_bias(depth_bias));
         rendy_core::hal::command::CommandBuffer::set_depth_bias(self.raw, depth_bias);
     }
 
+    /// Begin query.
+    /// Query is active until `end_query` is recorded.
+    ///
+    /// # Safety
+    ///
+    /// Query must be reset and not active.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdBeginQuery.html
+    pub unsafe fn begin_query<K>(
+        &mut self,
+        pool: &QueryPool<B, K>,
+        id: rendy_core::hal::query::Id,
+        flags: rendy_core::hal::query::ControlFlags,
+    ) where
+        K: ScopedQueryKind,
+        C: Supports<K::Capability>,
+    {
+        self.capability.assert();
+        self.capture(|capture| {
+            let pool = capture.query_pool_ref::<B>(pool.raw());
+            capture.encoder().begin_query(pool, id, flags)
+        });
+        rendy_core::hal::command::CommandBuffer::begin_query(self.raw, pool.query(id), flags)
+    }
+
+    /// End query.
+    ///
+    /// # Safety
+    ///
+    /// Query must be active and begun in this command buffer.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdEndQuery.html
+    pub unsafe fn end_query<K>(&mut self, pool: &QueryPool<B, K>, id: rendy_core::hal::query::Id)
+    where
+        K: ScopedQueryKind,
+        C: Supports<K::Capability>,
+    {
+        self.capability.assert();
+        self.capture(|capture| {
+            let pool = capture.query_pool_ref::<B>(pool.raw());
+            capture.encoder().end_query(pool, id)
+        });
+        rendy_core::hal::command::CommandBuffer::end_query(self.raw, pool.query(id))
+    }
+
+    /// Write timestamp when all previous commands reach specified `stage`.
+    ///
+    /// # Safety
+    ///
+    /// Query must be reset.
+    /// Queue family must support timestamps.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdWriteTimestamp.html
+    pub unsafe fn write_timestamp(
+        &mut self,
+        stage: rendy_core::hal::pso::PipelineStage,
+        pool: &QueryPool<B, Timestamp>,
+        id: rendy_core::hal::query::Id,
+    ) where
+        C: Supports<Transfer>,
+    {
+        self.capability.assert();
+        self.capture(|capture| {
+            let pool = capture.query_pool_ref::<B>(pool.raw());
+            capture.encoder().write_timestamp(stage, pool, id)
+        });
+        rendy_core::hal::command::CommandBuffer::write_timestamp(self.raw, stage, pool.query(id))
+    }
+
+    /// Open labeled group of commands.
+    /// Debugging and profiling tools show commands recorded until
+    /// matching [`pop_debug_group`] under this label.
+    /// Groups can be nested.
+    ///
+    /// `color` is RGBA packed in `u32` used by tools to highlight the group.
+    ///
+    /// `gfx-hal` doesn't expose debug markers yet, so for now the group
+    /// is only recorded into captured [`CommandStream`]s
+    /// and nothing is written into the command buffer.
+    ///
+    /// [`pop_debug_group`]: #method.pop_debug_group
+    /// [`CommandStream`]: struct.CommandStream.html
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdBeginDebugUtilsLabelEXT.html
+    pub fn push_debug_group(&mut self, name: &str, color: u32) {
+        self.capture(|capture| capture.encoder().push_debug_group(name, color));
+    }
+
+    /// Close group opened by last [`push_debug_group`].
+    ///
+    /// Like [`push_debug_group`] this is only recorded into captured streams.
+    ///
+    /// [`push_debug_group`]: #method.push_debug_group
+    ///
+    /// # Safety
+    ///
+    /// There must be group opened in this command buffer and not closed yet.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdEndDebugUtilsLabelEXT.html
+    pub unsafe fn pop_debug_group(&mut self) {
+        self.capture(|capture| capture.encoder().pop_debug_group());
+    }
+
+    /// Insert single label between commands.
+    ///
+    /// Like [`push_debug_group`] this is only recorded into captured streams.
+    ///
+    /// [`push_debug_group`]: #method.push_debug_group
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdInsertDebugUtilsLabelEXT.html
+    pub fn insert_debug_label(&mut self, name: &str, color: u32) {
+        self.capture(|capture| capture.encoder().insert_debug_label(name, color));
+    }
+
     /// Reborrow encoder.
     pub fn reborrow<K>(&mut self) -> EncoderCommon<'_, B, K>
     where
@@ -388,6 +785,9 @@ where
             capability: self.capability.supports().unwrap(),
             raw: &mut *self.raw,
             family: self.family,
+            #[cfg(not(feature = "no-slow-safety-checks"))]
+            shadow: &mut *self.shadow,
+            capture: self.capture.as_mut().map(|capture| &mut **capture),
         }
     }
 }
@@ -432,6 +832,17 @@ where
         >,
         rects: impl IntoIterator<Item = impl std::borrow::Borrow<rendy_core::hal::pso::ClearRect>>,
     ) {
+        let clears = self.inner.tee(clears);
+        let rects = self.inner.tee(rects);
+        self.inner.capture(|capture| {
+            capture.encoder().clear_attachments(
+                clears
+                    .items()
+                    .iter()
+                    .map(|clear| StreamCapture::attachment_clear(clear.borrow())),
+                rects.items().iter().map(|rect| rect.borrow().clone()),
+            )
+        });
         rendy_core::hal::command::CommandBuffer::clear_attachments(self.inner.raw, clears, rects);
     }
 
@@ -445,6 +856,10 @@ where
     ///
     /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdDraw.html
     pub unsafe fn draw(&mut self, vertices: std::ops::Range<u32>, instances: std::ops::Range<u32>) {
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.inner.shadow.draw("draw");
+        self.inner
+            .capture(|capture| capture.encoder().draw(vertices.clone(), instances.clone()));
         rendy_core::hal::command::CommandBuffer::draw(self.inner.raw, vertices, instances)
     }
 
@@ -463,6 +878,13 @@ where
         base_vertex: i32,
         instances: std::ops::Range<u32>,
     ) {
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.inner.shadow.draw_indexed("draw_indexed");
+        self.inner.capture(|capture| {
+            capture
+                .encoder()
+                .draw_indexed(indices.clone(), base_vertex, instances.clone())
+        });
         rendy_core::hal::command::CommandBuffer::draw_indexed(
             self.inner.raw,
             indices,
@@ -490,6 +912,14 @@ where
         draw_count: u32,
         stride: u32,
     ) {
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.inner.shadow.draw("draw_indirect");
+        self.inner.capture(|capture| {
+            let buffer = capture.buffer_ref::<B>(buffer);
+            capture
+                .encoder()
+                .draw_indirect(buffer, offset, draw_count, stride)
+        });
         rendy_core::hal::command::CommandBuffer::draw_indirect(
             self.inner.raw,
             buffer,
@@ -518,6 +948,14 @@ where
         draw_count: u32,
         stride: u32,
     ) {
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.inner.shadow.draw_indexed("draw_indexed_indirect");
+        self.inner.capture(|capture| {
+            let buffer = capture.buffer_ref::<B>(buffer);
+            capture
+                .encoder()
+                .draw_indexed_indirect(buffer, offset, draw_count, stride)
+        });
         rendy_core::hal::command::CommandBuffer::draw_indexed_indirect(
             self.inner.raw,
             buffer,
@@ -546,6 +984,11 @@ where
     B: rendy_core::hal::Backend,
 {
     fn drop(&mut self) {
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.inner.inner.shadow.end_render_pass();
+        self.inner
+            .inner
+            .capture(|capture| capture.encoder().end_render_pass());
         unsafe { rendy_core::hal::command::CommandBuffer::end_render_pass(self.inner.inner.raw) }
     }
 }
@@ -575,7 +1018,10 @@ where
     B: rendy_core::hal::Backend,
 {
     /// Record next subpass inline.
-    pub fn next_subpass_inline(self) -> RenderPassInlineEncoder<'a, B> {
+    pub fn next_subpass_inline(mut self) -> RenderPassInlineEncoder<'a, B> {
+        self.inner
+            .inner
+            .capture(|capture| capture.encoder().next_subpass(false));
         unsafe {
             rendy_core::hal::command::CommandBuffer::next_subpass(
                 self.inner.inner.raw,
@@ -587,7 +1033,10 @@ where
     }
 
     /// Record next subpass secondary.
-    pub fn next_subpass_secondary(self) -> RenderPassSecondaryEncoder<'a, B> {
+    pub fn next_subpass_secondary(mut self) -> RenderPassSecondaryEncoder<'a, B> {
+        self.inner
+            .inner
+            .capture(|capture| capture.encoder().next_subpass(true));
         unsafe {
             rendy_core::hal::command::CommandBuffer::next_subpass(
                 self.inner.inner.raw,
@@ -617,6 +1066,10 @@ where
     B: rendy_core::hal::Backend,
 {
     fn drop(&mut self) {
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.inner.shadow.end_render_pass();
+        self.inner
+            .capture(|capture| capture.encoder().end_render_pass());
         unsafe { rendy_core::hal::command::CommandBuffer::end_render_pass(self.inner.raw) }
     }
 }
@@ -631,19 +1084,27 @@ where
         submittables: impl IntoIterator<Item = impl Submittable<B, SecondaryLevel, RenderPassContinue>>,
     ) {
         let family = self.inner.family;
+        let secondaries = self.inner.tee(submittables.into_iter().map(|submit| {
+            assert_eq!(family, submit.family());
+            unsafe { submit.raw() }
+        }));
+        self.inner.capture(|capture| {
+            let secondaries = secondaries
+                .items()
+                .iter()
+                .map(|&secondary| capture.secondary_ref::<B>(secondary))
+                .collect::<Vec<_>>();
+            capture.encoder().execute_commands(secondaries)
+        });
         unsafe {
-            rendy_core::hal::command::CommandBuffer::execute_commands(
-                self.inner.raw,
-                submittables.into_iter().map(|submit| {
-                    assert_eq!(family, submit.family());
-                    submit.raw()
-                }),
-            )
+            rendy_core::hal::command::CommandBuffer::execute_commands(self.inner.raw, secondaries)
         }
     }
 
     /// Record next subpass inline.
-    pub fn next_subpass_inline(self) -> RenderPassInlineEncoder<'a, B> {
+    pub fn next_subpass_inline(mut self) -> RenderPassInlineEncoder<'a, B> {
+        self.inner
+            .capture(|capture| capture.encoder().next_subpass(false));
         unsafe {
             rendy_core::hal::command::CommandBuffer::next_subpass(
                 self.inner.raw,
@@ -662,7 +1123,9 @@ where
     }
 
     /// Record next subpass secondary.
-    pub fn next_subpass_secondary(self) -> RenderPassSecondaryEncoder<'a, B> {
+    pub fn next_subpass_secondary(mut self) -> RenderPassSecondaryEncoder<'a, B> {
+        self.inner
+            .capture(|capture| capture.encoder().next_subpass(true));
         unsafe {
             rendy_core::hal::command::CommandBuffer::next_subpass(
                 self.inner.raw,
@@ -717,6 +1180,17 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        self.inner.capture(|capture| {
+            let render_pass = capture.render_pass_ref::<B>(render_pass);
+            let framebuffer = capture.framebuffer_ref::<B>(framebuffer);
+            capture.encoder().begin_render_pass(
+                render_pass,
+                framebuffer,
+                render_area,
+                clear_values.iter().map(StreamCapture::clear_value),
+                false,
+            )
+        });
 
         unsafe {
             rendy_core::hal::command::CommandBuffer::begin_render_pass(
@@ -728,6 +1202,8 @@ where
                 rendy_core::hal::command::SubpassContents::Inline,
             )
         }
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.inner.shadow.begin_render_pass();
 
         RenderPassInlineEncoder {
             inner: RenderPassEncoder {
@@ -748,6 +1224,17 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        self.inner.capture(|capture| {
+            let render_pass = capture.render_pass_ref::<B>(render_pass);
+            let framebuffer = capture.framebuffer_ref::<B>(framebuffer);
+            capture.encoder().begin_render_pass(
+                render_pass,
+                framebuffer,
+                render_area,
+                clear_values.iter().map(StreamCapture::clear_value),
+                true,
+            )
+        });
 
         unsafe {
             rendy_core::hal::command::CommandBuffer::begin_render_pass(
@@ -759,6 +1246,8 @@ where
                 rendy_core::hal::command::SubpassContents::SecondaryBuffers,
             )
         }
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.inner.shadow.begin_render_pass();
 
         RenderPassSecondaryEncoder {
             inner: self.inner.reborrow(),
@@ -771,14 +1260,20 @@ where
         submittables: impl IntoIterator<Item = impl Submittable<B, SecondaryLevel>>,
     ) {
         let family = self.inner.family;
+        let secondaries = self.inner.tee(submittables.into_iter().map(|submit| {
+            assert_eq!(family, submit.family());
+            unsafe { submit.raw() }
+        }));
+        self.inner.capture(|capture| {
+            let secondaries = secondaries
+                .items()
+                .iter()
+                .map(|&secondary| capture.secondary_ref::<B>(secondary))
+                .collect::<Vec<_>>();
+            capture.encoder().execute_commands(secondaries)
+        });
         unsafe {
-            rendy_core::hal::command::CommandBuffer::execute_commands(
-                self.inner.raw,
-                submittables.into_iter().map(|submit| {
-                    assert_eq!(family, submit.family());
-                    submit.raw()
-                }),
-            )
+            rendy_core::hal::command::CommandBuffer::execute_commands(self.inner.raw, secondaries)
         }
     }
 }
@@ -815,6 +1310,14 @@ where
         C: Supports<Transfer>,
     {
         self.capability.assert();
+        let regions = self.inner.tee(regions);
+        self.inner.capture(|capture| {
+            let src = capture.buffer_ref::<B>(src);
+            let dst = capture.buffer_ref::<B>(dst);
+            capture
+                .encoder()
+                .copy_buffer(src, dst, regions.items().iter().cloned())
+        });
 
         rendy_core::hal::command::CommandBuffer::copy_buffer(self.inner.raw, src, dst, regions)
     }
@@ -836,6 +1339,17 @@ where
         C: Supports<Transfer>,
     {
         self.capability.assert();
+        let regions = self.inner.tee(regions);
+        self.inner.capture(|capture| {
+            let src = capture.buffer_ref::<B>(src);
+            let dst = capture.image_ref::<B>(dst);
+            capture.encoder().copy_buffer_to_image(
+                src,
+                dst,
+                dst_layout,
+                regions.items().iter().cloned(),
+            )
+        });
 
         rendy_core::hal::command::CommandBuffer::copy_buffer_to_image(
             self.inner.raw,
@@ -846,6 +1360,204 @@ where
         )
     }
 
+    /// Copy image subresource range to buffer region.
+    ///
+    /// # Safety
+    ///
+    /// Same as `copy_buffer()`
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdCopyImageToBuffer.html
+    pub unsafe fn copy_image_to_buffer(
+        &mut self,
+        src: &B::Image,
+        src_layout: rendy_core::hal::image::Layout,
+        dst: &B::Buffer,
+        regions: impl IntoIterator<Item = rendy_core::hal::command::BufferImageCopy>,
+    ) where
+        C: Supports<Transfer>,
+    {
+        self.capability.assert();
+        let regions = self.inner.tee(regions);
+        self.inner.capture(|capture| {
+            let src = capture.image_ref::<B>(src);
+            let dst = capture.buffer_ref::<B>(dst);
+            capture.encoder().copy_image_to_buffer(
+                src,
+                src_layout,
+                dst,
+                regions.items().iter().cloned(),
+            )
+        });
+
+        rendy_core::hal::command::CommandBuffer::copy_image_to_buffer(
+            self.inner.raw,
+            src,
+            src_layout,
+            dst,
+            regions,
+        )
+    }
+
+    /// Fill buffer region with repeated 4-byte `data`.
+    ///
+    /// # Safety
+    ///
+    /// `range` must be within the buffer.
+    /// `range.start` and `range.end - range.start` must be multiple of 4.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdFillBuffer.html
+    pub unsafe fn fill_buffer(&mut self, buffer: &B::Buffer, range: std::ops::Range<u64>, data: u32)
+    where
+        C: Supports<Transfer>,
+    {
+        self.capability.assert();
+        self.inner.capture(|capture| {
+            let buffer = capture.buffer_ref::<B>(buffer);
+            capture.encoder().fill_buffer(buffer, range.clone(), data)
+        });
+
+        rendy_core::hal::command::CommandBuffer::fill_buffer(self.inner.raw, buffer, range, data)
+    }
+
+    /// Update buffer region with `data` embedded into command buffer.
+    ///
+    /// # Safety
+    ///
+    /// `offset + data.len()` must not exceed the size of the buffer.
+    /// `offset` and `data.len()` must be multiple of 4.
+    /// `data.len()` must not exceed 65536.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdUpdateBuffer.html
+    pub unsafe fn update_buffer(&mut self, buffer: &B::Buffer, offset: u64, data: &[u8])
+    where
+        C: Supports<Transfer>,
+    {
+        self.capability.assert();
+        self.inner.capture(|capture| {
+            let buffer = capture.buffer_ref::<B>(buffer);
+            capture.encoder().update_buffer(buffer, offset, data)
+        });
+
+        rendy_core::hal::command::CommandBuffer::update_buffer(self.inner.raw, buffer, offset, data)
+    }
+
+    /// Clear color image subresource ranges.
+    ///
+    /// # Safety
+    ///
+    /// `image` must be in `layout`.
+    /// `ranges` must be within the image.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdClearColorImage.html
+    pub unsafe fn clear_color_image(
+        &mut self,
+        image: &B::Image,
+        layout: rendy_core::hal::image::Layout,
+        color: rendy_core::hal::command::ClearColor,
+        ranges: impl IntoIterator<Item = rendy_core::hal::image::SubresourceRange>,
+    ) where
+        C: Supports<Execute>,
+    {
+        self.capability.assert();
+        let ranges = self.inner.tee(ranges);
+        self.inner.capture(|capture| {
+            let image = capture.image_ref::<B>(image);
+            capture.encoder().clear_color_image(
+                image,
+                layout,
+                StreamCapture::clear_color(&color),
+                ranges.items().iter().cloned(),
+            )
+        });
+
+        rendy_core::hal::command::CommandBuffer::clear_image(
+            self.inner.raw,
+            image,
+            layout,
+            rendy_core::hal::command::ClearValue { color },
+            ranges,
+        )
+    }
+
+    /// Clear depth-stencil image subresource ranges.
+    ///
+    /// # Safety
+    ///
+    /// Same as `clear_color_image()`
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdClearDepthStencilImage.html
+    pub unsafe fn clear_depth_stencil_image(
+        &mut self,
+        image: &B::Image,
+        layout: rendy_core::hal::image::Layout,
+        depth_stencil: rendy_core::hal::command::ClearDepthStencil,
+        ranges: impl IntoIterator<Item = rendy_core::hal::image::SubresourceRange>,
+    ) where
+        C: Supports<Graphics>,
+    {
+        self.capability.assert();
+        let ranges = self.inner.tee(ranges);
+        self.inner.capture(|capture| {
+            let image = capture.image_ref::<B>(image);
+            capture.encoder().clear_depth_stencil_image(
+                image,
+                layout,
+                depth_stencil.depth,
+                depth_stencil.stencil,
+                ranges.items().iter().cloned(),
+            )
+        });
+
+        rendy_core::hal::command::CommandBuffer::clear_image(
+            self.inner.raw,
+            image,
+            layout,
+            rendy_core::hal::command::ClearValue { depth_stencil },
+            ranges,
+        )
+    }
+
+    /// Resolve multisample image regions into non-multisample image.
+    ///
+    /// # Safety
+    ///
+    /// Same as `copy_buffer()`
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdResolveImage.html
+    pub unsafe fn resolve_image(
+        &mut self,
+        src: &B::Image,
+        src_layout: rendy_core::hal::image::Layout,
+        dst: &B::Image,
+        dst_layout: rendy_core::hal::image::Layout,
+        regions: impl IntoIterator<Item = rendy_core::hal::command::ImageResolve>,
+    ) where
+        C: Supports<Graphics>,
+    {
+        self.capability.assert();
+        let regions = self.inner.tee(regions);
+        self.inner.capture(|capture| {
+            let src = capture.image_ref::<B>(src);
+            let dst = capture.image_ref::<B>(dst);
+            capture.encoder().resolve_image(
+                src,
+                src_layout,
+                dst,
+                dst_layout,
+                regions.items().iter().cloned(),
+            )
+        });
+
+        rendy_core::hal::command::CommandBuffer::resolve_image(
+            self.inner.raw,
+            src,
+            src_layout,
+            dst,
+            dst_layout,
+            regions,
+        )
+    }
+
     /// Copy image regions.
     ///
     /// # Safety
@@ -864,6 +1576,18 @@ where
         C: Supports<Transfer>,
     {
         self.capability.assert();
+        let regions = self.inner.tee(regions);
+        self.inner.capture(|capture| {
+            let src = capture.image_ref::<B>(src);
+            let dst = capture.image_ref::<B>(dst);
+            capture.encoder().copy_image(
+                src,
+                src_layout,
+                dst,
+                dst_layout,
+                regions.items().iter().cloned(),
+            )
+        });
 
         rendy_core::hal::command::CommandBuffer::copy_image(
             self.inner.raw,
@@ -894,6 +1618,19 @@ where
         C: Supports<Graphics>,
     {
         self.capability.assert();
+        let regions = self.inner.tee(regions);
+        self.inner.capture(|capture| {
+            let src = capture.image_ref::<B>(src);
+            let dst = capture.image_ref::<B>(dst);
+            capture.encoder().blit_image(
+                src,
+                src_layout,
+                dst,
+                dst_layout,
+                filter,
+                regions.items().iter().cloned(),
+            )
+        });
 
         rendy_core::hal::command::CommandBuffer::blit_image(
             self.inner.raw,
@@ -906,6 +1643,81 @@ where
         )
     }
 
+    /// Reset range of queries.
+    ///
+    /// # Safety
+    ///
+    /// Queries must not be active.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdResetQueryPool.html
+    pub unsafe fn reset_query_pool<K>(
+        &mut self,
+        pool: &QueryPool<B, K>,
+        queries: std::ops::Range<rendy_core::hal::query::Id>,
+    ) where
+        K: QueryKind,
+        C: Supports<Execute>,
+    {
+        self.capability.assert();
+        pool.assert_range(&queries);
+        self.inner.capture(|capture| {
+            let pool = capture.query_pool_ref::<B>(pool.raw());
+            capture.encoder().reset_query_pool(pool, queries.clone())
+        });
+
+        rendy_core::hal::command::CommandBuffer::reset_query_pool(
+            self.inner.raw,
+            pool.raw(),
+            queries,
+        )
+    }
+
+    /// Copy results of range of queries into `buffer` at `offset` with `stride` bytes between queries.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must be large enough to hold results.
+    /// `offset` and `stride` must be multiple of 4, or 8 if `BITS_64` flag is set.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdCopyQueryPoolResults.html
+    pub unsafe fn copy_query_pool_results<K>(
+        &mut self,
+        pool: &QueryPool<B, K>,
+        queries: std::ops::Range<rendy_core::hal::query::Id>,
+        buffer: &B::Buffer,
+        offset: u64,
+        stride: u64,
+        flags: rendy_core::hal::query::ResultFlags,
+    ) where
+        K: QueryKind,
+        C: Supports<Execute>,
+    {
+        self.capability.assert();
+        pool.assert_range(&queries);
+        self.inner.capture(|capture| {
+            let pool = capture.query_pool_ref::<B>(pool.raw());
+            let buffer = capture.buffer_ref::<B>(buffer);
+            capture.encoder().copy_query_pool_results(
+                pool,
+                queries.clone(),
+                buffer,
+                offset,
+                stride,
+                flags,
+            )
+        });
+
+        rendy_core::hal::command::CommandBuffer::copy_query_pool_results(
+            self.inner.raw,
+            pool.raw(),
+            queries,
+            buffer,
+            offset,
+            stride,
+            flags,
+        )
+    }
+
     /// Dispatch compute.
     ///
     /// # Safety
@@ -916,6 +1728,10 @@ where
         C: Supports<Compute>,
     {
         self.capability.assert();
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.inner.shadow.dispatch("dispatch");
+        self.inner
+            .capture(|capture| capture.encoder().dispatch(x, y, z));
 
         rendy_core::hal::command::CommandBuffer::dispatch(self.inner.raw, [x, y, z])
     }
@@ -935,6 +1751,12 @@ where
         C: Supports<Compute>,
     {
         self.capability.assert();
+        #[cfg(not(feature = "no-slow-safety-checks"))]
+        self.inner.shadow.dispatch("dispatch_indirect");
+        self.inner.capture(|capture| {
+            let buffer = capture.buffer_ref::<B>(buffer);
+            capture.encoder().dispatch_indirect(buffer, offset)
+        });
 
         rendy_core::hal::command::CommandBuffer::dispatch_indirect(self.inner.raw, buffer, offset)
     }
@@ -953,7 +1775,11 @@ where
             inner: EncoderCommon {
                 capability: self.capability,
                 family: self.family,
-                raw: self.raw(),
+                // state guarantees that raw command buffer is not shared.
+                raw: unsafe { self.raw.as_mut() },
+                #[cfg(not(feature = "no-slow-safety-checks"))]
+                shadow: &mut self.shadow,
+                capture: self.capture.as_mut().map(|capture| &mut **capture),
             },
         }
     }
@@ -970,7 +1796,11 @@ where
             inner: EncoderCommon {
                 capability: self.capability.supports().unwrap(),
                 family: self.family,
-                raw: self.raw(),
+                // state guarantees that raw command buffer is not shared.
+                raw: unsafe { self.raw.as_mut() },
+                #[cfg(not(feature = "no-slow-safety-checks"))]
+                shadow: &mut self.shadow,
+                capture: self.capture.as_mut().map(|capture| &mut **capture),
             },
         }
     }
@@ -3,6 +3,7 @@
 mod encoder;
 mod level;
 mod reset;
+mod shadow;
 mod state;
 mod submit;
 mod usage;
@@ -10,12 +11,19 @@ mod usage;
 use {
     crate::{
         capability::{Capability, Supports},
+        core::Device,
         family::FamilyId,
+        stream::StreamCapture,
     },
-    rendy_core::hal::Backend,
+    rendy_core::hal::{device::Device as _, Backend},
 };
 
-pub use self::{encoder::*, level::*, reset::*, state::*, submit::*, usage::*};
+pub use self::{
+    encoder::*, level::*, reset::*, shadow::PipelineRequirements, state::*, submit::*, usage::*,
+};
+
+#[cfg(not(feature = "no-slow-safety-checks"))]
+use self::shadow::ShadowState;
 
 /// Command buffer wrapper.
 /// This wrapper defines state with usage, level and ability to be individually reset at type level.
@@ -28,6 +36,9 @@ pub struct CommandBuffer<B: Backend, C, S, L = PrimaryLevel, R = NoIndividualRes
     level: L,
     reset: R,
     family: FamilyId,
+    #[cfg(not(feature = "no-slow-safety-checks"))]
+    shadow: ShadowState,
+    capture: Option<Box<StreamCapture>>,
     relevant: relevant::Relevant,
 }
 
@@ -88,6 +99,9 @@ where
             level,
             reset,
             family,
+            #[cfg(not(feature = "no-slow-safety-checks"))]
+            shadow: ShadowState::default(),
+            capture: None,
             relevant: relevant::Relevant,
         }
     }
@@ -105,6 +119,9 @@ where
             level: self.level,
             reset: self.reset,
             family: self.family,
+            #[cfg(not(feature = "no-slow-safety-checks"))]
+            shadow: self.shadow,
+            capture: self.capture,
             relevant: self.relevant,
         }
     }
@@ -122,6 +139,20 @@ where
         self.family
     }
 
+    /// Start capturing commands recorded with encoders of this buffer into [`CommandStream`].
+    /// Previous capture is discarded.
+    ///
+    /// [`CommandStream`]: ../stream/struct.CommandStream.html
+    pub fn start_capture(&mut self) {
+        self.capture = Some(Box::new(StreamCapture::new()));
+    }
+
+    /// Stop capturing commands and take captured ones.
+    /// Returns `None` if capture wasn't started.
+    pub fn take_capture(&mut self) -> Option<StreamCapture> {
+        self.capture.take().map(|capture| *capture)
+    }
+
     /// Convert capability level.
     pub fn with_queue_type(self) -> CommandBuffer<B, rendy_core::hal::queue::QueueType, S, L, R>
     where
@@ -134,6 +165,9 @@ where
             level: self.level,
             reset: self.reset,
             family: self.family,
+            #[cfg(not(feature = "no-slow-safety-checks"))]
+            shadow: self.shadow,
+            capture: self.capture,
             relevant: self.relevant,
         }
     }
@@ -151,6 +185,9 @@ where
                 level: self.level,
                 reset: self.reset,
                 family: self.family,
+                #[cfg(not(feature = "no-slow-safety-checks"))]
+                shadow: self.shadow,
+                capture: self.capture,
                 relevant: self.relevant,
             })
         } else {
@@ -255,6 +292,14 @@ where
                 info.inheritance_info(),
             );
 
+            #[cfg(not(feature = "no-slow-safety-checks"))]
+            {
+                self.shadow =
+                    ShadowState::begin(pass_relation.flags().contains(
+                        rendy_core::hal::command::CommandBufferFlags::RENDER_PASS_CONTINUE,
+                    ));
+            }
+
             self.change_state(|_| RecordingState(usage, pass_relation))
         }
     }
@@ -352,4 +397,13 @@ where
             self.raw.as_mut()
         }
     }
+
+    /// Set name of the command buffer shown by debugging tools.
+    /// This is no-op if backend doesn't support object names.
+    pub fn set_name(&mut self, device: &Device<B>, name: &str) {
+        unsafe {
+            // state guarantees that raw command buffer is not shared.
+            device.set_command_buffer_name(self.raw.as_mut(), name);
+        }
+    }
 }
@@ -0,0 +1,340 @@
+//! Shadow copy of command buffer state.
+//! Unless `"no-slow-safety-checks"` feature is enabled encoders track state set by recorded commands
+//! and check that draw and dispatch commands have all state they require.
+
+use {rendy_core::hal::pso::ShaderStageFlags, std::ops::Range};
+
+/// Requirements of pipeline that can't be derived from raw pipeline object.
+/// Passed along with pipeline to [`bind_graphics_pipeline_with_requirements`]
+/// and [`bind_compute_pipeline_with_requirements`],
+/// so that draw and dispatch commands check that required descriptor sets and vertex buffers are bound
+/// and push constants fit in the pipeline layout.
+///
+/// [`bind_graphics_pipeline_with_requirements`]: struct.EncoderCommon.html#method.bind_graphics_pipeline_with_requirements
+/// [`bind_compute_pipeline_with_requirements`]: struct.EncoderCommon.html#method.bind_compute_pipeline_with_requirements
+#[derive(Clone, Debug, Default)]
+pub struct PipelineRequirements {
+    /// Number of descriptor sets in pipeline layout.
+    /// Sets `0 .. sets` must be bound.
+    pub sets: u32,
+
+    /// Number of vertex buffers pipeline reads.
+    /// Bindings `0 .. vertex_buffers` must be bound.
+    pub vertex_buffers: u32,
+
+    /// Push constant ranges of the pipeline layout in bytes.
+    pub push_constants: Vec<(ShaderStageFlags, Range<u32>)>,
+}
+
+impl From<&rendy_core::types::Layout> for PipelineRequirements {
+    fn from(layout: &rendy_core::types::Layout) -> Self {
+        PipelineRequirements {
+            sets: layout.sets.len() as u32,
+            vertex_buffers: 0,
+            push_constants: layout.push_constants.clone(),
+        }
+    }
+}
+
+/// State of one pipeline bind point.
+#[cfg(not(feature = "no-slow-safety-checks"))]
+#[derive(Clone, Debug, Default)]
+struct BindPoint {
+    /// Whether any pipeline is bound.
+    pipeline: bool,
+    /// Requirements of bound pipeline.
+    /// `None` if pipeline was bound without requirements.
+    requirements: Option<PipelineRequirements>,
+    sets: u64,
+}
+
+#[cfg(not(feature = "no-slow-safety-checks"))]
+impl BindPoint {
+    fn bind_pipeline(&mut self, requirements: Option<PipelineRequirements>) {
+        self.pipeline = true;
+        self.requirements = requirements;
+    }
+
+    fn bind_sets(&mut self, first_set: u32, count: u32) {
+        self.sets |= bits(first_set..first_set + count);
+    }
+
+    fn check(&self, command: &str, kind: &str) -> Option<&PipelineRequirements> {
+        assert!(
+            self.pipeline,
+            "`{}` recorded without bound {} pipeline",
+            command, kind
+        );
+
+        let requirements = self.requirements.as_ref()?;
+        let missing = bits(0..requirements.sets) & !self.sets;
+        assert!(
+            missing == 0,
+            "`{}` recorded without descriptor sets {:?} required by {} pipeline layout",
+            command,
+            indices(missing),
+            kind
+        );
+        Some(requirements)
+    }
+
+    /// Push constants may be recorded before pipeline is bound.
+    /// Layout is unknown then and constants are not checked.
+    /// Same for pipelines bound without requirements.
+    fn check_push_constants(&self, stages: ShaderStageFlags, offset: u32, size: u32) {
+        let requirements = match &self.requirements {
+            Some(requirements) => requirements,
+            None => return,
+        };
+
+        for word in (offset..offset + size).step_by(4) {
+            let covered = requirements
+                .push_constants
+                .iter()
+                .filter(|(_, range)| range.start <= word && word + 4 <= range.end)
+                .fold(ShaderStageFlags::empty(), |acc, (stages, _)| acc | *stages);
+
+            assert!(
+                covered.contains(stages),
+                "Push constants {:?} for stages {:?} exceed pipeline layout ranges {:?}",
+                offset..offset + size,
+                stages,
+                requirements.push_constants
+            );
+        }
+    }
+}
+
+/// Shadow copy of state set by commands recorded into command buffer.
+#[cfg(not(feature = "no-slow-safety-checks"))]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ShadowState {
+    graphics: BindPoint,
+    compute: BindPoint,
+    vertex_buffers: u64,
+    index_buffer: bool,
+    render_pass: bool,
+}
+
+#[cfg(not(feature = "no-slow-safety-checks"))]
+impl ShadowState {
+    /// State of the command buffer that begins recording.
+    /// Secondary buffers that continue render pass start inside it.
+    pub(crate) fn begin(render_pass_continue: bool) -> Self {
+        ShadowState {
+            render_pass: render_pass_continue,
+            ..ShadowState::default()
+        }
+    }
+
+    pub(crate) fn bind_graphics_pipeline(&mut self, requirements: Option<PipelineRequirements>) {
+        self.graphics.bind_pipeline(requirements);
+    }
+
+    pub(crate) fn bind_compute_pipeline(&mut self, requirements: Option<PipelineRequirements>) {
+        self.compute.bind_pipeline(requirements);
+    }
+
+    pub(crate) fn bind_graphics_sets(&mut self, first_set: u32, count: u32) {
+        self.graphics.bind_sets(first_set, count);
+    }
+
+    pub(crate) fn bind_compute_sets(&mut self, first_set: u32, count: u32) {
+        self.compute.bind_sets(first_set, count);
+    }
+
+    pub(crate) fn bind_vertex_buffers(&mut self, first_binding: u32, count: u32) {
+        self.vertex_buffers |= bits(first_binding..first_binding + count);
+    }
+
+    pub(crate) fn bind_index_buffer(&mut self) {
+        self.index_buffer = true;
+    }
+
+    pub(crate) fn push_graphics_constants(
+        &self,
+        stages: ShaderStageFlags,
+        offset: u32,
+        count: u32,
+    ) {
+        self.graphics
+            .check_push_constants(stages, offset, count * 4);
+    }
+
+    pub(crate) fn push_compute_constants(&self, offset: u32, count: u32) {
+        self.compute
+            .check_push_constants(ShaderStageFlags::COMPUTE, offset, count * 4);
+    }
+
+    pub(crate) fn begin_render_pass(&mut self) {
+        assert!(
+            !self.render_pass,
+            "Render pass begins inside another render pass"
+        );
+        self.render_pass = true;
+    }
+
+    pub(crate) fn end_render_pass(&mut self) {
+        self.render_pass = false;
+    }
+
+    pub(crate) fn draw(&self, command: &str) {
+        assert!(
+            self.render_pass,
+            "`{}` recorded outside render pass",
+            command
+        );
+        let requirements = match self.graphics.check(command, "graphics") {
+            Some(requirements) => requirements,
+            None => return,
+        };
+
+        let missing = bits(0..requirements.vertex_buffers) & !self.vertex_buffers;
+        assert!(
+            missing == 0,
+            "`{}` recorded without vertex buffers {:?} required by graphics pipeline",
+            command,
+            indices(missing)
+        );
+    }
+
+    pub(crate) fn draw_indexed(&self, command: &str) {
+        self.draw(command);
+        assert!(
+            self.index_buffer,
+            "`{}` recorded without bound index buffer",
+            command
+        );
+    }
+
+    pub(crate) fn dispatch(&self, command: &str) {
+        assert!(
+            !self.render_pass,
+            "`{}` recorded inside render pass",
+            command
+        );
+        self.compute.check(command, "compute");
+    }
+}
+
+/// Mask with bits in `range` set.
+/// Indices beyond 63 are not tracked.
+#[cfg(not(feature = "no-slow-safety-checks"))]
+fn bits(range: Range<u32>) -> u64 {
+    range
+        .take_while(|&index| index < 64)
+        .fold(0, |acc, index| acc | (1 << index))
+}
+
+#[cfg(not(feature = "no-slow-safety-checks"))]
+fn indices(mask: u64) -> Vec<u32> {
+    (0..64).filter(|&index| mask & (1 << index) != 0).collect()
+}
+
+#[cfg(all(test, not(feature = "no-slow-safety-checks")))]
+mod tests {
+    use super::{PipelineRequirements, ShadowState};
+    use rendy_core::hal::pso::ShaderStageFlags;
+
+    fn ready() -> ShadowState {
+        let mut state = ShadowState::begin(false);
+        state.bind_graphics_pipeline(Some(PipelineRequirements {
+            sets: 2,
+            vertex_buffers: 1,
+            push_constants: vec![
+                (ShaderStageFlags::VERTEX, 0..16),
+                (ShaderStageFlags::FRAGMENT, 8..32),
+            ],
+        }));
+        state.bind_graphics_sets(0, 2);
+        state.bind_vertex_buffers(0, 1);
+        state.begin_render_pass();
+        state
+    }
+
+    #[test]
+    fn complete_state_passes() {
+        let mut state = ready();
+        state.draw("draw");
+        state.push_graphics_constants(ShaderStageFlags::VERTEX, 0, 4);
+        state.push_graphics_constants(ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT, 8, 2);
+        state.bind_index_buffer();
+        state.draw_indexed("draw_indexed");
+        state.end_render_pass();
+    }
+
+    #[test]
+    #[should_panic(expected = "exceed pipeline layout ranges")]
+    fn push_constants_out_of_range() {
+        ready().push_graphics_constants(ShaderStageFlags::VERTEX, 8, 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "without descriptor sets [1]")]
+    fn missing_descriptor_set() {
+        let mut state = ShadowState::begin(true);
+        state.bind_graphics_pipeline(Some(PipelineRequirements {
+            sets: 2,
+            ..PipelineRequirements::default()
+        }));
+        state.bind_graphics_sets(0, 1);
+        state.draw("draw");
+    }
+
+    #[test]
+    #[should_panic(expected = "without vertex buffers [0]")]
+    fn missing_vertex_buffer() {
+        let mut state = ShadowState::begin(true);
+        state.bind_graphics_pipeline(Some(PipelineRequirements {
+            vertex_buffers: 1,
+            ..PipelineRequirements::default()
+        }));
+        state.draw("draw");
+    }
+
+    #[test]
+    #[should_panic(expected = "without bound graphics pipeline")]
+    fn draw_without_pipeline() {
+        ShadowState::begin(true).draw("draw");
+    }
+
+    #[test]
+    fn pipeline_without_requirements_is_not_checked() {
+        let mut state = ShadowState::begin(true);
+        state.bind_graphics_pipeline(None);
+        state.push_graphics_constants(ShaderStageFlags::VERTEX, 64, 4);
+        state.draw("draw");
+    }
+
+    #[test]
+    #[should_panic(expected = "recorded inside render pass")]
+    fn dispatch_inside_render_pass() {
+        let mut state = ShadowState::begin(true);
+        state.bind_compute_pipeline(None);
+        state.dispatch("dispatch");
+    }
+
+    fn compute() -> ShadowState {
+        let mut state = ShadowState::begin(false);
+        state.bind_compute_pipeline(Some(PipelineRequirements {
+            sets: 1,
+            vertex_buffers: 0,
+            push_constants: vec![(ShaderStageFlags::COMPUTE, 0..8)],
+        }));
+        state
+    }
+
+    #[test]
+    fn compute_push_constants_in_range() {
+        let mut state = compute();
+        state.push_compute_constants(0, 2);
+        state.bind_compute_sets(0, 1);
+        state.dispatch("dispatch");
+    }
+
+    #[test]
+    #[should_panic(expected = "exceed pipeline layout ranges")]
+    fn compute_push_constants_out_of_range() {
+        compute().push_compute_constants(4, 2);
+    }
+}
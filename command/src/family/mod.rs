@@ -66,11 +66,13 @@ where
     /// Raw queue handle queried from device can make `Family` usage invalid.
     /// `family` must be one of the family indices used during `device` creation.
     /// `properties` must be the properties retuned for queue family from physical device.
+    /// `timestamp_period` is number of nanoseconds per timestamp tick of the physical device.
     pub unsafe fn from_device(
         queue_groups: &mut Vec<rendy_core::hal::queue::QueueGroup<B>>,
         id: FamilyId,
         count: usize,
         family: &impl rendy_core::hal::queue::QueueFamily,
+        timestamp_period: f32,
     ) -> Self {
         Family {
             id,
@@ -82,7 +84,9 @@ where
                     .queues
                     .into_iter()
                     .enumerate()
-                    .map(|(index, queue)| Queue::new(queue, QueueId { family: id, index }))
+                    .map(|(index, queue)| {
+                        Queue::new(queue, QueueId { family: id, index }, timestamp_period)
+                    })
                     .collect()
             },
             // min_image_transfer_granularity: properties.min_image_transfer_granularity,
@@ -245,18 +249,28 @@ where
 /// Raw queue handle queried from device can make returned `Family` usage invalid.
 /// `families` iterator must yeild unique family indices with queue count used during `device` creation.
 /// `properties` must contain properties retuned for queue family from physical device for each family id yielded by `families`.
+/// `timestamp_period` is number of nanoseconds per timestamp tick of the physical device.
 pub unsafe fn families_from_device<B>(
     device: DeviceId,
     queue_groups: &mut Vec<rendy_core::hal::queue::QueueGroup<B>>,
     families: impl IntoIterator<Item = (FamilyId, usize)>,
     queue_types: &[impl rendy_core::hal::queue::QueueFamily],
+    timestamp_period: f32,
 ) -> Families<B>
 where
     B: Backend,
 {
     let families: Vec<_> = families
         .into_iter()
-        .map(|(id, count)| Family::from_device(queue_groups, id, count, &queue_types[id.index]))
+        .map(|(id, count)| {
+            Family::from_device(
+                queue_groups,
+                id,
+                count,
+                &queue_types[id.index],
+                timestamp_period,
+            )
+        })
         .collect();
 
     let mut families_indices = vec![!0; families.len()];
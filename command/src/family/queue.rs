@@ -1,15 +1,88 @@
 use {
     super::{submission::*, QueueId},
     crate::{buffer::Submittable, fence::*},
-    rendy_core::hal::{queue::CommandQueue, Backend},
+    rendy_core::hal::{pso::PipelineStage, queue::CommandQueue, Backend},
 };
 
+/// Pointer to resource referenced by batched submission.
+/// Caller of `Queue::submit` guarantees it outlives the submission.
+#[derive(Debug)]
+struct Batched<T>(*const T);
+
+impl<T> Clone for Batched<T> {
+    fn clone(&self) -> Self {
+        Batched(self.0)
+    }
+}
+
+impl<T> Copy for Batched<T> {}
+
+impl<T> Batched<T> {
+    fn new(value: &T) -> Self {
+        Batched(value)
+    }
+}
+
+unsafe impl<T: Sync> Send for Batched<T> {}
+unsafe impl<T: Sync> Sync for Batched<T> {}
+
+/// Accumulated submission that is not yet passed to the driver.
+#[derive(Debug)]
+struct BatchedSubmission<S, C> {
+    waits: Vec<(S, PipelineStage)>,
+    command_buffers: Vec<C>,
+    signals: Vec<S>,
+}
+
+type RawBatchedSubmission<B> =
+    BatchedSubmission<Batched<<B as Backend>::Semaphore>, Batched<<B as Backend>::CommandBuffer>>;
+
+impl<S, C> BatchedSubmission<S, C> {
+    fn new() -> Self {
+        BatchedSubmission {
+            waits: Vec::new(),
+            command_buffers: Vec::new(),
+            signals: Vec::new(),
+        }
+    }
+
+    /// Check if submission with or without waits can be appended
+    /// without changing wait and signal semantics.
+    fn can_append(&self, waits: bool) -> bool {
+        self.signals.is_empty() && (!waits || self.command_buffers.is_empty())
+    }
+}
+
+/// Append submission to the batch.
+/// Merges it into the last batched submission if that doesn't change semantics.
+fn push_batched<S, C>(
+    batch: &mut Vec<BatchedSubmission<S, C>>,
+    waits: Vec<(S, PipelineStage)>,
+    command_buffers: impl IntoIterator<Item = C>,
+    signals: impl IntoIterator<Item = S>,
+) {
+    if !batch
+        .last()
+        .map_or(false, |last| last.can_append(!waits.is_empty()))
+    {
+        batch.push(BatchedSubmission::new());
+    }
+
+    let last = batch.last_mut().unwrap();
+    last.waits.extend(waits);
+    last.command_buffers.extend(command_buffers);
+    last.signals.extend(signals);
+}
+
 /// Command queue wrapper.
 #[derive(Debug)]
 pub struct Queue<B: Backend> {
     raw: B::CommandQueue,
     id: QueueId,
     next_epoch: u64,
+    timestamp_period: f32,
+    batch: Option<Vec<RawBatchedSubmission<B>>>,
+    driver_submits: u64,
 }
 
 family_owned!(@NOCAP Queue<B> @ |q: &Self| q.id.family);
@@ -18,11 +91,15 @@ impl<B> Queue<B>
 where
     B: Backend,
 {
-    pub(super) fn new(raw: B::CommandQueue, id: QueueId) -> Self {
+    pub(super) fn new(raw: B::CommandQueue, id: QueueId, timestamp_period: f32) -> Self {
+        assert!(timestamp_period > 0.0, "Timestamp period must be positive");
         Queue {
             id,
             raw,
             next_epoch: 0,
+            timestamp_period,
+            batch: None,
+            driver_submits: 0,
         }
     }
 
@@ -32,10 +109,108 @@ where
     }
 
     /// Get raw command queue.
+    /// Batched submissions are flushed first so that raw commands are ordered after them.
     pub fn raw(&mut self) -> &mut impl CommandQueue<B> {
+        self.flush();
         &mut self.raw
     }
 
+    /// Get number of nanoseconds per timestamp tick of this queue.
+    /// Initialized with period the family was created with.
+    pub fn timestamp_period(&self) -> f32 {
+        self.timestamp_period
+    }
+
+    /// Set number of nanoseconds per timestamp tick of this queue,
+    /// i.e. `timestampPeriod` limit of the physical device.
+    pub fn set_timestamp_period(&mut self, period: f32) {
+        assert!(period > 0.0, "Timestamp period must be positive");
+        self.timestamp_period = period;
+    }
+
+    /// Check if submissions are batched.
+    pub fn is_batching(&self) -> bool {
+        self.batch.is_some()
+    }
+
+    /// Enable or disable submission batching.
+    ///
+    /// In batching mode submissions are accumulated on the queue
+    /// and passed to the driver by [`flush`](#method.flush),
+    /// or when submitted with a fence.
+    /// Disabling batching flushes accumulated submissions.
+    pub fn set_batching(&mut self, batching: bool) {
+        if batching {
+            self.batch.get_or_insert_with(Vec::new);
+        } else {
+            self.flush();
+            self.batch = None;
+        }
+    }
+
+    /// Pass accumulated submissions to the driver.
+    /// Does nothing if batching is disabled or there is nothing to submit.
+    ///
+    /// Consecutive submissions are merged into one, unless that would
+    /// make commands wait for semaphores or delay signals of previous submissions.
+    pub fn flush(&mut self) {
+        unsafe {
+            // Submissions were validated by `submit` and resources are kept alive by the caller.
+            self.flush_batch(None)
+        }
+    }
+
+    /// Get number of submits passed to the driver since last [`reset_driver_submits`].
+    ///
+    /// [`reset_driver_submits`]: #method.reset_driver_submits
+    pub fn driver_submits(&self) -> u64 {
+        self.driver_submits
+    }
+
+    /// Reset counter of submits passed to the driver.
+    /// Returns counted value. Typically called once per frame.
+    pub fn reset_driver_submits(&mut self) -> u64 {
+        std::mem::replace(&mut self.driver_submits, 0)
+    }
+
+    unsafe fn flush_batch(&mut self, fence: Option<&B::Fence>) {
+        let batch = match &mut self.batch {
+            Some(batch) => std::mem::replace(batch, Vec::new()),
+            None => return,
+        };
+
+        if batch.is_empty() {
+            if fence.is_some() {
+                self.raw.submit(
+                    rendy_core::hal::queue::Submission {
+                        command_buffers: std::iter::empty::<&B::CommandBuffer>(),
+                        wait_semaphores: std::iter::empty::<(&B::Semaphore, _)>(),
+                        signal_semaphores: std::iter::empty::<&B::Semaphore>(),
+                    },
+                    fence,
+                );
+                self.driver_submits += 1;
+            }
+            return;
+        }
+
+        let last = batch.len() - 1;
+        for (index, submission) in batch.iter().enumerate() {
+            self.raw.submit(
+                rendy_core::hal::queue::Submission {
+                    command_buffers: submission.command_buffers.iter().map(|buffer| &*buffer.0),
+                    wait_semaphores: submission
+                        .waits
+                        .iter()
+                        .map(|&(semaphore, stage)| (&*semaphore.0, stage)),
+                    signal_semaphores: submission.signals.iter().map(|semaphore| &*semaphore.0),
+                },
+                if index == last { fence } else { None },
+            );
+            self.driver_submits += 1;
+        }
+    }
+
     /// Returns next queue epoch.
     pub fn next_epoch(&self) -> u64 {
         self.next_epoch
@@ -43,6 +218,10 @@ where
 
     /// Submit commands to the queue of the family.
     /// Fence must be submitted.
+    ///
+    /// In [batching](#method.set_batching) mode submissions are deferred
+    /// until flush, so semaphores and command buffers must be kept alive until then.
+    /// Submitting with fence flushes the batch.
     pub unsafe fn submit<'a>(
         &mut self,
         submissions: impl IntoIterator<
@@ -51,7 +230,7 @@ where
                 impl IntoIterator<
                     Item = (
                         &'a (impl std::borrow::Borrow<B::Semaphore> + 'a),
-                        rendy_core::hal::pso::PipelineStage,
+                        PipelineStage,
                     ),
                 >,
                 impl IntoIterator<Item = impl Submittable<B>>,
@@ -62,33 +241,32 @@ where
     ) {
         assert!(fence.as_ref().map_or(true, |f| f.is_unsignaled()));
 
-        let mut submissions = submissions.into_iter().peekable();
-        if submissions.peek().is_none() && fence.is_some() {
-            self.raw.submit(
-                rendy_core::hal::queue::Submission {
-                    command_buffers: std::iter::empty::<&'a B::CommandBuffer>(),
-                    wait_semaphores: std::iter::empty::<(&'a B::Semaphore, _)>(),
-                    signal_semaphores: std::iter::empty::<&'a B::Semaphore>(),
-                },
-                fence.as_ref().map(|f| f.raw()),
-            );
-        } else {
+        if let Some(batch) = &mut self.batch {
             let family = self.id.family;
-            while let Some(submission) = submissions.next() {
-                self.raw.submit(
-                    rendy_core::hal::queue::Submission {
-                        command_buffers: submission.submits.into_iter().map(|submit| {
-                            assert_eq!(submit.family(), family);
-                            submit.raw()
-                        }),
-                        wait_semaphores: submission.waits.into_iter().map(|w| (w.0.borrow(), w.1)),
-                        signal_semaphores: submission.signals.into_iter().map(|s| s.borrow()),
-                    },
-                    submissions
-                        .peek()
-                        .map_or(fence.as_ref().map(|f| f.raw()), |_| None),
+            for submission in submissions {
+                push_batched(
+                    batch,
+                    submission
+                        .waits
+                        .into_iter()
+                        .map(|w| (Batched::new(w.0.borrow()), w.1))
+                        .collect(),
+                    submission.submits.into_iter().map(|submit| {
+                        assert_eq!(submit.family(), family);
+                        Batched::new(submit.raw())
+                    }),
+                    submission
+                        .signals
+                        .into_iter()
+                        .map(|s| Batched::new(s.borrow())),
                 );
             }
+
+            if let Some(fence) = &fence {
+                self.flush_batch(Some(fence.raw()));
+            }
+        } else {
+            self.submit_immediate(submissions, fence.as_ref().map(|f| f.raw()));
         }
 
         if let Some(fence) = fence {
@@ -103,6 +281,7 @@ where
     /// Submit commands to the queue of the family.
     /// Fence must be submitted.
     /// This version uses raw fence and doesn't increment epoch.
+    /// Batched the same way as [`submit`](#method.submit).
     pub unsafe fn submit_raw_fence<'a>(
         &mut self,
         submissions: impl IntoIterator<
@@ -111,7 +290,53 @@ where
                 impl IntoIterator<
                     Item = (
                         &'a (impl std::borrow::Borrow<B::Semaphore> + 'a),
-                        rendy_core::hal::pso::PipelineStage,
+                        PipelineStage,
+                    ),
+                >,
+                impl IntoIterator<Item = impl Submittable<B>>,
+                impl IntoIterator<Item = &'a (impl std::borrow::Borrow<B::Semaphore> + 'a)>,
+            >,
+        >,
+        fence: Option<&B::Fence>,
+    ) {
+        if let Some(batch) = &mut self.batch {
+            let family = self.id.family;
+            for submission in submissions {
+                push_batched(
+                    batch,
+                    submission
+                        .waits
+                        .into_iter()
+                        .map(|w| (Batched::new(w.0.borrow()), w.1))
+                        .collect(),
+                    submission.submits.into_iter().map(|submit| {
+                        assert_eq!(submit.family(), family);
+                        Batched::new(submit.raw())
+                    }),
+                    submission
+                        .signals
+                        .into_iter()
+                        .map(|s| Batched::new(s.borrow())),
+                );
+            }
+
+            if fence.is_some() {
+                self.flush_batch(fence);
+            }
+        } else {
+            self.submit_immediate(submissions, fence);
+        }
+    }
+
+    unsafe fn submit_immediate<'a>(
+        &mut self,
+        submissions: impl IntoIterator<
+            Item = Submission<
+                B,
+                impl IntoIterator<
+                    Item = (
+                        &'a (impl std::borrow::Borrow<B::Semaphore> + 'a),
+                        PipelineStage,
                     ),
                 >,
                 impl IntoIterator<Item = impl Submittable<B>>,
@@ -130,6 +355,7 @@ where
                 },
                 fence,
             );
+            self.driver_submits += 1;
         } else {
             let family = self.id.family;
             while let Some(submission) = submissions.next() {
@@ -144,12 +370,83 @@ where
                     },
                     submissions.peek().map_or(fence, |_| None),
                 );
+                self.driver_submits += 1;
             }
         }
     }
 
     /// Wait for queue to finish all pending commands.
-    pub fn wait_idle(&self) -> Result<(), rendy_core::hal::device::OutOfMemory> {
+    /// Batched submissions are flushed first, so resources they reference
+    /// can be destroyed afterwards.
+    pub fn wait_idle(&mut self) -> Result<(), rendy_core::hal::device::OutOfMemory> {
+        self.flush();
         self.raw.wait_idle()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{push_batched, BatchedSubmission};
+    use rendy_core::hal::pso::PipelineStage;
+
+    fn push(
+        batch: &mut Vec<BatchedSubmission<u32, u32>>,
+        waits: &[u32],
+        command_buffers: &[u32],
+        signals: &[u32],
+    ) {
+        push_batched(
+            batch,
+            waits
+                .iter()
+                .map(|&wait| (wait, PipelineStage::TOP_OF_PIPE))
+                .collect(),
+            command_buffers.iter().cloned(),
+            signals.iter().cloned(),
+        );
+    }
+
+    fn command_buffers(batch: &[BatchedSubmission<u32, u32>]) -> Vec<Vec<u32>> {
+        batch
+            .iter()
+            .map(|submission| submission.command_buffers.clone())
+            .collect()
+    }
+
+    #[test]
+    fn plain_submissions_merge() {
+        let mut batch = Vec::new();
+        push(&mut batch, &[], &[0], &[]);
+        push(&mut batch, &[], &[1, 2], &[]);
+        push(&mut batch, &[], &[3], &[]);
+        assert_eq!(command_buffers(&batch), vec![vec![0, 1, 2, 3]]);
+    }
+
+    #[test]
+    fn signal_ends_submission() {
+        let mut batch = Vec::new();
+        push(&mut batch, &[], &[0], &[10]);
+        push(&mut batch, &[], &[1], &[]);
+        push(&mut batch, &[], &[2], &[11]);
+        assert_eq!(command_buffers(&batch), vec![vec![0], vec![1, 2]]);
+        assert_eq!(batch[0].signals, vec![10]);
+        assert_eq!(batch[1].signals, vec![11]);
+    }
+
+    #[test]
+    fn waits_start_submission() {
+        let mut batch = Vec::new();
+        push(&mut batch, &[10], &[0], &[]);
+        push(&mut batch, &[11], &[1], &[]);
+        push(&mut batch, &[], &[2], &[]);
+        assert_eq!(command_buffers(&batch), vec![vec![0], vec![1, 2]]);
+        assert_eq!(batch[1].waits.len(), 1);
+
+        // Waits are merged while no commands were batched.
+        let mut batch = Vec::new();
+        push(&mut batch, &[10], &[], &[]);
+        push(&mut batch, &[11], &[0], &[]);
+        assert_eq!(command_buffers(&batch), vec![vec![0]]);
+        assert_eq!(batch[0].waits.len(), 2);
+    }
+}
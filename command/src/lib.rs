@@ -83,6 +83,14 @@ mod buffer;
 mod capability;
 mod family;
 mod fence;
+mod manager;
+mod parallel;
 mod pool;
+mod query;
+mod stream;
+mod timeline;
 
-pub use crate::{buffer::*, capability::*, family::*, fence::*, pool::*};
+pub use crate::{
+    buffer::*, capability::*, family::*, fence::*, manager::*, parallel::*, pool::*, query::*,
+    stream::*, timeline::*,
+};
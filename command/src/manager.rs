@@ -0,0 +1,523 @@
+//! Command pool manager.
+//! Hands out command pools per family and level
+//! and recycles them once commands recorded from them are complete.
+
+use {
+    crate::{
+        buffer::{
+            CommandBuffer, InitialState, InvalidState, Level, NoIndividualReset, PendingState,
+            Resettable,
+        },
+        capability::QueueType,
+        core::{device_owned, Device, DeviceId},
+        family::{Family, FamilyId, QueueId},
+        fence::{Fence, FenceEpoch},
+        pool::CommandPool,
+    },
+    rendy_core::hal::Backend,
+    std::{any::TypeId, collections::HashMap, hash::Hash, sync::Mutex},
+};
+
+/// Acquired pools are owned exclusively,
+/// so any thread can reuse pool released by another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct PoolKey {
+    family: FamilyId,
+    level: TypeId,
+}
+
+impl PoolKey {
+    fn new<L: Level>(family: FamilyId) -> Self {
+        PoolKey {
+            family,
+            level: TypeId::of::<L>(),
+        }
+    }
+}
+
+/// Merge epoch of submission into latest epochs per queue.
+fn merge_epoch(epochs: &mut Vec<FenceEpoch>, epoch: FenceEpoch) {
+    match epochs.iter_mut().find(|known| known.queue == epoch.queue) {
+        Some(known) => known.epoch = known.epoch.max(epoch.epoch),
+        None => epochs.push(epoch),
+    }
+}
+
+/// Released pools waiting for their epochs to complete and pools ready to be acquired.
+#[derive(Debug)]
+struct Recycler<K: Eq + Hash, T> {
+    /// Latest complete epoch of each queue.
+    completed: HashMap<QueueId, u64>,
+
+    /// Pools ready to be acquired.
+    ready: HashMap<K, Vec<T>>,
+
+    /// Pools with commands that may still be executing
+    /// and latest epochs in which they were submitted.
+    pending: Vec<(K, Vec<FenceEpoch>, T)>,
+}
+
+impl<K, T> Recycler<K, T>
+where
+    K: Eq + Hash,
+{
+    fn new() -> Self {
+        Recycler {
+            completed: HashMap::new(),
+            ready: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    fn is_complete(&self, epochs: &[FenceEpoch]) -> bool {
+        epochs.iter().all(|epoch| {
+            self.completed
+                .get(&epoch.queue)
+                .map_or(false, |&complete| complete >= epoch.epoch)
+        })
+    }
+
+    fn acquire(&mut self, key: &K) -> Option<T> {
+        self.ready.get_mut(key).and_then(Vec::pop)
+    }
+
+    /// Release pool with buffers submitted in `epochs`.
+    /// Pool is reset with `reset` once all epochs are complete.
+    fn release(
+        &mut self,
+        key: K,
+        epochs: Vec<FenceEpoch>,
+        mut pool: T,
+        reset: impl FnOnce(&mut T),
+    ) {
+        if self.is_complete(&epochs) {
+            reset(&mut pool);
+            self.ready.entry(key).or_default().push(pool);
+        } else {
+            self.pending.push((key, epochs, pool));
+        }
+    }
+
+    /// Mark epoch complete and reset pools whose epochs all became complete.
+    fn complete(&mut self, epoch: FenceEpoch, mut reset: impl FnMut(&mut T)) {
+        let completed = self.completed.entry(epoch.queue).or_insert(0);
+        *completed = (*completed).max(epoch.epoch);
+
+        let mut index = 0;
+        while index < self.pending.len() {
+            if self.is_complete(&self.pending[index].1) {
+                let (key, _, mut pool) = self.pending.swap_remove(index);
+                reset(&mut pool);
+                self.ready.entry(key).or_default().push(pool);
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    /// Take all pools, complete or not.
+    fn drain(self) -> impl Iterator<Item = (K, T)>
+    where
+        K: Copy,
+    {
+        self.ready
+            .into_iter()
+            .flat_map(|(key, pools)| pools.into_iter().map(move |pool| (key, pool)))
+            .chain(self.pending.into_iter().map(|(key, _, pool)| (key, pool)))
+    }
+}
+
+#[derive(Debug)]
+struct PoolEntry<B: Backend> {
+    pool: CommandPool<B, QueueType, NoIndividualReset>,
+    capability: QueueType,
+
+    /// Buffers in initial state ready to be handed out.
+    free: Vec<B::CommandBuffer>,
+
+    /// Buffers that will become initial after pool reset.
+    used: Vec<B::CommandBuffer>,
+
+    /// Latest epochs in which buffers of this pool were submitted, per queue.
+    epochs: Vec<FenceEpoch>,
+}
+
+impl<B> PoolEntry<B>
+where
+    B: Backend,
+{
+    /// Reset pool.
+    ///
+    /// # Safety
+    ///
+    /// All buffers must be complete.
+    unsafe fn reset(&mut self) {
+        if !self.used.is_empty() {
+            self.pool.reset();
+            self.free.extend(self.used.drain(..));
+        }
+    }
+
+    /// # Safety
+    ///
+    /// All buffers must be complete.
+    unsafe fn dispose(mut self, device: &Device<B>, level: impl Level) {
+        let family = self.pool.family_id();
+        let capability = self.capability;
+        let buffers = self
+            .free
+            .drain(..)
+            .chain(self.used.drain(..))
+            .map(|raw| {
+                CommandBuffer::from_raw(
+                    raw,
+                    capability,
+                    InitialState,
+                    level,
+                    NoIndividualReset,
+                    family,
+                )
+            })
+            .collect::<Vec<_>>();
+        self.pool.free_buffers(buffers);
+        self.pool.dispose(device);
+    }
+}
+
+#[derive(Debug)]
+struct ManagerState<B: Backend> {
+    /// Capabilities of registered families.
+    families: HashMap<FamilyId, QueueType>,
+
+    /// Released pools.
+    pools: Recycler<PoolKey, PoolEntry<B>>,
+}
+
+/// Manager of command pools.
+/// Hands out pools per family and level.
+/// Acquired pool is owned exclusively until released,
+/// so that commands can be recorded without pool synchronization.
+///
+/// Pools returned to the manager with [`release`] are reset and recycled
+/// as soon as all epochs in which their buffers were submitted are complete.
+/// Completion is reported with [`check_fence`] or [`complete`].
+///
+/// [`release`]: #method.release
+/// [`check_fence`]: #method.check_fence
+/// [`complete`]: #method.complete
+#[derive(Debug)]
+pub struct CommandPoolManager<B: Backend> {
+    device: DeviceId,
+    state: Mutex<ManagerState<B>>,
+}
+
+device_owned!(CommandPoolManager<B>);
+
+impl<B> CommandPoolManager<B>
+where
+    B: Backend,
+{
+    /// Create new manager for the device.
+    pub fn new(device: &Device<B>) -> Self {
+        CommandPoolManager {
+            device: device.id(),
+            state: Mutex::new(ManagerState {
+                families: HashMap::new(),
+                pools: Recycler::new(),
+            }),
+        }
+    }
+
+    /// Register family so that pools for it can be acquired.
+    pub fn register(&self, family: &Family<B>) {
+        assert_eq!(
+            family.id().device,
+            self.device,
+            "Family is not owned by manager's device"
+        );
+        let mut state = self.state.lock().unwrap();
+        state.families.insert(family.id(), family.capability());
+    }
+
+    /// Acquire pool.
+    /// Recycled pool is returned if available, otherwise new pool is created.
+    ///
+    /// Panics if family wasn't [registered](#method.register).
+    pub fn acquire<L>(
+        &self,
+        device: &Device<B>,
+        family: FamilyId,
+    ) -> Result<ManagedPool<B, L>, rendy_core::hal::device::OutOfMemory>
+    where
+        L: Level,
+    {
+        self.assert_device_owner(device);
+        let key = PoolKey::new::<L>(family);
+
+        let (entry, capability) = {
+            let mut state = self.state.lock().unwrap();
+            let capability = *state
+                .families
+                .get(&family)
+                .expect("Family is not registered");
+            (state.pools.acquire(&key), capability)
+        };
+
+        let entry = match entry {
+            Some(entry) => entry,
+            None => PoolEntry {
+                pool: unsafe {
+                    // Registered family belongs to the device and has this capability.
+                    CommandPool::create(family, capability, device)?
+                },
+                capability,
+                free: Vec::new(),
+                used: Vec::new(),
+                epochs: Vec::new(),
+            },
+        };
+
+        Ok(ManagedPool {
+            entry,
+            key,
+            level: L::default(),
+            relevant: relevant::Relevant,
+        })
+    }
+
+    /// Return pool to the manager.
+    /// Pool will be recycled once epochs of all submitted buffers are complete.
+    pub fn release<L>(&self, pool: ManagedPool<B, L>)
+    where
+        L: Level,
+    {
+        let ManagedPool {
+            mut entry,
+            key,
+            relevant,
+            ..
+        } = pool;
+        relevant.dispose();
+        let epochs = std::mem::replace(&mut entry.epochs, Vec::new());
+        let mut state = self.state.lock().unwrap();
+        state.pools.release(key, epochs, entry, |entry| unsafe {
+            // All epochs in which buffers were submitted are complete.
+            entry.reset()
+        });
+    }
+
+    /// Check if submitted fence is signaled.
+    /// If it is, its epoch is reported [complete](#method.complete).
+    ///
+    /// Panics if fence is not submitted.
+    pub fn check_fence(
+        &self,
+        device: &Device<B>,
+        fence: &mut Fence<B>,
+    ) -> Result<bool, rendy_core::hal::device::DeviceLost> {
+        match fence.check_signaled(device)? {
+            Some(epoch) => {
+                unsafe {
+                    // Fence is signaled.
+                    self.complete(epoch);
+                }
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Report that all submissions to the queue up to and including specified epoch are complete.
+    /// Pools with all buffers complete are reset and become available immediately.
+    /// Typically called with epoch returned from [`Fence::wait_signaled`] or [`Fence::check_signaled`].
+    ///
+    /// # Safety
+    ///
+    /// Epoch must be actually complete.
+    ///
+    /// [`Fence::wait_signaled`]: struct.Fence.html#method.wait_signaled
+    /// [`Fence::check_signaled`]: struct.Fence.html#method.check_signaled
+    pub unsafe fn complete(&self, epoch: FenceEpoch) {
+        let mut state = self.state.lock().unwrap();
+        state.pools.complete(epoch, |entry| {
+            // All epochs in which buffers were submitted are complete.
+            entry.reset()
+        });
+    }
+
+    /// Dispose of the manager and all its pools.
+    ///
+    /// # Safety
+    ///
+    /// All buffers submitted from managed pools must be complete.
+    /// All acquired pools must be released.
+    pub unsafe fn dispose(self, device: &Device<B>) {
+        self.assert_device_owner(device);
+        let state = self.state.into_inner().unwrap();
+        for (key, entry) in state.pools.drain() {
+            if key.level == TypeId::of::<crate::buffer::PrimaryLevel>() {
+                entry.dispose(device, crate::buffer::PrimaryLevel);
+            } else {
+                entry.dispose(device, crate::buffer::SecondaryLevel);
+            }
+        }
+    }
+}
+
+/// Command pool acquired from [`CommandPoolManager`].
+/// Must be returned with [`CommandPoolManager::release`].
+///
+/// [`CommandPoolManager`]: struct.CommandPoolManager.html
+/// [`CommandPoolManager::release`]: struct.CommandPoolManager.html#method.release
+#[derive(Debug)]
+pub struct ManagedPool<B: Backend, L> {
+    entry: PoolEntry<B>,
+    key: PoolKey,
+    level: L,
+    relevant: relevant::Relevant,
+}
+
+impl<B, L> ManagedPool<B, L>
+where
+    B: Backend,
+    L: Level,
+{
+    /// Get family of the pool.
+    pub fn family(&self) -> FamilyId {
+        self.key.family
+    }
+
+    /// Get command buffer in initial state.
+    /// Recycled buffer is returned if available, otherwise new buffer is allocated.
+    pub fn allocate(&mut self) -> CommandBuffer<B, QueueType, InitialState, L> {
+        match self.entry.free.pop() {
+            Some(raw) => unsafe {
+                // Free buffers are reset with the pool.
+                CommandBuffer::from_raw(
+                    raw,
+                    self.entry.capability,
+                    InitialState,
+                    self.level,
+                    NoIndividualReset,
+                    self.key.family,
+                )
+            },
+            None => self.entry.pool.allocate_buffers(1).pop().unwrap(),
+        }
+    }
+
+    /// Return submitted buffer to the pool.
+    /// Pool won't be recycled until `epoch` is complete.
+    ///
+    /// For secondary buffers `epoch` must be the epoch of submission
+    /// of primary buffer that executes it.
+    pub fn submitted<C, N>(
+        &mut self,
+        buffer: CommandBuffer<B, C, PendingState<N>, L>,
+        epoch: FenceEpoch,
+    ) {
+        assert_eq!(
+            buffer.family(),
+            self.key.family,
+            "Buffer is not allocated from this pool"
+        );
+        let raw = unsafe {
+            // Buffer is considered invalid until pool is reset after `epoch` completes.
+            buffer.change_state(|_| InvalidState).into_raw()
+        };
+        self.entry.used.push(raw);
+        merge_epoch(&mut self.entry.epochs, epoch);
+    }
+
+    /// Return buffer that was never submitted to the pool.
+    /// It will be reset with the pool.
+    pub fn retire<C, S>(&mut self, buffer: CommandBuffer<B, C, S, L>)
+    where
+        S: Resettable,
+    {
+        assert_eq!(
+            buffer.family(),
+            self.key.family,
+            "Buffer is not allocated from this pool"
+        );
+        self.entry.used.push(buffer.into_raw());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{merge_epoch, Recycler},
+        crate::{
+            core::{DeviceId, InstanceId},
+            family::{FamilyId, QueueId},
+            fence::FenceEpoch,
+        },
+    };
+
+    fn epoch(device: DeviceId, queue: usize, epoch: u64) -> FenceEpoch {
+        FenceEpoch {
+            queue: QueueId {
+                index: queue,
+                family: FamilyId { index: 0, device },
+            },
+            epoch,
+        }
+    }
+
+    #[test]
+    fn epochs_merge_per_queue() {
+        let device = DeviceId::new(InstanceId::new());
+        let mut epochs = Vec::new();
+        merge_epoch(&mut epochs, epoch(device, 0, 3));
+        merge_epoch(&mut epochs, epoch(device, 1, 1));
+        merge_epoch(&mut epochs, epoch(device, 0, 2));
+        merge_epoch(&mut epochs, epoch(device, 1, 4));
+
+        let epochs = epochs
+            .iter()
+            .map(|epoch| (epoch.queue.index, epoch.epoch))
+            .collect::<Vec<_>>();
+        assert_eq!(epochs, vec![(0, 3), (1, 4)]);
+    }
+
+    #[test]
+    fn pools_recycle_on_completion() {
+        let device = DeviceId::new(InstanceId::new());
+        let mut recycler = Recycler::new();
+        let mut resets = 0;
+
+        recycler.release(
+            0,
+            vec![epoch(device, 0, 2), epoch(device, 1, 1)],
+            'a',
+            |_| resets += 1,
+        );
+        recycler.release(0, vec![epoch(device, 0, 1)], 'b', |_| resets += 1);
+        assert_eq!(recycler.acquire(&0), None);
+
+        recycler.complete(epoch(device, 0, 2), |_| resets += 1);
+        assert_eq!(resets, 1);
+        assert_eq!(recycler.acquire(&0), Some('b'));
+        assert_eq!(recycler.acquire(&0), None);
+
+        recycler.complete(epoch(device, 1, 1), |_| resets += 1);
+        assert_eq!(resets, 2);
+        assert_eq!(recycler.acquire(&1), None);
+        assert_eq!(recycler.acquire(&0), Some('a'));
+        assert!(recycler.pending.is_empty());
+    }
+
+    #[test]
+    fn complete_pools_are_ready_on_release() {
+        let device = DeviceId::new(InstanceId::new());
+        let mut recycler = Recycler::new();
+        let mut resets = 0;
+
+        recycler.complete(epoch(device, 0, 5), |_: &mut char| unreachable!());
+        recycler.release(0, Vec::new(), 'a', |_| resets += 1);
+        recycler.release(0, vec![epoch(device, 0, 4)], 'b', |_| resets += 1);
+        assert_eq!(resets, 2);
+        assert_eq!(recycler.drain().count(), 2);
+    }
+}
@@ -0,0 +1,171 @@
+//! Parallel recording of secondary command buffers.
+
+use {
+    crate::{
+        buffer::{
+            CommandBuffer, InvalidState, NoSimultaneousUse, OneShot, PendingState,
+            RenderPassContinue, RenderPassEncoder, RenderPassSecondaryEncoder, SecondaryLevel,
+            Submit,
+        },
+        capability::QueueType,
+        core::Device,
+        family::FamilyId,
+        fence::FenceEpoch,
+        manager::{CommandPoolManager, ManagedPool},
+    },
+    rayon::prelude::*,
+    rendy_core::hal::{device::OutOfMemory, pass::Subpass, Backend},
+};
+
+#[derive(Debug)]
+struct RecordedSecondary<B: Backend> {
+    submit: Option<Submit<B, NoSimultaneousUse, SecondaryLevel, RenderPassContinue>>,
+    buffer: CommandBuffer<B, QueueType, PendingState<InvalidState>, SecondaryLevel>,
+    pool: ManagedPool<B, SecondaryLevel>,
+}
+
+/// Secondary command buffers recorded by [`record_parallel`].
+/// Must be [executed](#method.execute) in the subpass they were recorded for
+/// and then returned to the manager with [`finish`](#method.finish).
+///
+/// Dropping them instead leaks buffers and pools with a warning.
+///
+/// [`record_parallel`]: fn.record_parallel.html
+#[derive(Debug)]
+#[must_use = "Recorded buffers must be returned to the manager with `ParallelSecondaries::finish`"]
+pub struct ParallelSecondaries<B: Backend> {
+    recorded: Vec<RecordedSecondary<B>>,
+}
+
+impl<B> ParallelSecondaries<B>
+where
+    B: Backend,
+{
+    /// Execute recorded buffers in order of jobs.
+    /// Panics if called more than once.
+    pub fn execute(&mut self, encoder: &mut RenderPassSecondaryEncoder<'_, B>) {
+        encoder.execute_commands(self.recorded.iter_mut().map(|recorded| {
+            recorded
+                .submit
+                .take()
+                .expect("Secondary buffers are already executed")
+        }));
+    }
+
+    /// Return buffers and their pools to the manager.
+    /// Pools will be recycled once `epoch` is complete.
+    ///
+    /// # Safety
+    ///
+    /// Completion of `epoch` must imply completion of the primary buffer
+    /// the secondary buffers were executed in.
+    pub unsafe fn finish(mut self, manager: &CommandPoolManager<B>, epoch: FenceEpoch) {
+        for recorded in self.recorded.drain(..) {
+            let mut pool = recorded.pool;
+            pool.submitted(recorded.buffer, epoch);
+            manager.release(pool);
+        }
+    }
+}
+
+impl<B> Drop for ParallelSecondaries<B>
+where
+    B: Backend,
+{
+    fn drop(&mut self) {
+        if !self.recorded.is_empty() {
+            log::warn!(
+                "{} parallel secondary buffers are dropped without `ParallelSecondaries::finish`. Their pools are leaked",
+                self.recorded.len()
+            );
+            for recorded in self.recorded.drain(..) {
+                // Pools can't be released without the manager.
+                std::mem::forget(recorded);
+            }
+        }
+    }
+}
+
+/// Record draw jobs into secondary buffers in parallel.
+///
+/// Jobs are split into at most `threads` contiguous chunks.
+/// Chunks are recorded concurrently on the global `rayon` thread pool,
+/// so `threads` limits number of buffers, not number of worker threads.
+/// Each chunk is recorded into one-shot `SecondaryLevel` buffer
+/// that continues `subpass`, using pool acquired from `manager` for that chunk.
+/// Buffers are returned in order of jobs so that executing them
+/// produces the same result as recording jobs inline one after another.
+pub fn record_parallel<B, J, F>(
+    device: &Device<B>,
+    manager: &CommandPoolManager<B>,
+    family: FamilyId,
+    subpass: Subpass<'_, B>,
+    framebuffer: Option<&B::Framebuffer>,
+    jobs: &mut [J],
+    threads: usize,
+    record: F,
+) -> Result<ParallelSecondaries<B>, OutOfMemory>
+where
+    B: Backend,
+    J: Send,
+    F: Fn(&mut J, RenderPassEncoder<'_, B>) + Sync,
+{
+    if jobs.is_empty() {
+        return Ok(ParallelSecondaries {
+            recorded: Vec::new(),
+        });
+    }
+
+    let threads = threads.max(1);
+    let chunk_size = (jobs.len() + threads - 1) / threads;
+    let index = subpass.index;
+    let main_pass = subpass.main_pass;
+
+    let results = jobs
+        .par_chunks_mut(chunk_size)
+        .map(|chunk| {
+            let mut pool = manager.acquire::<SecondaryLevel>(device, family)?;
+            let mut buffer = pool
+                .allocate()
+                .begin(OneShot, (Subpass { index, main_pass }, framebuffer));
+
+            {
+                let mut encoder = buffer.render_pass_encoder();
+                for job in chunk {
+                    record(job, encoder.reborrow());
+                }
+            }
+
+            let (submit, buffer) = buffer.finish().submit_once();
+            Ok(RecordedSecondary {
+                submit: Some(submit),
+                buffer,
+                pool,
+            })
+        })
+        .collect::<Vec<Result<_, OutOfMemory>>>();
+
+    if let Some(err) = results
+        .iter()
+        .find_map(|result| result.as_ref().err().cloned())
+    {
+        for recorded in results.into_iter().filter_map(Result::ok) {
+            let RecordedSecondary {
+                submit,
+                buffer,
+                mut pool,
+            } = recorded;
+            drop(submit);
+            pool.retire(unsafe {
+                // Buffer was never submitted.
+                buffer.change_state(|_| InvalidState)
+            });
+            manager.release(pool);
+        }
+        return Err(err);
+    }
+
+    Ok(ParallelSecondaries {
+        recorded: results.into_iter().filter_map(Result::ok).collect(),
+    })
+}
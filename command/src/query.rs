@@ -0,0 +1,244 @@
+//! Query pools.
+//!
+//! `QueryPool` owns raw pool of queries of one `QueryKind`.
+//! Queries are written by `Encoder` commands and results are read back on the host.
+//! Timestamps are converted to nanoseconds using period of the queue they were written on.
+
+use {
+    crate::{
+        capability::{Capability, Execute, Graphics},
+        core::{device_owned, Device, DeviceId},
+        family::Queue,
+    },
+    rendy_core::hal::{
+        device::Device as _,
+        query::{CreationError, Id, PipelineStatistic, ResultFlags, Type},
+        Backend,
+    },
+    std::ops::Range,
+};
+
+/// Kind of queries in the pool.
+pub trait QueryKind: Copy + std::fmt::Debug + 'static {
+    /// Get raw query type.
+    fn query_type(&self) -> Type;
+
+    /// Number of 64-bit values each query writes.
+    fn values_per_query(&self) -> usize {
+        1
+    }
+}
+
+/// Kind of queries that are active between `begin_query` and `end_query` commands.
+pub trait ScopedQueryKind: QueryKind {
+    /// Capability required to begin and end query of this kind.
+    type Capability: Capability;
+}
+
+/// Occlusion queries.
+/// Count samples that passed depth and stencil tests.
+#[derive(Clone, Copy, Debug)]
+pub struct Occlusion;
+
+/// Timestamp queries.
+/// Record device time when all previous commands reach specified pipeline stage.
+#[derive(Clone, Copy, Debug)]
+pub struct Timestamp;
+
+/// Pipeline statistics queries.
+/// Count invocations of pipeline stages specified.
+#[derive(Clone, Copy, Debug)]
+pub struct PipelineStatistics(pub PipelineStatistic);
+
+impl QueryKind for Occlusion {
+    fn query_type(&self) -> Type {
+        Type::Occlusion
+    }
+}
+
+impl ScopedQueryKind for Occlusion {
+    type Capability = Graphics;
+}
+
+impl QueryKind for Timestamp {
+    fn query_type(&self) -> Type {
+        Type::Timestamp
+    }
+}
+
+impl QueryKind for PipelineStatistics {
+    fn query_type(&self) -> Type {
+        Type::PipelineStatistics(self.0)
+    }
+
+    fn values_per_query(&self) -> usize {
+        self.0.bits().count_ones() as usize
+    }
+}
+
+impl ScopedQueryKind for PipelineStatistics {
+    type Capability = Execute;
+}
+
+/// Query pool wrapper.
+#[derive(Debug)]
+pub struct QueryPool<B: Backend, K> {
+    device: DeviceId,
+    raw: B::QueryPool,
+    kind: K,
+    count: Id,
+    relevant: relevant::Relevant,
+}
+
+device_owned!(QueryPool<B, K>);
+
+impl<B, K> QueryPool<B, K>
+where
+    B: Backend,
+    K: QueryKind,
+{
+    /// Create query pool with `count` queries of specified kind.
+    pub fn create(device: &Device<B>, kind: K, count: Id) -> Result<Self, CreationError> {
+        let raw = unsafe { device.create_query_pool(kind.query_type(), count) }?;
+        Ok(QueryPool {
+            device: device.id(),
+            raw,
+            kind,
+            count,
+            relevant: relevant::Relevant,
+        })
+    }
+
+    /// Destroy query pool.
+    ///
+    /// # Safety
+    ///
+    /// Pool must not be used by pending command buffers.
+    pub unsafe fn dispose(self, device: &Device<B>) {
+        self.assert_device_owner(device);
+        device.destroy_query_pool(self.raw);
+        self.relevant.dispose();
+    }
+
+    /// Get raw query pool.
+    pub fn raw(&self) -> &B::QueryPool {
+        &self.raw
+    }
+
+    /// Get kind of queries.
+    pub fn kind(&self) -> K {
+        self.kind
+    }
+
+    /// Get number of queries in the pool.
+    pub fn count(&self) -> Id {
+        self.count
+    }
+
+    /// Get raw query with `id` in this pool.
+    pub fn query(&self, id: Id) -> rendy_core::hal::query::Query<'_, B> {
+        assert!(id < self.count, "Query {} is out of pool bounds", id);
+        rendy_core::hal::query::Query {
+            pool: &self.raw,
+            id,
+        }
+    }
+
+    /// Check that range of queries fits in the pool.
+    pub(crate) fn assert_range(&self, queries: &Range<Id>) {
+        assert!(
+            queries.start <= queries.end && queries.end <= self.count,
+            "Queries {:?} are out of pool bounds",
+            queries
+        );
+    }
+
+    /// Read 64-bit results of range of queries.
+    /// Each query yields `K::values_per_query` values.
+    /// If `wait` is `false` and results are not available yet returns `None`.
+    pub fn read_results(
+        &self,
+        device: &Device<B>,
+        queries: Range<Id>,
+        wait: bool,
+    ) -> Result<Option<Vec<u64>>, rendy_core::hal::device::OomOrDeviceLost> {
+        self.assert_device_owner(device);
+        self.assert_range(&queries);
+
+        let values = self.kind.values_per_query();
+        let mut data = vec![0u64; values * (queries.end - queries.start) as usize];
+        let flags = if wait {
+            ResultFlags::BITS_64 | ResultFlags::WAIT
+        } else {
+            ResultFlags::BITS_64
+        };
+
+        let ready = unsafe {
+            device.get_query_pool_results(
+                &self.raw,
+                queries,
+                std::slice::from_raw_parts_mut(
+                    data.as_mut_ptr() as *mut u8,
+                    data.len() * std::mem::size_of::<u64>(),
+                ),
+                (values * std::mem::size_of::<u64>()) as u64,
+                flags,
+            )
+        }?;
+
+        Ok(if ready { Some(data) } else { None })
+    }
+}
+
+impl<B> QueryPool<B, Timestamp>
+where
+    B: Backend,
+{
+    /// Read timestamps written by commands submitted to the `queue`
+    /// and convert them to nanoseconds using queue's timestamp period.
+    /// If `wait` is `false` and results are not available yet returns `None`.
+    pub fn read_timestamps(
+        &self,
+        device: &Device<B>,
+        queue: &Queue<B>,
+        queries: Range<Id>,
+        wait: bool,
+    ) -> Result<Option<Vec<u64>>, rendy_core::hal::device::OomOrDeviceLost> {
+        let period = queue.timestamp_period();
+        Ok(self.read_results(device, queries, wait)?.map(|ticks| {
+            ticks
+                .into_iter()
+                .map(|ticks| ticks_to_nanoseconds(ticks, period))
+                .collect()
+        }))
+    }
+}
+
+/// Convert timestamp ticks to nanoseconds.
+/// `period` is the number of nanoseconds per tick.
+pub fn ticks_to_nanoseconds(ticks: u64, period: f32) -> u64 {
+    (ticks as f64 * period as f64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ticks_to_nanoseconds;
+
+    #[test]
+    fn unit_period_keeps_ticks() {
+        assert_eq!(ticks_to_nanoseconds(0, 1.0), 0);
+        assert_eq!(ticks_to_nanoseconds(12345, 1.0), 12345);
+    }
+
+    #[test]
+    fn period_scales_ticks() {
+        assert_eq!(ticks_to_nanoseconds(1000, 52.08), 52080);
+        assert_eq!(ticks_to_nanoseconds(3, 0.5), 1);
+    }
+
+    #[test]
+    fn large_tick_counts_dont_overflow() {
+        let ticks = 1 << 40;
+        assert_eq!(ticks_to_nanoseconds(ticks, 80.0), ticks * 80);
+    }
+}
@@ -0,0 +1,1645 @@
+//! Backend-agnostic recording of commands.
+//!
+//! `CommandStream` stores commands that `Encoder` and `RenderPassEncoder` can record
+//! with resources referenced by ids instead of raw objects.
+//! Streams can be recorded without device with `StreamEncoder`
+//! or captured from command buffer encoders with `StreamCapture`,
+//! serialized with `"serde-1"` feature, compared
+//! and replayed into real command buffer with `replay`.
+
+use {
+    crate::buffer::{CommandBuffer, RecordingState},
+    rendy_core::hal::{
+        self,
+        command::{BufferCopy, BufferImageCopy, ImageBlit, ImageCopy, ImageResolve},
+        image::{Filter, Layout, SubresourceRange},
+        pso::{
+            ClearRect, DepthBias, Face, PipelineStage, Rect, ShaderStageFlags, StencilValue,
+            Viewport,
+        },
+        query,
+        queue::QueueFamilyId,
+        Backend, IndexType,
+    },
+    std::{collections::HashMap, ops::Range},
+};
+
+macro_rules! stream_ids {
+    ($($(#[$meta:meta])* $name:ident,)*) => {$(
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        pub struct $name(pub u32);
+    )*};
+}
+
+stream_ids! {
+    /// Id of the buffer referenced by commands in the stream.
+    BufferRef,
+    /// Id of the image referenced by commands in the stream.
+    ImageRef,
+    /// Id of the graphics pipeline referenced by commands in the stream.
+    GraphicsPipelineRef,
+    /// Id of the compute pipeline referenced by commands in the stream.
+    ComputePipelineRef,
+    /// Id of the pipeline layout referenced by commands in the stream.
+    PipelineLayoutRef,
+    /// Id of the descriptor set referenced by commands in the stream.
+    DescriptorSetRef,
+    /// Id of the render pass referenced by commands in the stream.
+    RenderPassRef,
+    /// Id of the framebuffer referenced by commands in the stream.
+    FramebufferRef,
+    /// Id of the query pool referenced by commands in the stream.
+    QueryPoolRef,
+    /// Id of the secondary command buffer executed by commands in the stream.
+    SecondaryRef,
+}
+
+/// Clear color value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StreamClearColor {
+    /// Clear value for floating point and normalized formats.
+    Float([f32; 4]),
+
+    /// Clear value for unsigned integer formats.
+    Uint([u32; 4]),
+
+    /// Clear value for signed integer formats.
+    Sint([i32; 4]),
+}
+
+impl StreamClearColor {
+    /// Capture raw clear color.
+    /// Union doesn't tell how color is interpreted, so bits are stored as `Uint`.
+    /// Replaying it yields the same bits.
+    fn from_raw(color: &hal::command::ClearColor) -> Self {
+        StreamClearColor::Uint(unsafe { color.uint32 })
+    }
+
+    fn raw(&self) -> hal::command::ClearColor {
+        match *self {
+            StreamClearColor::Float(float32) => hal::command::ClearColor { float32 },
+            StreamClearColor::Uint(uint32) => hal::command::ClearColor { uint32 },
+            StreamClearColor::Sint(sint32) => hal::command::ClearColor { sint32 },
+        }
+    }
+}
+
+/// Clear value for render pass attachment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StreamClearValue {
+    /// Clear value for color attachment.
+    Color(StreamClearColor),
+
+    /// Clear value for depth-stencil attachment.
+    DepthStencil {
+        /// Depth value.
+        depth: f32,
+
+        /// Stencil value.
+        stencil: u32,
+    },
+}
+
+impl StreamClearValue {
+    /// Capture raw clear value.
+    /// Union doesn't tell whether value is color or depth-stencil,
+    /// so it is stored as color bits that cover both.
+    fn from_raw(value: &hal::command::ClearValue) -> Self {
+        StreamClearValue::Color(StreamClearColor::from_raw(unsafe { &value.color }))
+    }
+
+    fn raw(&self) -> hal::command::ClearValue {
+        match *self {
+            StreamClearValue::Color(color) => hal::command::ClearValue { color: color.raw() },
+            StreamClearValue::DepthStencil { depth, stencil } => hal::command::ClearValue {
+                depth_stencil: hal::command::ClearDepthStencil { depth, stencil },
+            },
+        }
+    }
+}
+
+/// Attachment clear inside render pass.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StreamAttachmentClear {
+    /// Clear color attachment.
+    Color {
+        /// Index of the color attachment in the subpass.
+        index: usize,
+
+        /// Clear color.
+        value: StreamClearColor,
+    },
+
+    /// Clear depth-stencil attachment.
+    DepthStencil {
+        /// Depth value, if depth aspect should be cleared.
+        depth: Option<f32>,
+
+        /// Stencil value, if stencil aspect should be cleared.
+        stencil: Option<u32>,
+    },
+}
+
+impl StreamAttachmentClear {
+    fn from_raw(clear: &hal::command::AttachmentClear) -> Self {
+        match *clear {
+            hal::command::AttachmentClear::Color { index, ref value } => {
+                StreamAttachmentClear::Color {
+                    index,
+                    value: StreamClearColor::from_raw(value),
+                }
+            }
+            hal::command::AttachmentClear::DepthStencil { depth, stencil } => {
+                StreamAttachmentClear::DepthStencil { depth, stencil }
+            }
+        }
+    }
+
+    fn raw(&self) -> hal::command::AttachmentClear {
+        match *self {
+            StreamAttachmentClear::Color { index, value } => hal::command::AttachmentClear::Color {
+                index,
+                value: value.raw(),
+            },
+            StreamAttachmentClear::DepthStencil { depth, stencil } => {
+                hal::command::AttachmentClear::DepthStencil { depth, stencil }
+            }
+        }
+    }
+}
+
+/// Pipeline barrier with resources referenced by ids.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StreamBarrier {
+    /// Applies the given access flags for all buffers.
+    AllBuffers(Range<hal::buffer::Access>),
+
+    /// Applies the given access flags for all images.
+    AllImages(Range<hal::image::Access>),
+
+    /// Buffer barrier.
+    Buffer {
+        /// State transition.
+        states: Range<hal::buffer::State>,
+
+        /// Buffer.
+        target: BufferRef,
+
+        /// Ownership transfer.
+        families: Option<Range<QueueFamilyId>>,
+
+        /// Range of the buffer.
+        range: Range<Option<u64>>,
+    },
+
+    /// Image barrier.
+    Image {
+        /// State transition.
+        states: Range<hal::image::State>,
+
+        /// Image.
+        target: ImageRef,
+
+        /// Ownership transfer.
+        families: Option<Range<QueueFamilyId>>,
+
+        /// Subresource range of the image.
+        range: SubresourceRange,
+    },
+}
+
+impl StreamBarrier {
+    fn raw<'a, B: Backend>(
+        &self,
+        resources: &'a impl StreamResources<B>,
+    ) -> hal::memory::Barrier<'a, B> {
+        match self {
+            StreamBarrier::AllBuffers(states) => hal::memory::Barrier::AllBuffers(states.clone()),
+            StreamBarrier::AllImages(states) => hal::memory::Barrier::AllImages(states.clone()),
+            StreamBarrier::Buffer {
+                states,
+                target,
+                families,
+                range,
+            } => hal::memory::Barrier::Buffer {
+                states: states.clone(),
+                target: resources.buffer(*target),
+                families: families.clone(),
+                range: range.clone(),
+            },
+            StreamBarrier::Image {
+                states,
+                target,
+                families,
+                range,
+            } => hal::memory::Barrier::Image {
+                states: states.clone(),
+                target: resources.image(*target),
+                families: families.clone(),
+                range: range.clone(),
+            },
+        }
+    }
+}
+
+/// Single command in the stream.
+/// Mirrors methods of encoders.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(missing_docs)]
+pub enum StreamCommand {
+    BindIndexBuffer {
+        buffer: BufferRef,
+        offset: u64,
+        index_type: IndexType,
+    },
+    BindVertexBuffers {
+        first_binding: u32,
+        buffers: Vec<(BufferRef, u64)>,
+    },
+    BindGraphicsPipeline(GraphicsPipelineRef),
+    BindGraphicsDescriptorSets {
+        layout: PipelineLayoutRef,
+        first_set: u32,
+        sets: Vec<DescriptorSetRef>,
+        offsets: Vec<u32>,
+    },
+    BindComputePipeline(ComputePipelineRef),
+    BindComputeDescriptorSets {
+        layout: PipelineLayoutRef,
+        first_set: u32,
+        sets: Vec<DescriptorSetRef>,
+        offsets: Vec<u32>,
+    },
+    PipelineBarrier {
+        stages: Range<PipelineStage>,
+        dependencies: hal::memory::Dependencies,
+        barriers: Vec<StreamBarrier>,
+    },
+    PushGraphicsConstants {
+        layout: PipelineLayoutRef,
+        stages: ShaderStageFlags,
+        offset: u32,
+        constants: Vec<u32>,
+    },
+    PushComputeConstants {
+        layout: PipelineLayoutRef,
+        offset: u32,
+        constants: Vec<u32>,
+    },
+    SetViewports {
+        first_viewport: u32,
+        viewports: Vec<Viewport>,
+    },
+    SetScissors {
+        first_scissor: u32,
+        rects: Vec<Rect>,
+    },
+    SetStencilReference {
+        faces: Face,
+        value: StencilValue,
+    },
+    SetStencilReadMask {
+        faces: Face,
+        value: StencilValue,
+    },
+    SetStencilWriteMask {
+        faces: Face,
+        value: StencilValue,
+    },
+    SetBlendConstants([f32; 4]),
+    SetDepthBounds(Range<f32>),
+    SetLineWidth(f32),
+    SetDepthBias(DepthBias),
+    BeginQuery {
+        pool: QueryPoolRef,
+        id: query::Id,
+        flags: query::ControlFlags,
+    },
+    EndQuery {
+        pool: QueryPoolRef,
+        id: query::Id,
+    },
+    WriteTimestamp {
+        stage: PipelineStage,
+        pool: QueryPoolRef,
+        id: query::Id,
+    },
+    PushDebugGroup {
+        name: String,
+        color: u32,
+    },
+    PopDebugGroup,
+    InsertDebugLabel {
+        name: String,
+        color: u32,
+    },
+    BeginRenderPass {
+        render_pass: RenderPassRef,
+        framebuffer: FramebufferRef,
+        render_area: Rect,
+        clear_values: Vec<StreamClearValue>,
+        secondary: bool,
+    },
+    NextSubpass {
+        secondary: bool,
+    },
+    EndRenderPass,
+    ExecuteCommands(Vec<SecondaryRef>),
+    ClearAttachments {
+        clears: Vec<StreamAttachmentClear>,
+        rects: Vec<ClearRect>,
+    },
+    Draw {
+        vertices: Range<u32>,
+        instances: Range<u32>,
+    },
+    DrawIndexed {
+        indices: Range<u32>,
+        base_vertex: i32,
+        instances: Range<u32>,
+    },
+    DrawIndirect {
+        buffer: BufferRef,
+        offset: u64,
+        draw_count: u32,
+        stride: u32,
+    },
+    DrawIndexedIndirect {
+        buffer: BufferRef,
+        offset: u64,
+        draw_count: u32,
+        stride: u32,
+    },
+    CopyBuffer {
+        src: BufferRef,
+        dst: BufferRef,
+        regions: Vec<BufferCopy>,
+    },
+    CopyBufferToImage {
+        src: BufferRef,
+        dst: ImageRef,
+        dst_layout: Layout,
+        regions: Vec<BufferImageCopy>,
+    },
+    CopyImageToBuffer {
+        src: ImageRef,
+        src_layout: Layout,
+        dst: BufferRef,
+        regions: Vec<BufferImageCopy>,
+    },
+    FillBuffer {
+        buffer: BufferRef,
+        range: Range<u64>,
+        data: u32,
+    },
+    UpdateBuffer {
+        buffer: BufferRef,
+        offset: u64,
+        data: Vec<u8>,
+    },
+    ClearColorImage {
+        image: ImageRef,
+        layout: Layout,
+        color: StreamClearColor,
+        ranges: Vec<SubresourceRange>,
+    },
+    ClearDepthStencilImage {
+        image: ImageRef,
+        layout: Layout,
+        depth: f32,
+        stencil: u32,
+        ranges: Vec<SubresourceRange>,
+    },
+    ResolveImage {
+        src: ImageRef,
+        src_layout: Layout,
+        dst: ImageRef,
+        dst_layout: Layout,
+        regions: Vec<ImageResolve>,
+    },
+    CopyImage {
+        src: ImageRef,
+        src_layout: Layout,
+        dst: ImageRef,
+        dst_layout: Layout,
+        regions: Vec<ImageCopy>,
+    },
+    BlitImage {
+        src: ImageRef,
+        src_layout: Layout,
+        dst: ImageRef,
+        dst_layout: Layout,
+        filter: Filter,
+        regions: Vec<ImageBlit>,
+    },
+    ResetQueryPool {
+        pool: QueryPoolRef,
+        queries: Range<query::Id>,
+    },
+    CopyQueryPoolResults {
+        pool: QueryPoolRef,
+        queries: Range<query::Id>,
+        buffer: BufferRef,
+        offset: u64,
+        stride: u64,
+        flags: query::ResultFlags,
+    },
+    Dispatch([u32; 3]),
+    DispatchIndirect {
+        buffer: BufferRef,
+        offset: u64,
+    },
+}
+
+/// Sequence of recorded commands.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CommandStream {
+    commands: Vec<StreamCommand>,
+}
+
+impl CommandStream {
+    /// Create empty stream.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get encoder that records commands into this stream.
+    pub fn encoder(&mut self) -> StreamEncoder<'_> {
+        StreamEncoder { stream: self }
+    }
+
+    /// Get recorded commands.
+    pub fn commands(&self) -> &[StreamCommand] {
+        &self.commands
+    }
+
+    /// Append command to the stream.
+    pub fn push(&mut self, command: StreamCommand) {
+        self.commands.push(command);
+    }
+
+    /// Check if stream has no commands.
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Get number of commands in the stream.
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+}
+
+impl std::fmt::Display for CommandStream {
+    /// Write one command per line.
+    /// Suitable for textual diff between streams.
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for command in &self.commands {
+            writeln!(fmt, "{:?}", command)?;
+        }
+        Ok(())
+    }
+}
+
+/// Encoder that records commands into `CommandStream`.
+/// Has the same methods as `Encoder` and `RenderPassEncoder`
+/// but takes ids instead of raw resources.
+/// Recording is not validated.
+#[derive(Debug)]
+pub struct StreamEncoder<'a> {
+    stream: &'a mut CommandStream,
+}
+
+impl<'a> StreamEncoder<'a> {
+    fn push(&mut self, command: StreamCommand) {
+        self.stream.push(command);
+    }
+
+    /// Record `bind_index_buffer` command.
+    pub fn bind_index_buffer(&mut self, buffer: BufferRef, offset: u64, index_type: IndexType) {
+        self.push(StreamCommand::BindIndexBuffer {
+            buffer,
+            offset,
+            index_type,
+        })
+    }
+
+    /// Record `bind_vertex_buffers` command.
+    pub fn bind_vertex_buffers(
+        &mut self,
+        first_binding: u32,
+        buffers: impl IntoIterator<Item = (BufferRef, u64)>,
+    ) {
+        self.push(StreamCommand::BindVertexBuffers {
+            first_binding,
+            buffers: buffers.into_iter().collect(),
+        })
+    }
+
+    /// Record `bind_graphics_pipeline` command.
+    pub fn bind_graphics_pipeline(&mut self, pipeline: GraphicsPipelineRef) {
+        self.push(StreamCommand::BindGraphicsPipeline(pipeline))
+    }
+
+    /// Record `bind_graphics_descriptor_sets` command.
+    pub fn bind_graphics_descriptor_sets(
+        &mut self,
+        layout: PipelineLayoutRef,
+        first_set: u32,
+        sets: impl IntoIterator<Item = DescriptorSetRef>,
+        offsets: impl IntoIterator<Item = u32>,
+    ) {
+        self.push(StreamCommand::BindGraphicsDescriptorSets {
+            layout,
+            first_set,
+            sets: sets.into_iter().collect(),
+            offsets: offsets.into_iter().collect(),
+        })
+    }
+
+    /// Record `bind_compute_pipeline` command.
+    pub fn bind_compute_pipeline(&mut self, pipeline: ComputePipelineRef) {
+        self.push(StreamCommand::BindComputePipeline(pipeline))
+    }
+
+    /// Record `bind_compute_descriptor_sets` command.
+    pub fn bind_compute_descriptor_sets(
+        &mut self,
+        layout: PipelineLayoutRef,
+        first_set: u32,
+        sets: impl IntoIterator<Item = DescriptorSetRef>,
+        offsets: impl IntoIterator<Item = u32>,
+    ) {
+        self.push(StreamCommand::BindComputeDescriptorSets {
+            layout,
+            first_set,
+            sets: sets.into_iter().collect(),
+            offsets: offsets.into_iter().collect(),
+        })
+    }
+
+    /// Record `pipeline_barrier` command.
+    pub fn pipeline_barrier(
+        &mut self,
+        stages: Range<PipelineStage>,
+        dependencies: hal::memory::Dependencies,
+        barriers: impl IntoIterator<Item = StreamBarrier>,
+    ) {
+        self.push(StreamCommand::PipelineBarrier {
+            stages,
+            dependencies,
+            barriers: barriers.into_iter().collect(),
+        })
+    }
+
+    /// Record `push_constants` command.
+    /// Constants are pushed for graphics pipeline.
+    pub fn push_constants(
+        &mut self,
+        layout: PipelineLayoutRef,
+        stages: ShaderStageFlags,
+        offset: u32,
+        constants: &[u32],
+    ) {
+        self.push(StreamCommand::PushGraphicsConstants {
+            layout,
+            stages,
+            offset,
+            constants: constants.to_vec(),
+        })
+    }
+
+    /// Record `push_compute_constants` command.
+    pub fn push_compute_constants(
+        &mut self,
+        layout: PipelineLayoutRef,
+        offset: u32,
+        constants: &[u32],
+    ) {
+        self.push(StreamCommand::PushComputeConstants {
+            layout,
+            offset,
+            constants: constants.to_vec(),
+        })
+    }
+
+    /// Record `set_viewports` command.
+    pub fn set_viewports(
+        &mut self,
+        first_viewport: u32,
+        viewports: impl IntoIterator<Item = Viewport>,
+    ) {
+        self.push(StreamCommand::SetViewports {
+            first_viewport,
+            viewports: viewports.into_iter().collect(),
+        })
+    }
+
+    /// Record `set_scissors` command.
+    pub fn set_scissors(&mut self, first_scissor: u32, rects: impl IntoIterator<Item = Rect>) {
+        self.push(StreamCommand::SetScissors {
+            first_scissor,
+            rects: rects.into_iter().collect(),
+        })
+    }
+
+    /// Record `set_stencil_reference` command.
+    pub fn set_stencil_reference(&mut self, faces: Face, value: StencilValue) {
+        self.push(StreamCommand::SetStencilReference { faces, value })
+    }
+
+    /// Record `set_stencil_read_mask` command.
+    pub fn set_stencil_read_mask(&mut self, faces: Face, value: StencilValue) {
+        self.push(StreamCommand::SetStencilReadMask { faces, value })
+    }
+
+    /// Record `set_stencil_write_mask` command.
+    pub fn set_stencil_write_mask(&mut self, faces: Face, value: StencilValue) {
+        self.push(StreamCommand::SetStencilWriteMask { faces, value })
+    }
+
+    /// Record `set_blend_constants` command.
+    pub fn set_blend_constants(&mut self, color: [f32; 4]) {
+        self.push(StreamCommand::SetBlendConstants(color))
+    }
+
+    /// Record `set_depth_bounds` command.
+    pub fn set_depth_bounds(&mut self, bounds: Range<f32>) {
+        self.push(StreamCommand::SetDepthBounds(bounds))
+    }
+
+    /// Record `set_line_width` command.
+    pub fn set_line_width(&mut self, width: f32) {
+        self.push(StreamCommand::SetLineWidth(width))
+    }
+
+    /// Record `set_depth_bias` command.
+    pub fn set_depth_bias(&mut self, depth_bias: DepthBias) {
+        self.push(StreamCommand::SetDepthBias(depth_bias))
+    }
+
+    /// Record `begin_query` command.
+    pub fn begin_query(&mut self, pool: QueryPoolRef, id: query::Id, flags: query::ControlFlags) {
+        self.push(StreamCommand::BeginQuery { pool, id, flags })
+    }
+
+    /// Record `end_query` command.
+    pub fn end_query(&mut self, pool: QueryPoolRef, id: query::Id) {
+        self.push(StreamCommand::EndQuery { pool, id })
+    }
+
+    /// Record `write_timestamp` command.
+    pub fn write_timestamp(&mut self, stage: PipelineStage, pool: QueryPoolRef, id: query::Id) {
+        self.push(StreamCommand::WriteTimestamp { stage, pool, id })
+    }
+
+    /// Record `push_debug_group` command.
+    pub fn push_debug_group(&mut self, name: &str, color: u32) {
+        self.push(StreamCommand::PushDebugGroup {
+            name: name.to_owned(),
+            color,
+        })
+    }
+
+    /// Record `pop_debug_group` command.
+    pub fn pop_debug_group(&mut self) {
+        self.push(StreamCommand::PopDebugGroup)
+    }
+
+    /// Record `insert_debug_label` command.
+    pub fn insert_debug_label(&mut self, name: &str, color: u32) {
+        self.push(StreamCommand::InsertDebugLabel {
+            name: name.to_owned(),
+            color,
+        })
+    }
+
+    /// Record beginning of render pass.
+    /// `secondary` tells if first subpass is recorded in secondary buffers.
+    pub fn begin_render_pass(
+        &mut self,
+        render_pass: RenderPassRef,
+        framebuffer: FramebufferRef,
+        render_area: Rect,
+        clear_values: impl IntoIterator<Item = StreamClearValue>,
+        secondary: bool,
+    ) {
+        self.push(StreamCommand::BeginRenderPass {
+            render_pass,
+            framebuffer,
+            render_area,
+            clear_values: clear_values.into_iter().collect(),
+            secondary,
+        })
+    }
+
+    /// Record transition to the next subpass.
+    /// `secondary` tells if subpass is recorded in secondary buffers.
+    pub fn next_subpass(&mut self, secondary: bool) {
+        self.push(StreamCommand::NextSubpass { secondary })
+    }
+
+    /// Record end of render pass.
+    pub fn end_render_pass(&mut self) {
+        self.push(StreamCommand::EndRenderPass)
+    }
+
+    /// Record `execute_commands` command.
+    pub fn execute_commands(&mut self, secondaries: impl IntoIterator<Item = SecondaryRef>) {
+        self.push(StreamCommand::ExecuteCommands(
+            secondaries.into_iter().collect(),
+        ))
+    }
+
+    /// Record `clear_attachments` command.
+    pub fn clear_attachments(
+        &mut self,
+        clears: impl IntoIterator<Item = StreamAttachmentClear>,
+        rects: impl IntoIterator<Item = ClearRect>,
+    ) {
+        self.push(StreamCommand::ClearAttachments {
+            clears: clears.into_iter().collect(),
+            rects: rects.into_iter().collect(),
+        })
+    }
+
+    /// Record `draw` command.
+    pub fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>) {
+        self.push(StreamCommand::Draw {
+            vertices,
+            instances,
+        })
+    }
+
+    /// Record `draw_indexed` command.
+    pub fn draw_indexed(&mut self, indices: Range<u32>, base_vertex: i32, instances: Range<u32>) {
+        self.push(StreamCommand::DrawIndexed {
+            indices,
+            base_vertex,
+            instances,
+        })
+    }
+
+    /// Record `draw_indirect` command.
+    pub fn draw_indirect(&mut self, buffer: BufferRef, offset: u64, draw_count: u32, stride: u32) {
+        self.push(StreamCommand::DrawIndirect {
+            buffer,
+            offset,
+            draw_count,
+            stride,
+        })
+    }
+
+    /// Record `draw_indexed_indirect` command.
+    pub fn draw_indexed_indirect(
+        &mut self,
+        buffer: BufferRef,
+        offset: u64,
+        draw_count: u32,
+        stride: u32,
+    ) {
+        self.push(StreamCommand::DrawIndexedIndirect {
+            buffer,
+            offset,
+            draw_count,
+            stride,
+        })
+    }
+
+    /// Record `copy_buffer` command.
+    pub fn copy_buffer(
+        &mut self,
+        src: BufferRef,
+        dst: BufferRef,
+        regions: impl IntoIterator<Item = BufferCopy>,
+    ) {
+        self.push(StreamCommand::CopyBuffer {
+            src,
+            dst,
+            regions: regions.into_iter().collect(),
+        })
+    }
+
+    /// Record `copy_buffer_to_image` command.
+    pub fn copy_buffer_to_image(
+        &mut self,
+        src: BufferRef,
+        dst: ImageRef,
+        dst_layout: Layout,
+        regions: impl IntoIterator<Item = BufferImageCopy>,
+    ) {
+        self.push(StreamCommand::CopyBufferToImage {
+            src,
+            dst,
+            dst_layout,
+            regions: regions.into_iter().collect(),
+        })
+    }
+
+    /// Record `copy_image_to_buffer` command.
+    pub fn copy_image_to_buffer(
+        &mut self,
+        src: ImageRef,
+        src_layout: Layout,
+        dst: BufferRef,
+        regions: impl IntoIterator<Item = BufferImageCopy>,
+    ) {
+        self.push(StreamCommand::CopyImageToBuffer {
+            src,
+            src_layout,
+            dst,
+            regions: regions.into_iter().collect(),
+        })
+    }
+
+    /// Record `fill_buffer` command.
+    pub fn fill_buffer(&mut self, buffer: BufferRef, range: Range<u64>, data: u32) {
+        self.push(StreamCommand::FillBuffer {
+            buffer,
+            range,
+            data,
+        })
+    }
+
+    /// Record `update_buffer` command.
+    pub fn update_buffer(&mut self, buffer: BufferRef, offset: u64, data: &[u8]) {
+        self.push(StreamCommand::UpdateBuffer {
+            buffer,
+            offset,
+            data: data.to_vec(),
+        })
+    }
+
+    /// Record `clear_color_image` command.
+    pub fn clear_color_image(
+        &mut self,
+        image: ImageRef,
+        layout: Layout,
+        color: StreamClearColor,
+        ranges: impl IntoIterator<Item = SubresourceRange>,
+    ) {
+        self.push(StreamCommand::ClearColorImage {
+            image,
+            layout,
+            color,
+            ranges: ranges.into_iter().collect(),
+        })
+    }
+
+    /// Record `clear_depth_stencil_image` command.
+    pub fn clear_depth_stencil_image(
+        &mut self,
+        image: ImageRef,
+        layout: Layout,
+        depth: f32,
+        stencil: u32,
+        ranges: impl IntoIterator<Item = SubresourceRange>,
+    ) {
+        self.push(StreamCommand::ClearDepthStencilImage {
+            image,
+            layout,
+            depth,
+            stencil,
+            ranges: ranges.into_iter().collect(),
+        })
+    }
+
+    /// Record `resolve_image` command.
+    pub fn resolve_image(
+        &mut self,
+        src: ImageRef,
+        src_layout: Layout,
+        dst: ImageRef,
+        dst_layout: Layout,
+        regions: impl IntoIterator<Item = ImageResolve>,
+    ) {
+        self.push(StreamCommand::ResolveImage {
+            src,
+            src_layout,
+            dst,
+            dst_layout,
+            regions: regions.into_iter().collect(),
+        })
+    }
+
+    /// Record `copy_image` command.
+    pub fn copy_image(
+        &mut self,
+        src: ImageRef,
+        src_layout: Layout,
+        dst: ImageRef,
+        dst_layout: Layout,
+        regions: impl IntoIterator<Item = ImageCopy>,
+    ) {
+        self.push(StreamCommand::CopyImage {
+            src,
+            src_layout,
+            dst,
+            dst_layout,
+            regions: regions.into_iter().collect(),
+        })
+    }
+
+    /// Record `blit_image` command.
+    pub fn blit_image(
+        &mut self,
+        src: ImageRef,
+        src_layout: Layout,
+        dst: ImageRef,
+        dst_layout: Layout,
+        filter: Filter,
+        regions: impl IntoIterator<Item = ImageBlit>,
+    ) {
+        self.push(StreamCommand::BlitImage {
+            src,
+            src_layout,
+            dst,
+            dst_layout,
+            filter,
+            regions: regions.into_iter().collect(),
+        })
+    }
+
+    /// Record `reset_query_pool` command.
+    pub fn reset_query_pool(&mut self, pool: QueryPoolRef, queries: Range<query::Id>) {
+        self.push(StreamCommand::ResetQueryPool { pool, queries })
+    }
+
+    /// Record `copy_query_pool_results` command.
+    pub fn copy_query_pool_results(
+        &mut self,
+        pool: QueryPoolRef,
+        queries: Range<query::Id>,
+        buffer: BufferRef,
+        offset: u64,
+        stride: u64,
+        flags: query::ResultFlags,
+    ) {
+        self.push(StreamCommand::CopyQueryPoolResults {
+            pool,
+            queries,
+            buffer,
+            offset,
+            stride,
+            flags,
+        })
+    }
+
+    /// Record `dispatch` command.
+    pub fn dispatch(&mut self, x: u32, y: u32, z: u32) {
+        self.push(StreamCommand::Dispatch([x, y, z]))
+    }
+
+    /// Record `dispatch_indirect` command.
+    pub fn dispatch_indirect(&mut self, buffer: BufferRef, offset: u64) {
+        self.push(StreamCommand::DispatchIndirect { buffer, offset })
+    }
+}
+
+/// Ids of captured objects.
+/// Objects get ids in order of first use.
+#[derive(Debug, Default)]
+struct CaptureIds {
+    ids: HashMap<usize, u32>,
+}
+
+impl CaptureIds {
+    fn get<T>(&mut self, object: &T) -> u32 {
+        let next = self.ids.len() as u32;
+        let ptr: *const T = object;
+        *self.ids.entry(ptr as usize).or_insert(next)
+    }
+}
+
+/// Commands captured from `Encoder` and `RenderPassEncoder`.
+///
+/// Capturing is enabled per command buffer with `CommandBuffer::start_capture`.
+/// Raw objects referenced by captured commands are replaced with ids
+/// assigned in order of first use, so capturing same recording twice yields equal streams.
+/// Id of an object can be looked up with `*_ref` methods to build `StreamResources` for `replay`.
+///
+/// Clear values are captured as raw bits, see `StreamClearColor::Uint`.
+#[derive(Debug, Default)]
+pub struct StreamCapture {
+    stream: CommandStream,
+    buffers: CaptureIds,
+    images: CaptureIds,
+    graphics_pipelines: CaptureIds,
+    compute_pipelines: CaptureIds,
+    pipeline_layouts: CaptureIds,
+    descriptor_sets: CaptureIds,
+    render_passes: CaptureIds,
+    framebuffers: CaptureIds,
+    query_pools: CaptureIds,
+    secondaries: CaptureIds,
+}
+
+impl StreamCapture {
+    /// Create empty capture.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get captured commands.
+    pub fn stream(&self) -> &CommandStream {
+        &self.stream
+    }
+
+    /// Take captured commands.
+    pub fn into_stream(self) -> CommandStream {
+        self.stream
+    }
+
+    /// Get encoder that records commands into captured stream.
+    pub(crate) fn encoder(&mut self) -> StreamEncoder<'_> {
+        self.stream.encoder()
+    }
+
+    /// Get id of the buffer.
+    pub fn buffer_ref<B: Backend>(&mut self, buffer: &B::Buffer) -> BufferRef {
+        BufferRef(self.buffers.get(buffer))
+    }
+
+    /// Get id of the image.
+    pub fn image_ref<B: Backend>(&mut self, image: &B::Image) -> ImageRef {
+        ImageRef(self.images.get(image))
+    }
+
+    /// Get id of the graphics pipeline.
+    pub fn graphics_pipeline_ref<B: Backend>(
+        &mut self,
+        pipeline: &B::GraphicsPipeline,
+    ) -> GraphicsPipelineRef {
+        GraphicsPipelineRef(self.graphics_pipelines.get(pipeline))
+    }
+
+    /// Get id of the compute pipeline.
+    pub fn compute_pipeline_ref<B: Backend>(
+        &mut self,
+        pipeline: &B::ComputePipeline,
+    ) -> ComputePipelineRef {
+        ComputePipelineRef(self.compute_pipelines.get(pipeline))
+    }
+
+    /// Get id of the pipeline layout.
+    pub fn pipeline_layout_ref<B: Backend>(
+        &mut self,
+        layout: &B::PipelineLayout,
+    ) -> PipelineLayoutRef {
+        PipelineLayoutRef(self.pipeline_layouts.get(layout))
+    }
+
+    /// Get id of the descriptor set.
+    pub fn descriptor_set_ref<B: Backend>(&mut self, set: &B::DescriptorSet) -> DescriptorSetRef {
+        DescriptorSetRef(self.descriptor_sets.get(set))
+    }
+
+    /// Get id of the render pass.
+    pub fn render_pass_ref<B: Backend>(&mut self, render_pass: &B::RenderPass) -> RenderPassRef {
+        RenderPassRef(self.render_passes.get(render_pass))
+    }
+
+    /// Get id of the framebuffer.
+    pub fn framebuffer_ref<B: Backend>(&mut self, framebuffer: &B::Framebuffer) -> FramebufferRef {
+        FramebufferRef(self.framebuffers.get(framebuffer))
+    }
+
+    /// Get id of the query pool.
+    pub fn query_pool_ref<B: Backend>(&mut self, pool: &B::QueryPool) -> QueryPoolRef {
+        QueryPoolRef(self.query_pools.get(pool))
+    }
+
+    /// Get id of the secondary command buffer.
+    pub fn secondary_ref<B: Backend>(&mut self, secondary: &B::CommandBuffer) -> SecondaryRef {
+        SecondaryRef(self.secondaries.get(secondary))
+    }
+
+    pub(crate) fn barrier<B: Backend>(
+        &mut self,
+        barrier: &hal::memory::Barrier<'_, B>,
+    ) -> StreamBarrier {
+        match barrier {
+            hal::memory::Barrier::AllBuffers(states) => StreamBarrier::AllBuffers(states.clone()),
+            hal::memory::Barrier::AllImages(states) => StreamBarrier::AllImages(states.clone()),
+            hal::memory::Barrier::Buffer {
+                states,
+                target,
+                families,
+                range,
+            } => StreamBarrier::Buffer {
+                states: states.clone(),
+                target: self.buffer_ref::<B>(target),
+                families: families.clone(),
+                range: range.clone(),
+            },
+            hal::memory::Barrier::Image {
+                states,
+                target,
+                families,
+                range,
+            } => StreamBarrier::Image {
+                states: states.clone(),
+                target: self.image_ref::<B>(target),
+                families: families.clone(),
+                range: range.clone(),
+            },
+        }
+    }
+
+    pub(crate) fn clear_value(value: &hal::command::ClearValue) -> StreamClearValue {
+        StreamClearValue::from_raw(value)
+    }
+
+    pub(crate) fn clear_color(color: &hal::command::ClearColor) -> StreamClearColor {
+        StreamClearColor::from_raw(color)
+    }
+
+    pub(crate) fn attachment_clear(clear: &hal::command::AttachmentClear) -> StreamAttachmentClear {
+        StreamAttachmentClear::from_raw(clear)
+    }
+}
+
+/// Resolves ids in `CommandStream` into raw resources for `replay`.
+pub trait StreamResources<B: Backend> {
+    /// Get buffer by id.
+    fn buffer(&self, id: BufferRef) -> &B::Buffer;
+
+    /// Get image by id.
+    fn image(&self, id: ImageRef) -> &B::Image;
+
+    /// Get graphics pipeline by id.
+    fn graphics_pipeline(&self, id: GraphicsPipelineRef) -> &B::GraphicsPipeline;
+
+    /// Get compute pipeline by id.
+    fn compute_pipeline(&self, id: ComputePipelineRef) -> &B::ComputePipeline;
+
+    /// Get pipeline layout by id.
+    fn pipeline_layout(&self, id: PipelineLayoutRef) -> &B::PipelineLayout;
+
+    /// Get descriptor set by id.
+    fn descriptor_set(&self, id: DescriptorSetRef) -> &B::DescriptorSet;
+
+    /// Get render pass by id.
+    fn render_pass(&self, id: RenderPassRef) -> &B::RenderPass;
+
+    /// Get framebuffer by id.
+    fn framebuffer(&self, id: FramebufferRef) -> &B::Framebuffer;
+
+    /// Get query pool by id.
+    fn query_pool(&self, id: QueryPoolRef) -> &B::QueryPool;
+
+    /// Get secondary command buffer by id.
+    fn secondary(&self, id: SecondaryRef) -> &B::CommandBuffer;
+}
+
+/// Re-encode commands from the stream into command buffer.
+///
+/// Commands are written directly to raw command buffer,
+/// bypassing capability checks and validation of encoders.
+///
+/// # Safety
+///
+/// Stream must be valid to record into `buffer`:
+/// commands must be supported by buffer's queue family,
+/// render pass commands must be properly nested,
+/// and resources returned by `resources` must satisfy requirements
+/// of corresponding `Encoder` methods.
+pub unsafe fn replay<B, C, U, P, L, R>(
+    stream: &CommandStream,
+    buffer: &mut CommandBuffer<B, C, RecordingState<U, P>, L, R>,
+    resources: &impl StreamResources<B>,
+) where
+    B: Backend,
+{
+    use hal::command::CommandBuffer as _;
+
+    let raw = buffer.raw();
+    for command in &stream.commands {
+        match command {
+            StreamCommand::BindIndexBuffer {
+                buffer,
+                offset,
+                index_type,
+            } => raw.bind_index_buffer(hal::buffer::IndexBufferView {
+                buffer: resources.buffer(*buffer),
+                offset: *offset,
+                index_type: *index_type,
+            }),
+            StreamCommand::BindVertexBuffers {
+                first_binding,
+                buffers,
+            } => raw.bind_vertex_buffers(
+                *first_binding,
+                buffers
+                    .iter()
+                    .map(|&(buffer, offset)| (resources.buffer(buffer), offset)),
+            ),
+            StreamCommand::BindGraphicsPipeline(pipeline) => {
+                raw.bind_graphics_pipeline(resources.graphics_pipeline(*pipeline))
+            }
+            StreamCommand::BindGraphicsDescriptorSets {
+                layout,
+                first_set,
+                sets,
+                offsets,
+            } => raw.bind_graphics_descriptor_sets(
+                resources.pipeline_layout(*layout),
+                *first_set as usize,
+                sets.iter().map(|&set| resources.descriptor_set(set)),
+                offsets.iter().cloned(),
+            ),
+            StreamCommand::BindComputePipeline(pipeline) => {
+                raw.bind_compute_pipeline(resources.compute_pipeline(*pipeline))
+            }
+            StreamCommand::BindComputeDescriptorSets {
+                layout,
+                first_set,
+                sets,
+                offsets,
+            } => raw.bind_compute_descriptor_sets(
+                resources.pipeline_layout(*layout),
+                *first_set as usize,
+                sets.iter().map(|&set| resources.descriptor_set(set)),
+                offsets.iter().cloned(),
+            ),
+            StreamCommand::PipelineBarrier {
+                stages,
+                dependencies,
+                barriers,
+            } => raw.pipeline_barrier(
+                stages.clone(),
+                *dependencies,
+                barriers.iter().map(|barrier| barrier.raw(resources)),
+            ),
+            StreamCommand::PushGraphicsConstants {
+                layout,
+                stages,
+                offset,
+                constants,
+            } => raw.push_graphics_constants(
+                resources.pipeline_layout(*layout),
+                *stages,
+                *offset,
+                constants,
+            ),
+            StreamCommand::PushComputeConstants {
+                layout,
+                offset,
+                constants,
+            } => raw.push_compute_constants(resources.pipeline_layout(*layout), *offset, constants),
+            StreamCommand::SetViewports {
+                first_viewport,
+                viewports,
+            } => raw.set_viewports(*first_viewport, viewports),
+            StreamCommand::SetScissors {
+                first_scissor,
+                rects,
+            } => raw.set_scissors(*first_scissor, rects),
+            StreamCommand::SetStencilReference { faces, value } => {
+                raw.set_stencil_reference(*faces, *value)
+            }
+            StreamCommand::SetStencilReadMask { faces, value } => {
+                raw.set_stencil_read_mask(*faces, *value)
+            }
+            StreamCommand::SetStencilWriteMask { faces, value } => {
+                raw.set_stencil_write_mask(*faces, *value)
+            }
+            StreamCommand::SetBlendConstants(color) => raw.set_blend_constants(*color),
+            StreamCommand::SetDepthBounds(bounds) => raw.set_depth_bounds(bounds.clone()),
+            StreamCommand::SetLineWidth(width) => raw.set_line_width(*width),
+            StreamCommand::SetDepthBias(depth_bias) => raw.set_depth_bias(*depth_bias),
+            StreamCommand::BeginQuery { pool, id, flags } => raw.begin_query(
+                query::Query {
+                    pool: resources.query_pool(*pool),
+                    id: *id,
+                },
+                *flags,
+            ),
+            StreamCommand::EndQuery { pool, id } => raw.end_query(query::Query {
+                pool: resources.query_pool(*pool),
+                id: *id,
+            }),
+            StreamCommand::WriteTimestamp { stage, pool, id } => raw.write_timestamp(
+                *stage,
+                query::Query {
+                    pool: resources.query_pool(*pool),
+                    id: *id,
+                },
+            ),
+            // `gfx-hal` has no debug marker commands to replay these into.
+            StreamCommand::PushDebugGroup { .. }
+            | StreamCommand::PopDebugGroup
+            | StreamCommand::InsertDebugLabel { .. } => {}
+            StreamCommand::BeginRenderPass {
+                render_pass,
+                framebuffer,
+                render_area,
+                clear_values,
+                secondary,
+            } => raw.begin_render_pass(
+                resources.render_pass(*render_pass),
+                resources.framebuffer(*framebuffer),
+                *render_area,
+                clear_values.iter().map(StreamClearValue::raw),
+                subpass_contents(*secondary),
+            ),
+            StreamCommand::NextSubpass { secondary } => {
+                raw.next_subpass(subpass_contents(*secondary))
+            }
+            StreamCommand::EndRenderPass => raw.end_render_pass(),
+            StreamCommand::ExecuteCommands(secondaries) => raw.execute_commands(
+                secondaries
+                    .iter()
+                    .map(|&secondary| resources.secondary(secondary)),
+            ),
+            StreamCommand::ClearAttachments { clears, rects } => {
+                raw.clear_attachments(clears.iter().map(StreamAttachmentClear::raw), rects)
+            }
+            StreamCommand::Draw {
+                vertices,
+                instances,
+            } => raw.draw(vertices.clone(), instances.clone()),
+            StreamCommand::DrawIndexed {
+                indices,
+                base_vertex,
+                instances,
+            } => raw.draw_indexed(indices.clone(), *base_vertex, instances.clone()),
+            StreamCommand::DrawIndirect {
+                buffer,
+                offset,
+                draw_count,
+                stride,
+            } => raw.draw_indirect(resources.buffer(*buffer), *offset, *draw_count, *stride),
+            StreamCommand::DrawIndexedIndirect {
+                buffer,
+                offset,
+                draw_count,
+                stride,
+            } => {
+                raw.draw_indexed_indirect(resources.buffer(*buffer), *offset, *draw_count, *stride)
+            }
+            StreamCommand::CopyBuffer { src, dst, regions } => raw.copy_buffer(
+                resources.buffer(*src),
+                resources.buffer(*dst),
+                regions.iter().cloned(),
+            ),
+            StreamCommand::CopyBufferToImage {
+                src,
+                dst,
+                dst_layout,
+                regions,
+            } => raw.copy_buffer_to_image(
+                resources.buffer(*src),
+                resources.image(*dst),
+                *dst_layout,
+                regions.iter().cloned(),
+            ),
+            StreamCommand::CopyImageToBuffer {
+                src,
+                src_layout,
+                dst,
+                regions,
+            } => raw.copy_image_to_buffer(
+                resources.image(*src),
+                *src_layout,
+                resources.buffer(*dst),
+                regions.iter().cloned(),
+            ),
+            StreamCommand::FillBuffer {
+                buffer,
+                range,
+                data,
+            } => raw.fill_buffer(resources.buffer(*buffer), range.clone(), *data),
+            StreamCommand::UpdateBuffer {
+                buffer,
+                offset,
+                data,
+            } => raw.update_buffer(resources.buffer(*buffer), *offset, data),
+            StreamCommand::ClearColorImage {
+                image,
+                layout,
+                color,
+                ranges,
+            } => raw.clear_image(
+                resources.image(*image),
+                *layout,
+                hal::command::ClearValue { color: color.raw() },
+                ranges.iter().cloned(),
+            ),
+            StreamCommand::ClearDepthStencilImage {
+                image,
+                layout,
+                depth,
+                stencil,
+                ranges,
+            } => raw.clear_image(
+                resources.image(*image),
+                *layout,
+                hal::command::ClearValue {
+                    depth_stencil: hal::command::ClearDepthStencil {
+                        depth: *depth,
+                        stencil: *stencil,
+                    },
+                },
+                ranges.iter().cloned(),
+            ),
+            StreamCommand::ResolveImage {
+                src,
+                src_layout,
+                dst,
+                dst_layout,
+                regions,
+            } => raw.resolve_image(
+                resources.image(*src),
+                *src_layout,
+                resources.image(*dst),
+                *dst_layout,
+                regions.iter().cloned(),
+            ),
+            StreamCommand::CopyImage {
+                src,
+                src_layout,
+                dst,
+                dst_layout,
+                regions,
+            } => raw.copy_image(
+                resources.image(*src),
+                *src_layout,
+                resources.image(*dst),
+                *dst_layout,
+                regions.iter().cloned(),
+            ),
+            StreamCommand::BlitImage {
+                src,
+                src_layout,
+                dst,
+                dst_layout,
+                filter,
+                regions,
+            } => raw.blit_image(
+                resources.image(*src),
+                *src_layout,
+                resources.image(*dst),
+                *dst_layout,
+                *filter,
+                regions.iter().cloned(),
+            ),
+            StreamCommand::ResetQueryPool { pool, queries } => {
+                raw.reset_query_pool(resources.query_pool(*pool), queries.clone())
+            }
+            StreamCommand::CopyQueryPoolResults {
+                pool,
+                queries,
+                buffer,
+                offset,
+                stride,
+                flags,
+            } => raw.copy_query_pool_results(
+                resources.query_pool(*pool),
+                queries.clone(),
+                resources.buffer(*buffer),
+                *offset,
+                *stride,
+                *flags,
+            ),
+            StreamCommand::Dispatch(count) => raw.dispatch(*count),
+            StreamCommand::DispatchIndirect { buffer, offset } => {
+                raw.dispatch_indirect(resources.buffer(*buffer), *offset)
+            }
+        }
+    }
+}
+
+fn subpass_contents(secondary: bool) -> hal::command::SubpassContents {
+    if secondary {
+        hal::command::SubpassContents::SecondaryBuffers
+    } else {
+        hal::command::SubpassContents::Inline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(stream: &mut CommandStream) {
+        let mut encoder = stream.encoder();
+        encoder.bind_graphics_pipeline(GraphicsPipelineRef(0));
+        encoder.push_constants(PipelineLayoutRef(1), ShaderStageFlags::VERTEX, 0, &[1, 2]);
+        encoder.bind_compute_pipeline(ComputePipelineRef(0));
+        encoder.push_compute_constants(PipelineLayoutRef(2), 4, &[3]);
+        encoder.push_debug_group("group", 0xff00_ff00);
+        encoder.dispatch(1, 2, 3);
+        encoder.pop_debug_group();
+        encoder.begin_render_pass(
+            RenderPassRef(0),
+            FramebufferRef(0),
+            Rect {
+                x: 0,
+                y: 0,
+                w: 16,
+                h: 16,
+            },
+            Some(StreamClearValue::Color(StreamClearColor::Float([0.0; 4]))),
+            false,
+        );
+        encoder.draw(0..3, 0..1);
+        encoder.end_render_pass();
+    }
+
+    #[test]
+    fn encoder_records_commands_in_order() {
+        let mut stream = CommandStream::new();
+        assert!(stream.is_empty());
+        record(&mut stream);
+        assert_eq!(stream.len(), 10);
+
+        match &stream.commands()[1] {
+            StreamCommand::PushGraphicsConstants {
+                layout,
+                stages,
+                offset,
+                constants,
+            } => {
+                assert_eq!(*layout, PipelineLayoutRef(1));
+                assert_eq!(*stages, ShaderStageFlags::VERTEX);
+                assert_eq!(*offset, 0);
+                assert_eq!(constants, &[1, 2]);
+            }
+            command => panic!("Unexpected command {:?}", command),
+        }
+
+        match &stream.commands()[3] {
+            StreamCommand::PushComputeConstants {
+                layout,
+                offset,
+                constants,
+            } => {
+                assert_eq!(*layout, PipelineLayoutRef(2));
+                assert_eq!(*offset, 4);
+                assert_eq!(constants, &[3]);
+            }
+            command => panic!("Unexpected command {:?}", command),
+        }
+
+        match &stream.commands()[5] {
+            StreamCommand::Dispatch(groups) => assert_eq!(*groups, [1, 2, 3]),
+            command => panic!("Unexpected command {:?}", command),
+        }
+
+        match stream.commands().last() {
+            Some(StreamCommand::EndRenderPass) => {}
+            command => panic!("Unexpected command {:?}", command),
+        }
+    }
+
+    #[test]
+    fn equal_recordings_display_equal() {
+        let mut first = CommandStream::new();
+        let mut second = CommandStream::new();
+        record(&mut first);
+        record(&mut second);
+        assert_eq!(first.to_string(), second.to_string());
+
+        second.encoder().draw(0..3, 0..1);
+        assert_ne!(first.to_string(), second.to_string());
+    }
+
+    #[test]
+    fn capture_ids_follow_first_use() {
+        let objects = [0u8; 3];
+        let mut ids = CaptureIds::default();
+        assert_eq!(ids.get(&objects[1]), 0);
+        assert_eq!(ids.get(&objects[0]), 1);
+        assert_eq!(ids.get(&objects[1]), 0);
+        assert_eq!(ids.get(&objects[2]), 2);
+    }
+
+    #[test]
+    fn raw_clear_color_bits_are_kept() {
+        let raw = hal::command::ClearColor {
+            float32: [1.0, 0.5, 0.0, 1.0],
+        };
+        let color = StreamClearColor::from_raw(&raw);
+        let replayed = color.raw();
+        assert_eq!(unsafe { replayed.float32 }, [1.0, 0.5, 0.0, 1.0]);
+    }
+
+    #[cfg(feature = "serde-1")]
+    #[test]
+    fn serde_round_trip() {
+        let mut stream = CommandStream::new();
+        record(&mut stream);
+
+        let serialized = ron::ser::to_string(&stream).unwrap();
+        let deserialized: CommandStream = ron::de::from_str(&serialized).unwrap();
+        assert_eq!(stream.to_string(), deserialized.to_string());
+    }
+}
@@ -0,0 +1,360 @@
+//! Emulation of timeline semaphores with queue epochs.
+
+use {
+    crate::{
+        buffer::Submittable,
+        core::{device_owned, Device, DeviceId},
+        family::{Queue, QueueId, Submission},
+        fence::{Fence, FenceEpoch},
+    },
+    rendy_core::hal::{
+        device::{Device as _, DeviceLost, OomOrDeviceLost},
+        pso::PipelineStage,
+        Backend,
+    },
+    std::collections::{HashMap, VecDeque},
+};
+
+/// Timeline of one queue.
+#[derive(Debug)]
+struct Track<B: Backend> {
+    /// Latest complete epoch.
+    completed: Option<u64>,
+
+    /// Fences of submissions in epoch order.
+    pending: VecDeque<Fence<B>>,
+
+    /// Semaphore signaled by the latest submission if it wasn't waited yet.
+    latest: Option<(u64, B::Semaphore)>,
+
+    /// Signaled semaphores that can't be waited anymore.
+    /// Destroyed once their epoch is complete.
+    stale: Vec<(u64, B::Semaphore)>,
+
+    /// Semaphores waited by submissions to this queue.
+    /// Become unsignaled once waiting epoch is complete.
+    consumed: Vec<(u64, B::Semaphore)>,
+}
+
+impl<B> Track<B>
+where
+    B: Backend,
+{
+    fn new() -> Self {
+        Track {
+            completed: None,
+            pending: VecDeque::new(),
+            latest: None,
+            stale: Vec::new(),
+            consumed: Vec::new(),
+        }
+    }
+
+    fn is_complete(&self, epoch: u64) -> bool {
+        self.completed.map_or(false, |completed| completed >= epoch)
+    }
+
+    /// Retire signaled fence from the front of the pending queue.
+    fn retire(
+        &mut self,
+        device: &Device<B>,
+        fences: &mut Vec<Fence<B>>,
+        semaphores: &mut Vec<B::Semaphore>,
+        epoch: u64,
+    ) {
+        fences.extend(self.pending.pop_front());
+        self.completed = Some(epoch);
+
+        if self.latest.as_ref().map_or(false, |&(e, _)| e <= epoch) {
+            self.stale.extend(self.latest.take());
+        }
+
+        for (_, semaphore) in drain_complete(&mut self.stale, epoch) {
+            unsafe {
+                // Semaphore was signaled by complete submission.
+                device.destroy_semaphore(semaphore);
+            }
+        }
+
+        semaphores
+            .extend(drain_complete(&mut self.consumed, epoch).map(|(_, semaphore)| semaphore));
+    }
+}
+
+fn drain_complete<T>(items: &mut Vec<(u64, T)>, epoch: u64) -> impl Iterator<Item = (u64, T)> {
+    let (complete, pending) = items.drain(..).partition::<Vec<_>, _>(|&(e, _)| e <= epoch);
+    *items = pending;
+    complete.into_iter()
+}
+
+/// Timeline of queue epochs.
+/// Emulates timeline semaphores for devices without them.
+///
+/// Each submission through the timeline gets fence and semaphore
+/// and advances epoch of the queue it was submitted to.
+/// Host can query and wait for epochs of any queue,
+/// and submissions can depend on epochs of other queues.
+#[derive(Debug)]
+pub struct QueueTimeline<B: Backend> {
+    device: DeviceId,
+    tracks: HashMap<QueueId, Track<B>>,
+    fences: Vec<Fence<B>>,
+    semaphores: Vec<B::Semaphore>,
+}
+
+device_owned!(QueueTimeline<B>);
+
+impl<B> QueueTimeline<B>
+where
+    B: Backend,
+{
+    /// Create new timeline.
+    pub fn new(device: &Device<B>) -> Self {
+        QueueTimeline {
+            device: device.id(),
+            tracks: HashMap::new(),
+            fences: Vec::new(),
+            semaphores: Vec::new(),
+        }
+    }
+
+    /// Get latest complete epoch of the queue.
+    /// Updated by `poll` and `wait`.
+    pub fn completed_epoch(&self, queue: QueueId) -> Option<u64> {
+        self.tracks.get(&queue).and_then(|track| track.completed)
+    }
+
+    /// Check if epoch is known to be complete.
+    /// Updated by `poll` and `wait`.
+    pub fn is_complete(&self, epoch: FenceEpoch) -> bool {
+        self.tracks
+            .get(&epoch.queue)
+            .map_or(false, |track| track.is_complete(epoch.epoch))
+    }
+
+    /// Check fences of all queues and update complete epochs.
+    pub fn poll(&mut self, device: &Device<B>) -> Result<(), DeviceLost> {
+        self.assert_device_owner(device);
+        let fences = &mut self.fences;
+        let semaphores = &mut self.semaphores;
+        for track in self.tracks.values_mut() {
+            while let Some(fence) = track.pending.front_mut() {
+                match fence.check_signaled(device)? {
+                    Some(epoch) => track.retire(device, fences, semaphores, epoch.epoch),
+                    None => break,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait until epoch is complete.
+    /// Returns `false` on timeout.
+    ///
+    /// Panics if epoch wasn't submitted through this timeline.
+    pub fn wait(
+        &mut self,
+        device: &Device<B>,
+        epoch: FenceEpoch,
+        timeout_ns: u64,
+    ) -> Result<bool, OomOrDeviceLost> {
+        self.assert_device_owner(device);
+        if self.is_complete(epoch) {
+            return Ok(true);
+        }
+
+        let track = self
+            .tracks
+            .get_mut(&epoch.queue)
+            .expect("Epoch was not submitted through the timeline");
+
+        assert!(
+            track
+                .pending
+                .back()
+                .map_or(false, |fence| fence.epoch().epoch >= epoch.epoch),
+            "Epoch was not submitted through the timeline"
+        );
+
+        while !track.is_complete(epoch.epoch) {
+            let fence = track.pending.front_mut().unwrap();
+            match fence.wait_signaled(device, timeout_ns)? {
+                Some(signaled) => track.retire(
+                    device,
+                    &mut self.fences,
+                    &mut self.semaphores,
+                    signaled.epoch,
+                ),
+                None => return Ok(false),
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Submit commands to the queue after `dependencies` are complete.
+    /// Returns epoch of the submission.
+    ///
+    /// Dependencies on other queues are resolved with semaphore
+    /// signaled by the latest submission to that queue.
+    /// If it was already waited by another submission, host waits for dependency instead.
+    ///
+    /// Panics if dependency wasn't submitted through this timeline.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Queue::submit`].
+    /// Queue must not be used for fence submissions outside the timeline.
+    ///
+    /// [`Queue::submit`]: struct.Queue.html#method.submit
+    pub unsafe fn submit<'a>(
+        &mut self,
+        device: &Device<B>,
+        queue: &mut Queue<B>,
+        dependencies: impl IntoIterator<Item = (FenceEpoch, PipelineStage)>,
+        waits: impl IntoIterator<Item = (&'a B::Semaphore, PipelineStage)>,
+        submits: impl IntoIterator<Item = impl Submittable<B>>,
+        signals: impl IntoIterator<Item = &'a B::Semaphore>,
+    ) -> Result<FenceEpoch, OomOrDeviceLost> {
+        self.assert_device_owner(device);
+        let queue_id = queue.id();
+
+        let mut fence = match self.fences.pop() {
+            Some(mut fence) => {
+                if fence.is_signaled() {
+                    if let Err(err) = fence.reset(device) {
+                        self.fences.push(fence);
+                        return Err(err.into());
+                    }
+                }
+                fence
+            }
+            None => Fence::new(device, false)?,
+        };
+
+        let signal = match self.semaphores.pop() {
+            Some(semaphore) => semaphore,
+            None => match device.create_semaphore() {
+                Ok(semaphore) => semaphore,
+                Err(err) => {
+                    self.fences.push(fence);
+                    return Err(err.into());
+                }
+            },
+        };
+
+        let mut taken = Vec::new();
+        for (dependency, stage) in dependencies {
+            if dependency.queue == queue_id || self.is_complete(dependency) {
+                continue;
+            }
+
+            let track = self
+                .tracks
+                .get_mut(&dependency.queue)
+                .expect("Epoch was not submitted through the timeline");
+
+            match track.latest.take() {
+                Some((epoch, semaphore)) if epoch >= dependency.epoch => {
+                    taken.push((dependency.queue, epoch, semaphore, stage));
+                }
+                latest => {
+                    track.latest = latest;
+                    if let Err(err) = self.wait(device, dependency, !0) {
+                        self.fences.push(fence);
+                        self.semaphores.push(signal);
+                        for (queue, epoch, semaphore, _) in taken {
+                            // Wasn't waited and can be used by later submissions.
+                            self.tracks.get_mut(&queue).unwrap().latest = Some((epoch, semaphore));
+                        }
+                        return Err(err);
+                    }
+                }
+            }
+        }
+
+        {
+            // Semaphores of dependencies are borrowed only for the duration of the submit.
+            let mut wait_list: Vec<(&B::Semaphore, PipelineStage)> = waits.into_iter().collect();
+            wait_list.extend(
+                taken
+                    .iter()
+                    .map(|(_, _, semaphore, stage)| (semaphore, *stage)),
+            );
+            let mut signal_list: Vec<&B::Semaphore> = signals.into_iter().collect();
+            signal_list.push(&signal);
+
+            queue.submit(
+                Some(Submission {
+                    waits: wait_list.iter().cloned(),
+                    submits,
+                    signals: signal_list.iter().cloned(),
+                    marker: std::marker::PhantomData,
+                }),
+                Some(&mut fence),
+            );
+        }
+
+        let epoch = fence.epoch();
+        let track = self.tracks.entry(queue_id).or_insert_with(Track::new);
+        track.pending.push_back(fence);
+        track
+            .stale
+            .extend(track.latest.replace((epoch.epoch, signal)));
+        track.consumed.extend(
+            taken
+                .into_iter()
+                .map(|(_, _, semaphore, _)| (epoch.epoch, semaphore)),
+        );
+
+        Ok(epoch)
+    }
+
+    /// Dispose of the timeline.
+    ///
+    /// # Safety
+    ///
+    /// All submissions through the timeline must be complete.
+    pub unsafe fn dispose(self, device: &Device<B>) {
+        self.assert_device_owner(device);
+        for (_, track) in self.tracks {
+            for mut fence in track.pending {
+                fence.mark_signaled();
+                device.destroy_fence(fence.into_inner());
+            }
+            for (_, semaphore) in track
+                .latest
+                .into_iter()
+                .chain(track.stale)
+                .chain(track.consumed)
+            {
+                device.destroy_semaphore(semaphore);
+            }
+        }
+
+        for fence in self.fences {
+            device.destroy_fence(fence.into_inner());
+        }
+
+        for semaphore in self.semaphores {
+            device.destroy_semaphore(semaphore);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::drain_complete;
+
+    #[test]
+    fn drain_complete_keeps_pending() {
+        let mut items = vec![(3, 'a'), (1, 'b'), (5, 'c'), (2, 'd')];
+        let complete = drain_complete(&mut items, 2).collect::<Vec<_>>();
+        assert_eq!(complete, vec![(1, 'b'), (2, 'd')]);
+        assert_eq!(items, vec![(3, 'a'), (5, 'c')]);
+
+        assert_eq!(drain_complete(&mut items, 2).count(), 0);
+        assert_eq!(drain_complete(&mut items, 5).count(), 2);
+        assert!(items.is_empty());
+    }
+}
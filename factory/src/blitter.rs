@@ -2,15 +2,16 @@ use {
     crate::{
         barriers::Barriers,
         command::{
-            CommandBuffer, CommandPool, Encoder, Families, Family, Graphics, IndividualReset,
-            InitialState, Level, OneShot, PendingOnceState, PrimaryLevel, QueueId, RecordingState,
-            Submission, Supports,
+            CommandBuffer, CommandPool, Encoder, Families, Family, FenceEpoch, Graphics,
+            IndividualReset, InitialState, Level, OneShot, PendingOnceState, PrimaryLevel, QueueId,
+            RecordingState, Submission, Supports,
         },
         core::Device,
+        fence_pool::FencePool,
         resource::{Handle, Image},
         upload::ImageState,
     },
-    rendy_core::hal::device::{Device as _, OutOfMemory},
+    rendy_core::hal::device::OutOfMemory,
     smallvec::SmallVec,
     std::{collections::VecDeque, iter::once, ops::DerefMut, ops::Range},
 };
@@ -257,12 +258,12 @@ where
     ///
     /// # Safety
     ///
-    /// `device` must be the same that was used to create this `Blitter`.
+    /// `fences` must be the same that was used to flush this `Blitter`.
     ///
-    pub(crate) unsafe fn cleanup(&mut self, device: &Device<B>) {
+    pub(crate) unsafe fn cleanup(&mut self, fences: &FencePool<B>) {
         for blitter in self.family_ops.iter_mut() {
             if let Some(blitter) = blitter {
-                blitter.get_mut().cleanup(device);
+                blitter.get_mut().cleanup(fences);
             }
         }
     }
@@ -273,12 +274,17 @@ where
     ///
     /// `families` must be the same that was used to create this `Blitter`.
     ///
-    pub(crate) unsafe fn flush(&mut self, families: &mut Families<B>) {
+    pub(crate) unsafe fn flush(
+        &mut self,
+        device: &Device<B>,
+        fences: &FencePool<B>,
+        families: &mut Families<B>,
+    ) {
         for family in families.as_slice_mut() {
             let blitter = self.family_ops[family.id().index]
                 .as_mut()
                 .expect("Blitter must be initialized for all families");
-            blitter.get_mut().flush(family);
+            blitter.get_mut().flush(device, fences, family);
         }
     }
 
@@ -379,7 +385,7 @@ pub(crate) struct FamilyGraphicsOps<B: rendy_core::hal::Backend> {
     pool: CommandPool<B, Graphics, IndividualReset>,
     initial: Vec<GraphicsOps<B, InitialState>>,
     next: Vec<Option<GraphicsOps<B, RecordingState<OneShot>>>>,
-    pending: VecDeque<GraphicsOps<B, PendingOnceState>>,
+    pending: VecDeque<(FenceEpoch, GraphicsOps<B, PendingOnceState>)>,
     read_barriers: Barriers<B>,
     write_barriers: Barriers<B>,
 }
@@ -387,32 +393,37 @@ pub(crate) struct FamilyGraphicsOps<B: rendy_core::hal::Backend> {
 #[derive(Debug)]
 struct GraphicsOps<B: rendy_core::hal::Backend, S> {
     command_buffer: CommandBuffer<B, Graphics, S, PrimaryLevel, IndividualReset>,
-    fence: B::Fence,
 }
 
 impl<B> FamilyGraphicsOps<B>
 where
     B: rendy_core::hal::Backend,
 {
-    unsafe fn flush(&mut self, family: &mut Family<B>) {
-        for (queue, next) in self
-            .next
-            .drain(..)
-            .enumerate()
-            .filter_map(|(i, x)| x.map(|x| (i, x)))
-        {
+    unsafe fn flush(&mut self, device: &Device<B>, fences: &FencePool<B>, family: &mut Family<B>) {
+        for queue in 0..self.next.len() {
+            if self.next[queue].is_none() {
+                continue;
+            }
+
+            let mut fence = match fences.acquire(device) {
+                Ok(fence) => fence,
+                Err(err) => {
+                    log::error!("Failed to acquire fence for blits: {:?}", err);
+                    continue;
+                }
+            };
+
             log::trace!("Flush blitter");
+            let next = self.next[queue].take().unwrap();
             let (submit, command_buffer) = next.command_buffer.finish().submit_once();
 
-            family.queue_mut(queue).submit_raw_fence(
+            family.queue_mut(queue).submit(
                 Some(Submission::new().submits(once(submit))),
-                Some(&next.fence),
+                Some(&mut fence),
             );
 
-            self.pending.push_back(GraphicsOps {
-                command_buffer,
-                fence: next.fence,
-            });
+            self.pending
+                .push_back((fences.submitted(fence), GraphicsOps { command_buffer }));
         }
     }
 
@@ -421,6 +432,7 @@ where
         device: &Device<B>,
         queue: usize,
     ) -> Result<&mut GraphicsOps<B, RecordingState<OneShot>>, OutOfMemory> {
+        self.pool.assert_device_owner(device);
         while self.next.len() <= queue {
             self.next.push(None);
         }
@@ -430,20 +442,12 @@ where
         match &mut self.next[queue] {
             Some(next) => Ok(next),
             slot @ None => {
-                let initial: Result<_, OutOfMemory> = self.initial.pop().map_or_else(
-                    || {
-                        Ok(GraphicsOps {
-                            command_buffer: pool.allocate_buffers(1).remove(0),
-                            fence: device.create_fence(false)?,
-                        })
-                    },
-                    Ok,
-                );
-                let initial = initial?;
+                let initial = self.initial.pop().unwrap_or_else(|| GraphicsOps {
+                    command_buffer: pool.allocate_buffers(1).remove(0),
+                });
 
                 *slot = Some(GraphicsOps {
                     command_buffer: initial.command_buffer.begin(OneShot, ()),
-                    fence: initial.fence,
                 });
 
                 Ok(slot.as_mut().unwrap())
@@ -455,28 +459,18 @@ where
     ///
     /// # Safety
     ///
-    /// `device` must be the same that was used with other methods of this instance.
+    /// `fences` must be the same that was used to flush this instance.
     ///
-    unsafe fn cleanup(&mut self, device: &Device<B>) {
-        while let Some(pending) = self.pending.pop_front() {
-            match device.get_fence_status(&pending.fence) {
-                Ok(false) => {
-                    self.pending.push_front(pending);
-                    return;
-                }
-                Err(rendy_core::hal::device::DeviceLost) => {
-                    panic!("Device lost error is not handled yet");
-                }
-                Ok(true) => {
-                    device
-                        .reset_fence(&pending.fence)
-                        .expect("Can always reset signalled fence");
-                    self.initial.push(GraphicsOps {
-                        command_buffer: pending.command_buffer.mark_complete().reset(),
-                        fence: pending.fence,
-                    })
-                }
+    unsafe fn cleanup(&mut self, fences: &FencePool<B>) {
+        while let Some((epoch, pending)) = self.pending.pop_front() {
+            if !fences.is_complete(epoch) {
+                self.pending.push_front((epoch, pending));
+                return;
             }
+
+            self.initial.push(GraphicsOps {
+                command_buffer: pending.command_buffer.mark_complete().reset(),
+            })
         }
     }
 
@@ -486,16 +480,13 @@ where
     ///
     unsafe fn dispose(mut self, device: &Device<B>) {
         let pool = &mut self.pool;
-        self.pending.drain(..).for_each(|pending| {
-            device.destroy_fence(pending.fence);
+        self.pending.drain(..).for_each(|(_, pending)| {
             pool.free_buffers(once(pending.command_buffer.mark_complete()));
         });
         self.initial.drain(..).for_each(|initial| {
-            device.destroy_fence(initial.fence);
             pool.free_buffers(once(initial.command_buffer));
         });
         self.next.drain(..).filter_map(|n| n).for_each(|next| {
-            device.destroy_fence(next.fence);
             pool.free_buffers(once(next.command_buffer));
         });
         drop(pool);
@@ -3,7 +3,7 @@ use std::cmp::min;
 use crate::{
     command::FamilyId,
     core::DeviceId,
-    memory::{DynamicConfig, HeapsConfig, LinearConfig},
+    memory::{BuddyConfig, DynamicConfig, HeapsConfig, LinearConfig, RingConfig, TlsfConfig},
 };
 
 /// Factory initialization config.
@@ -14,6 +14,8 @@ use crate::{
 /// [`BasicHeapsConfigure`] can be used as sane default.
 /// `queues` - [`QueuesConfigure`] implementation to configure device queues creation.
 /// [`OneGraphicsQueue`] can be used if only one graphics queue will satisfy requirements.
+/// `staging` - [`StagingConfig`] for staging memory used by uploads.
+/// `timestamp_period` - number of nanoseconds per timestamp tick of the device.
 ///
 /// [`DeviceConfigure`]: trait.DevicesConfigure.html
 /// [`BasicDevicesConfigure`]: struct.BasicDevicesConfigure.html
@@ -21,7 +23,8 @@ use crate::{
 /// [`BasicHeapsConfigure`]: struct.BasicHeapsConfigure.html
 /// [`QueuesConfigure`]: trait.QueuesConfigure.html
 /// [`OneGraphicsQueue`]: struct.OneGraphicsQueue.html
-#[derive(Clone, Debug, Default)]
+/// [`StagingConfig`]: struct.StagingConfig.html
+#[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Config<D = BasicDevicesConfigure, H = BasicHeapsConfigure, Q = OneGraphicsQueue> {
     /// Config to choose adapter.
@@ -32,6 +35,54 @@ pub struct Config<D = BasicDevicesConfigure, H = BasicHeapsConfigure, Q = OneGra
 
     /// Config for queue families.
     pub queues: Q,
+
+    /// Config for staging memory.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub staging: StagingConfig,
+
+    /// Number of nanoseconds per timestamp tick of the device.
+    /// Queues are created with this period as physical device doesn't report it.
+    /// Must be positive.
+    /// `Default` config has `1.0` which should be replaced with the period of the device.
+    pub timestamp_period: f32,
+}
+
+impl<D, H, Q> Default for Config<D, H, Q>
+where
+    D: Default,
+    H: Default,
+    Q: Default,
+{
+    fn default() -> Self {
+        Config {
+            devices: D::default(),
+            heaps: H::default(),
+            queues: Q::default(),
+            staging: StagingConfig::default(),
+            timestamp_period: 1.0,
+        }
+    }
+}
+
+/// Staging memory configuration.
+///
+/// `Factory` writes uploaded data into persistently mapped ring buffer
+/// of `ring_size` bytes and copies it to the destination resource from there.
+/// Data that doesn't fit into free space of the ring gets one-off staging buffer.
+/// Zero `ring_size` disables the ring.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StagingConfig {
+    /// Size of the staging ring buffer in bytes.
+    pub ring_size: u64,
+}
+
+impl Default for StagingConfig {
+    fn default() -> Self {
+        StagingConfig {
+            ring_size: 32 * 1024 * 1024,
+        }
+    }
 }
 
 /// Queues configuration.
@@ -141,8 +192,10 @@ pub unsafe trait HeapsConfigure {
 ///
 /// If default allocators configuration is suboptimal for the particular use case
 /// a custom [`HeapsConfigure`] implementation can be used instead.
+/// To pick general purpose sub-allocator use [`GeneralHeapsConfigure`].
 ///
 /// [`HeapsConfigure`]: trait.HeapsConfigure.html
+/// [`GeneralHeapsConfigure`]: struct.GeneralHeapsConfigure.html
 #[derive(Clone, Copy, Debug, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BasicHeapsConfigure;
@@ -151,6 +204,55 @@ unsafe impl HeapsConfigure for BasicHeapsConfigure {
     type Types = Vec<(rendy_core::hal::memory::Properties, u32, HeapsConfig)>;
     type Heaps = Vec<u64>;
 
+    fn configure(
+        &self,
+        properties: &rendy_core::hal::adapter::MemoryProperties,
+    ) -> (Self::Types, Self::Heaps) {
+        GeneralHeapsConfigure::default().configure(properties)
+    }
+}
+
+/// Heaps config with the same values as [`BasicHeapsConfigure`]
+/// and configurable general purpose sub-allocator.
+///
+/// [`BasicHeapsConfigure`]: struct.BasicHeapsConfigure.html
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GeneralHeapsConfigure {
+    /// General purpose sub-allocator to configure for every memory type.
+    pub general: GeneralAllocator,
+
+    /// Configure buddy sub-allocator for every memory type.
+    /// Buddy allocator serves blocks too large for general purpose one
+    /// from chunks of up to 256MB instead of allocating them from device directly.
+    /// Disabled by default.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub buddy: bool,
+}
+
+/// General purpose sub-allocator picked by [`GeneralHeapsConfigure`].
+///
+/// [`GeneralHeapsConfigure`]: struct.GeneralHeapsConfigure.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GeneralAllocator {
+    /// Use `DynamicAllocator`.
+    Dynamic,
+
+    /// Use `TlsfAllocator`.
+    Tlsf,
+}
+
+impl Default for GeneralAllocator {
+    fn default() -> Self {
+        GeneralAllocator::Dynamic
+    }
+}
+
+unsafe impl HeapsConfigure for GeneralHeapsConfigure {
+    type Types = Vec<(rendy_core::hal::memory::Properties, u32, HeapsConfig)>;
+    type Heaps = Vec<u64>;
+
     fn configure(
         &self,
         properties: &rendy_core::hal::adapter::MemoryProperties,
@@ -158,6 +260,7 @@ unsafe impl HeapsConfigure for BasicHeapsConfigure {
         let _1mb = 1024 * 1024;
         let _32mb = 32 * _1mb;
         let _128mb = 128 * _1mb;
+        let _256mb = 256 * _1mb;
 
         let types = properties
             .memory_types
@@ -174,17 +277,57 @@ unsafe impl HeapsConfigure for BasicHeapsConfigure {
                     } else {
                         None
                     },
-                    dynamic: Some(DynamicConfig {
-                        block_size_granularity: 256.min(
-                            (properties.memory_heaps[mt.heap_index] / 4096).next_power_of_two(),
-                        ),
-                        min_device_allocation: _1mb
-                            .min(properties.memory_heaps[mt.heap_index] / 1048)
-                            .next_power_of_two(),
-                        max_chunk_size: _32mb.min(
-                            (properties.memory_heaps[mt.heap_index] / 128).next_power_of_two(),
-                        ),
-                    }),
+                    dynamic: if self.general == GeneralAllocator::Dynamic {
+                        Some(DynamicConfig {
+                            block_size_granularity: 256.min(
+                                (properties.memory_heaps[mt.heap_index] / 4096).next_power_of_two(),
+                            ),
+                            min_device_allocation: _1mb
+                                .min(properties.memory_heaps[mt.heap_index] / 1048)
+                                .next_power_of_two(),
+                            max_chunk_size: _32mb.min(
+                                (properties.memory_heaps[mt.heap_index] / 128).next_power_of_two(),
+                            ),
+                        })
+                    } else {
+                        None
+                    },
+                    buddy: if self.buddy {
+                        Some(BuddyConfig {
+                            min_block_size: _1mb
+                                .min(properties.memory_heaps[mt.heap_index] / 1048)
+                                .next_power_of_two(),
+                            chunk_size: _256mb.min(
+                                (properties.memory_heaps[mt.heap_index] / 16).next_power_of_two(),
+                            ),
+                        })
+                    } else {
+                        None
+                    },
+                    tlsf: if self.general == GeneralAllocator::Tlsf {
+                        Some(TlsfConfig {
+                            block_size_granularity: 256.min(
+                                (properties.memory_heaps[mt.heap_index] / 4096).next_power_of_two(),
+                            ),
+                            chunk_size: _32mb.min(
+                                (properties.memory_heaps[mt.heap_index] / 128).next_power_of_two(),
+                            ),
+                        })
+                    } else {
+                        None
+                    },
+                    ring: if mt
+                        .properties
+                        .contains(rendy_core::hal::memory::Properties::CPU_VISIBLE)
+                    {
+                        Some(RingConfig {
+                            chunk_size: _32mb.min(
+                                (properties.memory_heaps[mt.heap_index] / 128).next_power_of_two(),
+                            ),
+                        })
+                    } else {
+                        None
+                    },
                 };
 
                 (mt.properties, mt.heap_index as u32, config)
@@ -2,13 +2,19 @@ use {
     crate::{
         blitter::Blitter,
         command::{
-            families_from_device, CommandPool, Families, Family, FamilyId, Fence, QueueType, Reset,
+            families_from_device, CommandPool, Families, Family, FamilyId, Fence, QueryKind,
+            QueryPool, QueueType, Reset,
         },
         config::{Config, DevicesConfigure, HeapsConfigure, QueuesConfigure},
         core::{rendy_with_slow_safety_checks, Device, DeviceId, Instance, InstanceId},
         descriptor::DescriptorAllocator,
-        memory::{self, Heaps, MemoryUsage, TotalMemoryUtilization, Write},
+        fence_pool::FencePool,
+        memory::{
+            self, AllocationTrace, BlockAllocator, BlockCache, BlockCacheConfig, Heaps, HeapsError,
+            LeakReport, MemoryBlock, MemoryBudget, MemoryUsage, TotalMemoryUtilization, Write,
+        },
         resource::*,
+        staging::Staging,
         upload::{BufferState, ImageState, ImageStateOrLayout, Uploader},
         wsi::{Surface, SwapchainError, Target},
     },
@@ -28,7 +34,13 @@ use {
         HasRawWindowHandle,
     },
     smallvec::SmallVec,
-    std::{borrow::BorrowMut, cmp::max, mem::ManuallyDrop},
+    std::{
+        borrow::BorrowMut,
+        cell::{RefCell, RefMut},
+        cmp::max,
+        mem::ManuallyDrop,
+    },
+    thread_local::ThreadLocal,
     thread_profiler::profile_scope,
 };
 
@@ -132,6 +144,17 @@ impl std::error::Error for UploadError {
     }
 }
 
+fn lcm(a: u64, b: u64) -> u64 {
+    fn gcd(a: u64, b: u64) -> u64 {
+        if b == 0 {
+            a
+        } else {
+            gcd(b, a % b)
+        }
+    }
+    a / gcd(a, b) * b
+}
+
 #[derive(Debug)]
 enum InstanceOrId<B: Backend> {
     Instance(Instance<B>),
@@ -163,16 +186,54 @@ where
 pub struct Factory<B: Backend> {
     descriptor_allocator: ManuallyDrop<parking_lot::Mutex<DescriptorAllocator<B>>>,
     heaps: ManuallyDrop<parking_lot::Mutex<Heaps<B>>>,
+    block_cache: Option<BlockCacheConfig>,
+    block_caches: ThreadLocal<RefCell<BlockCache<B>>>,
     resources: ManuallyDrop<ResourceHub<B>>,
     epochs: Vec<parking_lot::RwLock<Vec<u64>>>,
     uploader: Uploader<B>,
     blitter: Blitter<B>,
+    fences: ManuallyDrop<FencePool<B>>,
     families_indices: Vec<usize>,
     device: Device<B>,
     adapter: Adapter<B>,
     instance: InstanceOrId<B>,
 }
 
+/// Allocates memory blocks from thread's `BlockCache` if enabled.
+/// Locks shared `Heaps` only when cache can't serve the request.
+struct FactoryBlockAllocator<'a, B: Backend> {
+    cache: Option<RefMut<'a, BlockCache<B>>>,
+    heaps: &'a parking_lot::Mutex<Heaps<B>>,
+}
+
+impl<B> BlockAllocator<B> for FactoryBlockAllocator<'_, B>
+where
+    B: Backend,
+{
+    fn allocate(
+        &mut self,
+        device: &B::Device,
+        mask: u32,
+        usage: impl MemoryUsage,
+        size: u64,
+        align: u64,
+    ) -> Result<MemoryBlock<B>, HeapsError> {
+        let heaps = self.heaps;
+        match &mut self.cache {
+            Some(cache) => cache.allocate(device, || heaps.lock(), mask, usage, size, align),
+            None => heaps.lock().allocate(device, mask, usage, size, align),
+        }
+    }
+
+    fn free(&mut self, device: &B::Device, block: MemoryBlock<B>) {
+        let heaps = self.heaps;
+        match &mut self.cache {
+            Some(cache) => cache.free(device, || heaps.lock(), block),
+            None => heaps.lock().free(device, block),
+        }
+    }
+}
+
 #[allow(unused)]
 fn factory_is_send_sync<B: Backend>() {
     fn is_send_sync<T: Send + Sync>() {}
@@ -193,6 +254,8 @@ where
             log::trace!("Uploader disposed");
             self.blitter.dispose(&self.device);
             log::trace!("Blitter disposed");
+            std::ptr::read(&*self.fences).dispose(&self.device);
+            log::trace!("Fence pool disposed");
             std::ptr::read(&mut *self.resources).dispose(
                 &self.device,
                 self.heaps.get_mut(),
@@ -202,6 +265,11 @@ where
             log::trace!("Resources disposed");
         }
 
+        for cache in self.block_caches.iter_mut() {
+            cache.get_mut().drain(&self.device, self.heaps.get_mut());
+        }
+        log::trace!("Block caches drained");
+
         unsafe {
             std::ptr::read(&mut *self.heaps)
                 .into_inner()
@@ -249,7 +317,14 @@ where
     ) -> Result<Buffer<B>, BufferCreationError> {
         profile_scope!("create_relevant_buffer");
 
-        unsafe { Buffer::create(&self.device, &mut self.heaps.lock(), info, memory_usage) }
+        unsafe {
+            Buffer::create(
+                &self.device,
+                &mut self.block_allocator(),
+                info,
+                memory_usage,
+            )
+        }
     }
 
     /// Destroy buffer.
@@ -263,14 +338,16 @@ where
     ///
     /// [`create_buffer`]: #method.create_buffer
     pub unsafe fn destroy_relevant_buffer(&self, buffer: Buffer<B>) {
-        buffer.dispose(&self.device, &mut self.heaps.lock());
+        buffer.dispose(&self.device, &mut self.block_allocator());
     }
 
     /// Creates a buffer with the specified properties.
     ///
     /// This function (unlike [`create_relevant_buffer`]) returns value that can be dropped.
+    /// Wrap `memory_usage` into [`Tagged`] to account memory under a tag.
     ///
     /// [`create_relevant_buffer`]: #method.create_relevant_buffer
+    /// [`Tagged`]: ../rendy_memory/struct.Tagged.html
     pub fn create_buffer(
         &self,
         info: BufferInfo,
@@ -293,7 +370,14 @@ where
     ) -> Result<Image<B>, ImageCreationError> {
         profile_scope!("create_relevant_image");
 
-        unsafe { Image::create(&self.device, &mut self.heaps.lock(), info, memory_usage) }
+        unsafe {
+            Image::create(
+                &self.device,
+                &mut self.block_allocator(),
+                info,
+                memory_usage,
+            )
+        }
     }
 
     /// Destroy image.
@@ -307,14 +391,16 @@ where
     ///
     /// [`create_image`]: #method.create_image
     pub unsafe fn destroy_relevant_image(&self, image: Image<B>) {
-        image.dispose(&self.device, &mut self.heaps.lock());
+        image.dispose(&self.device, &mut self.block_allocator());
     }
 
     /// Creates an image with the specified properties.
     ///
     /// This function (unlike [`create_relevant_image`]) returns value that can be dropped.
+    /// Wrap `memory_usage` into [`Tagged`] to account memory under a tag.
     ///
     /// [`create_relevant_image`]: #method.create_relevant_image
+    /// [`Tagged`]: ../rendy_memory/struct.Tagged.html
     pub fn create_image(
         &self,
         info: ImageInfo,
@@ -493,6 +579,11 @@ where
     ///
     /// `#[repr(C)]` can be used to guarantee defined memory layout of struct fields.
     ///
+    /// `content` is staged in the staging ring configured by [`StagingConfig`],
+    /// or in one-off staging buffer if it doesn't fit.
+    ///
+    /// [`StagingConfig`]: struct.StagingConfig.html
+    ///
     /// # Safety
     ///
     /// If buffer is used by device then `last` state must match the last usage state of the buffer
@@ -512,19 +603,7 @@ where
     {
         assert!(buffer.info().usage.contains(buffer::Usage::TRANSFER_DST));
 
-        let content_size = content.len() as u64 * std::mem::size_of::<T>() as u64;
-        let mut staging = self
-            .create_buffer(
-                BufferInfo {
-                    size: content_size,
-                    usage: buffer::Usage::TRANSFER_SRC,
-                },
-                memory::Upload,
-            )
-            .map_err(UploadError::Create)?;
-
-        self.upload_visible_buffer(&mut staging, 0, content)
-            .map_err(UploadError::Map)?;
+        let staging = self.stage(content, 1)?;
 
         self.uploader
             .upload_buffer(&self.device, buffer, offset, staging, last, next)
@@ -555,8 +634,14 @@ where
     ) -> Result<(), OutOfMemory> {
         assert!(buffer.info().usage.contains(buffer::Usage::TRANSFER_DST));
         assert!(staging.info().usage.contains(buffer::Usage::TRANSFER_SRC));
-        self.uploader
-            .upload_buffer(&self.device, buffer, offset, staging, last, next)
+        self.uploader.upload_buffer(
+            &self.device,
+            buffer,
+            offset,
+            Staging::Buffer(staging),
+            last,
+            next,
+        )
     }
 
     /// Update image layers content with provided data.
@@ -598,6 +683,8 @@ where
     /// It usually should be compatible type of pixel or channel.
     /// For example `&[[u8; 4]]` or `&[u8]` for `Rgba8Unorm` format.
     ///
+    /// `content` is staged the same way as in [`upload_buffer`].
+    ///
     /// # Safety
     ///
     /// Image must be created by this `Factory`.
@@ -605,6 +692,8 @@ where
     /// before updating happen.
     /// In order to guarantee that updated content will be made visible to next device operation
     /// that reads content of the image layers the `next` must match image usage state in that operation.
+    ///
+    /// [`upload_buffer`]: #method.upload_buffer
     pub unsafe fn upload_image<T>(
         &self,
         image: Handle<Image<B>>,
@@ -638,18 +727,8 @@ where
             "Size of must match size of the image region"
         );
 
-        let mut staging = self
-            .create_buffer(
-                BufferInfo {
-                    size: content_size,
-                    usage: buffer::Usage::TRANSFER_SRC,
-                },
-                memory::Upload,
-            )
-            .map_err(UploadError::Create)?;
-
-        self.upload_visible_buffer(&mut staging, 0, content)
-            .map_err(UploadError::Map)?;
+        // Offset of buffer to image copy must be multiple of texel block size.
+        let staging = self.stage(content, format_desc.bits as u64 / 8)?;
 
         self.uploader
             .upload_image(
@@ -667,6 +746,63 @@ where
             .map_err(UploadError::Upload)
     }
 
+    /// Write `content` into the staging ring.
+    /// Falls back to one-off staging buffer if it doesn't fit into free space of the ring.
+    unsafe fn stage<T>(&self, content: &[T], texel_size: u64) -> Result<Staging<B>, UploadError>
+    where
+        T: 'static + Copy,
+    {
+        let bytes = std::slice::from_raw_parts(
+            content.as_ptr() as *const u8,
+            content.len() * std::mem::size_of::<T>(),
+        );
+
+        let align = lcm(
+            lcm(
+                4,
+                self.physical()
+                    .limits()
+                    .optimal_buffer_copy_offset_alignment
+                    .max(1),
+            ),
+            texel_size.max(1),
+        );
+
+        if let Some(staging) = self
+            .uploader
+            .stage(&self.device, bytes, align)
+            .map_err(UploadError::Map)?
+        {
+            return Ok(staging);
+        }
+
+        log::trace!(
+            "{} bytes don't fit into staging ring, creating staging buffer",
+            bytes.len()
+        );
+
+        let mut staging = self
+            .create_buffer(
+                BufferInfo {
+                    size: bytes.len() as u64,
+                    usage: buffer::Usage::TRANSFER_SRC,
+                },
+                memory::Upload,
+            )
+            .map_err(UploadError::Create)?;
+
+        self.upload_visible_buffer(&mut staging, 0, bytes)
+            .map_err(UploadError::Map)?;
+
+        Ok(Staging::Buffer(staging))
+    }
+
+    /// Get size of the staging ring used by uploads in bytes.
+    /// Zero if ring is disabled.
+    pub fn staging_ring_size(&self) -> u64 {
+        self.uploader.staging_ring_size()
+    }
+
     /// Get blitter instance
     pub fn blitter(&self) -> &Blitter<B> {
         &self.blitter
@@ -854,6 +990,27 @@ where
         Fence::new(&self.device, signaled)
     }
 
+    /// Acquire unsignaled fence from the factory's fence pool.
+    /// Return it with [`release_fence`] instead of destroying.
+    ///
+    /// [`release_fence`]: #method.release_fence
+    pub fn acquire_fence(&self) -> Result<Fence<B>, OutOfMemory> {
+        self.fences.acquire(&self.device)
+    }
+
+    /// Return fence to the factory's fence pool.
+    /// Submitted fence is reset and recycled once signaled.
+    ///
+    /// Fence must be created by this `Factory`.
+    pub fn release_fence(&self, fence: Fence<B>) {
+        self.fences.release(&self.device, fence)
+    }
+
+    /// Get factory's fence pool.
+    pub fn fence_pool(&self) -> &FencePool<B> {
+        &self.fences
+    }
+
     /// Wait for the fence become signeled.
     pub fn reset_fence(&self, fence: &mut Fence<B>) -> Result<(), OutOfMemory> {
         fence.reset(&self.device)
@@ -991,6 +1148,32 @@ where
         unsafe { self.device.destroy_fence(fence.into_inner()) }
     }
 
+    /// Create query pool with `count` queries of specified kind.
+    pub fn create_query_pool<K>(
+        &self,
+        kind: K,
+        count: u32,
+    ) -> Result<QueryPool<B, K>, rendy_core::hal::query::CreationError>
+    where
+        K: QueryKind,
+    {
+        profile_scope!("create_query_pool");
+
+        QueryPool::create(&self.device, kind, count)
+    }
+
+    /// Destroy query pool.
+    ///
+    /// # Safety
+    ///
+    /// Pool must not be used by pending command buffers.
+    pub unsafe fn destroy_query_pool<K>(&self, pool: QueryPool<B, K>)
+    where
+        K: QueryKind,
+    {
+        pool.dispose(&self.device)
+    }
+
     /// Create new command pool for specified family.
     pub fn create_command_pool<R>(
         &self,
@@ -1042,9 +1225,13 @@ where
 
         let next = self.next_epochs(families);
         let complete = self.complete_epochs();
+        if let Err(rendy_core::hal::device::DeviceLost) = self.fences.poll(&self.device) {
+            panic!("Device lost error is not handled yet");
+        }
+
         unsafe {
-            self.uploader.cleanup(&self.device);
-            self.blitter.cleanup(&self.device);
+            self.uploader.cleanup(&self.fences);
+            self.blitter.cleanup(&self.fences);
             self.resources.cleanup(
                 &self.device,
                 self.heaps.get_mut(),
@@ -1059,19 +1246,25 @@ where
 
     /// Flush uploads
     pub fn flush_uploads(&mut self, families: &mut Families<B>) {
-        unsafe { self.uploader.flush(families) }
+        unsafe { self.uploader.flush(&self.device, &self.fences, families) }
     }
 
     /// Flush blits
     pub fn flush_blits(&mut self, families: &mut Families<B>) {
-        unsafe { self.blitter.flush(families) }
+        unsafe { self.blitter.flush(&self.device, &self.fences, families) }
     }
 
-    /// Flush uploads and cleanup unused resources.
+    /// Flush uploads, cleanup unused resources
+    /// and return blocks of block caches unused since previous call.
     pub fn maintain(&mut self, families: &mut Families<B>) {
         self.flush_uploads(families);
         self.flush_blits(families);
         self.cleanup(families);
+        for cache in self.block_caches.iter_mut() {
+            cache
+                .get_mut()
+                .drain_idle(&self.device, self.heaps.get_mut());
+        }
     }
 
     /// Create descriptor set layout with specified bindings.
@@ -1149,6 +1342,88 @@ where
         self.heaps.lock().utilization()
     }
 
+    /// Set soft budget for the memory heap.
+    /// Resource creation that would exceed the budget fails with `HeapsError::BudgetExceeded`
+    /// unless eviction callback releases enough memory.
+    pub fn set_memory_budget(&self, heap_index: usize, budget: Option<MemoryBudget>) {
+        self.heaps.lock().set_budget(heap_index, budget)
+    }
+
+    /// Set callback invoked when resource creation would exceed memory heap budget.
+    /// See `memory::EvictionCallback` for details.
+    pub fn set_memory_eviction_callback(
+        &self,
+        callback: impl FnMut(&mut Heaps<B>, &B::Device, usize, u64) -> bool + Send + Sync + 'static,
+    ) {
+        self.heaps.lock().set_eviction_callback(callback)
+    }
+
+    /// Enable per-thread caches of small memory blocks
+    /// so that concurrent resource creation doesn't contend on shared `Heaps`.
+    /// Disable caches with `None`.
+    /// Blocks cached so far are returned to `Heaps`.
+    ///
+    /// See `memory::BlockCache` for details.
+    pub fn set_block_cache(&mut self, config: Option<BlockCacheConfig>) {
+        for cache in self.block_caches.iter_mut() {
+            cache.get_mut().drain(&self.device, self.heaps.get_mut());
+        }
+        self.block_caches.clear();
+        self.block_cache = config;
+    }
+
+    /// Get allocator of memory blocks for current thread.
+    fn block_allocator(&self) -> FactoryBlockAllocator<'_, B> {
+        let cache = self.block_cache.map(|config| {
+            self.block_caches
+                .get_or(|| RefCell::new(BlockCache::new(&self.heaps.lock(), config)))
+                .borrow_mut()
+        });
+
+        FactoryBlockAllocator {
+            cache,
+            heaps: &self.heaps,
+        }
+    }
+
+    /// Set epoch of following transient allocations served by ring allocators.
+    /// See `memory::Heaps::set_ring_epoch`.
+    pub fn set_ring_epoch(&self, epoch: u64) {
+        self.heaps.lock().set_ring_epoch(epoch)
+    }
+
+    /// Retire transient allocations of epochs less than `complete`.
+    /// See `memory::Heaps::retire_ring_epochs`.
+    /// Device must not access blocks of retired epochs anymore.
+    pub fn retire_ring_epochs(&self, complete: u64) {
+        self.heaps.lock().retire_ring_epochs(&self.device, complete)
+    }
+
+    /// Start recording trace of memory allocations.
+    /// See `memory::Heaps::start_trace`.
+    pub fn start_memory_trace(&self) {
+        self.heaps.lock().start_trace()
+    }
+
+    /// Stop recording and take trace of memory allocations.
+    /// Returns `None` if recording wasn't started.
+    pub fn take_memory_trace(&self) -> Option<AllocationTrace> {
+        self.heaps.lock().take_trace()
+    }
+
+    /// Enable or disable tracking of leaked memory blocks.
+    /// Leaks are reported when factory is dropped.
+    /// See `memory::Heaps::track_leaks`.
+    pub fn track_memory_leaks(&self, enable: bool) {
+        self.heaps.lock().track_leaks(enable)
+    }
+
+    /// Get memory blocks left allocated since leak tracking was enabled.
+    /// Returns `None` if leak tracking is disabled.
+    pub fn memory_leak_report(&self) -> Option<LeakReport> {
+        self.heaps.lock().leak_report()
+    }
+
     /// Get Factory's instance id.
     pub fn instance_id(&self) -> InstanceId {
         self.device.id().instance
@@ -1264,6 +1539,7 @@ where
                 &mut queue_groups,
                 get_queues,
                 &adapter.queue_families,
+                config.timestamp_period,
             )
         };
         (device, families)
@@ -1287,16 +1563,19 @@ where
         .map(|f| parking_lot::RwLock::new(vec![0; f.as_slice().len()]))
         .collect();
 
-    let factory = Factory {
+    let mut factory = Factory {
         descriptor_allocator: ManuallyDrop::new(
             parking_lot::Mutex::new(DescriptorAllocator::new()),
         ),
         heaps: ManuallyDrop::new(parking_lot::Mutex::new(heaps)),
+        block_cache: None,
+        block_caches: ThreadLocal::new(),
         resources: ManuallyDrop::new(ResourceHub::default()),
         uploader: unsafe { Uploader::new(&device, &families) }
             .map_err(rendy_core::hal::device::CreationError::OutOfMemory)?,
         blitter: unsafe { Blitter::new(&device, &families) }
             .map_err(rendy_core::hal::device::CreationError::OutOfMemory)?,
+        fences: ManuallyDrop::new(FencePool::new(&device)),
         families_indices: families.indices().into(),
         epochs,
         device,
@@ -1304,5 +1583,24 @@ where
         instance: InstanceOrId::Id(instance.id()),
     };
 
+    if config.staging.ring_size > 0 {
+        let ring = factory.create_buffer(
+            BufferInfo {
+                size: config.staging.ring_size,
+                usage: buffer::Usage::TRANSFER_SRC,
+            },
+            memory::Upload,
+        );
+
+        match ring {
+            Ok(ring) => unsafe {
+                if let Err(err) = factory.uploader.init_staging_ring(&factory.device, ring) {
+                    log::warn!("Failed to map staging ring: {:?}", err);
+                }
+            },
+            Err(err) => log::warn!("Failed to create staging ring: {:?}", err),
+        }
+    }
+
     Ok((factory, families))
 }
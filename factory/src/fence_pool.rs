@@ -0,0 +1,200 @@
+//! Pool of reusable fences.
+
+use {
+    crate::{
+        command::{Fence, FenceEpoch, QueueId},
+        core::{device_owned, Device, DeviceId},
+    },
+    rendy_core::hal::{
+        device::{Device as _, DeviceLost, OutOfMemory},
+        Backend,
+    },
+    std::collections::{HashMap, VecDeque},
+};
+
+#[derive(Debug)]
+struct FencePoolState<B: Backend> {
+    /// Reset fences ready to be acquired.
+    free: Vec<Fence<B>>,
+
+    /// Submitted fences returned to the pool, in epoch order per queue.
+    outstanding: HashMap<QueueId, VecDeque<Fence<B>>>,
+
+    /// Latest complete epoch of each queue.
+    completed: HashMap<QueueId, u64>,
+
+    /// Number of fences created by the pool.
+    created: u64,
+}
+
+/// Pool of fences owned by `Factory`.
+///
+/// Hands out unsignaled fences and recycles them when they are returned.
+/// Submitted fences can be returned right away,
+/// pool tracks them by their `FenceEpoch` and resets them once signaled,
+/// so that in steady state no fences are created.
+#[derive(Debug)]
+pub struct FencePool<B: Backend> {
+    device: DeviceId,
+    state: parking_lot::Mutex<FencePoolState<B>>,
+}
+
+device_owned!(FencePool<B>);
+
+impl<B> FencePool<B>
+where
+    B: Backend,
+{
+    /// Create empty pool.
+    pub fn new(device: &Device<B>) -> Self {
+        FencePool {
+            device: device.id(),
+            state: parking_lot::Mutex::new(FencePoolState {
+                free: Vec::new(),
+                outstanding: HashMap::new(),
+                completed: HashMap::new(),
+                created: 0,
+            }),
+        }
+    }
+
+    /// Acquire unsignaled fence.
+    /// Recycled fence is returned if available, otherwise new fence is created.
+    pub fn acquire(&self, device: &Device<B>) -> Result<Fence<B>, OutOfMemory> {
+        self.assert_device_owner(device);
+        let mut state = self.state.lock();
+        match state.free.pop() {
+            Some(fence) => Ok(fence),
+            None => {
+                let fence = Fence::new(device, false)?;
+                state.created += 1;
+                Ok(fence)
+            }
+        }
+    }
+
+    /// Return fence to the pool.
+    /// Submitted fence is tracked until it is signaled.
+    pub fn release(&self, device: &Device<B>, mut fence: Fence<B>) {
+        self.assert_device_owner(device);
+        fence.assert_device_owner(device);
+        if fence.is_submitted() {
+            self.submitted(fence);
+            return;
+        }
+
+        if fence.is_signaled() {
+            if let Err(err) = fence.reset(device) {
+                log::warn!("Failed to reset fence: {:?}", err);
+                unsafe {
+                    // Fence is not submitted.
+                    device.destroy_fence(fence.into_inner());
+                }
+                return;
+            }
+        }
+        self.state.lock().free.push(fence);
+    }
+
+    /// Return submitted fence to the pool.
+    /// Returns epoch of the fence that can be checked with [`is_complete`].
+    ///
+    /// Panics if fence is not submitted.
+    ///
+    /// [`is_complete`]: #method.is_complete
+    pub fn submitted(&self, fence: Fence<B>) -> FenceEpoch {
+        let epoch = fence.epoch();
+        let mut state = self.state.lock();
+        let queue = state.outstanding.entry(epoch.queue).or_default();
+        let index = queue
+            .iter()
+            .rposition(|known| known.epoch().epoch < epoch.epoch)
+            .map_or(0, |index| index + 1);
+        queue.insert(index, fence);
+        epoch
+    }
+
+    /// Check outstanding fences, reset signaled ones and update complete epochs.
+    pub fn poll(&self, device: &Device<B>) -> Result<(), DeviceLost> {
+        self.assert_device_owner(device);
+        let mut state = self.state.lock();
+        let FencePoolState {
+            free,
+            outstanding,
+            completed,
+            ..
+        } = &mut *state;
+
+        for (queue, fences) in outstanding.iter_mut() {
+            while let Some(fence) = fences.front_mut() {
+                match fence.check_signaled(device)? {
+                    Some(epoch) => {
+                        let complete = completed.entry(*queue).or_insert(0);
+                        *complete = (*complete).max(epoch.epoch);
+                    }
+                    None => break,
+                }
+
+                let mut fence = fences.pop_front().unwrap();
+                match fence.reset(device) {
+                    Ok(()) => free.push(fence),
+                    Err(err) => {
+                        log::warn!("Failed to reset fence: {:?}", err);
+                        unsafe {
+                            // Fence is signaled.
+                            device.destroy_fence(fence.into_inner());
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if fence with specified epoch is known to be signaled.
+    /// Updated by [`poll`](#method.poll).
+    pub fn is_complete(&self, epoch: FenceEpoch) -> bool {
+        self.state
+            .lock()
+            .completed
+            .get(&epoch.queue)
+            .map_or(false, |&complete| complete >= epoch.epoch)
+    }
+
+    /// Get number of fences created by the pool so far.
+    pub fn created(&self) -> u64 {
+        self.state.lock().created
+    }
+
+    /// Get number of fences ready to be acquired.
+    pub fn free(&self) -> usize {
+        self.state.lock().free.len()
+    }
+
+    /// Get number of submitted fences that are not known to be signaled yet.
+    pub fn outstanding(&self) -> usize {
+        self.state
+            .lock()
+            .outstanding
+            .values()
+            .map(VecDeque::len)
+            .sum()
+    }
+
+    /// Destroy all fences in the pool.
+    ///
+    /// # Safety
+    ///
+    /// Outstanding fences must be signaled.
+    pub unsafe fn dispose(self, device: &Device<B>) {
+        self.assert_device_owner(device);
+        let state = self.state.into_inner();
+        for mut fence in state.outstanding.into_iter().flat_map(|(_, fences)| fences) {
+            fence.mark_signaled();
+            device.destroy_fence(fence.into_inner());
+        }
+        for fence in state.free {
+            device.destroy_fence(fence.into_inner());
+        }
+    }
+}
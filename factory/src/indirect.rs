@@ -0,0 +1,269 @@
+//! Typed buffers of indirect draw and dispatch arguments.
+
+use {
+    crate::{
+        command::{
+            Compute, DispatchCommand, DrawCommand, DrawIndexedCommand, Encoder, RenderPassEncoder,
+            Supports,
+        },
+        factory::{Factory, UploadError},
+        memory::Dynamic,
+        resource::{Buffer, BufferInfo, Escape},
+    },
+    rendy_core::hal::{buffer::Usage, device::MapError, pso::Descriptor, Backend},
+    std::marker::PhantomData,
+};
+
+/// Command that can be stored in [`IndirectBuffer`].
+///
+/// # Safety
+///
+/// Type must have layout of the command expected by the device.
+///
+/// [`IndirectBuffer`]: struct.IndirectBuffer.html
+pub unsafe trait IndirectCommand: Copy + 'static {}
+
+unsafe impl IndirectCommand for DrawCommand {}
+unsafe impl IndirectCommand for DrawIndexedCommand {}
+unsafe impl IndirectCommand for DispatchCommand {}
+
+/// Offset of the `u32` count of commands in the [`IndirectBuffer`].
+///
+/// [`IndirectBuffer`]: struct.IndirectBuffer.html
+pub const INDIRECT_COUNT_OFFSET: u64 = 0;
+
+/// Offset of the first command in the [`IndirectBuffer`].
+///
+/// [`IndirectBuffer`]: struct.IndirectBuffer.html
+pub const INDIRECT_COMMANDS_OFFSET: u64 = 16;
+
+/// Buffer with `INDIRECT` usage that stores commands of type `C`.
+///
+/// Buffer starts with `u32` count of commands followed by tightly packed commands
+/// at [`INDIRECT_COMMANDS_OFFSET`]. Host writes commands through mapping
+/// and keeps the count in sync. Compute shaders can write commands
+/// through [`storage_descriptor`] instead, e.g. for GPU culling
+/// by zeroing instance count of culled commands.
+///
+/// [`INDIRECT_COMMANDS_OFFSET`]: constant.INDIRECT_COMMANDS_OFFSET.html
+/// [`storage_descriptor`]: #method.storage_descriptor
+#[derive(Debug)]
+pub struct IndirectBuffer<B: Backend, C> {
+    buffer: Escape<Buffer<B>>,
+    capacity: u32,
+    len: u32,
+    marker: PhantomData<fn() -> C>,
+}
+
+impl<B, C> IndirectBuffer<B, C>
+where
+    B: Backend,
+    C: IndirectCommand,
+{
+    const STRIDE: u32 = std::mem::size_of::<C>() as u32;
+
+    /// Create buffer for up to `capacity` commands.
+    /// Count stored in the buffer is initialized to zero.
+    pub fn new(factory: &Factory<B>, capacity: u32) -> Result<Self, UploadError> {
+        let buffer = factory
+            .create_buffer(
+                BufferInfo {
+                    size: INDIRECT_COMMANDS_OFFSET + capacity.max(1) as u64 * Self::STRIDE as u64,
+                    usage: Usage::INDIRECT | Usage::STORAGE | Usage::TRANSFER_DST,
+                },
+                Dynamic,
+            )
+            .map_err(UploadError::Create)?;
+
+        let mut indirect = IndirectBuffer {
+            buffer,
+            capacity,
+            len: 0,
+            marker: PhantomData,
+        };
+
+        unsafe {
+            // New buffer is not used by device yet.
+            indirect.write_count(factory, 0)
+        }
+        .map_err(UploadError::Map)?;
+
+        Ok(indirect)
+    }
+
+    /// Get maximum number of commands.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Get number of commands written by host.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Check if no commands were written by host.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get underlying buffer.
+    pub fn buffer(&self) -> &Buffer<B> {
+        &self.buffer
+    }
+
+    /// Get raw buffer.
+    pub fn raw(&self) -> &B::Buffer {
+        self.buffer.raw()
+    }
+
+    /// Get size in bytes of one command.
+    pub fn stride(&self) -> u32 {
+        Self::STRIDE
+    }
+
+    /// Get offset of the command with specified index.
+    pub fn offset(&self, index: u32) -> u64 {
+        assert!(index < self.capacity, "Command index is out of bounds");
+        INDIRECT_COMMANDS_OFFSET + index as u64 * Self::STRIDE as u64
+    }
+
+    /// Get descriptor for binding whole buffer as storage buffer.
+    /// Shader sees `u32` count followed by array of commands at [`INDIRECT_COMMANDS_OFFSET`].
+    ///
+    /// [`INDIRECT_COMMANDS_OFFSET`]: constant.INDIRECT_COMMANDS_OFFSET.html
+    pub fn storage_descriptor(&self) -> Descriptor<'_, B> {
+        Descriptor::Buffer(self.buffer.raw(), Some(0)..Some(self.buffer.size()))
+    }
+
+    /// Append command.
+    ///
+    /// # Panics
+    ///
+    /// Panics if buffer is full.
+    ///
+    /// # Safety
+    ///
+    /// Device must not access the buffer.
+    pub unsafe fn push(&mut self, factory: &Factory<B>, command: C) -> Result<(), MapError> {
+        assert!(self.len < self.capacity, "Indirect buffer is full");
+        let index = self.len;
+        self.update(factory, index, &[command])
+    }
+
+    /// Overwrite commands starting from `index`.
+    /// Commands written past current length extend it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if commands don't fit into capacity or `index` is greater than length.
+    ///
+    /// # Safety
+    ///
+    /// Device must not access the buffer.
+    pub unsafe fn update(
+        &mut self,
+        factory: &Factory<B>,
+        index: u32,
+        commands: &[C],
+    ) -> Result<(), MapError> {
+        assert!(
+            index <= self.len,
+            "Indirect commands must be written without gaps"
+        );
+        let end = index as u64 + commands.len() as u64;
+        assert!(
+            end <= self.capacity as u64,
+            "Indirect commands don't fit into buffer"
+        );
+
+        if !commands.is_empty() {
+            let offset = self.offset(index);
+            factory.upload_visible_buffer(&mut self.buffer, offset, commands)?;
+        }
+
+        if end as u32 > self.len {
+            self.write_count(factory, end as u32)?;
+        }
+        Ok(())
+    }
+
+    /// Remove all commands.
+    ///
+    /// # Safety
+    ///
+    /// Device must not access the buffer.
+    pub unsafe fn clear(&mut self, factory: &Factory<B>) -> Result<(), MapError> {
+        self.write_count(factory, 0)
+    }
+
+    unsafe fn write_count(&mut self, factory: &Factory<B>, count: u32) -> Result<(), MapError> {
+        factory.upload_visible_buffer(&mut self.buffer, INDIRECT_COUNT_OFFSET, &[count])?;
+        self.len = count;
+        Ok(())
+    }
+}
+
+impl<B> IndirectBuffer<B, DrawCommand>
+where
+    B: Backend,
+{
+    /// Draw commands written by host.
+    ///
+    /// # Safety
+    ///
+    /// See [`RenderPassEncoder::draw_indirect`].
+    ///
+    /// [`RenderPassEncoder::draw_indirect`]: ../rendy_command/struct.RenderPassEncoder.html#method.draw_indirect
+    pub unsafe fn draw(&self, encoder: &mut RenderPassEncoder<'_, B>) {
+        if self.len > 0 {
+            encoder.draw_indirect(
+                self.buffer.raw(),
+                INDIRECT_COMMANDS_OFFSET,
+                self.len,
+                Self::STRIDE,
+            );
+        }
+    }
+}
+
+impl<B> IndirectBuffer<B, DrawIndexedCommand>
+where
+    B: Backend,
+{
+    /// Draw indexed commands written by host.
+    ///
+    /// # Safety
+    ///
+    /// See [`RenderPassEncoder::draw_indexed_indirect`].
+    ///
+    /// [`RenderPassEncoder::draw_indexed_indirect`]: ../rendy_command/struct.RenderPassEncoder.html#method.draw_indexed_indirect
+    pub unsafe fn draw(&self, encoder: &mut RenderPassEncoder<'_, B>) {
+        if self.len > 0 {
+            encoder.draw_indexed_indirect(
+                self.buffer.raw(),
+                INDIRECT_COMMANDS_OFFSET,
+                self.len,
+                Self::STRIDE,
+            );
+        }
+    }
+}
+
+impl<B> IndirectBuffer<B, DispatchCommand>
+where
+    B: Backend,
+{
+    /// Dispatch command with specified index.
+    ///
+    /// # Safety
+    ///
+    /// See [`Encoder::dispatch_indirect`].
+    ///
+    /// [`Encoder::dispatch_indirect`]: ../rendy_command/struct.Encoder.html#method.dispatch_indirect
+    pub unsafe fn dispatch<C, L>(&self, encoder: &mut Encoder<'_, B, C, L>, index: u32)
+    where
+        C: Supports<Compute>,
+    {
+        encoder.dispatch_indirect(self.buffer.raw(), self.offset(index));
+    }
+}
@@ -21,6 +21,11 @@ mod barriers;
 mod blitter;
 mod config;
 mod factory;
+mod fence_pool;
+mod indirect;
+mod staging;
 mod upload;
 
-pub use crate::{barriers::*, blitter::*, config::*, factory::*, upload::*};
+pub use crate::{
+    barriers::*, blitter::*, config::*, factory::*, fence_pool::*, indirect::*, upload::*,
+};
@@ -0,0 +1,313 @@
+//! Staging ring buffer for uploads.
+
+use {
+    crate::{
+        core::Device,
+        memory::Write as _,
+        resource::{Buffer, Escape},
+    },
+    rendy_core::hal::{device::MapError, Backend},
+    std::collections::VecDeque,
+};
+
+/// Region of the staging ring that holds data of one upload.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct StagingSlice {
+    /// Index of the region in the ring.
+    region: u64,
+
+    /// Offset of the data in the ring buffer.
+    pub(crate) offset: u64,
+
+    /// Size of the data.
+    pub(crate) size: u64,
+}
+
+/// Source of the uploaded data.
+#[derive(Debug)]
+pub(crate) enum Staging<B: Backend> {
+    /// One-off staging buffer.
+    Buffer(Escape<Buffer<B>>),
+
+    /// Region of the staging ring.
+    Ring(StagingSlice),
+}
+
+#[derive(Debug)]
+struct Region {
+    start: u64,
+    free: bool,
+}
+
+/// Regions allocated from the ring.
+///
+/// Regions are allocated one after another and wrap around the end of the ring.
+/// Released regions are reclaimed in allocation order,
+/// so one region that is still in use holds all regions allocated after it.
+#[derive(Debug)]
+struct Regions {
+    /// Offset right after the last allocated region.
+    head: u64,
+
+    /// Allocated regions in allocation order.
+    regions: VecDeque<Region>,
+
+    /// Index of the first region in `regions`.
+    first: u64,
+}
+
+impl Regions {
+    fn new() -> Self {
+        Regions {
+            head: 0,
+            regions: VecDeque::new(),
+            first: 0,
+        }
+    }
+
+    /// Find offset for the region in the ring of `ring_size` bytes.
+    fn find(&self, ring_size: u64, size: u64, align: u64) -> Option<u64> {
+        if size == 0 || size > ring_size {
+            return None;
+        }
+
+        let tail = match self.regions.front() {
+            Some(front) => front.start,
+            None => return Some(0),
+        };
+
+        let aligned = (self.head + align - 1) / align * align;
+        if self.head > tail {
+            // Free space is after the head and before the tail.
+            if aligned + size <= ring_size {
+                Some(aligned)
+            } else if size <= tail {
+                Some(0)
+            } else {
+                None
+            }
+        } else if aligned + size <= tail {
+            Some(aligned)
+        } else {
+            None
+        }
+    }
+
+    /// Add region found with [`find`](#method.find).
+    /// Returns index of the region.
+    fn push(&mut self, offset: u64, size: u64) -> u64 {
+        self.regions.push_back(Region {
+            start: offset,
+            free: false,
+        });
+        self.head = offset + size;
+        self.first + self.regions.len() as u64 - 1
+    }
+
+    /// Release region by index.
+    fn release(&mut self, region: u64) {
+        let index = (region - self.first) as usize;
+        self.regions[index].free = true;
+
+        while self.regions.front().map_or(false, |region| region.free) {
+            self.regions.pop_front();
+            self.first += 1;
+        }
+
+        if self.regions.is_empty() {
+            self.head = 0;
+        }
+    }
+
+    /// Forget all regions.
+    fn clear(&mut self) {
+        self.first += self.regions.len() as u64;
+        self.regions.clear();
+        self.head = 0;
+    }
+}
+
+/// Persistently mapped buffer that staging data is sub-allocated from.
+/// See `Regions` for allocation strategy.
+#[derive(Debug)]
+pub(crate) struct StagingRing<B: Backend> {
+    buffer: Option<Escape<Buffer<B>>>,
+    regions: Regions,
+}
+
+impl<B> StagingRing<B>
+where
+    B: Backend,
+{
+    /// Create ring without buffer.
+    /// It can't stage any data until buffer is set with [`init`](#method.init).
+    pub(crate) fn new() -> Self {
+        StagingRing {
+            buffer: None,
+            regions: Regions::new(),
+        }
+    }
+
+    /// Set buffer of the ring.
+    /// Whole buffer gets mapped so that mapping is kept for the ring's lifetime.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must belong to the `device` and be used only by the ring.
+    pub(crate) unsafe fn init(
+        &mut self,
+        device: &Device<B>,
+        mut buffer: Escape<Buffer<B>>,
+    ) -> Result<(), MapError> {
+        assert!(self.buffer.is_none(), "Staging ring is already initialized");
+        let size = buffer.size();
+        buffer.map(device, 0..size)?;
+        self.buffer = Some(buffer);
+        Ok(())
+    }
+
+    /// Get size of the ring buffer.
+    /// Zero if ring is not initialized.
+    pub(crate) fn size(&self) -> u64 {
+        self.buffer.as_ref().map_or(0, |buffer| buffer.size())
+    }
+
+    /// Get raw ring buffer.
+    pub(crate) fn raw(&self) -> &B::Buffer {
+        self.buffer
+            .as_ref()
+            .expect("Staging ring is not initialized")
+            .raw()
+    }
+
+    /// Write `content` into free region of the ring.
+    /// Returns `None` if there is not enough contiguous free space.
+    ///
+    /// # Safety
+    ///
+    /// `device` must be the same that was used to initialize the ring.
+    pub(crate) unsafe fn stage(
+        &mut self,
+        device: &Device<B>,
+        content: &[u8],
+        align: u64,
+    ) -> Result<Option<StagingSlice>, MapError> {
+        let size = content.len() as u64;
+        let offset = match self.regions.find(self.size(), size, align) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+
+        let mut mapped = self
+            .buffer
+            .as_mut()
+            .unwrap()
+            .map(device, offset..offset + size)?;
+        mapped.write(device, 0..size)?.write(content);
+
+        let region = self.regions.push(offset, size);
+        Ok(Some(StagingSlice {
+            region,
+            offset,
+            size,
+        }))
+    }
+
+    /// Release region of the slice.
+    /// Space is reclaimed once all regions allocated before it are released too.
+    ///
+    /// # Safety
+    ///
+    /// Device must not read the slice anymore.
+    pub(crate) unsafe fn release(&mut self, slice: StagingSlice) {
+        self.regions.release(slice.region);
+    }
+
+    /// Release ring buffer.
+    ///
+    /// # Safety
+    ///
+    /// Device must not read the ring anymore.
+    pub(crate) unsafe fn dispose(&mut self) {
+        self.buffer = None;
+        self.regions.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Regions;
+
+    fn allocate(regions: &mut Regions, size: u64, align: u64) -> Option<(u64, u64)> {
+        let offset = regions.find(256, size, align)?;
+        Some((regions.push(offset, size), offset))
+    }
+
+    #[test]
+    fn regions_are_sequential() {
+        let mut regions = Regions::new();
+        assert_eq!(allocate(&mut regions, 0, 1), None);
+        assert_eq!(allocate(&mut regions, 257, 1), None);
+        assert_eq!(allocate(&mut regions, 10, 4), Some((0, 0)));
+        assert_eq!(allocate(&mut regions, 10, 4), Some((1, 12)));
+        assert_eq!(allocate(&mut regions, 10, 16), Some((2, 32)));
+    }
+
+    #[test]
+    fn full_ring_refuses_allocation() {
+        let mut regions = Regions::new();
+        assert_eq!(allocate(&mut regions, 128, 1), Some((0, 0)));
+        assert_eq!(allocate(&mut regions, 128, 1), Some((1, 128)));
+        regions.release(0);
+
+        // Wrapped region ends right at the tail, so head equals tail.
+        assert_eq!(allocate(&mut regions, 128, 1), Some((2, 0)));
+        assert_eq!(regions.head, 128);
+        assert_eq!(allocate(&mut regions, 1, 1), None);
+    }
+
+    #[test]
+    fn allocation_wraps_to_start() {
+        let mut regions = Regions::new();
+        assert_eq!(allocate(&mut regions, 100, 1), Some((0, 0)));
+        assert_eq!(allocate(&mut regions, 100, 1), Some((1, 100)));
+
+        // Tail is still at 0, nothing fits before it.
+        assert_eq!(allocate(&mut regions, 100, 1), None);
+
+        regions.release(0);
+        assert_eq!(allocate(&mut regions, 101, 1), None);
+        assert_eq!(allocate(&mut regions, 100, 1), Some((2, 0)));
+
+        // Space between head and tail is used after wrapping.
+        regions.release(1);
+        assert_eq!(allocate(&mut regions, 50, 64), Some((3, 128)));
+        assert_eq!(allocate(&mut regions, 100, 1), None);
+    }
+
+    #[test]
+    fn out_of_order_release_waits_for_oldest() {
+        let mut regions = Regions::new();
+        assert_eq!(allocate(&mut regions, 128, 1), Some((0, 0)));
+        assert_eq!(allocate(&mut regions, 128, 1), Some((1, 128)));
+
+        // Second region is released first, but the first one still holds the ring.
+        regions.release(1);
+        assert_eq!(regions.regions.len(), 2);
+        assert_eq!(allocate(&mut regions, 1, 1), None);
+
+        regions.release(0);
+        assert!(regions.regions.is_empty());
+        assert_eq!(regions.head, 0);
+        assert_eq!(allocate(&mut regions, 256, 1), Some((2, 0)));
+    }
+
+    #[test]
+    fn clear_keeps_region_indices_unique() {
+        let mut regions = Regions::new();
+        assert_eq!(allocate(&mut regions, 16, 1), Some((0, 0)));
+        assert_eq!(allocate(&mut regions, 16, 1), Some((1, 16)));
+        regions.clear();
+        assert_eq!(allocate(&mut regions, 16, 1), Some((2, 0)));
+    }
+}
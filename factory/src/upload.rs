@@ -2,13 +2,16 @@ use {
     crate::{
         barriers::Barriers,
         command::{
-            CommandBuffer, CommandPool, Families, Family, IndividualReset, InitialState, OneShot,
-            PendingOnceState, PrimaryLevel, QueueId, RecordingState, Submission, Transfer,
+            CommandBuffer, CommandPool, Families, Family, FenceEpoch, IndividualReset,
+            InitialState, OneShot, PendingOnceState, PrimaryLevel, QueueId, RecordingState,
+            Submission, Transfer,
         },
         core::Device,
+        fence_pool::FencePool,
         resource::{Buffer, Escape, Handle, Image},
+        staging::{Staging, StagingRing, StagingSlice},
     },
-    rendy_core::hal::device::{Device as _, OutOfMemory},
+    rendy_core::hal::device::{MapError, OutOfMemory},
     std::{collections::VecDeque, iter::once},
 };
 
@@ -123,6 +126,7 @@ impl From<rendy_core::hal::image::Layout> for ImageStateOrLayout {
 #[derive(Debug)]
 pub(crate) struct Uploader<B: rendy_core::hal::Backend> {
     family_uploads: Vec<Option<parking_lot::Mutex<FamilyUploads<B>>>>,
+    staging: parking_lot::Mutex<StagingRing<B>>,
 }
 
 impl<B> Uploader<B>
@@ -143,7 +147,6 @@ where
             }
 
             family_uploads[family.id().index] = Some(parking_lot::Mutex::new(FamilyUploads {
-                fences: Vec::new(),
                 pool: family
                     .create_pool(device)
                     .map(|pool| pool.with_capability().unwrap())?,
@@ -158,7 +161,58 @@ where
             }));
         }
 
-        Ok(Uploader { family_uploads })
+        Ok(Uploader {
+            family_uploads,
+            staging: parking_lot::Mutex::new(StagingRing::new()),
+        })
+    }
+
+    /// Set buffer of the staging ring.
+    ///
+    /// # Safety
+    ///
+    /// `device` must be the same that was used to create this `Uploader`.
+    /// `buffer` must belong to the `device`, be mappable and have `TRANSFER_SRC` usage.
+    ///
+    pub(crate) unsafe fn init_staging_ring(
+        &mut self,
+        device: &Device<B>,
+        buffer: Escape<Buffer<B>>,
+    ) -> Result<(), MapError> {
+        self.staging.get_mut().init(device, buffer)
+    }
+
+    /// Get size of the staging ring in bytes.
+    pub(crate) fn staging_ring_size(&self) -> u64 {
+        self.staging.lock().size()
+    }
+
+    /// Write `content` into the staging ring.
+    /// Returns `None` if it doesn't fit into free space of the ring.
+    ///
+    /// # Safety
+    ///
+    /// `device` must be the same that was used to create this `Uploader`.
+    ///
+    pub(crate) unsafe fn stage(
+        &self,
+        device: &Device<B>,
+        content: &[u8],
+        align: u64,
+    ) -> Result<Option<Staging<B>>, MapError> {
+        Ok(self
+            .staging
+            .lock()
+            .stage(device, content, align)?
+            .map(Staging::Ring))
+    }
+
+    /// Release staging data that won't be uploaded.
+    unsafe fn discard(&self, staging: Staging<B>) {
+        if let Staging::Ring(slice) = staging {
+            // Slice was never submitted.
+            self.staging.lock().release(slice);
+        }
     }
 
     /// # Safety
@@ -171,7 +225,7 @@ where
         device: &Device<B>,
         buffer: &Buffer<B>,
         offset: u64,
-        staging: Escape<Buffer<B>>,
+        staging: Staging<B>,
         last: Option<BufferState>,
         next: BufferState,
     ) -> Result<(), OutOfMemory> {
@@ -193,19 +247,35 @@ where
             next.access,
         );
 
-        let next_upload = family_uploads.next_upload(device, next.queue.index)?;
+        let next_upload = match family_uploads.next_upload(device, next.queue.index) {
+            Ok(next_upload) => next_upload,
+            Err(err) => {
+                self.discard(staging);
+                return Err(err);
+            }
+        };
+
+        let ring;
+        let (src, src_offset, size) = match &staging {
+            Staging::Buffer(staging) => (staging.raw(), 0, staging.size()),
+            Staging::Ring(slice) => {
+                ring = self.staging.lock();
+                (ring.raw(), slice.offset, slice.size)
+            }
+        };
+
         let mut encoder = next_upload.command_buffer.encoder();
         encoder.copy_buffer(
-            staging.raw(),
+            src,
             buffer.raw(),
             Some(rendy_core::hal::command::BufferCopy {
-                src: 0,
+                src: src_offset,
                 dst: offset,
-                size: staging.size(),
+                size,
             }),
         );
 
-        next_upload.staging_buffers.push(staging);
+        next_upload.keep(staging);
 
         Ok(())
     }
@@ -273,7 +343,7 @@ where
         image_layers: rendy_core::hal::image::SubresourceLayers,
         image_offset: rendy_core::hal::image::Offset,
         image_extent: rendy_core::hal::image::Extent,
-        staging: Escape<Buffer<B>>,
+        staging: Staging<B>,
         last: ImageStateOrLayout,
         next: ImageState,
     ) -> Result<(), OutOfMemory> {
@@ -348,14 +418,30 @@ where
             next.layout,
         );
 
-        let next_upload = family_uploads.next_upload(device, next.queue.index)?;
+        let next_upload = match family_uploads.next_upload(device, next.queue.index) {
+            Ok(next_upload) => next_upload,
+            Err(err) => {
+                self.discard(staging);
+                return Err(err);
+            }
+        };
+
+        let ring;
+        let (src, src_offset) = match &staging {
+            Staging::Buffer(staging) => (staging.raw(), 0),
+            Staging::Ring(slice) => {
+                ring = self.staging.lock();
+                (ring.raw(), slice.offset)
+            }
+        };
+
         let mut encoder = next_upload.command_buffer.encoder();
         encoder.copy_buffer_to_image(
-            staging.raw(),
+            src,
             image.raw(),
             target_layout,
             Some(rendy_core::hal::command::BufferImageCopy {
-                buffer_offset: 0,
+                buffer_offset: src_offset,
                 buffer_width: data_width,
                 buffer_height: data_height,
                 image_layers,
@@ -364,7 +450,7 @@ where
             }),
         );
 
-        next_upload.staging_buffers.push(staging);
+        next_upload.keep(staging);
         Ok(())
     }
 
@@ -372,12 +458,13 @@ where
     ///
     /// # Safety
     ///
-    /// `device` must be the same that was used to create this `Uploader`.
+    /// `fences` must be the same that was used to flush this `Uploader`.
     ///
-    pub(crate) unsafe fn cleanup(&mut self, device: &Device<B>) {
+    pub(crate) unsafe fn cleanup(&mut self, fences: &FencePool<B>) {
+        let staging = self.staging.get_mut();
         for uploader in self.family_uploads.iter_mut() {
             if let Some(uploader) = uploader {
-                uploader.get_mut().cleanup(device);
+                uploader.get_mut().cleanup(fences, staging);
             }
         }
     }
@@ -388,12 +475,17 @@ where
     ///
     /// `families` must be the same that was used to create this `Uploader`.
     ///
-    pub(crate) unsafe fn flush(&mut self, families: &mut Families<B>) {
+    pub(crate) unsafe fn flush(
+        &mut self,
+        device: &Device<B>,
+        fences: &FencePool<B>,
+        families: &mut Families<B>,
+    ) {
         for family in families.as_slice_mut() {
             let uploader = self.family_uploads[family.id().index]
                 .as_mut()
                 .expect("Uploader must be initialized for all families");
-            uploader.get_mut().flush(family);
+            uploader.get_mut().flush(device, fences, family);
         }
     }
 
@@ -406,6 +498,7 @@ where
         self.family_uploads.drain(..).for_each(|fu| {
             fu.map(|fu| fu.into_inner().dispose(device));
         });
+        self.staging.get_mut().dispose();
     }
 }
 
@@ -416,7 +509,6 @@ pub(crate) struct FamilyUploads<B: rendy_core::hal::Backend> {
         Vec<[CommandBuffer<B, Transfer, InitialState, PrimaryLevel, IndividualReset>; 2]>,
     next: Vec<Option<NextUploads<B>>>,
     pending: VecDeque<PendingUploads<B>>,
-    fences: Vec<B::Fence>,
     barriers: Barriers<B>,
 }
 
@@ -425,7 +517,8 @@ pub(crate) struct PendingUploads<B: rendy_core::hal::Backend> {
     barrier_buffer: CommandBuffer<B, Transfer, PendingOnceState, PrimaryLevel, IndividualReset>,
     command_buffer: CommandBuffer<B, Transfer, PendingOnceState, PrimaryLevel, IndividualReset>,
     staging_buffers: Vec<Escape<Buffer<B>>>,
-    fence: B::Fence,
+    staging_slices: Vec<StagingSlice>,
+    epoch: FenceEpoch,
 }
 
 #[derive(Debug)]
@@ -435,20 +528,41 @@ struct NextUploads<B: rendy_core::hal::Backend> {
     command_buffer:
         CommandBuffer<B, Transfer, RecordingState<OneShot>, PrimaryLevel, IndividualReset>,
     staging_buffers: Vec<Escape<Buffer<B>>>,
-    fence: B::Fence,
+    staging_slices: Vec<StagingSlice>,
+}
+
+impl<B> NextUploads<B>
+where
+    B: rendy_core::hal::Backend,
+{
+    /// Keep staging data until uploads are complete.
+    fn keep(&mut self, staging: Staging<B>) {
+        match staging {
+            Staging::Buffer(buffer) => self.staging_buffers.push(buffer),
+            Staging::Ring(slice) => self.staging_slices.push(slice),
+        }
+    }
 }
 
 impl<B> FamilyUploads<B>
 where
     B: rendy_core::hal::Backend,
 {
-    unsafe fn flush(&mut self, family: &mut Family<B>) {
-        for (queue, mut next) in self
-            .next
-            .drain(..)
-            .enumerate()
-            .filter_map(|(i, x)| x.map(|x| (i, x)))
-        {
+    unsafe fn flush(&mut self, device: &Device<B>, fences: &FencePool<B>, family: &mut Family<B>) {
+        for queue in 0..self.next.len() {
+            if self.next[queue].is_none() {
+                continue;
+            }
+
+            let mut fence = match fences.acquire(device) {
+                Ok(fence) => fence,
+                Err(err) => {
+                    log::error!("Failed to acquire fence for uploads: {:?}", err);
+                    continue;
+                }
+            };
+
+            let mut next = self.next[queue].take().unwrap();
             let mut barriers_encoder = next.barrier_buffer.encoder();
             let mut encoder = next.command_buffer.encoder();
 
@@ -458,16 +572,17 @@ where
             let (barriers_submit, barrier_buffer) = next.barrier_buffer.finish().submit_once();
             let (submit, command_buffer) = next.command_buffer.finish().submit_once();
 
-            family.queue_mut(queue).submit_raw_fence(
+            family.queue_mut(queue).submit(
                 Some(Submission::new().submits(once(barriers_submit).chain(once(submit)))),
-                Some(&next.fence),
+                Some(&mut fence),
             );
 
             self.pending.push_back(PendingUploads {
                 barrier_buffer,
                 command_buffer,
                 staging_buffers: next.staging_buffers,
-                fence: next.fence,
+                staging_slices: next.staging_slices,
+                epoch: fences.submitted(fence),
             });
         }
     }
@@ -477,6 +592,7 @@ where
         device: &Device<B>,
         queue: usize,
     ) -> Result<&mut NextUploads<B>, OutOfMemory> {
+        self.pool.assert_device_owner(device);
         while self.next.len() <= queue {
             self.next.push(None);
         }
@@ -490,15 +606,11 @@ where
                     let mut bufs = pool.allocate_buffers(2);
                     [bufs.remove(1), bufs.remove(0)]
                 });
-                let fence = self
-                    .fences
-                    .pop()
-                    .map_or_else(|| device.create_fence(false), Ok)?;
                 *slot = Some(NextUploads {
                     barrier_buffer: buf_a.begin(OneShot, ()),
                     command_buffer: buf_b.begin(OneShot, ()),
                     staging_buffers: Vec::new(),
-                    fence,
+                    staging_slices: Vec::new(),
                 });
 
                 Ok(slot.as_mut().unwrap())
@@ -510,29 +622,24 @@ where
     ///
     /// # Safety
     ///
-    /// `device` must be the same that was used with other methods of this instance.
+    /// `fences` must be the same that was used to flush this instance.
+    /// `staging` must be the ring that staging slices were allocated from.
     ///
-    unsafe fn cleanup(&mut self, device: &Device<B>) {
+    unsafe fn cleanup(&mut self, fences: &FencePool<B>, staging: &mut StagingRing<B>) {
         while let Some(pending) = self.pending.pop_front() {
-            match device.get_fence_status(&pending.fence) {
-                Ok(false) => {
-                    self.pending.push_front(pending);
-                    return;
-                }
-                Err(rendy_core::hal::device::DeviceLost) => {
-                    panic!("Device lost error is not handled yet");
-                }
-                Ok(true) => {
-                    device
-                        .reset_fence(&pending.fence)
-                        .expect("Can always reset signalled fence");
-                    self.fences.push(pending.fence);
-                    self.command_buffers.push([
-                        pending.command_buffer.mark_complete().reset(),
-                        pending.barrier_buffer.mark_complete().reset(),
-                    ]);
-                }
+            if !fences.is_complete(pending.epoch) {
+                self.pending.push_front(pending);
+                return;
+            }
+
+            for slice in pending.staging_slices {
+                staging.release(slice);
             }
+
+            self.command_buffers.push([
+                pending.command_buffer.mark_complete().reset(),
+                pending.barrier_buffer.mark_complete().reset(),
+            ]);
         }
     }
 
@@ -543,24 +650,22 @@ where
     unsafe fn dispose(mut self, device: &Device<B>) {
         let pool = &mut self.pool;
         self.pending.drain(..).for_each(|pending| {
-            device.destroy_fence(pending.fence);
             pool.free_buffers(Some(pending.command_buffer.mark_complete()));
             pool.free_buffers(Some(pending.barrier_buffer.mark_complete()));
         });
 
-        self.fences
-            .drain(..)
-            .for_each(|fence| device.destroy_fence(fence));
         pool.free_buffers(
             self.command_buffers
                 .drain(..)
                 .flat_map(|[a, b]| once(a).chain(once(b))),
         );
 
-        pool.free_buffers(self.next.drain(..).filter_map(|n| n).flat_map(|next| {
-            device.destroy_fence(next.fence);
-            once(next.command_buffer).chain(once(next.barrier_buffer))
-        }));
+        pool.free_buffers(
+            self.next
+                .drain(..)
+                .filter_map(|n| n)
+                .flat_map(|next| once(next.command_buffer).chain(once(next.barrier_buffer))),
+        );
         drop(pool);
         self.pool.dispose(device);
     }
@@ -158,6 +158,14 @@ where
         }
     }
 
+    /// Retire ring allocations of complete frames
+    /// and make following ring allocations belong to the next frame.
+    /// Call after `wait_complete` and before recording the next frame.
+    pub fn retire_ring_allocations(&self, factory: &Factory<B>) {
+        factory.retire_ring_epochs(self.complete_upper_bound());
+        factory.set_ring_epoch(self.next);
+    }
+
     /// Dispose of the `Frames`
     pub fn dispose(mut self, factory: &mut Factory<B>) {
         let ready = factory.wait_for_fences(
@@ -170,7 +178,7 @@ where
         self.pending
             .drain(..)
             .flatten()
-            .for_each(|fence| factory.destroy_fence(fence));
+            .for_each(|fence| factory.release_fence(fence));
     }
 
     /// Get range of frame indices in this form:
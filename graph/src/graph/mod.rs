@@ -33,7 +33,6 @@ pub struct Graph<B: Backend, T: ?Sized> {
     schedule: chain::Schedule<chain::SyncData<usize, usize>>,
     semaphores: Vec<B::Semaphore>,
     frames: Frames<B>,
-    fences: Vec<Fences<B>>,
     inflight: u32,
     ctx: GraphContext<B>,
 }
@@ -171,16 +170,16 @@ where
 
         if self.frames.next().index() >= self.inflight as _ {
             let wait = Frame::with_index(self.frames.next().index() - self.inflight as u64);
-            let ref mut self_fences = self.fences;
-            self.frames.wait_complete(wait, factory, |mut fences| {
-                factory.reset_fences(&mut fences).unwrap();
-                self_fences.push(fences);
+            self.frames.wait_complete(wait, factory, |fences| {
+                fences
+                    .into_iter()
+                    .for_each(|fence| factory.release_fence(fence));
             });
         }
 
-        let mut fences = self.fences.pop().unwrap_or_else(Fences::<B>::default);
-        let mut fences_used = 0;
+        let mut fences = Fences::<B>::default();
         let ref semaphores = self.semaphores;
+        let mut queues = smallvec::SmallVec::<[(usize, usize); 8]>::new();
 
         for submission in self.schedule.ordered() {
             log::trace!("Run node {}", submission.node());
@@ -197,13 +196,23 @@ where
                 "Node's queue doesn't match schedule"
             );
 
+            // Flush batched submissions before switching queues
+            // so that semaphores are signaled by the driver before they are waited.
+            if let Some(&previous) = queues.last() {
+                if previous != *queue {
+                    families
+                        .family_by_index_mut(previous.0)
+                        .queue_mut(previous.1)
+                        .flush();
+                }
+            }
+            queues.retain(|q| *q != *queue);
+            queues.push(*queue);
+
             let last_in_queue = sid.index() + 1 == self.schedule.queue(qid).unwrap().len();
             let fence = if last_in_queue {
-                if fences_used >= fences.len() {
-                    fences.push(factory.create_fence(false).unwrap());
-                }
-                fences_used += 1;
-                Some(&mut fences[fences_used - 1])
+                fences.push(factory.acquire_fence().unwrap());
+                fences.last_mut()
             } else {
                 None
             };
@@ -246,7 +255,16 @@ where
             }
         }
 
-        fences.truncate(fences_used);
+        for &(family, index) in &queues {
+            let queue = families.family_by_index_mut(family).queue_mut(index);
+            queue.flush();
+            log::trace!(
+                "Queue {:?} made {} driver submits since last reset",
+                queue.id(),
+                queue.driver_submits()
+            );
+        }
+
         self.frames.advance(fences);
     }
 
@@ -283,7 +301,6 @@ where
         }
         drop(self.device);
         drop(self.schedule);
-        drop(self.fences);
         drop(self.inflight);
         drop(self.ctx);
     }
@@ -500,7 +517,6 @@ where
             semaphores,
             inflight: self.frames_in_flight,
             frames: Frames::new(),
-            fences: Vec::new(),
         })
     }
 }
@@ -6,14 +6,21 @@ pub mod render;
 
 use {
     crate::{
-        command::{Capability, Families, Family, FamilyId, Fence, Queue, Submission, Submittable},
+        command::{
+            Capability, CommandBuffer, CommandPool, EncoderCommon, ExecutableState, Families,
+            Family, FamilyId, Fence, MultiShot, PendingState, Queue, SimultaneousUse, Submission,
+            Submit, Submittable,
+        },
         factory::{Factory, UploadError},
         frame::Frames,
         graph::GraphContext,
         wsi::SwapchainError,
         BufferId, ImageId, NodeId,
     },
-    rendy_core::hal::{queue::QueueFamilyId, Backend},
+    rendy_core::hal::{
+        queue::{QueueFamilyId, QueueType},
+        Backend,
+    },
 };
 
 /// Buffer access node will perform.
@@ -240,6 +247,106 @@ pub trait DynNode<B: Backend, T: ?Sized>: std::fmt::Debug + Sync + Send {
     unsafe fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &T);
 }
 
+/// Command buffer with single debug marker command.
+#[derive(Debug)]
+struct DebugMarker<B: Backend> {
+    submit: Submit<B, SimultaneousUse>,
+    buffer: CommandBuffer<B, QueueType, PendingState<ExecutableState<MultiShot<SimultaneousUse>>>>,
+}
+
+impl<B> DebugMarker<B>
+where
+    B: Backend,
+{
+    fn record(
+        pool: &mut CommandPool<B, QueueType>,
+        record: impl FnOnce(&mut EncoderCommon<'_, B, QueueType>),
+    ) -> Self {
+        let mut buffer = pool
+            .allocate_buffers(1)
+            .pop()
+            .unwrap()
+            .begin(MultiShot(SimultaneousUse), ());
+        record(&mut buffer.encoder());
+        let (submit, buffer) = buffer.finish().submit();
+        DebugMarker { submit, buffer }
+    }
+
+    unsafe fn dispose(self, pool: &mut CommandPool<B, QueueType>) {
+        drop(self.submit);
+        pool.free_buffers(Some(self.buffer.mark_complete()));
+    }
+}
+
+/// Submittable of the node or one of debug markers around it.
+enum Labeled<'a, B: Backend, S> {
+    Marker(&'a Submit<B, SimultaneousUse>),
+    Node(S),
+}
+
+unsafe impl<'a, B, S> Submittable<B> for Labeled<'a, B, S>
+where
+    B: Backend,
+    S: Submittable<B>,
+{
+    fn family(&self) -> FamilyId {
+        match self {
+            Labeled::Marker(submit) => submit.family(),
+            Labeled::Node(submittable) => submittable.family(),
+        }
+    }
+
+    unsafe fn raw<'b>(self) -> &'b B::CommandBuffer {
+        match self {
+            Labeled::Marker(submit) => submit.raw(),
+            Labeled::Node(submittable) => submittable.raw(),
+        }
+    }
+}
+
+/// `Node` wrapped into `DynNode`.
+/// Commands recorded by the node are submitted inside debug group named after the node.
+/// Debug group takes two extra command buffers,
+/// so only nodes that were given a name are wrapped.
+#[derive(Debug)]
+struct LabeledNode<B: Backend, N> {
+    node: N,
+    pool: CommandPool<B, QueueType>,
+    begin: DebugMarker<B>,
+    end: DebugMarker<B>,
+}
+
+impl<B, N> LabeledNode<B, N>
+where
+    B: Backend,
+{
+    fn new(
+        factory: &Factory<B>,
+        family: &Family<B>,
+        node: N,
+        name: &str,
+    ) -> Result<Self, NodeBuildError> {
+        let mut pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?;
+
+        let begin = DebugMarker::record(&mut pool, |encoder| {
+            encoder.push_debug_group(name, NODE_LABEL_COLOR)
+        });
+        let end = DebugMarker::record(&mut pool, |encoder| unsafe { encoder.pop_debug_group() });
+
+        Ok(LabeledNode {
+            node,
+            pool,
+            begin,
+            end,
+        })
+    }
+}
+
+/// Color of debug groups around nodes' commands.
+pub(crate) const NODE_LABEL_COLOR: u32 = 0;
+
 impl<B, T, N> DynNode<B, T> for (N,)
 where
     B: Backend,
@@ -274,6 +381,53 @@ where
     }
 }
 
+impl<B, T, N> DynNode<B, T> for LabeledNode<B, N>
+where
+    B: Backend,
+    T: ?Sized,
+    N: Node<B, T>,
+{
+    unsafe fn run<'a>(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+        queue: &mut Queue<B>,
+        aux: &T,
+        frames: &Frames<B>,
+        waits: &[(&'a B::Semaphore, rendy_core::hal::pso::PipelineStage)],
+        signals: &[&'a B::Semaphore],
+        fence: Option<&mut Fence<B>>,
+    ) {
+        let submittables = Node::run(&mut self.node, ctx, factory, aux, frames);
+        queue.submit(
+            Some(
+                Submission::new()
+                    .submits(
+                        std::iter::once(Labeled::Marker(&self.begin.submit))
+                            .chain(submittables.into_iter().map(Labeled::Node))
+                            .chain(std::iter::once(Labeled::Marker(&self.end.submit))),
+                    )
+                    .wait(waits.iter().cloned())
+                    .signal(signals.iter().cloned()),
+            ),
+            fence,
+        )
+    }
+
+    unsafe fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &T) {
+        let LabeledNode {
+            node,
+            mut pool,
+            begin,
+            end,
+        } = *self;
+        N::dispose(node, factory, aux);
+        begin.dispose(&mut pool);
+        end.dispose(&mut pool);
+        factory.destroy_command_pool(pool);
+    }
+}
+
 /// Error building a node of the graph.
 #[derive(Debug)]
 pub enum NodeBuildError {
@@ -321,6 +475,7 @@ pub trait NodeBuilder<B: Backend, T: ?Sized>: std::fmt::Debug {
 /// Builder for the node.
 pub struct DescBuilder<B: Backend, T: ?Sized, N> {
     desc: N,
+    name: Option<String>,
     buffers: Vec<BufferId>,
     images: Vec<ImageId>,
     dependencies: Vec<NodeId>,
@@ -336,6 +491,7 @@ where
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         fmt.debug_struct("DescBuilder")
             .field("desc", &self.desc)
+            .field("name", &self.name)
             .field("buffers", &self.buffers)
             .field("images", &self.images)
             .field("dependencies", &self.dependencies)
@@ -352,12 +508,27 @@ where
     pub fn new(desc: N) -> Self {
         DescBuilder {
             desc,
+            name: None,
             buffers: Vec::new(),
             images: Vec::new(),
             dependencies: Vec::new(),
             marker: std::marker::PhantomData,
         }
     }
+    /// Set name of the node.
+    /// Commands of named node are submitted inside debug group with this name.
+    pub fn set_name(&mut self, name: impl Into<String>) -> &mut Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set name of the node.
+    /// Commands of named node are submitted inside debug group with this name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.set_name(name);
+        self
+    }
+
     /// Add buffer to the node.
     /// This method must be called for each buffer node uses.
     pub fn add_buffer(&mut self, buffer: BufferId) -> &mut Self {
@@ -439,9 +610,12 @@ where
         buffers: Vec<NodeBuffer>,
         images: Vec<NodeImage>,
     ) -> Result<Box<dyn DynNode<B, T>>, NodeBuildError> {
-        Ok(Box::new((self.desc.build(
-            ctx, factory, family, queue, aux, buffers, images,
-        )?,)))
+        let DescBuilder { desc, name, .. } = *self;
+        let node = desc.build(ctx, factory, family, queue, aux, buffers, images)?;
+        match &name {
+            Some(name) => Ok(Box::new(LabeledNode::new(factory, family, node, name)?)),
+            None => Ok(Box::new((node,))),
+        }
     }
 }
 
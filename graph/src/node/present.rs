@@ -113,6 +113,7 @@ fn create_per_image_data<B: rendy_core::hal::Backend>(
         .map(|(target_image, buf_initial)| {
             let mut buf_recording = buf_initial.begin(MultiShot(SimultaneousUse), ());
             let mut encoder = buf_recording.encoder();
+            encoder.push_debug_group("PresentNode", 0);
             let (mut stages, mut barriers) =
                 gfx_acquire_barriers(ctx, None, Some(input_image));
             stages.start |= rendy_core::hal::pso::PipelineStage::TRANSFER;
@@ -243,6 +244,10 @@ fn create_per_image_data<B: rendy_core::hal::Backend>(
                 }
             }
 
+            unsafe {
+                encoder.pop_debug_group();
+            }
+
             let (submit, buffer) = buf_recording.finish().submit();
 
             ForImage {
@@ -25,6 +25,7 @@ pub trait RenderGroupDesc<B: Backend, T: ?Sized>: std::fmt::Debug {
     {
         DescBuilder {
             desc: self,
+            name: None,
             buffers: Vec::new(),
             images: Vec::new(),
             dependencies: Vec::new(),
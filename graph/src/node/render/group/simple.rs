@@ -1,7 +1,7 @@
 use {
     super::{RenderGroup, RenderGroupDesc},
     crate::{
-        command::{QueueId, RenderPassEncoder},
+        command::{PipelineRequirements, QueueId, RenderPassEncoder},
         factory::Factory,
         graph::GraphContext,
         node::{
@@ -203,6 +203,7 @@ pub struct SimpleRenderGroup<B: Backend, P> {
     set_layouts: Vec<Handle<DescriptorSetLayout<B>>>,
     pipeline_layout: B::PipelineLayout,
     graphics_pipeline: B::GraphicsPipeline,
+    requirements: PipelineRequirements,
     pipeline: P,
 }
 
@@ -252,6 +253,12 @@ where
 
         let pipeline = self.inner.pipeline();
 
+        let requirements = PipelineRequirements {
+            sets: pipeline.layout.sets.len() as u32,
+            vertex_buffers: pipeline.vertices.len() as u32,
+            push_constants: pipeline.layout.push_constants.clone(),
+        };
+
         let set_layouts = pipeline
             .layout
             .sets
@@ -354,6 +361,7 @@ where
             set_layouts,
             pipeline_layout,
             graphics_pipeline,
+            requirements,
             pipeline,
         }))
     }
@@ -384,7 +392,8 @@ where
         _subpass: rendy_core::hal::pass::Subpass<'_, B>,
         aux: &T,
     ) {
-        encoder.bind_graphics_pipeline(&self.graphics_pipeline);
+        encoder
+            .bind_graphics_pipeline_with_requirements(&self.graphics_pipeline, &self.requirements);
         self.pipeline
             .draw(&self.pipeline_layout, encoder, index, aux);
     }
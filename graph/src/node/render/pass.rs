@@ -1,8 +1,10 @@
 use {
     crate::{
         command::{
-            CommandBuffer, CommandPool, ExecutableState, Families, Family, FamilyId, Fence,
-            Graphics, IndividualReset, MultiShot, NoSimultaneousUse, PendingState, Queue, QueueId,
+            record_parallel, CommandBuffer, CommandPool, CommandPoolManager, Encoder,
+            ExecutableState, Families, Family, FamilyId, Fence, FenceEpoch, Graphics,
+            IndividualReset, MultiShot, NoSimultaneousUse, ParallelSecondaries, PendingState,
+            PrimaryLevel, Queue, QueueId, RenderPassInlineEncoder, RenderPassSecondaryEncoder,
             SecondaryLevel, SimultaneousUse, Submission, Submit,
         },
         core::{
@@ -12,19 +14,23 @@ use {
         factory::Factory,
         frame::{
             cirque::{CirqueRef, CommandCirque},
-            Frames,
+            Frame, Frames,
         },
         graph::GraphContext,
         node::{
             gfx_acquire_barriers, gfx_release_barriers,
             render::group::{RenderGroup, RenderGroupBuilder},
             BufferAccess, DynNode, ImageAccess, NodeBuffer, NodeBuildError, NodeBuilder, NodeImage,
+            NODE_LABEL_COLOR,
         },
         wsi::{Surface, Target},
         BufferId, ImageId, NodeId,
     },
     either::Either,
-    std::{cmp::min, collections::HashMap},
+    std::{
+        cmp::min,
+        collections::{HashMap, VecDeque},
+    },
 };
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -32,6 +38,60 @@ struct RenderPassSurface;
 
 type Attachment = Either<ImageId, RenderPassSurface>;
 
+/// Records render groups of the subpass into secondary buffers in parallel.
+/// Instantiated where `T: Sync` is known so that nodes don't require it.
+type RecordGroups<B, T> =
+    fn(
+        &Factory<B>,
+        &CommandPoolManager<B>,
+        FamilyId,
+        rendy_core::hal::pass::Subpass<'_, B>,
+        &<B as Backend>::Framebuffer,
+        &mut [Box<dyn RenderGroup<B, T>>],
+        usize,
+        &T,
+        usize,
+    ) -> Result<ParallelSecondaries<B>, rendy_core::hal::device::OutOfMemory>;
+
+fn record_groups<B, T>(
+    factory: &Factory<B>,
+    pools: &CommandPoolManager<B>,
+    family: FamilyId,
+    subpass: rendy_core::hal::pass::Subpass<'_, B>,
+    framebuffer: &B::Framebuffer,
+    groups: &mut [Box<dyn RenderGroup<B, T>>],
+    index: usize,
+    aux: &T,
+    threads: usize,
+) -> Result<ParallelSecondaries<B>, rendy_core::hal::device::OutOfMemory>
+where
+    B: Backend,
+    T: Sync + ?Sized,
+{
+    let subpass_index = subpass.index;
+    let main_pass = subpass.main_pass;
+    record_parallel(
+        factory.device(),
+        pools,
+        family,
+        subpass,
+        Some(framebuffer),
+        groups,
+        threads,
+        |group, encoder| {
+            group.draw_inline(
+                encoder,
+                index,
+                rendy_core::hal::pass::Subpass {
+                    index: subpass_index,
+                    main_pass,
+                },
+                aux,
+            )
+        },
+    )
+}
+
 /// Build for rendering sub-pass.
 pub struct SubpassBuilder<B: Backend, T: ?Sized> {
     groups: Vec<Box<dyn RenderGroupBuilder<B, T>>>,
@@ -39,6 +99,7 @@ pub struct SubpassBuilder<B: Backend, T: ?Sized> {
     colors: Vec<Attachment>,
     depth_stencil: Option<Attachment>,
     dependencies: Vec<NodeId>,
+    parallel: Option<(usize, RecordGroups<B, T>)>,
 }
 
 impl<B, T> std::fmt::Debug for SubpassBuilder<B, T>
@@ -53,6 +114,7 @@ where
             .field("colors", &self.colors)
             .field("depth_stencil", &self.depth_stencil)
             .field("dependencies", &self.dependencies)
+            .field("parallel", &self.parallel.map(|(threads, _)| threads))
             .finish()
     }
 }
@@ -69,6 +131,7 @@ where
             colors: Vec::default(),
             depth_stencil: None,
             dependencies: Vec::default(),
+            parallel: None,
         }
     }
 }
@@ -187,6 +250,30 @@ where
         self
     }
 
+    /// Record render groups of this subpass into up to `threads` secondary command buffers
+    /// instead of recording them inline.
+    /// Buffers are recorded concurrently on the global `rayon` thread pool.
+    /// Render pass with such subpass is re-recorded every frame.
+    pub fn set_parallel_recording(&mut self, threads: usize) -> &mut Self
+    where
+        T: Sync,
+    {
+        self.parallel = Some((threads, record_groups::<B, T>));
+        self
+    }
+
+    /// Record render groups of this subpass into up to `threads` secondary command buffers
+    /// instead of recording them inline.
+    /// Buffers are recorded concurrently on the global `rayon` thread pool.
+    /// Render pass with such subpass is re-recorded every frame.
+    pub fn with_parallel_recording(mut self, threads: usize) -> Self
+    where
+        T: Sync,
+    {
+        self.set_parallel_recording(threads);
+        self
+    }
+
     /// Make render pass from subpass.
     pub fn into_pass(self) -> RenderPassNodeBuilder<B, T> {
         RenderPassNodeBuilder::new().with_subpass(self)
@@ -195,6 +282,7 @@ where
 
 /// Builder for render-pass node.
 pub struct RenderPassNodeBuilder<B: Backend, T: ?Sized> {
+    name: Option<String>,
     subpasses: Vec<SubpassBuilder<B, T>>,
     surface: Option<(
         Surface<B>,
@@ -210,6 +298,7 @@ where
 {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         fmt.debug_struct("RenderPassNodeBuilder")
+            .field("name", &self.name)
             .field("subpasses", &self.subpasses)
             .field("surface", &self.surface)
             .finish()
@@ -223,6 +312,7 @@ where
 {
     fn default() -> Self {
         RenderPassNodeBuilder {
+            name: None,
             subpasses: Vec::default(),
             surface: None,
         }
@@ -239,6 +329,20 @@ where
         Self::default()
     }
 
+    /// Set name of the render-pass node.
+    /// Commands of named node are recorded inside debug group with this name.
+    pub fn set_name(&mut self, name: impl Into<String>) -> &mut Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set name of the render-pass node.
+    /// Commands of named node are recorded inside debug group with this name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.set_name(name);
+        self
+    }
+
     /// Add sub-pass to the render-pass.
     pub fn add_subpass(&mut self, subpass: SubpassBuilder<B, T>) -> &mut Self {
         self.subpasses.push(subpass);
@@ -400,6 +504,7 @@ where
     ) -> Result<Box<dyn DynNode<B, T>>, NodeBuildError> {
         use rendy_core::hal::window::PresentMode;
 
+        let name = self.name;
         let mut surface_color_usage = false;
         let mut surface_depth_usage = false;
 
@@ -757,6 +862,9 @@ where
 
         let command_cirque = CommandCirque::new();
 
+        let parallel_pools = CommandPoolManager::new(factory.device());
+        parallel_pools.register(family);
+
         let acquire = if uses_pipeline_barriers::<B>(factory.device()) {
             let (stages, barriers) = gfx_acquire_barriers(ctx, &buffers, &images);
 
@@ -816,6 +924,7 @@ where
             .map(|(index, subpass)| {
                 let subpass_colors = subpass.colors.len();
                 let subpass_depth = subpass.depth_stencil.is_some();
+                let parallel = subpass.parallel;
 
                 subpass
                     .groups
@@ -866,7 +975,7 @@ where
                         )
                     })
                     .collect::<Result<Vec<_>, _>>()
-                    .map(|groups| SubpassNode { groups })
+                    .map(|groups| SubpassNode { groups, parallel })
             })
             .collect::<Result<Vec<_>, _>>()
             .map_err(NodeBuildError::Pipeline)?;
@@ -876,6 +985,7 @@ where
                 log::debug!("Construct RenderPassNodeWithSurface");
                 Box::new(RenderPassNodeWithSurface {
                     common: RenderPassNodeCommon {
+                        name,
                         subpasses,
 
                         framebuffer_width,
@@ -889,6 +999,9 @@ where
                         command_pool,
                         command_cirque,
 
+                        parallel_pools,
+                        parallel_epochs: VecDeque::new(),
+
                         acquire,
                         release,
 
@@ -912,6 +1025,7 @@ where
                 log::debug!("Construct RenderPassNodeWithoutSurface");
                 Box::new(RenderPassNodeWithoutSurface {
                     common: RenderPassNodeCommon {
+                        name,
                         subpasses,
 
                         framebuffer_width,
@@ -925,6 +1039,9 @@ where
                         command_pool,
                         command_cirque,
 
+                        parallel_pools,
+                        parallel_epochs: VecDeque::new(),
+
                         acquire,
                         release,
 
@@ -946,6 +1063,9 @@ where
 struct SubpassNode<B: Backend, T: ?Sized> {
     /// RenderGroups of pipelines to exeucte withing subpass.
     groups: Vec<Box<dyn RenderGroup<B, T>>>,
+
+    /// Number of threads and function to record groups in parallel.
+    parallel: Option<(usize, RecordGroups<B, T>)>,
 }
 
 impl<B, T> std::fmt::Debug for SubpassNode<B, T>
@@ -956,6 +1076,7 @@ where
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         fmt.debug_struct("SubpassNode")
             .field("groups", &self.groups)
+            .field("parallel", &self.parallel.map(|(threads, _)| threads))
             .finish()
     }
 }
@@ -984,6 +1105,7 @@ where
 }
 
 struct RenderPassNodeCommon<B: Backend, T: ?Sized> {
+    name: Option<String>,
     subpasses: Vec<SubpassNode<B, T>>,
 
     framebuffer_width: u32,
@@ -997,6 +1119,9 @@ struct RenderPassNodeCommon<B: Backend, T: ?Sized> {
     command_pool: CommandPool<B, Graphics, IndividualReset>,
     command_cirque: CommandCirque<B, Graphics>,
 
+    parallel_pools: CommandPoolManager<B>,
+    parallel_epochs: VecDeque<(Frame, FenceEpoch)>,
+
     acquire: Option<BarriersCommands<B>>,
     release: Option<BarriersCommands<B>>,
 
@@ -1010,6 +1135,7 @@ where
 {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         fmt.debug_struct("RenderPassNodeCommon")
+            .field("name", &self.name)
             .field("subpasses", &self.subpasses)
             .field("framebuffer_width", &self.framebuffer_width)
             .field("framebuffer_height", &self.framebuffer_height)
@@ -1019,6 +1145,8 @@ where
             .field("clears", &self.clears)
             .field("command_pool", &self.command_pool)
             .field("command_cirque", &self.command_cirque)
+            .field("parallel_pools", &self.parallel_pools)
+            .field("parallel_epochs", &self.parallel_epochs)
             .field("acquire", &self.acquire)
             .field("release", &self.release)
             .field("relevant", &self.relevant)
@@ -1060,6 +1188,7 @@ where
             pool.free_buffers(Some(executable));
         }
         factory.destroy_command_pool(self.command_pool.with_queue_type());
+        self.parallel_pools.dispose(factory.device());
 
         for view in self.views {
             factory.device().destroy_image_view(view);
@@ -1117,6 +1246,7 @@ where
         let RenderPassNodeWithSurface {
             common:
                 RenderPassNodeCommon {
+                    name,
                     subpasses,
 
                     framebuffer_width,
@@ -1128,6 +1258,9 @@ where
                     command_cirque,
                     command_pool,
 
+                    parallel_pools,
+                    parallel_epochs,
+
                     acquire,
                     release,
                     ..
@@ -1149,6 +1282,11 @@ where
             }
         };
 
+        complete_parallel_epochs(parallel_pools, parallel_epochs, frames);
+        let family = queue.id().family;
+        let parallel = subpasses.iter().any(|subpass| subpass.parallel.is_some());
+        let mut secondaries = Vec::new();
+
         let submit = command_cirque.encode(frames, command_pool, |mut cbuf| {
             let index = cbuf.index();
 
@@ -1179,16 +1317,33 @@ where
                     },
                 );
 
-                if force_record || for_image.index != index {
+                if force_record || parallel || for_image.index != index {
                     for_image.index = index;
                     cbuf = CirqueRef::Initial(cbuf.or_reset(|cbuf| cbuf.reset()));
                 }
+
+                if parallel {
+                    secondaries = record_parallel_subpasses(
+                        subpasses,
+                        factory,
+                        parallel_pools,
+                        family,
+                        &render_pass,
+                        &for_image.framebuffer,
+                        index,
+                        aux,
+                    );
+                }
             }
 
             cbuf.or_init(|cbuf| {
                 let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
                 let mut encoder = cbuf.encoder();
 
+                if let Some(name) = name.as_ref() {
+                    encoder.push_debug_group(name, NODE_LABEL_COLOR);
+                }
+
                 if let Some(barriers) = &acquire {
                     encoder.execute_commands(std::iter::once(&barriers.submit));
                 }
@@ -1203,42 +1358,38 @@ where
                         h: *framebuffer_height as _,
                     };
 
-                    let mut pass_encoder = encoder.begin_render_pass_inline(
+                    secondaries.resize_with(subpasses.len(), || None);
+                    encode_render_pass(
+                        &mut encoder,
+                        subpasses,
+                        &mut secondaries,
                         &render_pass,
                         &for_image.framebuffer,
                         area,
                         &clears,
+                        index,
+                        aux,
                     );
-
-                    subpasses
-                        .iter_mut()
-                        .enumerate()
-                        .for_each(|(subpass_index, subpass)| {
-                            subpass.groups.iter_mut().for_each(|group| {
-                                group.draw_inline(
-                                    pass_encoder.reborrow(),
-                                    index,
-                                    rendy_core::hal::pass::Subpass {
-                                        index: subpass_index,
-                                        main_pass: &render_pass,
-                                    },
-                                    aux,
-                                )
-                            })
-                        });
-
-                    drop(pass_encoder);
                 }
 
                 if let Some(barriers) = &release {
                     encoder.execute_commands(std::iter::once(&barriers.submit));
                 }
+
+                if name.is_some() {
+                    encoder.pop_debug_group();
+                }
                 cbuf.finish()
             })
         });
 
         log::trace!("Submit render pass");
 
+        let epoch = FenceEpoch {
+            queue: queue.id(),
+            epoch: queue.next_epoch(),
+        };
+
         queue.submit(
             Some(
                 Submission::new()
@@ -1259,6 +1410,8 @@ where
             fence,
         );
 
+        finish_parallel_subpasses(parallel_pools, parallel_epochs, secondaries, frames, epoch);
+
         if let Some(next) = next {
             log::trace!("Present");
             let ref mut for_image = per_image[next[0] as usize];
@@ -1316,6 +1469,7 @@ where
         let RenderPassNodeWithoutSurface {
             common:
                 RenderPassNodeCommon {
+                    name,
                     subpasses,
 
                     framebuffer_width,
@@ -1327,6 +1481,9 @@ where
                     command_cirque,
                     command_pool,
 
+                    parallel_pools,
+                    parallel_epochs,
+
                     acquire,
                     release,
                     ..
@@ -1334,6 +1491,11 @@ where
             framebuffer,
         } = self;
 
+        complete_parallel_epochs(parallel_pools, parallel_epochs, frames);
+        let family = queue.id().family;
+        let parallel = subpasses.iter().any(|subpass| subpass.parallel.is_some());
+        let mut secondaries = Vec::new();
+
         let submit = command_cirque.encode(frames, command_pool, |mut cbuf| {
             let index = cbuf.index();
 
@@ -1361,14 +1523,31 @@ where
                 },
             );
 
-            if force_record {
+            if force_record || parallel {
                 cbuf = CirqueRef::Initial(cbuf.or_reset(|cbuf| cbuf.reset()));
             }
 
+            if parallel {
+                secondaries = record_parallel_subpasses(
+                    subpasses,
+                    factory,
+                    parallel_pools,
+                    family,
+                    &render_pass,
+                    framebuffer,
+                    index,
+                    aux,
+                );
+            }
+
             cbuf.or_init(|cbuf| {
                 let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
                 let mut encoder = cbuf.encoder();
 
+                if let Some(name) = name.as_ref() {
+                    encoder.push_debug_group(name, NODE_LABEL_COLOR);
+                }
+
                 if let Some(barriers) = &acquire {
                     encoder.execute_commands(std::iter::once(&barriers.submit));
                 }
@@ -1380,35 +1559,35 @@ where
                     h: *framebuffer_height as _,
                 };
 
-                let mut pass_encoder =
-                    encoder.begin_render_pass_inline(&render_pass, framebuffer, area, &clears);
-
-                subpasses
-                    .iter_mut()
-                    .enumerate()
-                    .for_each(|(subpass_index, subpass)| {
-                        subpass.groups.iter_mut().for_each(|group| {
-                            group.draw_inline(
-                                pass_encoder.reborrow(),
-                                index,
-                                rendy_core::hal::pass::Subpass {
-                                    index: subpass_index,
-                                    main_pass: &render_pass,
-                                },
-                                aux,
-                            )
-                        })
-                    });
-
-                drop(pass_encoder);
+                secondaries.resize_with(subpasses.len(), || None);
+                encode_render_pass(
+                    &mut encoder,
+                    subpasses,
+                    &mut secondaries,
+                    &render_pass,
+                    framebuffer,
+                    area,
+                    &clears,
+                    index,
+                    aux,
+                );
 
                 if let Some(barriers) = &release {
                     encoder.execute_commands(std::iter::once(&barriers.submit));
                 }
+
+                if name.is_some() {
+                    encoder.pop_debug_group();
+                }
                 cbuf.finish()
             })
         });
 
+        let epoch = FenceEpoch {
+            queue: queue.id(),
+            epoch: queue.next_epoch(),
+        };
+
         queue.submit(
             Some(
                 Submission::new()
@@ -1418,6 +1597,8 @@ where
             ),
             fence,
         );
+
+        finish_parallel_subpasses(parallel_pools, parallel_epochs, secondaries, frames, epoch);
     }
 
     unsafe fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &T) {
@@ -1426,6 +1607,182 @@ where
     }
 }
 
+/// Encoder of the current subpass.
+enum SubpassEncoder<'a, B: Backend> {
+    Inline(RenderPassInlineEncoder<'a, B>),
+    Secondary(RenderPassSecondaryEncoder<'a, B>),
+}
+
+impl<'a, B> SubpassEncoder<'a, B>
+where
+    B: Backend,
+{
+    fn next_subpass(self, secondary: bool) -> Self {
+        match (self, secondary) {
+            (SubpassEncoder::Inline(encoder), false) => {
+                SubpassEncoder::Inline(encoder.next_subpass_inline())
+            }
+            (SubpassEncoder::Inline(encoder), true) => {
+                SubpassEncoder::Secondary(encoder.next_subpass_secondary())
+            }
+            (SubpassEncoder::Secondary(encoder), false) => {
+                SubpassEncoder::Inline(encoder.next_subpass_inline())
+            }
+            (SubpassEncoder::Secondary(encoder), true) => {
+                SubpassEncoder::Secondary(encoder.next_subpass_secondary())
+            }
+        }
+    }
+}
+
+/// Report epochs of secondary buffers executed in complete frames.
+fn complete_parallel_epochs<B: Backend>(
+    pools: &CommandPoolManager<B>,
+    epochs: &mut VecDeque<(Frame, FenceEpoch)>,
+    frames: &Frames<B>,
+) {
+    while let Some(&(frame, epoch)) = epochs.front() {
+        if !frames.is_complete(frame) {
+            break;
+        }
+        unsafe {
+            // Frame includes fence submitted to the queue not earlier than the epoch.
+            pools.complete(epoch);
+        }
+        epochs.pop_front();
+    }
+}
+
+/// Return secondary buffers executed in the submitted frame to their pools.
+///
+/// # Safety
+///
+/// Render pass executing secondary buffers must be submitted before `epoch` of the queue.
+unsafe fn finish_parallel_subpasses<B: Backend>(
+    pools: &CommandPoolManager<B>,
+    epochs: &mut VecDeque<(Frame, FenceEpoch)>,
+    secondaries: Vec<Option<ParallelSecondaries<B>>>,
+    frames: &Frames<B>,
+    epoch: FenceEpoch,
+) {
+    let mut any = false;
+    for recorded in secondaries.into_iter().flatten() {
+        recorded.finish(pools, epoch);
+        any = true;
+    }
+    if any {
+        epochs.push_back((frames.next(), epoch));
+    }
+}
+
+/// Record subpasses that opted into parallel recording.
+/// Subpass that failed to record in parallel is recorded inline.
+fn record_parallel_subpasses<B, T>(
+    subpasses: &mut [SubpassNode<B, T>],
+    factory: &Factory<B>,
+    pools: &CommandPoolManager<B>,
+    family: FamilyId,
+    render_pass: &B::RenderPass,
+    framebuffer: &B::Framebuffer,
+    index: usize,
+    aux: &T,
+) -> Vec<Option<ParallelSecondaries<B>>>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    subpasses
+        .iter_mut()
+        .enumerate()
+        .map(|(subpass_index, subpass)| {
+            let (threads, record) = subpass.parallel?;
+            match record(
+                factory,
+                pools,
+                family,
+                rendy_core::hal::pass::Subpass {
+                    index: subpass_index,
+                    main_pass: render_pass,
+                },
+                framebuffer,
+                &mut subpass.groups,
+                index,
+                aux,
+                threads,
+            ) {
+                Ok(secondaries) => Some(secondaries),
+                Err(err) => {
+                    log::warn!(
+                        "Failed to record subpass {} in parallel: {:?}",
+                        subpass_index,
+                        err
+                    );
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Record render pass.
+/// Subpasses with secondary buffers execute them, other subpasses are drawn inline.
+fn encode_render_pass<B, T>(
+    encoder: &mut Encoder<'_, B, Graphics, PrimaryLevel>,
+    subpasses: &mut [SubpassNode<B, T>],
+    secondaries: &mut [Option<ParallelSecondaries<B>>],
+    render_pass: &B::RenderPass,
+    framebuffer: &B::Framebuffer,
+    area: rendy_core::hal::pso::Rect,
+    clears: &[rendy_core::hal::command::ClearValue],
+    index: usize,
+    aux: &T,
+) where
+    B: Backend,
+    T: ?Sized,
+{
+    let mut pass_encoder = if secondaries.first().map_or(false, Option::is_some) {
+        SubpassEncoder::Secondary(encoder.begin_render_pass_secondary(
+            render_pass,
+            framebuffer,
+            area,
+            clears,
+        ))
+    } else {
+        SubpassEncoder::Inline(encoder.begin_render_pass_inline(
+            render_pass,
+            framebuffer,
+            area,
+            clears,
+        ))
+    };
+
+    for (subpass_index, (subpass, recorded)) in
+        subpasses.iter_mut().zip(secondaries.iter_mut()).enumerate()
+    {
+        if subpass_index > 0 {
+            pass_encoder = pass_encoder.next_subpass(recorded.is_some());
+        }
+
+        match (&mut pass_encoder, recorded) {
+            (SubpassEncoder::Secondary(encoder), Some(recorded)) => recorded.execute(encoder),
+            (SubpassEncoder::Inline(encoder), None) => {
+                subpass.groups.iter_mut().for_each(|group| {
+                    group.draw_inline(
+                        encoder.reborrow(),
+                        index,
+                        rendy_core::hal::pass::Subpass {
+                            index: subpass_index,
+                            main_pass: render_pass,
+                        },
+                        aux,
+                    )
+                })
+            }
+            _ => unreachable!("Subpass contents must match recorded secondary buffers"),
+        }
+    }
+}
+
 fn common_layout(acc: Layout, layout: Layout) -> Layout {
     match (acc, layout) {
         (Layout::Undefined, layout) => layout,
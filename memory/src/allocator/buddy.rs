@@ -0,0 +1,649 @@
+use std::{collections::BTreeSet, ops::Range, ptr::NonNull, thread};
+
+use {
+    crate::{
+        allocator::{Allocator, Kind},
+        block::Block,
+        mapping::*,
+        memory::*,
+        util::*,
+        utilization::AllocatorUtilization,
+    },
+    gfx_hal::{device::Device as _, Backend},
+};
+
+/// Memory block allocated from `BuddyAllocator`
+#[derive(Debug)]
+pub struct BuddyBlock<B: Backend> {
+    chunk_index: u32,
+    order: u32,
+    memory: *const Memory<B>,
+    ptr: Option<NonNull<u8>>,
+    range: Range<u64>,
+    relevant: relevant::Relevant,
+}
+
+unsafe impl<B> Send for BuddyBlock<B> where B: Backend {}
+unsafe impl<B> Sync for BuddyBlock<B> where B: Backend {}
+
+impl<B> BuddyBlock<B>
+where
+    B: Backend,
+{
+    fn shared_memory(&self) -> &Memory<B> {
+        // Memory won't be freed until last block created from it deallocated.
+        unsafe { &*self.memory }
+    }
+
+    fn dispose(self) {
+        self.relevant.dispose();
+    }
+}
+
+impl<B> Block<B> for BuddyBlock<B>
+where
+    B: Backend,
+{
+    #[inline]
+    fn properties(&self) -> gfx_hal::memory::Properties {
+        self.shared_memory().properties()
+    }
+
+    #[inline]
+    fn memory(&self) -> &B::Memory {
+        self.shared_memory().raw()
+    }
+
+    #[inline]
+    fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    #[inline]
+    fn map<'a>(
+        &'a mut self,
+        _device: &B::Device,
+        range: Range<u64>,
+    ) -> Result<MappedRange<'a, B>, gfx_hal::device::MapError> {
+        debug_assert!(
+            range.start < range.end,
+            "Memory mapping region must have valid size"
+        );
+        if !self.shared_memory().host_visible() {
+            //TODO: invalid access error
+            return Err(gfx_hal::device::MapError::MappingFailed);
+        }
+
+        if let Some(ptr) = self.ptr {
+            if let Some((ptr, range)) = mapped_sub_range(ptr, self.range.clone(), range) {
+                let mapping = unsafe { MappedRange::from_raw(self.shared_memory(), ptr, range) };
+                Ok(mapping)
+            } else {
+                Err(gfx_hal::device::MapError::OutOfBounds)
+            }
+        } else {
+            Err(gfx_hal::device::MapError::MappingFailed)
+        }
+    }
+
+    #[inline]
+    fn unmap(&mut self, _device: &B::Device) {}
+}
+
+/// Config for `BuddyAllocator`.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BuddyConfig {
+    /// Size of the smallest block.
+    /// All requests are rounded up to power of two not less than this value.
+    pub min_block_size: u64,
+
+    /// Size of the memory object allocated from device.
+    /// This is also the largest block allocator can return.
+    pub chunk_size: u64,
+}
+
+/// Buddy allocator.
+/// Splits big memory objects into power-of-two sized blocks
+/// and merges freed blocks back with their buddies.
+///
+/// This allocator suites best medium-sized allocations
+/// that are too big for `DynamicAllocator`
+/// but too small to justify dedicated memory object.
+#[derive(Debug)]
+pub struct BuddyAllocator<B: Backend> {
+    /// Memory type that this allocator allocates.
+    memory_type: gfx_hal::MemoryTypeId,
+
+    /// Memory properties of the memory type.
+    memory_properties: gfx_hal::memory::Properties,
+
+    /// Size of the smallest block.
+    min_block_size: u64,
+
+    /// Size of the memory object allocated from device.
+    chunk_size: u64,
+
+    /// Allocated chunks.
+    chunks: slab::Slab<BuddyChunk<B>>,
+}
+
+unsafe impl<B> Send for BuddyAllocator<B> where B: Backend {}
+unsafe impl<B> Sync for BuddyAllocator<B> where B: Backend {}
+
+#[derive(Debug)]
+struct BuddyChunk<B: Backend> {
+    memory: Box<Memory<B>>,
+    ptr: Option<NonNull<u8>>,
+    buddies: Buddies,
+}
+
+impl<B> BuddyAllocator<B>
+where
+    B: Backend,
+{
+    /// Create new `BuddyAllocator`
+    /// for `memory_type` with `memory_properties` specified,
+    /// with `BuddyConfig` provided.
+    pub fn new(
+        memory_type: gfx_hal::MemoryTypeId,
+        memory_properties: gfx_hal::memory::Properties,
+        config: BuddyConfig,
+    ) -> Self {
+        log::trace!(
+            "Create new 'buddy' allocator: type: '{:?}', properties: '{:#?}' config: '{:#?}'",
+            memory_type,
+            memory_properties,
+            config
+        );
+
+        assert!(
+            config.min_block_size.is_power_of_two(),
+            "Min block size must be power of two"
+        );
+
+        assert!(
+            config.chunk_size.is_power_of_two(),
+            "Chunk size must be power of two"
+        );
+
+        assert!(
+            config.min_block_size <= config.chunk_size,
+            "Min block size must be less than or equal to chunk size"
+        );
+
+        if memory_properties.contains(gfx_hal::memory::Properties::CPU_VISIBLE) {
+            assert!(
+                fits_usize(config.chunk_size),
+                "Chunk size must fit usize for mapping"
+            );
+        }
+
+        BuddyAllocator {
+            memory_type,
+            memory_properties,
+            min_block_size: config.min_block_size,
+            chunk_size: config.chunk_size,
+            chunks: slab::Slab::new(),
+        }
+    }
+
+    /// Maximum allocation size.
+    pub fn max_allocation(&self) -> u64 {
+        self.chunk_size
+    }
+
+    /// Get fragmentation stats.
+    pub fn utilization(&self) -> AllocatorUtilization {
+        AllocatorUtilization {
+            kind: Kind::Buddy,
+            device_allocations: self.chunks.len() as u64,
+            largest_free_range: self
+                .chunks
+                .iter()
+                .map(|(_, chunk)| chunk.buddies.largest_free())
+                .max()
+                .unwrap_or(0),
+            size_classes: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    /// Allocate memory chunk from device.
+    fn alloc_chunk(
+        &self,
+        device: &B::Device,
+    ) -> Result<BuddyChunk<B>, gfx_hal::device::AllocationError> {
+        log::trace!("Allocate chunk of size: {} from device", self.chunk_size);
+
+        let (memory, ptr) = unsafe {
+            // Valid memory type specified.
+            let raw = device.allocate_memory(self.memory_type, self.chunk_size)?;
+
+            let ptr = if self
+                .memory_properties
+                .contains(gfx_hal::memory::Properties::CPU_VISIBLE)
+            {
+                log::trace!("Map new memory object");
+                match device.map_memory(&raw, 0..self.chunk_size) {
+                    Ok(ptr) => Some(NonNull::new_unchecked(ptr)),
+                    Err(gfx_hal::device::MapError::OutOfMemory(error)) => {
+                        device.free_memory(raw);
+                        return Err(error.into());
+                    }
+                    Err(err) => panic!("Unexpected mapping failure: {:?}", err),
+                }
+            } else {
+                None
+            };
+            let memory = Memory::from_raw(raw, self.chunk_size, self.memory_properties);
+            (memory, ptr)
+        };
+
+        Ok(BuddyChunk {
+            memory: Box::new(memory),
+            ptr,
+            buddies: Buddies::new(self.min_block_size, self.chunk_size),
+        })
+    }
+
+    /// Free memory chunk to the device.
+    fn free_chunk(&self, device: &B::Device, chunk: BuddyChunk<B>) -> u64 {
+        log::trace!("Free chunk: {:#?}", chunk);
+        debug_assert!(chunk.buddies.is_unused());
+        let size = chunk.memory.size();
+        unsafe {
+            if chunk.ptr.is_some() {
+                device.unmap_memory(chunk.memory.raw());
+            }
+            device.free_memory(chunk.memory.into_raw());
+        }
+        size
+    }
+
+    /// Allocate block of given `order` from particular chunk.
+    fn alloc_from_chunk(
+        chunks: &mut slab::Slab<BuddyChunk<B>>,
+        chunk_index: usize,
+        order: u32,
+    ) -> Option<BuddyBlock<B>> {
+        let chunk = &mut chunks[chunk_index];
+        let offset = chunk.buddies.acquire(order)?;
+        let range = offset..offset + chunk.buddies.order_size(order);
+
+        Some(BuddyBlock {
+            chunk_index: chunk_index as u32,
+            order,
+            memory: &*chunk.memory,
+            ptr: chunk.ptr.map(|ptr| {
+                mapped_fitting_range(ptr, 0..chunk.memory.size(), range.clone())
+                    .expect("Block must be sub-range of chunk")
+            }),
+            range,
+            relevant: relevant::Relevant,
+        })
+    }
+
+    /// Perform full cleanup of the memory allocated.
+    pub fn dispose(self) {
+        if !thread::panicking() {
+            assert_eq!(self.chunks.len(), 0, "Not all blocks were freed");
+        } else if !self.chunks.is_empty() {
+            log::error!(
+                "Memory leak: {} buddy chunks are still used",
+                self.chunks.len()
+            );
+        }
+    }
+}
+
+impl<B> Allocator<B> for BuddyAllocator<B>
+where
+    B: Backend,
+{
+    type Block = BuddyBlock<B>;
+
+    fn kind() -> Kind {
+        Kind::Buddy
+    }
+
+    fn alloc(
+        &mut self,
+        device: &B::Device,
+        size: u64,
+        align: u64,
+    ) -> Result<(BuddyBlock<B>, u64), gfx_hal::device::AllocationError> {
+        debug_assert!(size <= self.max_allocation());
+        debug_assert!(align.is_power_of_two());
+
+        // Blocks are aligned to their size relative to chunk start.
+        let block_size = size.max(align).max(self.min_block_size).next_power_of_two();
+        if block_size > self.chunk_size {
+            log::error!(
+                "Buddy block of size {} can't fit chunk of size {} (requested size: {}, align: {})",
+                block_size,
+                self.chunk_size,
+                size,
+                align
+            );
+            return Err(gfx_hal::device::OutOfMemory::Device.into());
+        }
+        let order = (block_size / self.min_block_size).trailing_zeros();
+
+        log::trace!(
+            "Allocate buddy block: size: {}, align: {}, block size: {}, type: {}",
+            size,
+            align,
+            block_size,
+            self.memory_type.0
+        );
+
+        let ready = self
+            .chunks
+            .iter()
+            .find(|(_, chunk)| chunk.buddies.has_free(order))
+            .map(|(index, _)| index);
+
+        if let Some(chunk_index) = ready {
+            let block = Self::alloc_from_chunk(&mut self.chunks, chunk_index, order)
+                .expect("Chunk reported free block of this order");
+            return Ok((block, 0));
+        }
+
+        let chunk = self.alloc_chunk(device)?;
+        let chunk_index = self.chunks.insert(chunk);
+        assert!(fits_u32(chunk_index));
+        let block = Self::alloc_from_chunk(&mut self.chunks, chunk_index, order)
+            .expect("New chunk should yield block");
+        Ok((block, self.chunk_size))
+    }
+
+    fn free(&mut self, device: &B::Device, block: BuddyBlock<B>) -> u64 {
+        log::trace!("Free block: {:#?}", block);
+
+        let chunk_index = block.chunk_index as usize;
+        let offset = block.range.start;
+        let order = block.order;
+        block.dispose();
+
+        let chunk = &mut self.chunks[chunk_index];
+        chunk.buddies.release(offset, order);
+        if chunk.buddies.is_unused() {
+            let chunk = self.chunks.remove(chunk_index);
+            self.free_chunk(device, chunk)
+        } else {
+            0
+        }
+    }
+}
+
+/// Device-independent buddy bookkeeping for one chunk.
+/// Order `n` blocks are `min_block_size << n` bytes long
+/// and are aligned to their size.
+#[derive(Debug)]
+struct Buddies {
+    min_block_size: u64,
+
+    /// Offsets of free blocks per order.
+    free: Vec<BTreeSet<u64>>,
+}
+
+impl Buddies {
+    fn new(min_block_size: u64, size: u64) -> Self {
+        debug_assert!(min_block_size.is_power_of_two());
+        debug_assert!(size.is_power_of_two());
+        debug_assert!(min_block_size <= size);
+
+        let max_order = (size / min_block_size).trailing_zeros() as usize;
+        let mut free = vec![BTreeSet::new(); max_order + 1];
+        free[max_order].insert(0);
+
+        Buddies {
+            min_block_size,
+            free,
+        }
+    }
+
+    fn max_order(&self) -> u32 {
+        (self.free.len() - 1) as u32
+    }
+
+    fn order_size(&self, order: u32) -> u64 {
+        self.min_block_size << order
+    }
+
+    /// Check if block of `order` can be acquired.
+    fn has_free(&self, order: u32) -> bool {
+        order <= self.max_order()
+            && self.free[order as usize..]
+                .iter()
+                .any(|free| !free.is_empty())
+    }
+
+    /// Get size of the biggest free block.
+    fn largest_free(&self) -> u64 {
+        (0..=self.max_order())
+            .rev()
+            .find(|&order| !self.free[order as usize].is_empty())
+            .map_or(0, |order| self.order_size(order))
+    }
+
+    /// Check if whole chunk is free.
+    fn is_unused(&self) -> bool {
+        self.free[self.max_order() as usize].contains(&0)
+    }
+
+    /// Acquire block of `order`.
+    /// Splits bigger block if there is no free block of this `order`.
+    fn acquire(&mut self, order: u32) -> Option<u64> {
+        if order > self.max_order() {
+            return None;
+        }
+
+        let found = (order..=self.max_order()).find(|&o| !self.free[o as usize].is_empty())?;
+        let offset = *self.free[found as usize].iter().next().unwrap();
+        self.free[found as usize].remove(&offset);
+
+        // Split and put upper halves back.
+        for o in (order..found).rev() {
+            let buddy = offset + self.order_size(o);
+            let inserted = self.free[o as usize].insert(buddy);
+            debug_assert!(inserted);
+        }
+
+        Some(offset)
+    }
+
+    /// Release block of `order` at `offset`.
+    /// Merges it with free buddies.
+    fn release(&mut self, mut offset: u64, mut order: u32) {
+        debug_assert_eq!(offset % self.order_size(order), 0);
+
+        while order < self.max_order() {
+            let buddy = offset ^ self.order_size(order);
+            if !self.free[order as usize].remove(&buddy) {
+                break;
+            }
+            offset = offset.min(buddy);
+            order += 1;
+        }
+
+        let inserted = self.free[order as usize].insert(offset);
+        debug_assert!(inserted, "Block was freed twice");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Buddies, BuddyAllocator, BuddyConfig};
+    use crate::{allocator::Allocator as _, block::Block as _, fake};
+    use gfx_hal::{memory::Properties, MemoryTypeId};
+    use rand::{seq::SliceRandom as _, Rng as _};
+
+    fn allocator(properties: Properties) -> BuddyAllocator<fake::Backend> {
+        BuddyAllocator::new(
+            MemoryTypeId(0),
+            properties,
+            BuddyConfig {
+                min_block_size: 256,
+                chunk_size: 4096,
+            },
+        )
+    }
+
+    #[test]
+    fn blocks_share_chunk() {
+        let device = fake::Device::new();
+        let mut allocator = allocator(Properties::DEVICE_LOCAL);
+
+        let (a, allocated) = allocator.alloc(&device, 1000, 1).unwrap();
+        assert_eq!(allocated, 4096);
+        assert_eq!(a.range(), 0..1024);
+
+        let (b, allocated) = allocator.alloc(&device, 100, 256).unwrap();
+        assert_eq!(allocated, 0);
+        assert_eq!(b.range(), 1024..1280);
+        assert_eq!(device.allocations(), 1);
+        assert_eq!(allocator.utilization().device_allocations, 1);
+
+        assert_eq!(allocator.free(&device, a), 0);
+        assert_eq!(allocator.free(&device, b), 4096);
+        assert_eq!(device.allocations(), 0);
+        assert_eq!(device.allocated(), 0);
+        allocator.dispose();
+    }
+
+    #[test]
+    fn full_chunk_allocates_another() {
+        let device = fake::Device::new();
+        let mut allocator = allocator(Properties::DEVICE_LOCAL);
+
+        let (a, _) = allocator.alloc(&device, 4096, 1).unwrap();
+        let (b, allocated) = allocator.alloc(&device, 1, 1).unwrap();
+        assert_eq!(allocated, 4096);
+        assert_eq!(device.allocations(), 2);
+
+        assert_eq!(allocator.free(&device, b), 4096);
+        assert_eq!(allocator.free(&device, a), 4096);
+        assert_eq!(device.allocated(), 0);
+        allocator.dispose();
+    }
+
+    #[test]
+    fn blocks_of_mapped_chunk_are_mapped() {
+        let device = fake::Device::new();
+        let mut allocator = allocator(Properties::CPU_VISIBLE | Properties::COHERENT);
+
+        let (mut a, _) = allocator.alloc(&device, 256, 1).unwrap();
+        let (mut b, _) = allocator.alloc(&device, 256, 1).unwrap();
+        let a_ptr = a.map(&device, 0..256).unwrap().ptr();
+        let b_ptr = b.map(&device, 0..256).unwrap().ptr();
+        assert_eq!(b_ptr.as_ptr() as usize - a_ptr.as_ptr() as usize, 256);
+        assert!(b.map(&device, 0..512).is_err());
+
+        allocator.free(&device, a);
+        allocator.free(&device, b);
+        allocator.dispose();
+    }
+
+    #[test]
+    fn device_out_of_memory() {
+        let device = fake::Device::with_limit(4096);
+        let mut allocator = allocator(Properties::DEVICE_LOCAL);
+
+        let (a, _) = allocator.alloc(&device, 4096, 1).unwrap();
+        assert!(allocator.alloc(&device, 1, 1).is_err());
+        assert_eq!(allocator.utilization().device_allocations, 1);
+
+        allocator.free(&device, a);
+        allocator.dispose();
+    }
+
+    #[test]
+    fn alignment_larger_than_chunk_fails() {
+        let device = fake::Device::new();
+        let mut allocator = allocator(Properties::DEVICE_LOCAL);
+
+        assert!(allocator.alloc(&device, 16, 8192).is_err());
+        assert_eq!(device.allocations(), 0);
+        allocator.dispose();
+    }
+
+    #[test]
+    #[should_panic(expected = "Not all blocks were freed")]
+    fn dispose_with_used_blocks() {
+        let device = fake::Device::new();
+        let mut allocator = allocator(Properties::DEVICE_LOCAL);
+        let (block, _) = allocator.alloc(&device, 1, 1).unwrap();
+        std::mem::forget(block);
+        allocator.dispose();
+    }
+
+    #[test]
+    fn split_and_coalesce() {
+        let mut buddies = Buddies::new(256, 4096);
+        assert_eq!(buddies.max_order(), 4);
+
+        let a = buddies.acquire(0).unwrap();
+        let b = buddies.acquire(0).unwrap();
+        let c = buddies.acquire(1).unwrap();
+        assert_eq!((a, b, c), (0, 256, 512));
+        assert!(!buddies.is_unused());
+
+        buddies.release(a, 0);
+        buddies.release(c, 1);
+        assert!(!buddies.is_unused());
+
+        buddies.release(b, 0);
+        assert!(buddies.is_unused());
+    }
+
+    #[test]
+    fn exhaustion() {
+        let mut buddies = Buddies::new(1024, 8192);
+        let blocks = (0..8)
+            .map(|_| buddies.acquire(0).unwrap())
+            .collect::<Vec<_>>();
+        assert!(!buddies.has_free(0));
+        assert_eq!(buddies.acquire(0), None);
+        assert_eq!(buddies.acquire(4), None);
+
+        for block in blocks {
+            buddies.release(block, 0);
+        }
+        assert!(buddies.is_unused());
+        assert_eq!(buddies.acquire(3), Some(0));
+    }
+
+    #[test]
+    fn random_blocks_are_aligned_and_disjoint() {
+        let mut rng = rand::thread_rng();
+        let mut buddies = Buddies::new(64, 1 << 20);
+        let mut live: Vec<(u64, u32)> = Vec::new();
+
+        for _ in 0..10000 {
+            if live.is_empty() || rng.gen_bool(0.6) {
+                let order = rng.gen_range(0, 8);
+                if let Some(offset) = buddies.acquire(order) {
+                    let size = buddies.order_size(order);
+                    assert_eq!(offset % size, 0);
+                    assert!(offset + size <= 1 << 20);
+                    for &(other, other_order) in &live {
+                        let other_size = buddies.order_size(other_order);
+                        assert!(offset + size <= other || other + other_size <= offset);
+                    }
+                    live.push((offset, order));
+                }
+            } else {
+                live.shuffle(&mut rng);
+                let (offset, order) = live.pop().unwrap();
+                buddies.release(offset, order);
+            }
+        }
+
+        for (offset, order) in live {
+            buddies.release(offset, order);
+        }
+        assert!(buddies.is_unused());
+    }
+}
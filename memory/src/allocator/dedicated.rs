@@ -6,6 +6,7 @@ use {
         block::Block,
         mapping::{mapped_fitting_range, MappedRange},
         memory::*,
+        utilization::AllocatorUtilization,
     },
     gfx_hal::{device::Device as _, Backend},
 };
@@ -115,6 +116,7 @@ pub struct DedicatedAllocator {
     memory_type: gfx_hal::MemoryTypeId,
     memory_properties: gfx_hal::memory::Properties,
     used: u64,
+    count: u64,
 }
 
 impl DedicatedAllocator {
@@ -133,6 +135,18 @@ impl DedicatedAllocator {
             memory_type,
             memory_properties,
             used: 0,
+            count: 0,
+        }
+    }
+
+    /// Get fragmentation stats.
+    pub fn utilization(&self) -> AllocatorUtilization {
+        AllocatorUtilization {
+            kind: Kind::Dedicated,
+            device_allocations: self.count,
+            largest_free_range: 0,
+            size_classes: Vec::new(),
+            lines: Vec::new(),
         }
     }
 }
@@ -163,6 +177,7 @@ where
         };
 
         self.used += size;
+        self.count += 1;
 
         Ok((DedicatedBlock::from_memory(memory), size))
     }
@@ -172,6 +187,7 @@ where
         block.unmap(device);
         let size = block.memory.size();
         self.used -= size;
+        self.count -= 1;
         unsafe {
             device.free_memory(block.memory.into_raw());
         }
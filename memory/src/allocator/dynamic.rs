@@ -1,5 +1,5 @@
 use std::{
-    collections::{BTreeSet, HashMap},
+    collections::{BTreeSet, HashMap, HashSet},
     ops::Range,
     ptr::NonNull,
     thread,
@@ -12,6 +12,7 @@ use {
         mapping::*,
         memory::*,
         util::*,
+        utilization::{AllocatorUtilization, SizeClassUtilization},
     },
     gfx_hal::{device::Device as _, Backend},
     hibitset::{BitSet, BitSetLike as _},
@@ -140,6 +141,10 @@ pub struct DynamicAllocator<B: Backend> {
 
     /// Ordered set of sizes that have allocated chunks.
     chunks: BTreeSet<u64>,
+
+    /// Chunks being evacuated by defragmentation.
+    /// No new blocks are allocated from them.
+    evacuating: Evacuation,
 }
 
 unsafe impl<B> Send for DynamicAllocator<B> where B: Backend {}
@@ -227,6 +232,7 @@ where
             min_device_allocation: config.min_device_allocation,
             sizes: HashMap::new(),
             chunks: BTreeSet::new(),
+            evacuating: Evacuation::default(),
         }
     }
 
@@ -373,6 +379,9 @@ where
         let size_entry = self.sizes.entry(block_size).or_default();
 
         for chunk_index in (&size_entry.ready_chunks).iter() {
+            if self.evacuating.contains(block_size, chunk_index) {
+                continue;
+            }
             if let Some(block) = Self::alloc_from_chunk(
                 &mut size_entry.chunks,
                 chunk_index,
@@ -492,6 +501,79 @@ where
         }
     }
 
+    /// Pick sparsely used chunks to evacuate their blocks into fewer chunks.
+    /// Picked chunks won't serve new allocations until `end_defragmentation` is called.
+    /// Returns number of chunks picked.
+    pub fn begin_defragmentation(&mut self) -> usize {
+        let mut picked = 0;
+        for (&block_size, size_entry) in &self.sizes {
+            picked += self.evacuating.plan(
+                block_size,
+                size_entry.chunks.iter().map(|(chunk_index, chunk)| {
+                    (
+                        chunk_index as u32,
+                        chunk.blocks_count(block_size),
+                        chunk.free_blocks(),
+                    )
+                }),
+            );
+        }
+
+        log::trace!("Picked {} chunks for evacuation", picked);
+        picked
+    }
+
+    /// Check if block belongs to the chunk picked for evacuation.
+    pub fn is_evacuating(&self, block: &DynamicBlock<B>) -> bool {
+        let block_size = block.size() / block.count as u64;
+        self.evacuating.contains(block_size, block.chunk_index)
+    }
+
+    /// Allow allocations from all chunks again.
+    pub fn end_defragmentation(&mut self) {
+        self.evacuating.clear();
+    }
+
+    /// Get fragmentation stats.
+    pub fn utilization(&self) -> AllocatorUtilization {
+        let mut device_allocations = 0;
+        let mut largest_free_range = 0;
+        let mut size_classes = Vec::new();
+
+        for (&block_size, size_entry) in &self.sizes {
+            if size_entry.chunks.is_empty() {
+                continue;
+            }
+
+            let mut blocks = 0;
+            let mut free_blocks = 0;
+            for (_, chunk) in size_entry.chunks.iter() {
+                if let ChunkFlavor::Dedicated(..) = chunk.flavor {
+                    device_allocations += 1;
+                }
+                blocks += chunk.blocks_count(block_size);
+                free_blocks += chunk.free_blocks();
+                largest_free_range = largest_free_range.max(chunk.longest_free_run() * block_size);
+            }
+
+            size_classes.push(SizeClassUtilization {
+                block_size,
+                chunks: size_entry.chunks.len() as u64,
+                blocks,
+                free_blocks,
+            });
+        }
+        size_classes.sort_by_key(|class| class.block_size);
+
+        AllocatorUtilization {
+            kind: Kind::Dynamic,
+            device_allocations,
+            largest_free_range,
+            size_classes,
+            lines: Vec::new(),
+        }
+    }
+
     /// Perform full cleanup of the memory allocated.
     pub fn dispose(self) {
         if !thread::panicking() {
@@ -626,6 +708,27 @@ where
         self.blocks == mask
     }
 
+    /// Get number of blocks in the chunk.
+    fn blocks_count(&self, block_size: u64) -> u64 {
+        (self.size() / block_size).min(MAX_BLOCKS_PER_CHUNK as u64)
+    }
+
+    /// Get number of free blocks in the chunk.
+    fn free_blocks(&self) -> u64 {
+        self.blocks.count_ones() as u64
+    }
+
+    /// Get length of the longest run of free blocks in the chunk.
+    fn longest_free_run(&self) -> u64 {
+        let mut blocks = self.blocks;
+        let mut run = 0;
+        while blocks != 0 {
+            blocks &= blocks >> 1;
+            run += 1;
+        }
+        run
+    }
+
     /// Check if there are free blocks.
     fn is_exhausted(&self) -> bool {
         self.blocks == 0
@@ -667,8 +770,107 @@ where
     }
 }
 
+/// Chunks picked for evacuation by defragmentation.
+#[derive(Debug, Default)]
+struct Evacuation {
+    /// Block size and index of picked chunks.
+    chunks: HashSet<(u64, u32)>,
+}
+
+impl Evacuation {
+    /// Pick sparsely used chunks of the size class.
+    /// `chunks` yields index, total blocks count and free blocks count for each chunk.
+    /// Returns number of chunks picked.
+    fn plan(
+        &mut self,
+        block_size: u64,
+        chunks: impl IntoIterator<Item = (u32, u64, u64)>,
+    ) -> usize {
+        let mut sparse = Vec::new();
+        let mut sparse_used = 0;
+        let mut dense_free = 0;
+        for (chunk_index, total, free) in chunks {
+            if (total - free) * 2 <= total {
+                sparse.push(chunk_index);
+                sparse_used += total - free;
+            } else {
+                dense_free += free;
+            }
+        }
+
+        // Evacuation pays off only if it releases more chunks than it may allocate.
+        if sparse.len() > 1 || (!sparse.is_empty() && sparse_used <= dense_free) {
+            let picked = sparse.len();
+            self.chunks.extend(
+                sparse
+                    .into_iter()
+                    .map(|chunk_index| (block_size, chunk_index)),
+            );
+            picked
+        } else {
+            0
+        }
+    }
+
+    fn contains(&self, block_size: u64, chunk_index: u32) -> bool {
+        self.chunks.contains(&(block_size, chunk_index))
+    }
+
+    fn clear(&mut self) {
+        self.chunks.clear();
+    }
+}
+
 fn max_chunks_per_size() -> usize {
     let value = (std::mem::size_of::<usize>() * 8).pow(4);
     debug_assert!(fits_u32(value));
     value
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Evacuation;
+
+    #[test]
+    fn sparse_chunks_are_planned() {
+        let mut evacuation = Evacuation::default();
+        let picked = evacuation.plan(256, vec![(0, 64, 60), (1, 64, 8), (2, 64, 40)]);
+        assert_eq!(picked, 2);
+        assert!(evacuation.contains(256, 0));
+        assert!(!evacuation.contains(256, 1));
+        assert!(evacuation.contains(256, 2));
+        assert!(!evacuation.contains(512, 0));
+    }
+
+    #[test]
+    fn single_sparse_chunk_needs_room() {
+        let mut evacuation = Evacuation::default();
+        assert_eq!(evacuation.plan(256, vec![(0, 64, 40), (1, 64, 20)]), 0);
+        assert!(!evacuation.contains(256, 0));
+
+        assert_eq!(evacuation.plan(256, vec![(0, 64, 40), (1, 64, 8)]), 0);
+        assert_eq!(evacuation.plan(256, vec![(0, 64, 60), (1, 64, 8)]), 1);
+        assert!(evacuation.contains(256, 0));
+    }
+
+    #[test]
+    fn dense_chunks_are_not_planned() {
+        let mut evacuation = Evacuation::default();
+        assert_eq!(evacuation.plan(256, vec![(0, 64, 0), (1, 64, 31)]), 0);
+        assert_eq!(evacuation.plan(256, None), 0);
+    }
+
+    #[test]
+    fn clear_ends_evacuation() {
+        let mut evacuation = Evacuation::default();
+        evacuation.plan(256, vec![(0, 64, 60), (1, 64, 60)]);
+        evacuation.plan(1024, vec![(3, 16, 12), (4, 16, 16)]);
+        assert!(evacuation.contains(256, 1));
+        assert!(evacuation.contains(1024, 4));
+
+        evacuation.clear();
+        assert!(!evacuation.contains(256, 0));
+        assert!(!evacuation.contains(256, 1));
+        assert!(!evacuation.contains(1024, 3));
+    }
+}
@@ -7,6 +7,7 @@ use {
         mapping::*,
         memory::*,
         util::*,
+        utilization::{AllocatorUtilization, LineUtilization},
     },
     gfx_hal::{device::Device as _, Backend},
     std::sync::Arc,
@@ -179,6 +180,28 @@ where
         }
     }
 
+    /// Get fragmentation stats.
+    pub fn utilization(&self) -> AllocatorUtilization {
+        AllocatorUtilization {
+            kind: Kind::Linear,
+            device_allocations: self.lines.len() as u64,
+            largest_free_range: self
+                .lines
+                .back()
+                .map_or(0, |line| self.linear_size - line.used),
+            size_classes: Vec::new(),
+            lines: self
+                .lines
+                .iter()
+                .map(|line| LineUtilization {
+                    size: self.linear_size,
+                    used: line.used,
+                    live: line.used - line.free,
+                })
+                .collect(),
+        }
+    }
+
     /// Perform full cleanup of the memory allocated.
     pub fn dispose(mut self, device: &B::Device) {
         let _ = self.cleanup(device, 0);
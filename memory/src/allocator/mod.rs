@@ -1,19 +1,28 @@
 //! This module provides `Allocator` trait and few allocators that implements the trait.
 
+mod buddy;
 mod dedicated;
 mod dynamic;
 mod linear;
+mod ring;
+mod tlsf;
 
 use crate::block::Block;
 
 pub use self::{
+    buddy::{BuddyAllocator, BuddyBlock, BuddyConfig},
     dedicated::{DedicatedAllocator, DedicatedBlock},
     dynamic::{DynamicAllocator, DynamicBlock, DynamicConfig},
     linear::{LinearAllocator, LinearBlock, LinearConfig},
+    ring::{RingAllocator, RingBlock, RingConfig},
+    tlsf::{TlsfAllocator, TlsfBlock, TlsfConfig},
 };
 
+pub(crate) use self::tlsf::Tlsf;
+
 /// Allocator kind.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Kind {
     /// Memory object per allocation.
     Dedicated,
@@ -25,6 +34,18 @@ pub enum Kind {
     /// Fast and low overhead.
     /// Suitable for one-time-use allocations.
     Linear,
+
+    /// Power-of-two blocks with coalescing on free.
+    /// Suitable for medium-sized allocations.
+    Buddy,
+
+    /// Two-Level Segregated Fit.
+    /// General purpose allocator with low fragmentation.
+    Tlsf,
+
+    /// Bumps pointer and reclaims memory by retired epochs.
+    /// Suitable for transient per-frame data.
+    Ring,
 }
 
 /// Allocator trait implemented for various allocators.
@@ -0,0 +1,465 @@
+use std::{collections::VecDeque, ops::Range, ptr::NonNull, sync::Arc};
+
+use {
+    crate::{
+        allocator::{Allocator, Kind},
+        block::Block,
+        mapping::*,
+        memory::*,
+        util::*,
+        utilization::AllocatorUtilization,
+    },
+    gfx_hal::{device::Device as _, Backend},
+};
+
+/// Memory block allocated from `RingAllocator`
+pub struct RingBlock<B: Backend> {
+    memory: Arc<Memory<B>>,
+    epoch: u64,
+    ptr: NonNull<u8>,
+    range: Range<u64>,
+    relevant: relevant::Relevant,
+}
+
+impl<B> std::fmt::Debug for RingBlock<B>
+where
+    B: Backend,
+{
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("RingBlock")
+            .field("memory", &*self.memory)
+            .field("epoch", &self.epoch)
+            .field("ptr", &self.ptr)
+            .field("range", &self.range)
+            .finish()
+    }
+}
+
+unsafe impl<B> Send for RingBlock<B> where B: Backend {}
+unsafe impl<B> Sync for RingBlock<B> where B: Backend {}
+
+impl<B> RingBlock<B>
+where
+    B: Backend,
+{
+    fn size(&self) -> u64 {
+        self.range.end - self.range.start
+    }
+
+    fn dispose(self) {
+        self.relevant.dispose();
+    }
+}
+
+impl<B> Block<B> for RingBlock<B>
+where
+    B: Backend,
+{
+    #[inline]
+    fn properties(&self) -> gfx_hal::memory::Properties {
+        self.memory.properties()
+    }
+
+    #[inline]
+    fn memory(&self) -> &B::Memory {
+        self.memory.raw()
+    }
+
+    #[inline]
+    fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    #[inline]
+    fn map<'a>(
+        &'a mut self,
+        _device: &B::Device,
+        range: Range<u64>,
+    ) -> Result<MappedRange<'a, B>, gfx_hal::device::MapError> {
+        assert!(
+            range.start < range.end,
+            "Memory mapping region must have valid size"
+        );
+
+        if let Some((ptr, range)) = mapped_sub_range(self.ptr, self.range.clone(), range) {
+            let mapping = unsafe { MappedRange::from_raw(&*self.memory, ptr, range) };
+            Ok(mapping)
+        } else {
+            Err(gfx_hal::device::MapError::OutOfBounds)
+        }
+    }
+
+    #[inline]
+    fn unmap(&mut self, _device: &B::Device) {}
+}
+
+/// Config for `RingAllocator`.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RingConfig {
+    /// Size of the memory object allocated from device.
+    pub chunk_size: u64,
+}
+
+/// Epoch-based ring allocator.
+/// Allocation is a pointer bump in the current memory object.
+/// Blocks are not reclaimed individually.
+/// Memory object is reused once all epochs it served are retired.
+///
+/// Epochs are monotonically increasing counters,
+/// like frame indices or fence epochs of the submitting queue.
+///
+/// This allocator suites transient per-frame data,
+/// like uniforms and streaming vertices written once per frame.
+#[derive(Debug)]
+pub struct RingAllocator<B: Backend> {
+    /// Memory type that this allocator allocates.
+    memory_type: gfx_hal::MemoryTypeId,
+
+    /// Memory properties of the memory type.
+    memory_properties: gfx_hal::memory::Properties,
+
+    /// Size of the memory object allocated from device.
+    chunk_size: u64,
+
+    /// Memory objects from oldest to current.
+    chunks: VecDeque<RingChunk<Arc<Memory<B>>>>,
+
+    /// Epoch of new allocations.
+    epoch: u64,
+
+    /// All epochs less than this are retired.
+    complete: u64,
+}
+
+#[derive(Debug)]
+struct RingChunk<M> {
+    memory: M,
+    ptr: NonNull<u8>,
+
+    /// Bump pointer.
+    offset: u64,
+
+    /// Latest epoch of blocks allocated from the chunk.
+    epoch: u64,
+}
+
+unsafe impl<M> Send for RingChunk<M> where M: Send {}
+unsafe impl<M> Sync for RingChunk<M> where M: Sync {}
+
+impl<M> RingChunk<M> {
+    /// Bump pointer to fit `size` bytes aligned to `align` allocated in `epoch`.
+    /// Returns offset of the allocated range.
+    fn bump(&mut self, chunk_size: u64, size: u64, align: u64, epoch: u64) -> Option<u64> {
+        let aligned = aligned(self.offset, align);
+        if aligned + size > chunk_size {
+            return None;
+        }
+
+        self.offset = aligned + size;
+        self.epoch = epoch;
+        Some(aligned)
+    }
+}
+
+/// Move oldest chunk to the back of the ring if it served only retired epochs.
+/// Returns `true` if chunk was moved.
+fn reuse_front<M>(chunks: &mut VecDeque<RingChunk<M>>, complete: u64) -> bool {
+    match chunks.front() {
+        Some(front) if front.epoch < complete => {
+            let mut chunk = chunks.pop_front().unwrap();
+            chunk.offset = 0;
+            chunks.push_back(chunk);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Count oldest chunks that are not needed after retiring epochs less than `complete`.
+/// Current chunk and one retired spare are kept.
+fn surplus<M>(chunks: &VecDeque<RingChunk<M>>, complete: u64) -> usize {
+    let mut count = 0;
+    while chunks.len() - count > 2
+        && chunks[count].epoch < complete
+        && chunks[count + 1].epoch < complete
+    {
+        count += 1;
+    }
+    count
+}
+
+impl<B> RingAllocator<B>
+where
+    B: Backend,
+{
+    /// Get properties required by the allocator.
+    pub fn properties_required() -> gfx_hal::memory::Properties {
+        gfx_hal::memory::Properties::CPU_VISIBLE
+    }
+
+    /// Maximum allocation size.
+    pub fn max_allocation(&self) -> u64 {
+        self.chunk_size / 2
+    }
+
+    /// Create new `RingAllocator`
+    /// for `memory_type` with `memory_properties` specified,
+    /// with `RingConfig` provided.
+    pub fn new(
+        memory_type: gfx_hal::MemoryTypeId,
+        memory_properties: gfx_hal::memory::Properties,
+        config: RingConfig,
+    ) -> Self {
+        log::trace!(
+            "Create new 'ring' allocator: type: '{:?}', properties: '{:#?}' config: '{:#?}'",
+            memory_type,
+            memory_properties,
+            config
+        );
+        assert!(memory_properties.contains(Self::properties_required()));
+        assert!(
+            fits_usize(config.chunk_size),
+            "Chunk size must fit in both usize and u64"
+        );
+
+        RingAllocator {
+            memory_type,
+            memory_properties,
+            chunk_size: config.chunk_size,
+            chunks: VecDeque::new(),
+            epoch: 0,
+            complete: 0,
+        }
+    }
+
+    /// Set epoch of following allocations.
+    pub fn set_epoch(&mut self, epoch: u64) {
+        debug_assert!(epoch >= self.epoch, "Epochs must not go back");
+        self.epoch = epoch;
+    }
+
+    /// Retire all epochs less than `complete`.
+    /// Memory objects that served only retired epochs are reused,
+    /// except for one spare they are returned to the device.
+    /// Returns amount of memory returned to the device.
+    pub fn retire(&mut self, device: &B::Device, complete: u64) -> u64 {
+        debug_assert!(complete >= self.complete, "Epochs must not go back");
+        self.complete = complete;
+
+        let mut freed = 0;
+        for _ in 0..surplus(&self.chunks, complete) {
+            let chunk = self.chunks.pop_front().unwrap();
+            match Arc::try_unwrap(chunk.memory) {
+                Ok(memory) => unsafe {
+                    device.unmap_memory(memory.raw());
+                    freed += memory.size();
+                    device.free_memory(memory.into_raw());
+                },
+                Err(memory) => {
+                    log::warn!("Retired ring memory is still referenced by blocks");
+                    self.chunks.push_front(RingChunk { memory, ..chunk });
+                    break;
+                }
+            }
+        }
+        freed
+    }
+
+    /// Get fragmentation stats.
+    pub fn utilization(&self) -> AllocatorUtilization {
+        let reusable = self.chunks.len() > 1 && self.chunks[0].epoch < self.complete;
+        AllocatorUtilization {
+            kind: Kind::Ring,
+            device_allocations: self.chunks.len() as u64,
+            largest_free_range: if reusable {
+                self.chunk_size
+            } else {
+                self.chunks
+                    .back()
+                    .map_or(0, |chunk| self.chunk_size - chunk.offset)
+            },
+            size_classes: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    /// Perform full cleanup of the memory allocated.
+    pub fn dispose(self, device: &B::Device) {
+        for chunk in self.chunks {
+            match Arc::try_unwrap(chunk.memory) {
+                Ok(memory) => unsafe {
+                    device.unmap_memory(memory.raw());
+                    device.free_memory(memory.into_raw());
+                },
+                Err(_) => log::error!(
+                    "Ring memory is still referenced by blocks during allocator disposal"
+                ),
+            }
+        }
+    }
+
+    fn alloc_chunk(
+        &mut self,
+        device: &B::Device,
+    ) -> Result<RingChunk<Arc<Memory<B>>>, gfx_hal::device::AllocationError> {
+        log::trace!(
+            "Allocate ring chunk of size: {} from device",
+            self.chunk_size
+        );
+
+        let (memory, ptr) = unsafe {
+            let raw = device.allocate_memory(self.memory_type, self.chunk_size)?;
+
+            let ptr = match device.map_memory(&raw, 0..self.chunk_size) {
+                Ok(ptr) => NonNull::new_unchecked(ptr),
+                Err(gfx_hal::device::MapError::OutOfMemory(error)) => {
+                    device.free_memory(raw);
+                    return Err(error.into());
+                }
+                Err(err) => panic!("Unexpected mapping failure: {:?}", err),
+            };
+
+            let memory = Memory::from_raw(raw, self.chunk_size, self.memory_properties);
+            (memory, ptr)
+        };
+
+        Ok(RingChunk {
+            memory: Arc::new(memory),
+            ptr,
+            offset: 0,
+            epoch: self.epoch,
+        })
+    }
+
+    fn alloc_from_back(&mut self, size: u64, align: u64) -> Option<RingBlock<B>> {
+        let chunk_size = self.chunk_size;
+        let epoch = self.epoch;
+        let chunk = self.chunks.back_mut()?;
+        let aligned = chunk.bump(chunk_size, size, align, epoch)?;
+        let (ptr, range) = mapped_sub_range(chunk.ptr, 0..chunk_size, aligned..aligned + size)
+            .expect("This sub-range must fit in chunk mapping");
+
+        Some(RingBlock {
+            memory: chunk.memory.clone(),
+            epoch,
+            ptr,
+            range,
+            relevant: relevant::Relevant,
+        })
+    }
+}
+
+impl<B> Allocator<B> for RingAllocator<B>
+where
+    B: Backend,
+{
+    type Block = RingBlock<B>;
+
+    fn kind() -> Kind {
+        Kind::Ring
+    }
+
+    fn alloc(
+        &mut self,
+        device: &B::Device,
+        size: u64,
+        align: u64,
+    ) -> Result<(RingBlock<B>, u64), gfx_hal::device::AllocationError> {
+        if size > self.max_allocation() || align > self.max_allocation() {
+            log::error!(
+                "Ring allocator can't serve block of size {} aligned to {}, max allocation is {}",
+                size,
+                align,
+                self.max_allocation()
+            );
+            return Err(gfx_hal::device::OutOfMemory::Device.into());
+        }
+
+        if let Some(block) = self.alloc_from_back(size, align) {
+            return Ok((block, 0));
+        }
+
+        // Current chunk is exhausted.
+        // Reuse the oldest one if it is retired, allocate new one otherwise.
+        let allocated = if reuse_front(&mut self.chunks, self.complete) {
+            0
+        } else {
+            let chunk = self.alloc_chunk(device)?;
+            self.chunks.push_back(chunk);
+            self.chunk_size
+        };
+
+        let block = self
+            .alloc_from_back(size, align)
+            .expect("Fresh chunk must fit allocation");
+        Ok((block, allocated))
+    }
+
+    fn free(&mut self, _device: &B::Device, block: RingBlock<B>) -> u64 {
+        debug_assert!(block.size() <= self.max_allocation());
+        block.dispose();
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reuse_front, surplus, RingChunk};
+    use std::{collections::VecDeque, ptr::NonNull};
+
+    fn chunk(memory: u32, epoch: u64) -> RingChunk<u32> {
+        RingChunk {
+            memory,
+            ptr: NonNull::dangling(),
+            offset: 0,
+            epoch,
+        }
+    }
+
+    fn ring(epochs: &[u64]) -> VecDeque<RingChunk<u32>> {
+        epochs
+            .iter()
+            .enumerate()
+            .map(|(index, &epoch)| chunk(index as u32, epoch))
+            .collect()
+    }
+
+    #[test]
+    fn bump_aligns_and_tags_epoch() {
+        let mut chunk = chunk(0, 0);
+        assert_eq!(chunk.bump(1024, 10, 1, 3), Some(0));
+        assert_eq!(chunk.bump(1024, 100, 64, 4), Some(64));
+        assert_eq!((chunk.offset, chunk.epoch), (164, 4));
+
+        assert_eq!(chunk.bump(1024, 900, 1, 5), None);
+        assert_eq!((chunk.offset, chunk.epoch), (164, 4));
+        assert_eq!(chunk.bump(1024, 860, 1, 5), Some(164));
+        assert_eq!(chunk.offset, 1024);
+    }
+
+    #[test]
+    fn front_is_reused_after_retire() {
+        let mut chunks = ring(&[2, 5]);
+        chunks[0].offset = 100;
+
+        assert!(!reuse_front(&mut chunks, 2));
+        assert_eq!(chunks[0].memory, 0);
+
+        assert!(reuse_front(&mut chunks, 3));
+        let memories = chunks.iter().map(|chunk| chunk.memory).collect::<Vec<_>>();
+        assert_eq!(memories, [1, 0]);
+        assert_eq!(chunks[1].offset, 0);
+
+        assert!(!reuse_front(&mut VecDeque::<RingChunk<u32>>::new(), 10));
+    }
+
+    #[test]
+    fn retire_keeps_current_and_spare() {
+        assert_eq!(surplus(&ring(&[0, 1, 2, 3]), 0), 0);
+        assert_eq!(surplus(&ring(&[0, 1, 2, 3]), 2), 1);
+        assert_eq!(surplus(&ring(&[0, 1, 2, 3]), 4), 2);
+        assert_eq!(surplus(&ring(&[0, 1]), 4), 0);
+        assert_eq!(surplus(&ring(&[]), 4), 0);
+    }
+}
@@ -0,0 +1,856 @@
+use std::{ops::Range, ptr::NonNull, thread};
+
+use {
+    crate::{
+        allocator::{Allocator, Kind},
+        block::Block,
+        mapping::*,
+        memory::*,
+        util::*,
+        utilization::AllocatorUtilization,
+    },
+    gfx_hal::{device::Device as _, Backend},
+};
+
+/// Memory block allocated from `TlsfAllocator`
+#[derive(Debug)]
+pub struct TlsfBlock<B: Backend> {
+    region_index: u32,
+    memory: *const Memory<B>,
+    ptr: Option<NonNull<u8>>,
+    range: Range<u64>,
+    relevant: relevant::Relevant,
+}
+
+unsafe impl<B> Send for TlsfBlock<B> where B: Backend {}
+unsafe impl<B> Sync for TlsfBlock<B> where B: Backend {}
+
+impl<B> TlsfBlock<B>
+where
+    B: Backend,
+{
+    fn shared_memory(&self) -> &Memory<B> {
+        // Memory won't be freed until last block created from it deallocated.
+        unsafe { &*self.memory }
+    }
+
+    fn dispose(self) {
+        self.relevant.dispose();
+    }
+}
+
+impl<B> Block<B> for TlsfBlock<B>
+where
+    B: Backend,
+{
+    #[inline]
+    fn properties(&self) -> gfx_hal::memory::Properties {
+        self.shared_memory().properties()
+    }
+
+    #[inline]
+    fn memory(&self) -> &B::Memory {
+        self.shared_memory().raw()
+    }
+
+    #[inline]
+    fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    #[inline]
+    fn map<'a>(
+        &'a mut self,
+        _device: &B::Device,
+        range: Range<u64>,
+    ) -> Result<MappedRange<'a, B>, gfx_hal::device::MapError> {
+        debug_assert!(
+            range.start < range.end,
+            "Memory mapping region must have valid size"
+        );
+        if !self.shared_memory().host_visible() {
+            //TODO: invalid access error
+            return Err(gfx_hal::device::MapError::MappingFailed);
+        }
+
+        if let Some(ptr) = self.ptr {
+            if let Some((ptr, range)) = mapped_sub_range(ptr, self.range.clone(), range) {
+                let mapping = unsafe { MappedRange::from_raw(self.shared_memory(), ptr, range) };
+                Ok(mapping)
+            } else {
+                Err(gfx_hal::device::MapError::OutOfBounds)
+            }
+        } else {
+            Err(gfx_hal::device::MapError::MappingFailed)
+        }
+    }
+
+    #[inline]
+    fn unmap(&mut self, _device: &B::Device) {}
+}
+
+/// Config for `TlsfAllocator`.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TlsfConfig {
+    /// All requests are rounded up to multiple of this value.
+    pub block_size_granularity: u64,
+
+    /// Size of the memory object allocated from device.
+    /// This is also the largest block allocator can return.
+    pub chunk_size: u64,
+}
+
+/// Two-Level Segregated Fit allocator.
+/// Keeps free ranges in fine-grained size classes
+/// and finds suitable range in constant time.
+/// Freed ranges are merged with free neighbours.
+///
+/// This allocator suites general-purpose allocations of arbitrary size.
+/// Unlike `DynamicAllocator` it doesn't round sizes up to power-of-two-ish classes.
+#[derive(Debug)]
+pub struct TlsfAllocator<B: Backend> {
+    /// Memory type that this allocator allocates.
+    memory_type: gfx_hal::MemoryTypeId,
+
+    /// Memory properties of the memory type.
+    memory_properties: gfx_hal::memory::Properties,
+
+    /// Size of the memory object allocated from device.
+    chunk_size: u64,
+
+    /// Allocated chunks.
+    chunks: slab::Slab<TlsfChunk<B>>,
+
+    /// Ranges of all chunks.
+    tlsf: Tlsf,
+}
+
+unsafe impl<B> Send for TlsfAllocator<B> where B: Backend {}
+unsafe impl<B> Sync for TlsfAllocator<B> where B: Backend {}
+
+#[derive(Debug)]
+struct TlsfChunk<B: Backend> {
+    memory: Box<Memory<B>>,
+    ptr: Option<NonNull<u8>>,
+}
+
+impl<B> TlsfAllocator<B>
+where
+    B: Backend,
+{
+    /// Create new `TlsfAllocator`
+    /// for `memory_type` with `memory_properties` specified,
+    /// with `TlsfConfig` provided.
+    pub fn new(
+        memory_type: gfx_hal::MemoryTypeId,
+        memory_properties: gfx_hal::memory::Properties,
+        config: TlsfConfig,
+    ) -> Self {
+        log::trace!(
+            "Create new 'tlsf' allocator: type: '{:?}', properties: '{:#?}' config: '{:#?}'",
+            memory_type,
+            memory_properties,
+            config
+        );
+
+        assert!(
+            config.block_size_granularity.is_power_of_two(),
+            "Allocation granularity must be power of two"
+        );
+
+        assert!(
+            config.block_size_granularity <= config.chunk_size,
+            "Allocation granularity must be less than or equal to chunk size"
+        );
+
+        assert_eq!(
+            config.chunk_size % config.block_size_granularity,
+            0,
+            "Chunk size must be multiple of allocation granularity"
+        );
+
+        if memory_properties.contains(gfx_hal::memory::Properties::CPU_VISIBLE) {
+            assert!(
+                fits_usize(config.chunk_size),
+                "Chunk size must fit usize for mapping"
+            );
+        }
+
+        TlsfAllocator {
+            memory_type,
+            memory_properties,
+            chunk_size: config.chunk_size,
+            chunks: slab::Slab::new(),
+            tlsf: Tlsf::new(config.block_size_granularity),
+        }
+    }
+
+    /// Maximum allocation size.
+    pub fn max_allocation(&self) -> u64 {
+        self.chunk_size
+    }
+
+    /// Allocate memory chunk from device.
+    fn alloc_chunk(
+        &self,
+        device: &B::Device,
+    ) -> Result<TlsfChunk<B>, gfx_hal::device::AllocationError> {
+        log::trace!("Allocate chunk of size: {} from device", self.chunk_size);
+
+        let (memory, ptr) = unsafe {
+            // Valid memory type specified.
+            let raw = device.allocate_memory(self.memory_type, self.chunk_size)?;
+
+            let ptr = if self
+                .memory_properties
+                .contains(gfx_hal::memory::Properties::CPU_VISIBLE)
+            {
+                log::trace!("Map new memory object");
+                match device.map_memory(&raw, 0..self.chunk_size) {
+                    Ok(ptr) => Some(NonNull::new_unchecked(ptr)),
+                    Err(gfx_hal::device::MapError::OutOfMemory(error)) => {
+                        device.free_memory(raw);
+                        return Err(error.into());
+                    }
+                    Err(err) => panic!("Unexpected mapping failure: {:?}", err),
+                }
+            } else {
+                None
+            };
+            let memory = Memory::from_raw(raw, self.chunk_size, self.memory_properties);
+            (memory, ptr)
+        };
+
+        Ok(TlsfChunk {
+            memory: Box::new(memory),
+            ptr,
+        })
+    }
+
+    /// Free memory chunk to the device.
+    fn free_chunk(&self, device: &B::Device, chunk: TlsfChunk<B>) -> u64 {
+        log::trace!("Free chunk: {:#?}", chunk);
+        let size = chunk.memory.size();
+        unsafe {
+            if chunk.ptr.is_some() {
+                device.unmap_memory(chunk.memory.raw());
+            }
+            device.free_memory(chunk.memory.into_raw());
+        }
+        size
+    }
+
+    /// Get fragmentation stats.
+    pub fn utilization(&self) -> AllocatorUtilization {
+        AllocatorUtilization {
+            kind: Kind::Tlsf,
+            device_allocations: self.chunks.len() as u64,
+            largest_free_range: self.tlsf.largest_free(),
+            size_classes: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    /// Perform full cleanup of the memory allocated.
+    pub fn dispose(self) {
+        if !thread::panicking() {
+            assert_eq!(self.chunks.len(), 0, "Not all blocks were freed");
+        } else if !self.chunks.is_empty() {
+            log::error!(
+                "Memory leak: {} tlsf chunks are still used",
+                self.chunks.len()
+            );
+        }
+    }
+}
+
+impl<B> Allocator<B> for TlsfAllocator<B>
+where
+    B: Backend,
+{
+    type Block = TlsfBlock<B>;
+
+    fn kind() -> Kind {
+        Kind::Tlsf
+    }
+
+    fn alloc(
+        &mut self,
+        device: &B::Device,
+        size: u64,
+        align: u64,
+    ) -> Result<(TlsfBlock<B>, u64), gfx_hal::device::AllocationError> {
+        debug_assert!(size <= self.max_allocation());
+        debug_assert!(align.is_power_of_two());
+
+        log::trace!(
+            "Allocate tlsf block: size: {}, align: {}, type: {}",
+            size,
+            align,
+            self.memory_type.0
+        );
+
+        let (region_index, allocated) = match self.tlsf.alloc(size, align) {
+            Some(region_index) => (region_index, 0),
+            None => {
+                let chunk = self.alloc_chunk(device)?;
+                let chunk_index = self.chunks.insert(chunk);
+                let chunk_region = self.tlsf.add_chunk(chunk_index, self.chunk_size);
+                match self.tlsf.alloc(size, align) {
+                    Some(region_index) => (region_index, self.chunk_size),
+                    None => {
+                        // Rounding and alignment padding may exceed the chunk.
+                        log::error!(
+                            "Request size: {}, align: {} can't be served by fresh chunk of size {}",
+                            size,
+                            align,
+                            self.chunk_size
+                        );
+                        self.tlsf.remove_chunk(chunk_region);
+                        let chunk = self.chunks.remove(chunk_index);
+                        self.free_chunk(device, chunk);
+                        return Err(gfx_hal::device::OutOfMemory::Device.into());
+                    }
+                }
+            }
+        };
+
+        assert!(fits_u32(region_index));
+        let (chunk_index, range) = self.tlsf.region(region_index);
+        let chunk = &self.chunks[chunk_index];
+
+        let block = TlsfBlock {
+            region_index: region_index as u32,
+            memory: &*chunk.memory,
+            ptr: chunk.ptr.map(|ptr| {
+                mapped_fitting_range(ptr, 0..chunk.memory.size(), range.clone())
+                    .expect("Block must be sub-range of chunk")
+            }),
+            range,
+            relevant: relevant::Relevant,
+        };
+
+        Ok((block, allocated))
+    }
+
+    fn free(&mut self, device: &B::Device, block: TlsfBlock<B>) -> u64 {
+        log::trace!("Free block: {:#?}", block);
+
+        let region_index = block.region_index as usize;
+        block.dispose();
+
+        match self.tlsf.free(region_index) {
+            Some(chunk_index) => {
+                let chunk = self.chunks.remove(chunk_index);
+                self.free_chunk(device, chunk)
+            }
+            None => 0,
+        }
+    }
+}
+
+/// Number of bits used for second-level index.
+const SL_BITS: u32 = 4;
+const SL_COUNT: usize = 1 << SL_BITS;
+const FL_COUNT: usize = 64 - SL_BITS as usize + 1;
+
+/// Contiguous range of a chunk.
+#[derive(Debug)]
+struct Region {
+    chunk: usize,
+    offset: u64,
+    size: u64,
+    free: bool,
+
+    /// Physical neighbours in the same chunk.
+    prev: Option<usize>,
+    next: Option<usize>,
+
+    /// Neighbours in the free list of the size class.
+    prev_free: Option<usize>,
+    next_free: Option<usize>,
+}
+
+/// Device-independent TLSF bookkeeping.
+/// Offsets and sizes are multiples of `granularity`.
+pub(crate) struct Tlsf {
+    granularity: u64,
+    regions: slab::Slab<Region>,
+
+    /// Bit per first-level index that has non-empty free lists.
+    fl_bitmap: u64,
+
+    /// Bit per second-level index that has non-empty free list.
+    sl_bitmaps: [u32; FL_COUNT],
+
+    /// Heads of free lists.
+    heads: [[Option<usize>; SL_COUNT]; FL_COUNT],
+}
+
+// Arrays longer than 32 elements don't implement `Debug`.
+impl std::fmt::Debug for Tlsf {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("Tlsf")
+            .field("granularity", &self.granularity)
+            .field("regions", &self.regions)
+            .field("fl_bitmap", &self.fl_bitmap)
+            .field("sl_bitmaps", &&self.sl_bitmaps[..])
+            .field("heads", &&self.heads[..])
+            .finish()
+    }
+}
+
+impl Tlsf {
+    pub(crate) fn new(granularity: u64) -> Self {
+        debug_assert!(granularity.is_power_of_two());
+        Tlsf {
+            granularity,
+            regions: slab::Slab::new(),
+            fl_bitmap: 0,
+            sl_bitmaps: [0; FL_COUNT],
+            heads: [[None; SL_COUNT]; FL_COUNT],
+        }
+    }
+
+    /// Get size of the biggest free region.
+    pub(crate) fn largest_free(&self) -> u64 {
+        self.regions
+            .iter()
+            .filter(|(_, region)| region.free)
+            .map(|(_, region)| region.size)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Get sizes of all free regions.
+    pub(crate) fn free_sizes(&self) -> impl Iterator<Item = u64> + '_ {
+        self.regions
+            .iter()
+            .filter(|(_, region)| region.free)
+            .map(|(_, region)| region.size)
+    }
+
+    /// Get chunk index and range of the region.
+    pub(crate) fn region(&self, index: usize) -> (usize, Range<u64>) {
+        let region = &self.regions[index];
+        (region.chunk, region.offset..region.offset + region.size)
+    }
+
+    /// Size class containing `size`.
+    fn mapping(&self, size: u64) -> (usize, usize) {
+        let units = size / self.granularity;
+        debug_assert_ne!(units, 0);
+        let log2 = 63 - units.leading_zeros();
+        if log2 < SL_BITS {
+            (0, units as usize)
+        } else {
+            let sl = (units >> (log2 - SL_BITS)) as usize ^ SL_COUNT;
+            ((log2 - SL_BITS + 1) as usize, sl)
+        }
+    }
+
+    /// Smallest size class all ranges of which can hold `size`.
+    /// Returns `None` if rounded size doesn't fit `u64`.
+    fn mapping_search(&self, size: u64) -> Option<(usize, usize)> {
+        let units = size / self.granularity;
+        let log2 = 63 - units.leading_zeros();
+        if log2 < SL_BITS {
+            Some((0, units as usize))
+        } else {
+            let round = (1u64 << (log2 - SL_BITS)) - 1;
+            let units = units.checked_add(round)?;
+            Some(self.mapping(units.checked_mul(self.granularity)?))
+        }
+    }
+
+    /// Round `size` up to granularity.
+    /// Returns `None` if rounded size doesn't fit `u64`.
+    fn granular(&self, size: u64) -> Option<u64> {
+        let mask = self.granularity - 1;
+        Some(size.max(1).checked_add(mask)? & !mask)
+    }
+
+    /// Find non-empty free list with ranges not smaller than class `(fl, sl)`.
+    fn find_free(&self, fl: usize, sl: usize) -> Option<usize> {
+        let sl_map = self.sl_bitmaps[fl] & (!0u32 << sl);
+        let (fl, sl_map) = if sl_map != 0 {
+            (fl, sl_map)
+        } else {
+            let fl_map = self.fl_bitmap & (!0u64).checked_shl(fl as u32 + 1).unwrap_or(0);
+            if fl_map == 0 {
+                return None;
+            }
+            let fl = fl_map.trailing_zeros() as usize;
+            (fl, self.sl_bitmaps[fl])
+        };
+        let sl = sl_map.trailing_zeros() as usize;
+        self.heads[fl][sl]
+    }
+
+    fn insert_free(&mut self, index: usize) {
+        let (fl, sl) = self.mapping(self.regions[index].size);
+        let head = self.heads[fl][sl];
+        {
+            let region = &mut self.regions[index];
+            region.free = true;
+            region.prev_free = None;
+            region.next_free = head;
+        }
+        if let Some(head) = head {
+            self.regions[head].prev_free = Some(index);
+        }
+        self.heads[fl][sl] = Some(index);
+        self.sl_bitmaps[fl] |= 1 << sl;
+        self.fl_bitmap |= 1 << fl;
+    }
+
+    fn remove_free(&mut self, index: usize) {
+        let (fl, sl) = self.mapping(self.regions[index].size);
+        let (prev, next) = {
+            let region = &mut self.regions[index];
+            debug_assert!(region.free);
+            region.free = false;
+            (region.prev_free.take(), region.next_free.take())
+        };
+        if let Some(next) = next {
+            self.regions[next].prev_free = prev;
+        }
+        match prev {
+            Some(prev) => self.regions[prev].next_free = next,
+            None => {
+                debug_assert_eq!(self.heads[fl][sl], Some(index));
+                self.heads[fl][sl] = next;
+                if next.is_none() {
+                    self.sl_bitmaps[fl] &= !(1 << sl);
+                    if self.sl_bitmaps[fl] == 0 {
+                        self.fl_bitmap &= !(1 << fl);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Split region at `size` bytes from its start.
+    /// Returns index of the new region that holds the tail.
+    fn split(&mut self, index: usize, size: u64) -> usize {
+        let (chunk, offset, total, next) = {
+            let region = &self.regions[index];
+            debug_assert!(!region.free);
+            debug_assert!(size < region.size);
+            (region.chunk, region.offset, region.size, region.next)
+        };
+        let tail = self.regions.insert(Region {
+            chunk,
+            offset: offset + size,
+            size: total - size,
+            free: false,
+            prev: Some(index),
+            next,
+            prev_free: None,
+            next_free: None,
+        });
+        if let Some(next) = next {
+            self.regions[next].prev = Some(tail);
+        }
+        let region = &mut self.regions[index];
+        region.size = size;
+        region.next = Some(tail);
+        tail
+    }
+
+    /// Merge region with its next physical neighbour.
+    fn merge_next(&mut self, index: usize) {
+        let next = self.regions[index].next.expect("Region must have next");
+        let next = self.regions.remove(next);
+        debug_assert!(!next.free);
+        if let Some(after) = next.next {
+            self.regions[after].prev = Some(index);
+        }
+        let region = &mut self.regions[index];
+        debug_assert_eq!(region.offset + region.size, next.offset);
+        region.size += next.size;
+        region.next = next.next;
+    }
+
+    /// Add whole chunk as free region.
+    /// Returns index of the region.
+    pub(crate) fn add_chunk(&mut self, chunk: usize, size: u64) -> usize {
+        debug_assert_eq!(size % self.granularity, 0);
+        let index = self.regions.insert(Region {
+            chunk,
+            offset: 0,
+            size,
+            free: false,
+            prev: None,
+            next: None,
+            prev_free: None,
+            next_free: None,
+        });
+        self.insert_free(index);
+        index
+    }
+
+    /// Remove unused chunk added by `add_chunk`.
+    /// `index` is the region returned by `add_chunk`.
+    pub(crate) fn remove_chunk(&mut self, index: usize) {
+        {
+            let region = &self.regions[index];
+            debug_assert!(
+                region.free && region.prev.is_none() && region.next.is_none(),
+                "Chunk is in use"
+            );
+        }
+        self.remove_free(index);
+        self.regions.remove(index);
+    }
+
+    /// Allocate region.
+    /// Returns its index.
+    pub(crate) fn alloc(&mut self, size: u64, align: u64) -> Option<usize> {
+        debug_assert!(align.is_power_of_two());
+        let size = self.granular(size)?;
+
+        // Reserve space to align the region.
+        let padding = align.saturating_sub(self.granularity);
+        let search = size.checked_add(padding)?;
+
+        let (fl, sl) = self.mapping_search(search)?;
+        let index = self.find_free(fl, sl)?;
+        debug_assert!(self.regions[index].size >= search);
+        self.remove_free(index);
+
+        let offset = self.regions[index].offset;
+        let aligned_offset = aligned(offset, align);
+        let index = if aligned_offset > offset {
+            let tail = self.split(index, aligned_offset - offset);
+            self.insert_free(index);
+            tail
+        } else {
+            index
+        };
+
+        if self.regions[index].size > size {
+            let tail = self.split(index, size);
+            self.insert_free(tail);
+        }
+
+        Some(index)
+    }
+
+    /// Free region.
+    /// Returns chunk index if chunk became unused.
+    /// Unused chunk is removed.
+    pub(crate) fn free(&mut self, index: usize) -> Option<usize> {
+        debug_assert!(!self.regions[index].free, "Region was freed twice");
+
+        if let Some(next) = self.regions[index].next {
+            if self.regions[next].free {
+                self.remove_free(next);
+                self.merge_next(index);
+            }
+        }
+
+        let index = match self.regions[index].prev {
+            Some(prev) if self.regions[prev].free => {
+                self.remove_free(prev);
+                self.merge_next(prev);
+                prev
+            }
+            _ => index,
+        };
+
+        let region = &self.regions[index];
+        if region.prev.is_none() && region.next.is_none() {
+            Some(self.regions.remove(index).chunk)
+        } else {
+            self.insert_free(index);
+            None
+        }
+    }
+
+    /// Resize allocated region in place.
+    /// Growing consumes free region that follows.
+    /// Shrinking returns the tail to free regions.
+    /// Returns `false` if region can't grow in place.
+    pub(crate) fn resize(&mut self, index: usize, size: u64) -> bool {
+        debug_assert!(!self.regions[index].free, "Region is not allocated");
+        let size = match self.granular(size) {
+            Some(size) => size,
+            None => return false,
+        };
+
+        let current = self.regions[index].size;
+        if size > current {
+            match self.regions[index].next {
+                Some(next)
+                    if self.regions[next].free && current + self.regions[next].size >= size =>
+                {
+                    self.remove_free(next);
+                    self.merge_next(index);
+                }
+                _ => return false,
+            }
+        }
+
+        if self.regions[index].size > size {
+            let tail = self.split(index, size);
+            let released = self.free(tail);
+            debug_assert!(released.is_none());
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tlsf;
+    use rand::{seq::SliceRandom as _, Rng as _};
+
+    #[test]
+    fn exact_fit_and_merge() {
+        let mut tlsf = Tlsf::new(256);
+        tlsf.add_chunk(0, 4096);
+
+        let a = tlsf.alloc(1000, 256).unwrap();
+        let b = tlsf.alloc(300, 256).unwrap();
+        assert_eq!(tlsf.region(a), (0, 0..1024));
+        assert_eq!(tlsf.region(b), (0, 1024..1536));
+
+        assert_eq!(tlsf.free(a), None);
+        assert_eq!(tlsf.free(b), Some(0));
+        assert_eq!(tlsf.regions.len(), 0);
+    }
+
+    #[test]
+    fn alignment() {
+        let mut tlsf = Tlsf::new(16);
+        tlsf.add_chunk(3, 1 << 16);
+
+        let a = tlsf.alloc(48, 16).unwrap();
+        let b = tlsf.alloc(64, 4096).unwrap();
+        assert_eq!(tlsf.region(a).1, 0..48);
+        assert_eq!(tlsf.region(b).1, 4096..4160);
+
+        // Padding in front of `b` is reusable.
+        let c = tlsf.alloc(1024, 16).unwrap();
+        assert!(tlsf.region(c).1.end <= 4096);
+
+        assert_eq!(tlsf.free(b), None);
+        assert_eq!(tlsf.free(a), None);
+        assert_eq!(tlsf.free(c), Some(3));
+    }
+
+    #[test]
+    fn exhaustion() {
+        let mut tlsf = Tlsf::new(64);
+        assert_eq!(tlsf.alloc(64, 64), None);
+
+        tlsf.add_chunk(0, 1024);
+        assert_eq!(tlsf.alloc(2048, 64), None);
+
+        let a = tlsf.alloc(1024, 64).unwrap();
+        assert_eq!(tlsf.alloc(64, 64), None);
+        assert_eq!(tlsf.free(a), Some(0));
+    }
+
+    #[test]
+    fn remove_unused_chunk() {
+        let mut tlsf = Tlsf::new(64);
+        let chunk = tlsf.add_chunk(0, 1024);
+
+        // Alignment padding doesn't fit into the chunk.
+        assert_eq!(tlsf.alloc(1024, 128), None);
+        tlsf.remove_chunk(chunk);
+        assert_eq!(tlsf.regions.len(), 0);
+        assert_eq!(tlsf.alloc(64, 64), None);
+
+        tlsf.add_chunk(1, 1024);
+        let a = tlsf.alloc(1024, 64).unwrap();
+        assert_eq!(tlsf.region(a), (1, 0..1024));
+        assert_eq!(tlsf.free(a), Some(1));
+    }
+
+    #[test]
+    fn huge_requests_fail() {
+        let mut tlsf = Tlsf::new(256);
+        tlsf.add_chunk(0, 1 << 20);
+
+        assert_eq!(tlsf.mapping_search(u64::max_value()), None);
+        assert_eq!(tlsf.alloc(u64::max_value(), 1), None);
+        assert_eq!(tlsf.alloc(u64::max_value() - 255, 256), None);
+        assert_eq!(tlsf.alloc(1 << 63, 1), None);
+        assert_eq!(tlsf.alloc(1, 1 << 63), None);
+        assert_eq!(tlsf.alloc(u64::max_value() / 2, 1 << 62), None);
+
+        let a = tlsf.alloc(1, 1).unwrap();
+        assert!(!tlsf.resize(a, u64::max_value()));
+        assert!(!tlsf.resize(a, 1 << 21));
+        assert_eq!(tlsf.region(a), (0, 0..256));
+        assert_eq!(tlsf.free(a), Some(0));
+    }
+
+    #[test]
+    fn huge_chunk() {
+        let mut tlsf = Tlsf::new(1 << 12);
+        let size = 1 << 62;
+        tlsf.add_chunk(0, size);
+
+        let a = tlsf.alloc(1 << 61, 1 << 12).unwrap();
+        let b = tlsf.alloc(1 << 60, 1 << 12).unwrap();
+        assert_eq!(tlsf.region(a), (0, 0..1 << 61));
+        assert_eq!(tlsf.region(b), (0, 1 << 61..3 << 60));
+        assert_eq!(tlsf.alloc(1 << 61, 1), None);
+
+        assert_eq!(tlsf.free(a), None);
+        assert_eq!(tlsf.free(b), Some(0));
+    }
+
+    #[test]
+    fn random_regions_are_aligned_and_disjoint() {
+        let mut rng = rand::thread_rng();
+        let mut tlsf = Tlsf::new(32);
+        tlsf.add_chunk(0, 1 << 20);
+        tlsf.add_chunk(1, 1 << 20);
+        let mut live: Vec<(usize, u64)> = Vec::new();
+
+        for _ in 0..10000 {
+            if live.is_empty() || rng.gen_bool(0.6) {
+                let size = rng.gen_range(1, 20000);
+                let align = 1 << rng.gen_range(0, 10);
+                if let Some(index) = tlsf.alloc(size, align) {
+                    let (chunk, range) = tlsf.region(index);
+                    assert_eq!(range.start % align, 0);
+                    assert!(range.end - range.start >= size);
+                    assert!(range.end <= 1 << 20);
+                    for &(other, _) in &live {
+                        let (other_chunk, other_range) = tlsf.region(other);
+                        assert!(
+                            chunk != other_chunk
+                                || range.end <= other_range.start
+                                || other_range.end <= range.start
+                        );
+                    }
+                    live.push((index, size));
+                }
+            } else {
+                live.shuffle(&mut rng);
+                let (index, _) = live.pop().unwrap();
+                if let Some(chunk) = tlsf.free(index) {
+                    tlsf.add_chunk(chunk, 1 << 20);
+                }
+            }
+        }
+
+        let freed = live
+            .into_iter()
+            .filter_map(|(index, _)| tlsf.free(index))
+            .count();
+
+        // Chunks that weren't released must be whole free regions.
+        assert_eq!(freed + tlsf.regions.len(), 2);
+        for (_, region) in tlsf.regions.iter() {
+            assert!(region.free && region.prev.is_none() && region.next.is_none());
+        }
+    }
+}
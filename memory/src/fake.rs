@@ -0,0 +1,1099 @@
+//! Fake backend for testing memory management without GPU.
+//! Its [`Device`] emulates device memory on host.
+//! All operations other than memory allocation and mapping panic.
+//!
+//! [`Device`]: struct.Device.html
+
+use {
+    gfx_hal::{
+        self as hal, adapter, buffer, command, device, format, image, memory, pass, pool, pso,
+        query, queue, range::RangeArg, window,
+    },
+    std::{
+        borrow::Borrow,
+        ops::Range,
+        sync::{
+            atomic::{AtomicU64, AtomicUsize, Ordering},
+            Mutex,
+        },
+    },
+};
+
+fn unsupported() -> ! {
+    panic!("Fake backend supports only device memory operations")
+}
+
+/// Fake backend.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Backend {}
+impl hal::Backend for Backend {
+    type Instance = Instance;
+    type PhysicalDevice = PhysicalDevice;
+    type Device = Device;
+
+    type Surface = Surface;
+    type Swapchain = Swapchain;
+
+    type QueueFamily = QueueFamily;
+    type CommandQueue = CommandQueue;
+    type CommandBuffer = CommandBuffer;
+
+    type Memory = Memory;
+    type CommandPool = CommandPool;
+
+    type ShaderModule = ();
+    type RenderPass = ();
+    type Framebuffer = ();
+
+    type Buffer = ();
+    type BufferView = ();
+    type Image = ();
+    type ImageView = ();
+    type Sampler = ();
+
+    type ComputePipeline = ();
+    type GraphicsPipeline = ();
+    type PipelineCache = ();
+    type PipelineLayout = ();
+    type DescriptorSetLayout = ();
+    type DescriptorPool = DescriptorPool;
+    type DescriptorSet = ();
+
+    type Fence = ();
+    type Semaphore = ();
+    type Event = ();
+    type QueryPool = ();
+}
+
+/// Unsupported physical device.
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicalDevice;
+impl adapter::PhysicalDevice<Backend> for PhysicalDevice {
+    unsafe fn open(
+        &self,
+        _: &[(&QueueFamily, &[queue::QueuePriority])],
+        _: hal::Features,
+    ) -> Result<adapter::Gpu<Backend>, device::CreationError> {
+        unsupported()
+    }
+
+    fn format_properties(&self, _: Option<format::Format>) -> format::Properties {
+        unsupported()
+    }
+
+    fn image_format_properties(
+        &self,
+        _: format::Format,
+        _dim: u8,
+        _: image::Tiling,
+        _: image::Usage,
+        _: image::ViewCapabilities,
+    ) -> Option<image::FormatProperties> {
+        unsupported()
+    }
+
+    fn memory_properties(&self) -> adapter::MemoryProperties {
+        unsupported()
+    }
+
+    fn features(&self) -> hal::Features {
+        unsupported()
+    }
+
+    fn limits(&self) -> hal::Limits {
+        unsupported()
+    }
+}
+
+/// Unsupported command queue.
+#[derive(Clone, Copy, Debug)]
+pub struct CommandQueue;
+impl queue::CommandQueue<Backend> for CommandQueue {
+    unsafe fn submit<'a, T, Ic, S, Iw, Is>(
+        &mut self,
+        _: queue::Submission<Ic, Iw, Is>,
+        _: Option<&()>,
+    ) where
+        T: 'a + Borrow<CommandBuffer>,
+        Ic: IntoIterator<Item = &'a T>,
+        S: 'a + Borrow<()>,
+        Iw: IntoIterator<Item = (&'a S, pso::PipelineStage)>,
+        Is: IntoIterator<Item = &'a S>,
+    {
+        unsupported()
+    }
+
+    unsafe fn present<'a, W, Is, S, Iw>(
+        &mut self,
+        _: Is,
+        _: Iw,
+    ) -> Result<Option<window::Suboptimal>, window::PresentError>
+    where
+        W: 'a + Borrow<Swapchain>,
+        Is: IntoIterator<Item = (&'a W, window::SwapImageIndex)>,
+        S: 'a + Borrow<()>,
+        Iw: IntoIterator<Item = &'a S>,
+    {
+        unsupported()
+    }
+
+    unsafe fn present_surface(
+        &mut self,
+        _surface: &mut Surface,
+        _image: (),
+        _wait_semaphore: Option<&()>,
+    ) -> Result<Option<window::Suboptimal>, window::PresentError> {
+        unsupported()
+    }
+
+    fn wait_idle(&self) -> Result<(), device::OutOfMemory> {
+        unsupported()
+    }
+}
+
+/// Device that emulates memory on host.
+/// Memory is allocated from host only once it is mapped.
+#[derive(Debug)]
+pub struct Device {
+    limit: u64,
+    allocated: AtomicU64,
+    allocations: AtomicUsize,
+}
+
+impl Device {
+    /// Create device with unlimited memory.
+    pub fn new() -> Self {
+        Device::with_limit(!0)
+    }
+
+    /// Create device that fails to allocate more than `limit` bytes in total.
+    pub fn with_limit(limit: u64) -> Self {
+        Device {
+            limit,
+            allocated: AtomicU64::new(0),
+            allocations: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get number of bytes allocated and not freed yet.
+    pub fn allocated(&self) -> u64 {
+        self.allocated.load(Ordering::Acquire)
+    }
+
+    /// Get number of memory objects allocated and not freed yet.
+    pub fn allocations(&self) -> usize {
+        self.allocations.load(Ordering::Acquire)
+    }
+}
+
+impl Default for Device {
+    fn default() -> Self {
+        Device::new()
+    }
+}
+
+/// Memory object of the fake [`Device`](struct.Device.html).
+#[derive(Debug)]
+pub struct Memory {
+    size: u64,
+    data: Mutex<Vec<u8>>,
+}
+
+impl device::Device<Backend> for Device {
+    unsafe fn create_command_pool(
+        &self,
+        _: queue::QueueFamilyId,
+        _: pool::CommandPoolCreateFlags,
+    ) -> Result<CommandPool, device::OutOfMemory> {
+        unsupported()
+    }
+
+    unsafe fn destroy_command_pool(&self, _: CommandPool) {
+        unsupported()
+    }
+
+    unsafe fn allocate_memory(
+        &self,
+        _: hal::MemoryTypeId,
+        size: u64,
+    ) -> Result<Memory, device::AllocationError> {
+        let mut allocated = self.allocated.load(Ordering::Acquire);
+        loop {
+            let total = allocated
+                .checked_add(size)
+                .filter(|&total| total <= self.limit)
+                .ok_or(device::OutOfMemory::Device)?;
+
+            match self.allocated.compare_exchange_weak(
+                allocated,
+                total,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(actual) => allocated = actual,
+            }
+        }
+        self.allocations.fetch_add(1, Ordering::AcqRel);
+
+        Ok(Memory {
+            size,
+            data: Mutex::new(Vec::new()),
+        })
+    }
+
+    unsafe fn create_render_pass<'a, IA, IS, ID>(
+        &self,
+        _: IA,
+        _: IS,
+        _: ID,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        IA: IntoIterator,
+        IA::Item: Borrow<pass::Attachment>,
+        IS: IntoIterator,
+        IS::Item: Borrow<pass::SubpassDesc<'a>>,
+        ID: IntoIterator,
+        ID::Item: Borrow<pass::SubpassDependency>,
+    {
+        unsupported()
+    }
+
+    unsafe fn create_pipeline_layout<IS, IR>(&self, _: IS, _: IR) -> Result<(), device::OutOfMemory>
+    where
+        IS: IntoIterator,
+        IS::Item: Borrow<()>,
+        IR: IntoIterator,
+        IR::Item: Borrow<(pso::ShaderStageFlags, Range<u32>)>,
+    {
+        unsupported()
+    }
+
+    unsafe fn create_pipeline_cache(
+        &self,
+        _data: Option<&[u8]>,
+    ) -> Result<(), device::OutOfMemory> {
+        unsupported()
+    }
+
+    unsafe fn get_pipeline_cache_data(&self, _cache: &()) -> Result<Vec<u8>, device::OutOfMemory> {
+        unsupported()
+    }
+
+    unsafe fn destroy_pipeline_cache(&self, _: ()) {
+        unsupported()
+    }
+
+    unsafe fn create_graphics_pipeline<'a>(
+        &self,
+        _: &pso::GraphicsPipelineDesc<'a, Backend>,
+        _: Option<&()>,
+    ) -> Result<(), pso::CreationError> {
+        unsupported()
+    }
+
+    unsafe fn create_compute_pipeline<'a>(
+        &self,
+        _: &pso::ComputePipelineDesc<'a, Backend>,
+        _: Option<&()>,
+    ) -> Result<(), pso::CreationError> {
+        unsupported()
+    }
+
+    unsafe fn merge_pipeline_caches<I>(&self, _: &(), _: I) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+    {
+        unsupported()
+    }
+
+    unsafe fn create_framebuffer<I>(
+        &self,
+        _: &(),
+        _: I,
+        _: image::Extent,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+    {
+        unsupported()
+    }
+
+    unsafe fn create_shader_module(&self, _: &[u32]) -> Result<(), device::ShaderError> {
+        unsupported()
+    }
+
+    unsafe fn create_sampler(&self, _: &image::SamplerDesc) -> Result<(), device::AllocationError> {
+        unsupported()
+    }
+    unsafe fn create_buffer(&self, _: u64, _: buffer::Usage) -> Result<(), buffer::CreationError> {
+        unsupported()
+    }
+
+    unsafe fn get_buffer_requirements(&self, _: &()) -> memory::Requirements {
+        unsupported()
+    }
+
+    unsafe fn bind_buffer_memory(
+        &self,
+        _: &Memory,
+        _: u64,
+        _: &mut (),
+    ) -> Result<(), device::BindError> {
+        unsupported()
+    }
+
+    unsafe fn create_buffer_view<R: RangeArg<u64>>(
+        &self,
+        _: &(),
+        _: Option<format::Format>,
+        _: R,
+    ) -> Result<(), buffer::ViewCreationError> {
+        unsupported()
+    }
+
+    unsafe fn create_image(
+        &self,
+        _: image::Kind,
+        _: image::Level,
+        _: format::Format,
+        _: image::Tiling,
+        _: image::Usage,
+        _: image::ViewCapabilities,
+    ) -> Result<(), image::CreationError> {
+        unsupported()
+    }
+
+    unsafe fn get_image_requirements(&self, _: &()) -> memory::Requirements {
+        unsupported()
+    }
+
+    unsafe fn get_image_subresource_footprint(
+        &self,
+        _: &(),
+        _: image::Subresource,
+    ) -> image::SubresourceFootprint {
+        unsupported()
+    }
+
+    unsafe fn bind_image_memory(
+        &self,
+        _: &Memory,
+        _: u64,
+        _: &mut (),
+    ) -> Result<(), device::BindError> {
+        unsupported()
+    }
+
+    unsafe fn create_image_view(
+        &self,
+        _: &(),
+        _: image::ViewKind,
+        _: format::Format,
+        _: format::Swizzle,
+        _: image::SubresourceRange,
+    ) -> Result<(), image::ViewError> {
+        unsupported()
+    }
+
+    unsafe fn create_descriptor_pool<I>(
+        &self,
+        _: usize,
+        _: I,
+        _: pso::DescriptorPoolCreateFlags,
+    ) -> Result<DescriptorPool, device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorRangeDesc>,
+    {
+        unsupported()
+    }
+
+    unsafe fn create_descriptor_set_layout<I, J>(
+        &self,
+        _: I,
+        _: J,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetLayoutBinding>,
+        J: IntoIterator,
+        J::Item: Borrow<()>,
+    {
+        unsupported()
+    }
+
+    unsafe fn write_descriptor_sets<'a, I, J>(&self, _: I)
+    where
+        I: IntoIterator<Item = pso::DescriptorSetWrite<'a, Backend, J>>,
+        J: IntoIterator,
+        J::Item: Borrow<pso::Descriptor<'a, Backend>>,
+    {
+        unsupported()
+    }
+
+    unsafe fn copy_descriptor_sets<'a, I>(&self, _: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetCopy<'a, Backend>>,
+    {
+        unsupported()
+    }
+
+    fn create_semaphore(&self) -> Result<(), device::OutOfMemory> {
+        unsupported()
+    }
+
+    fn create_fence(&self, _: bool) -> Result<(), device::OutOfMemory> {
+        unsupported()
+    }
+
+    unsafe fn get_fence_status(&self, _: &()) -> Result<bool, device::DeviceLost> {
+        unsupported()
+    }
+
+    fn create_event(&self) -> Result<(), device::OutOfMemory> {
+        unsupported()
+    }
+
+    unsafe fn get_event_status(&self, _: &()) -> Result<bool, device::OomOrDeviceLost> {
+        unsupported()
+    }
+
+    unsafe fn set_event(&self, _: &()) -> Result<(), device::OutOfMemory> {
+        unsupported()
+    }
+
+    unsafe fn reset_event(&self, _: &()) -> Result<(), device::OutOfMemory> {
+        unsupported()
+    }
+
+    unsafe fn create_query_pool(&self, _: query::Type, _: u32) -> Result<(), query::CreationError> {
+        unsupported()
+    }
+
+    unsafe fn destroy_query_pool(&self, _: ()) {
+        unsupported()
+    }
+
+    unsafe fn get_query_pool_results(
+        &self,
+        _: &(),
+        _: Range<query::Id>,
+        _: &mut [u8],
+        _: buffer::Offset,
+        _: query::ResultFlags,
+    ) -> Result<bool, device::OomOrDeviceLost> {
+        unsupported()
+    }
+
+    unsafe fn map_memory<R: RangeArg<u64>>(
+        &self,
+        memory: &Memory,
+        range: R,
+    ) -> Result<*mut u8, device::MapError> {
+        let start = range.start().cloned().unwrap_or(0);
+        let end = range.end().cloned().unwrap_or(memory.size);
+        if start > end || end > memory.size {
+            return Err(device::MapError::OutOfBounds);
+        }
+
+        // Host memory is allocated on first mapping and is never moved after.
+        let mut data = memory.data.lock().unwrap();
+        if data.is_empty() {
+            data.resize(memory.size as usize, 0);
+        }
+        Ok(data.as_mut_ptr().add(start as usize))
+    }
+
+    unsafe fn unmap_memory(&self, _: &Memory) {}
+
+    unsafe fn flush_mapped_memory_ranges<'a, I, R>(&self, _: I) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a Memory, R)>,
+        R: RangeArg<u64>,
+    {
+        Ok(())
+    }
+
+    unsafe fn invalidate_mapped_memory_ranges<'a, I, R>(
+        &self,
+        _: I,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a Memory, R)>,
+        R: RangeArg<u64>,
+    {
+        Ok(())
+    }
+
+    unsafe fn free_memory(&self, memory: Memory) {
+        self.allocated.fetch_sub(memory.size, Ordering::AcqRel);
+        self.allocations.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    unsafe fn destroy_shader_module(&self, _: ()) {
+        unsupported()
+    }
+
+    unsafe fn destroy_render_pass(&self, _: ()) {
+        unsupported()
+    }
+
+    unsafe fn destroy_pipeline_layout(&self, _: ()) {
+        unsupported()
+    }
+    unsafe fn destroy_graphics_pipeline(&self, _: ()) {
+        unsupported()
+    }
+    unsafe fn destroy_compute_pipeline(&self, _: ()) {
+        unsupported()
+    }
+    unsafe fn destroy_framebuffer(&self, _: ()) {
+        unsupported()
+    }
+
+    unsafe fn destroy_buffer(&self, _: ()) {
+        unsupported()
+    }
+    unsafe fn destroy_buffer_view(&self, _: ()) {
+        unsupported()
+    }
+    unsafe fn destroy_image(&self, _: ()) {
+        unsupported()
+    }
+    unsafe fn destroy_image_view(&self, _: ()) {
+        unsupported()
+    }
+    unsafe fn destroy_sampler(&self, _: ()) {
+        unsupported()
+    }
+
+    unsafe fn destroy_descriptor_pool(&self, _: DescriptorPool) {
+        unsupported()
+    }
+
+    unsafe fn destroy_descriptor_set_layout(&self, _: ()) {
+        unsupported()
+    }
+
+    unsafe fn destroy_fence(&self, _: ()) {
+        unsupported()
+    }
+
+    unsafe fn destroy_semaphore(&self, _: ()) {
+        unsupported()
+    }
+
+    unsafe fn destroy_event(&self, _: ()) {
+        unsupported()
+    }
+
+    unsafe fn create_swapchain(
+        &self,
+        _: &mut Surface,
+        _: window::SwapchainConfig,
+        _: Option<Swapchain>,
+    ) -> Result<(Swapchain, Vec<()>), window::CreationError> {
+        unsupported()
+    }
+
+    unsafe fn destroy_swapchain(&self, _: Swapchain) {
+        unsupported()
+    }
+
+    fn wait_idle(&self) -> Result<(), device::OutOfMemory> {
+        unsupported()
+    }
+
+    unsafe fn set_image_name(&self, _: &mut (), _: &str) {
+        unsupported()
+    }
+
+    unsafe fn set_buffer_name(&self, _: &mut (), _: &str) {
+        unsupported()
+    }
+
+    unsafe fn set_command_buffer_name(&self, _: &mut CommandBuffer, _: &str) {
+        unsupported()
+    }
+
+    unsafe fn set_semaphore_name(&self, _: &mut (), _: &str) {
+        unsupported()
+    }
+
+    unsafe fn set_fence_name(&self, _: &mut (), _: &str) {
+        unsupported()
+    }
+
+    unsafe fn set_framebuffer_name(&self, _: &mut (), _: &str) {
+        unsupported()
+    }
+
+    unsafe fn set_render_pass_name(&self, _: &mut (), _: &str) {
+        unsupported()
+    }
+
+    unsafe fn set_descriptor_set_name(&self, _: &mut (), _: &str) {
+        unsupported()
+    }
+
+    unsafe fn set_descriptor_set_layout_name(&self, _: &mut (), _: &str) {
+        unsupported()
+    }
+}
+
+/// Unsupported queue family.
+#[derive(Clone, Copy, Debug)]
+pub struct QueueFamily;
+impl queue::QueueFamily for QueueFamily {
+    fn queue_type(&self) -> queue::QueueType {
+        unsupported()
+    }
+    fn max_queues(&self) -> usize {
+        unsupported()
+    }
+    fn id(&self) -> queue::QueueFamilyId {
+        unsupported()
+    }
+}
+
+/// Unsupported raw command pool.
+#[derive(Clone, Copy, Debug)]
+pub struct CommandPool;
+impl pool::CommandPool<Backend> for CommandPool {
+    unsafe fn reset(&mut self, _: bool) {
+        unsupported()
+    }
+
+    unsafe fn free<I>(&mut self, _: I)
+    where
+        I: IntoIterator<Item = CommandBuffer>,
+    {
+        unsupported()
+    }
+}
+
+/// Unsupported command buffer.
+#[derive(Clone, Copy, Debug)]
+pub struct CommandBuffer;
+impl command::CommandBuffer<Backend> for CommandBuffer {
+    unsafe fn begin(
+        &mut self,
+        _: command::CommandBufferFlags,
+        _: command::CommandBufferInheritanceInfo<Backend>,
+    ) {
+        unsupported()
+    }
+
+    unsafe fn finish(&mut self) {
+        unsupported()
+    }
+
+    unsafe fn reset(&mut self, _: bool) {
+        unsupported()
+    }
+
+    unsafe fn pipeline_barrier<'a, T>(
+        &mut self,
+        _: Range<pso::PipelineStage>,
+        _: memory::Dependencies,
+        _: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        unsupported()
+    }
+
+    unsafe fn fill_buffer<R>(&mut self, _: &(), _: R, _: u32)
+    where
+        R: RangeArg<buffer::Offset>,
+    {
+        unsupported()
+    }
+
+    unsafe fn update_buffer(&mut self, _: &(), _: buffer::Offset, _: &[u8]) {
+        unsupported()
+    }
+
+    unsafe fn clear_image<T>(&mut self, _: &(), _: image::Layout, _: command::ClearValue, _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<image::SubresourceRange>,
+    {
+        unsupported()
+    }
+
+    unsafe fn clear_attachments<T, U>(&mut self, _: T, _: U)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<command::AttachmentClear>,
+        U: IntoIterator,
+        U::Item: Borrow<pso::ClearRect>,
+    {
+        unsupported()
+    }
+
+    unsafe fn resolve_image<T>(&mut self, _: &(), _: image::Layout, _: &(), _: image::Layout, _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<command::ImageResolve>,
+    {
+        unsupported()
+    }
+
+    unsafe fn blit_image<T>(
+        &mut self,
+        _: &(),
+        _: image::Layout,
+        _: &(),
+        _: image::Layout,
+        _: image::Filter,
+        _: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<command::ImageBlit>,
+    {
+        unsupported()
+    }
+
+    unsafe fn bind_index_buffer(&mut self, _: buffer::IndexBufferView<Backend>) {
+        unsupported()
+    }
+
+    unsafe fn bind_vertex_buffers<I, T>(&mut self, _: u32, _: I)
+    where
+        I: IntoIterator<Item = (T, buffer::Offset)>,
+        T: Borrow<()>,
+    {
+        unsupported()
+    }
+
+    unsafe fn set_viewports<T>(&mut self, _: u32, _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Viewport>,
+    {
+        unsupported()
+    }
+
+    unsafe fn set_scissors<T>(&mut self, _: u32, _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Rect>,
+    {
+        unsupported()
+    }
+
+    unsafe fn set_stencil_reference(&mut self, _: pso::Face, _: pso::StencilValue) {
+        unsupported()
+    }
+
+    unsafe fn set_stencil_read_mask(&mut self, _: pso::Face, _: pso::StencilValue) {
+        unsupported()
+    }
+
+    unsafe fn set_stencil_write_mask(&mut self, _: pso::Face, _: pso::StencilValue) {
+        unsupported()
+    }
+
+    unsafe fn set_blend_constants(&mut self, _: pso::ColorValue) {
+        unsupported()
+    }
+
+    unsafe fn set_depth_bounds(&mut self, _: Range<f32>) {
+        unsupported()
+    }
+
+    unsafe fn set_line_width(&mut self, _: f32) {
+        unsupported()
+    }
+
+    unsafe fn set_depth_bias(&mut self, _: pso::DepthBias) {
+        unsupported()
+    }
+
+    unsafe fn begin_render_pass<T>(
+        &mut self,
+        _: &(),
+        _: &(),
+        _: pso::Rect,
+        _: T,
+        _: command::SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<command::ClearValue>,
+    {
+        unsupported()
+    }
+
+    unsafe fn next_subpass(&mut self, _: command::SubpassContents) {
+        unsupported()
+    }
+
+    unsafe fn end_render_pass(&mut self) {
+        unsupported()
+    }
+
+    unsafe fn bind_graphics_pipeline(&mut self, _: &()) {
+        unsupported()
+    }
+
+    unsafe fn bind_graphics_descriptor_sets<I, J>(&mut self, _: &(), _: usize, _: I, _: J)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+        J: IntoIterator,
+        J::Item: Borrow<command::DescriptorSetOffset>,
+    {
+        unsupported()
+    }
+
+    unsafe fn bind_compute_pipeline(&mut self, _: &()) {
+        unsupported()
+    }
+
+    unsafe fn bind_compute_descriptor_sets<I, J>(&mut self, _: &(), _: usize, _: I, _: J)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+        J: IntoIterator,
+        J::Item: Borrow<command::DescriptorSetOffset>,
+    {
+        unsupported()
+    }
+
+    unsafe fn dispatch(&mut self, _: hal::WorkGroupCount) {
+        unsupported()
+    }
+
+    unsafe fn dispatch_indirect(&mut self, _: &(), _: buffer::Offset) {
+        unsupported()
+    }
+
+    unsafe fn copy_buffer<T>(&mut self, _: &(), _: &(), _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<command::BufferCopy>,
+    {
+        unsupported()
+    }
+
+    unsafe fn copy_image<T>(&mut self, _: &(), _: image::Layout, _: &(), _: image::Layout, _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<command::ImageCopy>,
+    {
+        unsupported()
+    }
+
+    unsafe fn copy_buffer_to_image<T>(&mut self, _: &(), _: &(), _: image::Layout, _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<command::BufferImageCopy>,
+    {
+        unsupported()
+    }
+
+    unsafe fn copy_image_to_buffer<T>(&mut self, _: &(), _: image::Layout, _: &(), _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<command::BufferImageCopy>,
+    {
+        unsupported()
+    }
+
+    unsafe fn draw(&mut self, _: Range<hal::VertexCount>, _: Range<hal::InstanceCount>) {
+        unsupported()
+    }
+
+    unsafe fn draw_indexed(
+        &mut self,
+        _: Range<hal::IndexCount>,
+        _: hal::VertexOffset,
+        _: Range<hal::InstanceCount>,
+    ) {
+        unsupported()
+    }
+
+    unsafe fn draw_indirect(&mut self, _: &(), _: buffer::Offset, _: hal::DrawCount, _: u32) {
+        unsupported()
+    }
+
+    unsafe fn draw_indexed_indirect(
+        &mut self,
+        _: &(),
+        _: buffer::Offset,
+        _: hal::DrawCount,
+        _: u32,
+    ) {
+        unsupported()
+    }
+
+    unsafe fn set_event(&mut self, _: &(), _: pso::PipelineStage) {
+        unsupported()
+    }
+
+    unsafe fn reset_event(&mut self, _: &(), _: pso::PipelineStage) {
+        unsupported()
+    }
+
+    unsafe fn wait_events<'a, I, J>(&mut self, _: I, _: Range<pso::PipelineStage>, _: J)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+        J: IntoIterator,
+        J::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        unsupported()
+    }
+
+    unsafe fn begin_query(&mut self, _: query::Query<Backend>, _: query::ControlFlags) {
+        unsupported()
+    }
+
+    unsafe fn end_query(&mut self, _: query::Query<Backend>) {
+        unsupported()
+    }
+
+    unsafe fn reset_query_pool(&mut self, _: &(), _: Range<query::Id>) {
+        unsupported()
+    }
+
+    unsafe fn copy_query_pool_results(
+        &mut self,
+        _: &(),
+        _: Range<query::Id>,
+        _: &(),
+        _: buffer::Offset,
+        _: buffer::Offset,
+        _: query::ResultFlags,
+    ) {
+        unsupported()
+    }
+
+    unsafe fn write_timestamp(&mut self, _: pso::PipelineStage, _: query::Query<Backend>) {
+        unsupported()
+    }
+
+    unsafe fn push_graphics_constants(
+        &mut self,
+        _: &(),
+        _: pso::ShaderStageFlags,
+        _: u32,
+        _: &[u32],
+    ) {
+        unsupported()
+    }
+
+    unsafe fn push_compute_constants(&mut self, _: &(), _: u32, _: &[u32]) {
+        unsupported()
+    }
+
+    unsafe fn execute_commands<'a, T, I>(&mut self, _: I)
+    where
+        T: 'a + Borrow<CommandBuffer>,
+        I: IntoIterator<Item = &'a T>,
+    {
+        unsupported()
+    }
+}
+
+/// Unsupported descriptor pool.
+#[derive(Clone, Copy, Debug)]
+pub struct DescriptorPool;
+impl pso::DescriptorPool<Backend> for DescriptorPool {
+    unsafe fn free_sets<I>(&mut self, _descriptor_sets: I)
+    where
+        I: IntoIterator<Item = ()>,
+    {
+        unsupported()
+    }
+
+    unsafe fn reset(&mut self) {
+        unsupported()
+    }
+}
+
+/// Unsupported surface.
+#[derive(Clone, Copy, Debug)]
+pub struct Surface;
+impl window::Surface<Backend> for Surface {
+    fn supports_queue_family(&self, _: &QueueFamily) -> bool {
+        unsupported()
+    }
+
+    fn capabilities(&self, _: &PhysicalDevice) -> window::SurfaceCapabilities {
+        unsupported()
+    }
+
+    fn supported_formats(&self, _: &PhysicalDevice) -> Option<Vec<format::Format>> {
+        unsupported()
+    }
+}
+impl window::PresentationSurface<Backend> for Surface {
+    type SwapchainImage = ();
+
+    unsafe fn configure_swapchain(
+        &mut self,
+        _: &Device,
+        _: window::SwapchainConfig,
+    ) -> Result<(), window::CreationError> {
+        unsupported()
+    }
+
+    unsafe fn unconfigure_swapchain(&mut self, _: &Device) {
+        unsupported()
+    }
+
+    unsafe fn acquire_image(
+        &mut self,
+        _: u64,
+    ) -> Result<((), Option<window::Suboptimal>), window::AcquireError> {
+        unsupported()
+    }
+}
+
+/// Unsupported swapchain.
+#[derive(Clone, Copy, Debug)]
+pub struct Swapchain;
+impl window::Swapchain<Backend> for Swapchain {
+    unsafe fn acquire_image(
+        &mut self,
+        _: u64,
+        _: Option<&()>,
+        _: Option<&()>,
+    ) -> Result<(window::SwapImageIndex, Option<window::Suboptimal>), window::AcquireError> {
+        unsupported()
+    }
+}
+
+/// Instance without adapters.
+#[derive(Clone, Copy, Debug)]
+pub struct Instance;
+
+impl hal::Instance<Backend> for Instance {
+    fn create(_name: &str, _version: u32) -> Result<Self, hal::UnsupportedBackend> {
+        Ok(Instance)
+    }
+
+    fn enumerate_adapters(&self) -> Vec<adapter::Adapter<Backend>> {
+        vec![]
+    }
+
+    unsafe fn create_surface(
+        &self,
+        _: &impl raw_window_handle::HasRawWindowHandle,
+    ) -> Result<Surface, window::InitError> {
+        unsupported()
+    }
+
+    unsafe fn destroy_surface(&self, _surface: Surface) {
+        unsupported()
+    }
+}
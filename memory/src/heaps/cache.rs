@@ -0,0 +1,477 @@
+use {
+    super::{
+        memory_type::{pick_kind, suitable_types},
+        Heaps, HeapsError, MemoryBlock,
+    },
+    crate::{allocator::Kind, block::Block, usage::MemoryUsage},
+    gfx_hal::memory::Properties,
+    std::{
+        collections::HashMap,
+        hash::Hash,
+        ops::DerefMut,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    },
+};
+
+/// Config for `BlockCache`.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockCacheConfig {
+    /// Largest allocation served by the cache.
+    /// Must be power of two.
+    pub max_block_size: u64,
+
+    /// Number of blocks allocated from `Heaps` at once when size class runs dry.
+    pub batch_size: usize,
+
+    /// Maximum number of free blocks kept per size class.
+    pub max_cached: usize,
+}
+
+impl Default for BlockCacheConfig {
+    fn default() -> Self {
+        BlockCacheConfig {
+            max_block_size: 64 * 1024,
+            batch_size: 16,
+            max_cached: 64,
+        }
+    }
+}
+
+/// Cache of small memory blocks owned by single thread
+/// in front of `Heaps` shared between threads.
+///
+/// Requests are rounded up to power-of-two size classes
+/// and served from blocks cached per memory type, allocator kind and size class
+/// without touching `Heaps`.
+/// Size class that runs dry is refilled with `batch_size` blocks at once.
+/// Freed blocks are kept until `max_cached` blocks of the class are cached.
+///
+/// Cached blocks are accounted as used by `Heaps`.
+/// Only blocks of dynamic, buddy and TLSF allocators are cached
+/// as those reuse memory of freed blocks individually.
+/// Tagged allocations and allocations that would get other kinds bypass the cache.
+/// Bins are filled only from the fittest memory type.
+/// If it can't refill the bin, request is served by `Heaps::allocate`
+/// which may spill it to another type.
+/// Spilled blocks are returned to `Heaps` when freed.
+///
+/// Blocks are allocated from `Heaps` when bin is refilled
+/// and served and freed later without `Heaps` knowing.
+/// So while `Heaps` has budget set, records allocation trace or tracks leaks
+/// the cache is bypassed and cached blocks are returned to `Heaps`.
+/// Blocks of caches left unused are returned by `drain_idle`.
+#[derive(Debug)]
+pub struct BlockCache<B: gfx_hal::Backend> {
+    config: BlockCacheConfig,
+    types: Vec<(Properties, Vec<(Kind, u64)>)>,
+    bins: Bins<(u32, Kind, u64), MemoryBlock<B>>,
+    caching: Arc<AtomicBool>,
+    idle: bool,
+}
+
+/// Check if blocks of allocator kind can be kept in the cache.
+fn cacheable(kind: Kind) -> bool {
+    match kind {
+        Kind::Dynamic | Kind::Buddy | Kind::Tlsf => true,
+        _ => false,
+    }
+}
+
+/// Free blocks grouped by key.
+#[derive(Debug)]
+struct Bins<K: Eq + Hash, T> {
+    max_cached: usize,
+    bins: HashMap<K, Vec<T>>,
+}
+
+impl<K, T> Bins<K, T>
+where
+    K: Eq + Hash,
+{
+    fn new(max_cached: usize) -> Self {
+        Bins {
+            max_cached,
+            bins: HashMap::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.bins.values().map(Vec::len).sum()
+    }
+
+    /// Take cached block.
+    fn take(&mut self, key: &K) -> Option<T> {
+        self.bins.get_mut(key).and_then(Vec::pop)
+    }
+
+    /// Allocate up to `batch_size` blocks.
+    /// Returns first block and caches the rest, up to `max_cached`.
+    /// Fails only if first allocation fails.
+    fn refill<E>(
+        &mut self,
+        key: K,
+        batch_size: usize,
+        mut allocate: impl FnMut() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let block = allocate()?;
+        let bin = self.bins.entry(key).or_default();
+        for _ in 1..batch_size {
+            if bin.len() >= self.max_cached {
+                break;
+            }
+            match allocate() {
+                Ok(block) => bin.push(block),
+                Err(_) => break,
+            }
+        }
+        Ok(block)
+    }
+
+    /// Cache freed block.
+    /// Returns block back if its bin is full.
+    fn put(&mut self, key: K, block: T) -> Result<(), T> {
+        let bin = self.bins.entry(key).or_default();
+        if bin.len() < self.max_cached {
+            bin.push(block);
+            Ok(())
+        } else {
+            Err(block)
+        }
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.bins.drain().flat_map(|(_, bin)| bin)
+    }
+}
+
+impl<B> BlockCache<B>
+where
+    B: gfx_hal::Backend,
+{
+    /// Create cache for blocks of the `heaps`.
+    pub fn new(heaps: &Heaps<B>, config: BlockCacheConfig) -> Self {
+        assert!(
+            config.max_block_size.is_power_of_two(),
+            "Max block size must be power of two"
+        );
+        assert_ne!(config.batch_size, 0, "Batch size must be non-zero");
+
+        BlockCache {
+            config,
+            types: heaps
+                .types
+                .iter()
+                .map(|mt| (mt.properties(), mt.allocators().collect()))
+                .collect(),
+            bins: Bins::new(config.max_cached),
+            caching: heaps.caching.clone(),
+            idle: false,
+        }
+    }
+
+    /// Get number of cached blocks.
+    pub fn cached(&self) -> usize {
+        self.bins.len()
+    }
+
+    /// Pick memory type, allocator kind and size class the same way `Heaps` would.
+    /// Returns `None` if request must bypass the cache.
+    fn key(
+        &self,
+        mask: u32,
+        usage: &impl MemoryUsage,
+        size: u64,
+        align: u64,
+    ) -> Option<(u32, Kind, u64)> {
+        if usage.tag().is_some()
+            || size > self.config.max_block_size
+            || align > self.config.max_block_size
+        {
+            return None;
+        }
+
+        let class = size.max(align).next_power_of_two();
+        let &(memory_index, _) = suitable_types(
+            self.types.iter().map(|&(properties, _)| properties),
+            mask,
+            usage,
+        )
+        .first()?;
+        let (_, allocators) = &self.types[memory_index as usize];
+
+        let kind = pick_kind(allocators.iter().cloned(), usage, class);
+        if cacheable(kind) {
+            Some((memory_index, kind, class))
+        } else {
+            None
+        }
+    }
+
+    /// Allocate memory block.
+    /// Calls `heaps` to lock shared `Heaps` only if request can't be served from the cache.
+    /// See `Heaps::allocate`.
+    pub fn allocate<G>(
+        &mut self,
+        device: &B::Device,
+        heaps: impl FnOnce() -> G,
+        mask: u32,
+        usage: impl MemoryUsage,
+        size: u64,
+        align: u64,
+    ) -> Result<MemoryBlock<B>, HeapsError>
+    where
+        G: DerefMut<Target = Heaps<B>>,
+    {
+        self.idle = false;
+        if !self.caching.load(Ordering::Relaxed) {
+            let mut heaps = heaps();
+            self.drain(device, &mut heaps);
+            return heaps.allocate(device, mask, usage, size, align);
+        }
+
+        let key = match self.key(mask, &usage, size, align) {
+            Some(key) => key,
+            None => return heaps().allocate(device, mask, usage, size, align),
+        };
+
+        if let Some(block) = self.bins.take(&key) {
+            return Ok(block);
+        }
+
+        let (memory_index, _, class) = key;
+        let mut heaps = heaps();
+        let refilled = self.bins.refill(key, self.config.batch_size, || {
+            heaps.allocate_from(
+                device,
+                1 << memory_index,
+                memory_index,
+                &usage,
+                class,
+                class,
+            )
+        });
+
+        match refilled {
+            Ok(block) => Ok(block),
+            Err(error) => {
+                log::trace!("Failed to refill block cache: {}", error);
+                heaps.allocate(device, mask, usage, size, align)
+            }
+        }
+    }
+
+    /// Free memory block.
+    /// Calls `heaps` to lock shared `Heaps` only if block can't be kept in the cache.
+    pub fn free<G>(&mut self, device: &B::Device, heaps: impl FnOnce() -> G, block: MemoryBlock<B>)
+    where
+        G: DerefMut<Target = Heaps<B>>,
+    {
+        self.idle = false;
+        if !self.caching.load(Ordering::Relaxed) {
+            let mut heaps = heaps();
+            self.drain(device, &mut heaps);
+            heaps.free(device, block);
+            return;
+        }
+
+        let class = block.requested;
+        let kind = block.block.kind();
+        if block.tag.is_none()
+            && block.spill.is_none()
+            && cacheable(kind)
+            && class.is_power_of_two()
+            && class <= self.config.max_block_size
+            && block.range().start % class == 0
+        {
+            if let Err(block) = self.bins.put((block.memory_index, kind, class), block) {
+                heaps().free(device, block)
+            }
+        } else {
+            heaps().free(device, block)
+        }
+    }
+
+    /// Free all cached blocks.
+    pub fn drain(&mut self, device: &B::Device, heaps: &mut Heaps<B>) {
+        for block in self.bins.drain() {
+            heaps.free(device, block);
+        }
+    }
+
+    /// Free all cached blocks if cache wasn't used since previous call.
+    pub fn drain_idle(&mut self, device: &B::Device, heaps: &mut Heaps<B>) {
+        if self.idle {
+            self.drain(device, heaps);
+        }
+        self.idle = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cacheable, Bins, BlockCache, BlockCacheConfig};
+    use crate::{
+        allocator::{DynamicConfig, Kind},
+        fake,
+        heaps::{Heaps, HeapsConfig},
+        trace::TraceEvent,
+        usage::Data,
+    };
+    use gfx_hal::memory::Properties;
+
+    fn heaps() -> Heaps<fake::Backend> {
+        let config = HeapsConfig {
+            linear: None,
+            dynamic: Some(DynamicConfig {
+                block_size_granularity: 256,
+                max_chunk_size: 4096,
+                min_device_allocation: 4096,
+            }),
+            buddy: None,
+            tlsf: None,
+            ring: None,
+        };
+        unsafe { Heaps::new(vec![(Properties::DEVICE_LOCAL, 0, config)], vec![1 << 20]) }
+    }
+
+    #[test]
+    fn only_reusing_kinds_are_cacheable() {
+        assert!(cacheable(Kind::Dynamic));
+        assert!(cacheable(Kind::Buddy));
+        assert!(cacheable(Kind::Tlsf));
+        assert!(!cacheable(Kind::Dedicated));
+        assert!(!cacheable(Kind::Linear));
+        assert!(!cacheable(Kind::Ring));
+    }
+
+    #[test]
+    fn bins_are_separate_per_key() {
+        let mut bins = Bins::new(4);
+        assert_eq!(bins.put(1, 10), Ok(()));
+        assert_eq!(bins.put(2, 20), Ok(()));
+        assert_eq!(bins.len(), 2);
+
+        assert_eq!(bins.take(&3), None);
+        assert_eq!(bins.take(&2), Some(20));
+        assert_eq!(bins.take(&2), None);
+        assert_eq!(bins.take(&1), Some(10));
+        assert_eq!(bins.len(), 0);
+    }
+
+    #[test]
+    fn full_bin_rejects_block() {
+        let mut bins = Bins::new(2);
+        assert_eq!(bins.put(1, 10), Ok(()));
+        assert_eq!(bins.put(1, 11), Ok(()));
+        assert_eq!(bins.put(1, 12), Err(12));
+        assert_eq!(bins.put(2, 20), Ok(()));
+
+        let mut drained = bins.drain().collect::<Vec<_>>();
+        drained.sort();
+        assert_eq!(drained, vec![10, 11, 20]);
+        assert_eq!(bins.len(), 0);
+    }
+
+    #[test]
+    fn refill_caches_batch() {
+        let mut bins = Bins::new(8);
+        let mut next = 0;
+        let block = bins.refill(1, 4, || -> Result<u32, ()> {
+            next += 1;
+            Ok(next)
+        });
+        assert_eq!(block, Ok(1));
+        assert_eq!(next, 4);
+        assert_eq!(bins.len(), 3);
+        assert_eq!(bins.take(&1), Some(4));
+    }
+
+    #[test]
+    fn refill_respects_max_cached() {
+        let mut bins = Bins::new(2);
+        let mut next = 0;
+        let block = bins.refill(1, 16, || -> Result<u32, ()> {
+            next += 1;
+            Ok(next)
+        });
+        assert_eq!(block, Ok(1));
+        assert_eq!(next, 3);
+        assert_eq!(bins.len(), 2);
+    }
+
+    #[test]
+    fn refill_stops_on_failure() {
+        let mut bins = Bins::new(8);
+        let mut next = 0;
+        let block = bins.refill(1, 8, || {
+            next += 1;
+            if next <= 2 {
+                Ok(next)
+            } else {
+                Err(())
+            }
+        });
+        assert_eq!(block, Ok(1));
+        assert_eq!(bins.len(), 1);
+
+        let failed = bins.refill(2, 8, || -> Result<u32, ()> { Err(()) });
+        assert_eq!(failed, Err(()));
+        assert_eq!(bins.take(&2), None);
+    }
+
+    #[test]
+    fn traced_heaps_bypass_cache() {
+        let device = fake::Device::new();
+        let mut heaps = heaps();
+        let mut cache = BlockCache::new(&heaps, BlockCacheConfig::default());
+
+        let block = cache
+            .allocate(&device, || &mut heaps, 1, Data, 256, 1)
+            .unwrap();
+        assert_ne!(cache.cached(), 0);
+
+        heaps.start_trace();
+        let traced = cache
+            .allocate(&device, || &mut heaps, 1, Data, 256, 1)
+            .unwrap();
+        assert_eq!(cache.cached(), 0);
+        cache.free(&device, || &mut heaps, traced);
+        cache.free(&device, || &mut heaps, block);
+        assert_eq!(cache.cached(), 0);
+
+        let sizes = heaps
+            .take_trace()
+            .unwrap()
+            .events
+            .into_iter()
+            .filter_map(|event| match event {
+                TraceEvent::Allocate { size, .. } => Some(size),
+                TraceEvent::Free { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![256]);
+        heaps.dispose(&device);
+    }
+
+    #[test]
+    fn idle_cache_is_drained() {
+        let device = fake::Device::new();
+        let mut heaps = heaps();
+        let mut cache = BlockCache::new(&heaps, BlockCacheConfig::default());
+
+        let block = cache
+            .allocate(&device, || &mut heaps, 1, Data, 256, 1)
+            .unwrap();
+        cache.free(&device, || &mut heaps, block);
+        cache.drain_idle(&device, &mut heaps);
+        assert_ne!(cache.cached(), 0);
+        cache.drain_idle(&device, &mut heaps);
+        assert_eq!(cache.cached(), 0);
+
+        heaps.dispose(&device);
+    }
+}
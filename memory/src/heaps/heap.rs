@@ -1,10 +1,11 @@
-use crate::utilization::*;
+use {super::MemoryBudget, crate::utilization::*};
 
 #[derive(Debug)]
 pub(super) struct MemoryHeap {
     size: u64,
     used: u64,
     effective: u64,
+    budget: Option<u64>,
 }
 
 impl MemoryHeap {
@@ -13,9 +14,39 @@ impl MemoryHeap {
             size,
             used: 0,
             effective: 0,
+            budget: None,
         }
     }
 
+    pub(super) fn set_budget(&mut self, budget: Option<MemoryBudget>) {
+        self.budget = budget.map(|budget| match budget {
+            MemoryBudget::Absolute(bytes) => bytes,
+            MemoryBudget::Fraction(fraction) => {
+                assert!(
+                    fraction >= 0.0 && fraction <= 1.0,
+                    "Budget fraction must be in range [0; 1]"
+                );
+                (self.size as f64 * fraction as f64) as u64
+            }
+        });
+    }
+
+    pub(super) fn budget(&self) -> Option<u64> {
+        self.budget
+    }
+
+    /// Get number of bytes to release for device allocation of `size` to fit in the budget.
+    pub(super) fn budget_deficit(&self, size: u64) -> u64 {
+        match self.budget {
+            Some(budget) => (self.used + size).saturating_sub(budget),
+            None => 0,
+        }
+    }
+
+    pub(super) fn used(&self) -> u64 {
+        self.used
+    }
+
     pub(super) fn available(&self) -> u64 {
         if self.used > self.size {
             log::warn!("Heap size exceeded");
@@ -47,3 +78,126 @@ impl MemoryHeap {
         }
     }
 }
+
+/// Memory that can be released when device allocation doesn't fit in the heap budget.
+pub(super) trait Evict {
+    /// Get number of bytes to release for device allocation of `size` to fit in the budget.
+    fn deficit(&self, size: u64) -> u64;
+
+    /// Try to release `deficit` bytes.
+    /// Returns `None` if there is no way to release memory,
+    /// otherwise whether allocation should be retried.
+    fn evict(&mut self, deficit: u64) -> Option<bool>;
+}
+
+/// Evict memory until device allocation of `size` fits in the budget.
+/// Returns `false` if eviction doesn't make progress.
+pub(super) fn fit_budget(evict: &mut impl Evict, size: u64) -> bool {
+    let mut deficit = evict.deficit(size);
+    while deficit > 0 {
+        match evict.evict(deficit) {
+            Some(true) => {}
+            Some(false) | None => return false,
+        }
+
+        let new_deficit = evict.deficit(size);
+        if new_deficit >= deficit {
+            return false;
+        }
+        deficit = new_deficit;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fit_budget, Evict, MemoryBudget, MemoryHeap};
+
+    #[test]
+    fn deficit_counts_used_memory() {
+        let mut heap = MemoryHeap::new(1024);
+        assert_eq!(heap.budget_deficit(4096), 0);
+
+        heap.set_budget(Some(MemoryBudget::Fraction(0.5)));
+        assert_eq!(heap.budget(), Some(512));
+        heap.allocated(256, 100);
+        assert_eq!(heap.budget_deficit(0), 0);
+        assert_eq!(heap.budget_deficit(256), 0);
+        assert_eq!(heap.budget_deficit(300), 44);
+
+        heap.freed(256, 100);
+        assert_eq!(heap.budget_deficit(300), 0);
+    }
+
+    /// Heap with evictable blocks.
+    struct FakeHeap {
+        heap: MemoryHeap,
+        evictable: Vec<u64>,
+        callback: bool,
+        calls: Vec<u64>,
+    }
+
+    impl FakeHeap {
+        fn new(budget: u64, used: u64, evictable: Vec<u64>) -> Self {
+            let mut heap = MemoryHeap::new(1 << 30);
+            heap.set_budget(Some(MemoryBudget::Absolute(budget)));
+            heap.allocated(used, used);
+            FakeHeap {
+                heap,
+                evictable,
+                callback: true,
+                calls: Vec::new(),
+            }
+        }
+    }
+
+    impl Evict for FakeHeap {
+        fn deficit(&self, size: u64) -> u64 {
+            self.heap.budget_deficit(size)
+        }
+
+        fn evict(&mut self, deficit: u64) -> Option<bool> {
+            if !self.callback {
+                return None;
+            }
+            self.calls.push(deficit);
+            // Release one block per call.
+            match self.evictable.pop() {
+                Some(size) => {
+                    self.heap.freed(size, size);
+                    Some(true)
+                }
+                None => Some(false),
+            }
+        }
+    }
+
+    #[test]
+    fn fitting_allocation_doesnt_evict() {
+        let mut heap = FakeHeap::new(1024, 512, vec![256]);
+        assert!(fit_budget(&mut heap, 512));
+        assert!(heap.calls.is_empty());
+        assert_eq!(heap.evictable, vec![256]);
+    }
+
+    #[test]
+    fn evicts_until_allocation_fits() {
+        let mut heap = FakeHeap::new(1024, 1024, vec![64, 256, 128]);
+        assert!(fit_budget(&mut heap, 300));
+        assert_eq!(heap.calls, vec![300, 172]);
+        assert_eq!(heap.evictable, vec![64]);
+        assert_eq!(heap.deficit(300), 0);
+    }
+
+    #[test]
+    fn fails_without_progress() {
+        let mut heap = FakeHeap::new(1024, 1024, vec![128]);
+        assert!(!fit_budget(&mut heap, 512));
+        assert_eq!(heap.calls, vec![512, 384]);
+
+        let mut heap = FakeHeap::new(1024, 1024, vec![128]);
+        heap.callback = false;
+        assert!(!fit_budget(&mut heap, 512));
+        assert_eq!(heap.evictable, vec![128]);
+    }
+}
@@ -11,9 +11,11 @@ pub(super) struct MemoryType<B: gfx_hal::Backend> {
     dedicated: DedicatedAllocator,
     linear: Option<LinearAllocator<B>>,
     dynamic: Option<DynamicAllocator<B>>,
+    buddy: Option<BuddyAllocator<B>>,
+    tlsf: Option<TlsfAllocator<B>>,
+    ring: Option<RingAllocator<B>>,
     // chunk: Option<ChunkAllocator>,
-    used: u64,
-    effective: u64,
+    utilization: MemoryUtilization,
 }
 
 impl<B> MemoryType<B>
@@ -40,8 +42,23 @@ where
             dynamic: config
                 .dynamic
                 .map(|config| DynamicAllocator::new(memory_type, properties, config)),
-            used: 0,
-            effective: 0,
+            buddy: config
+                .buddy
+                .map(|config| BuddyAllocator::new(memory_type, properties, config)),
+            tlsf: config
+                .tlsf
+                .map(|config| TlsfAllocator::new(memory_type, properties, config)),
+            ring: if properties.contains(Properties::CPU_VISIBLE) {
+                config
+                    .ring
+                    .map(|config| RingAllocator::new(memory_type, properties, config))
+            } else {
+                None
+            },
+            utilization: MemoryUtilization {
+                used: 0,
+                effective: 0,
+            },
         }
     }
 
@@ -53,6 +70,37 @@ where
         self.heap_index
     }
 
+    /// Get kinds of sub-allocators with their max allocation sizes.
+    pub(super) fn allocators(&self) -> impl Iterator<Item = (Kind, u64)> {
+        let dynamic = self
+            .dynamic
+            .as_ref()
+            .map(|dynamic| (Kind::Dynamic, dynamic.max_allocation()));
+        let linear = self
+            .linear
+            .as_ref()
+            .map(|linear| (Kind::Linear, linear.max_allocation()));
+        let buddy = self
+            .buddy
+            .as_ref()
+            .map(|buddy| (Kind::Buddy, buddy.max_allocation()));
+        let tlsf = self
+            .tlsf
+            .as_ref()
+            .map(|tlsf| (Kind::Tlsf, tlsf.max_allocation()));
+        let ring = self
+            .ring
+            .as_ref()
+            .map(|ring| (Kind::Ring, ring.max_allocation()));
+
+        dynamic
+            .into_iter()
+            .chain(linear)
+            .chain(buddy)
+            .chain(tlsf)
+            .chain(ring)
+    }
+
     pub(super) fn alloc(
         &mut self,
         device: &B::Device,
@@ -61,8 +109,7 @@ where
         align: u64,
     ) -> Result<(BlockFlavor<B>, u64), gfx_hal::device::AllocationError> {
         let (block, allocated) = self.alloc_impl(device, usage, size, align)?;
-        self.effective += block.size();
-        self.used += allocated;
+        self.utilization.allocated(allocated, block.size());
         Ok((block, allocated))
     }
 
@@ -73,62 +120,114 @@ where
         size: u64,
         align: u64,
     ) -> Result<(BlockFlavor<B>, u64), gfx_hal::device::AllocationError> {
-        match (self.dynamic.as_mut(), self.linear.as_mut()) {
-            (Some(dynamic), Some(linear)) => {
-                if dynamic.max_allocation() >= size
-                    && usage.allocator_fitness(Kind::Dynamic)
-                        > usage.allocator_fitness(Kind::Linear)
-                {
-                    dynamic
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dynamic(block), size))
-                } else if linear.max_allocation() >= size
-                    && usage.allocator_fitness(Kind::Linear) > 0
-                {
-                    linear
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Linear(block), size))
-                } else {
-                    self.dedicated
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dedicated(block), size))
-                }
-            }
-            (Some(dynamic), None) => {
-                if dynamic.max_allocation() >= size && usage.allocator_fitness(Kind::Dynamic) > 0 {
-                    dynamic
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dynamic(block), size))
-                } else {
-                    self.dedicated
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dedicated(block), size))
-                }
-            }
-            (None, Some(linear)) => {
-                if linear.max_allocation() >= size && usage.allocator_fitness(Kind::Linear) > 0 {
-                    linear
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Linear(block), size))
-                } else {
-                    self.dedicated
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dedicated(block), size))
-                }
-            }
-            (None, None) => self
+        let kind = pick_kind(self.allocators(), &usage, size.max(align));
+
+        match kind {
+            Kind::Dynamic => self
+                .dynamic
+                .as_mut()
+                .unwrap()
+                .alloc(device, size, align)
+                .map(|(block, size)| (BlockFlavor::Dynamic(block), size)),
+            Kind::Linear => self
+                .linear
+                .as_mut()
+                .unwrap()
+                .alloc(device, size, align)
+                .map(|(block, size)| (BlockFlavor::Linear(block), size)),
+            Kind::Buddy => self
+                .buddy
+                .as_mut()
+                .unwrap()
+                .alloc(device, size, align)
+                .map(|(block, size)| (BlockFlavor::Buddy(block), size)),
+            Kind::Tlsf => self
+                .tlsf
+                .as_mut()
+                .unwrap()
+                .alloc(device, size, align)
+                .map(|(block, size)| (BlockFlavor::Tlsf(block), size)),
+            Kind::Ring => self
+                .ring
+                .as_mut()
+                .unwrap()
+                .alloc(device, size, align)
+                .map(|(block, size)| (BlockFlavor::Ring(block), size)),
+            Kind::Dedicated => self
                 .dedicated
                 .alloc(device, size, align)
                 .map(|(block, size)| (BlockFlavor::Dedicated(block), size)),
         }
     }
 
+    /// Set epoch of following ring allocations.
+    pub(super) fn set_ring_epoch(&mut self, epoch: u64) {
+        if let Some(ring) = self.ring.as_mut() {
+            ring.set_epoch(epoch);
+        }
+    }
+
+    /// Retire ring allocations of epochs less than `complete`.
+    /// Returns amount of memory returned to the device.
+    pub(super) fn retire_ring_epochs(&mut self, device: &B::Device, complete: u64) -> u64 {
+        let freed = self
+            .ring
+            .as_mut()
+            .map_or(0, |ring| ring.retire(device, complete));
+        self.utilization.freed(freed, 0);
+        freed
+    }
+
+    /// Pick chunks to evacuate.
+    pub(super) fn begin_defragmentation(&mut self) -> usize {
+        self.dynamic
+            .as_mut()
+            .map_or(0, |dynamic| dynamic.begin_defragmentation())
+    }
+
+    /// Check if block should be moved.
+    pub(super) fn is_evacuating(&self, block: &BlockFlavor<B>) -> bool {
+        match (block, &self.dynamic) {
+            (BlockFlavor::Dynamic(block), Some(dynamic)) => dynamic.is_evacuating(block),
+            _ => false,
+        }
+    }
+
+    /// Allocate block to move evacuated block into.
+    pub(super) fn alloc_relocation(
+        &mut self,
+        device: &B::Device,
+        size: u64,
+        align: u64,
+    ) -> Result<(BlockFlavor<B>, u64), gfx_hal::device::AllocationError> {
+        let (block, allocated) = self
+            .dynamic
+            .as_mut()
+            .expect("Only dynamic blocks are relocated")
+            .alloc(device, size, align)?;
+        let block = BlockFlavor::Dynamic(block);
+        self.utilization.allocated(allocated, block.size());
+        Ok((block, allocated))
+    }
+
+    pub(super) fn end_defragmentation(&mut self) {
+        if let Some(dynamic) = self.dynamic.as_mut() {
+            dynamic.end_defragmentation();
+        }
+    }
+
     pub(super) fn free(&mut self, device: &B::Device, block: BlockFlavor<B>) -> u64 {
-        match block {
+        let size = block.size();
+        let freed = match block {
             BlockFlavor::Dedicated(block) => self.dedicated.free(device, block),
             BlockFlavor::Linear(block) => self.linear.as_mut().unwrap().free(device, block),
             BlockFlavor::Dynamic(block) => self.dynamic.as_mut().unwrap().free(device, block),
-        }
+            BlockFlavor::Buddy(block) => self.buddy.as_mut().unwrap().free(device, block),
+            BlockFlavor::Tlsf(block) => self.tlsf.as_mut().unwrap().free(device, block),
+            BlockFlavor::Ring(block) => self.ring.as_mut().unwrap().free(device, block),
+        };
+        self.utilization.freed(freed, size);
+        freed
     }
 
     pub(super) fn dispose(self, device: &B::Device) {
@@ -142,16 +241,175 @@ where
             dynamic.dispose();
             log::trace!("Dynamic allocator disposed");
         }
+        if let Some(buddy) = self.buddy {
+            buddy.dispose();
+            log::trace!("Buddy allocator disposed");
+        }
+        if let Some(tlsf) = self.tlsf {
+            tlsf.dispose();
+            log::trace!("Tlsf allocator disposed");
+        }
+        if let Some(ring) = self.ring {
+            ring.dispose(device);
+            log::trace!("Ring allocator disposed");
+        }
     }
 
     pub(super) fn utilization(&self) -> MemoryTypeUtilization {
         MemoryTypeUtilization {
-            utilization: MemoryUtilization {
-                used: self.used,
-                effective: self.effective,
-            },
+            utilization: self.utilization,
             properties: self.properties,
             heap_index: self.heap_index,
         }
     }
+
+    pub(super) fn allocators_utilization(&self) -> Vec<AllocatorUtilization> {
+        std::iter::once(self.dedicated.utilization())
+            .chain(self.linear.as_ref().map(LinearAllocator::utilization))
+            .chain(self.dynamic.as_ref().map(DynamicAllocator::utilization))
+            .chain(self.buddy.as_ref().map(BuddyAllocator::utilization))
+            .chain(self.tlsf.as_ref().map(TlsfAllocator::utilization))
+            .chain(self.ring.as_ref().map(RingAllocator::utilization))
+            .collect()
+    }
+}
+
+/// Get memory types from `mask` that have properties required by `usage`
+/// with their fitness. Fittest types go first.
+/// Among equally fit types the last one goes first.
+pub(super) fn suitable_types(
+    properties: impl IntoIterator<Item = Properties>,
+    mask: u32,
+    usage: &impl MemoryUsage,
+) -> smallvec::SmallVec<[(u32, u32); 64]> {
+    let required = usage.properties_required();
+    let mut suitable = properties
+        .into_iter()
+        .enumerate()
+        .filter(|&(index, _)| (mask & (1u32 << index)) != 0)
+        .filter(|&(_, properties)| properties.contains(required))
+        .map(|(index, properties)| (index as u32, usage.memory_fitness(properties)))
+        .collect::<smallvec::SmallVec<[_; 64]>>();
+
+    suitable.sort_by_key(|&(_, fitness)| fitness);
+    suitable.reverse();
+    suitable
+}
+
+/// Check if allocation from `memory_index` is spilled from fitter memory type.
+/// `suitable` must be sorted with `suitable_types`.
+/// Returns the fittest memory type if spilled.
+pub(super) fn spilled_from(suitable: &[(u32, u32)], memory_index: u32) -> Option<u32> {
+    let &(preferred, preferred_fitness) = suitable.first()?;
+    let (_, fitness) = suitable
+        .iter()
+        .find(|&&(index, _)| index == memory_index)
+        .expect("Memory type must be suitable");
+
+    if *fitness < preferred_fitness {
+        Some(preferred)
+    } else {
+        None
+    }
+}
+
+/// Pick fittest sub-allocator able to serve the request.
+/// Fallback to dedicated allocation otherwise.
+///
+/// `size` must account for alignment of the request,
+/// as sub-allocators can't serve blocks aligned stricter than their max allocation.
+pub(super) fn pick_kind(
+    allocators: impl IntoIterator<Item = (Kind, u64)>,
+    usage: &impl MemoryUsage,
+    size: u64,
+) -> Kind {
+    allocators
+        .into_iter()
+        .filter(|&(_, max_allocation)| max_allocation >= size)
+        .map(|(kind, _)| (kind, usage.allocator_fitness(kind)))
+        .filter(|&(_, fitness)| fitness > 0)
+        .max_by_key(|&(_, fitness)| fitness)
+        .map_or(Kind::Dedicated, |(kind, _)| kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pick_kind, spilled_from, suitable_types};
+    use crate::{
+        allocator::Kind,
+        usage::{Data, Download},
+    };
+    use gfx_hal::memory::Properties;
+
+    fn types() -> [Properties; 4] {
+        [
+            Properties::DEVICE_LOCAL,
+            Properties::CPU_VISIBLE,
+            Properties::DEVICE_LOCAL,
+            Properties::DEVICE_LOCAL | Properties::CPU_VISIBLE,
+        ]
+    }
+
+    #[test]
+    fn suitable_types_are_ordered_by_fitness() {
+        let suitable = suitable_types(types().iter().cloned(), !0, &Data);
+        let indices = suitable.iter().map(|&(index, _)| index).collect::<Vec<_>>();
+        assert_eq!(indices, [2, 0, 3]);
+
+        let suitable = suitable_types(types().iter().cloned(), 0b1010, &Data);
+        let indices = suitable.iter().map(|&(index, _)| index).collect::<Vec<_>>();
+        assert_eq!(indices, [3]);
+
+        assert!(suitable_types(types().iter().cloned(), 0b0010, &Data).is_empty());
+    }
+
+    #[test]
+    fn equally_fit_types_dont_spill() {
+        let suitable = suitable_types(types().iter().cloned(), !0, &Data);
+        assert_eq!(spilled_from(&suitable, 2), None);
+        assert_eq!(spilled_from(&suitable, 0), None);
+        assert_eq!(spilled_from(&suitable, 3), Some(2));
+    }
+
+    #[test]
+    fn spill_is_relative_to_mask() {
+        let suitable = suitable_types(types().iter().cloned(), 0b1000, &Data);
+        assert_eq!(spilled_from(&suitable, 3), None);
+
+        let suitable = suitable_types(types().iter().cloned(), !0, &Download);
+        assert_eq!(spilled_from(&suitable, suitable[0].0), None);
+    }
+
+    const ALLOCATORS: [(Kind, u64); 2] = [(Kind::Dynamic, 1 << 20), (Kind::Buddy, 1 << 26)];
+
+    #[test]
+    fn fittest_allocator_is_picked() {
+        assert_eq!(
+            pick_kind(ALLOCATORS.iter().cloned(), &Data, 1024),
+            Kind::Dynamic
+        );
+        assert_eq!(
+            pick_kind(ALLOCATORS.iter().cloned(), &Data, 1 << 22),
+            Kind::Buddy
+        );
+    }
+
+    #[test]
+    fn oversized_requests_are_dedicated() {
+        assert_eq!(
+            pick_kind(ALLOCATORS.iter().cloned(), &Data, 1 << 27),
+            Kind::Dedicated
+        );
+        assert_eq!(pick_kind(None, &Data, 1), Kind::Dedicated);
+    }
+
+    #[test]
+    fn alignment_limits_allocators() {
+        let size: u64 = 1024;
+        let align: u64 = 1 << 27;
+        assert_eq!(
+            pick_kind(ALLOCATORS.iter().cloned(), &Data, size.max(align)),
+            Kind::Dedicated
+        );
+    }
 }
@@ -1,10 +1,32 @@
+mod cache;
 mod heap;
 mod memory_type;
 
+pub use self::cache::{BlockCache, BlockCacheConfig};
+
 use {
-    self::{heap::MemoryHeap, memory_type::MemoryType},
-    crate::{allocator::*, block::Block, mapping::*, usage::MemoryUsage, util::*, utilization::*},
-    std::ops::Range,
+    self::{
+        heap::{Evict, MemoryHeap},
+        memory_type::{spilled_from, suitable_types, MemoryType},
+    },
+    crate::{
+        allocator::*,
+        block::Block,
+        leak::{LeakReport, LeakTracker},
+        mapping::*,
+        trace::{AllocationTrace, TraceRecorder, TracedUsage},
+        usage::{MemoryUsage, Tagged},
+        util::*,
+        utilization::*,
+    },
+    std::{
+        collections::HashMap,
+        ops::Range,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    },
 };
 
 /// Possible errors returned by `Heaps`.
@@ -15,6 +37,8 @@ pub enum HeapsError {
     AllocationError(gfx_hal::device::AllocationError),
     /// No memory types among required for resource with requested properties was found.
     NoSuitableMemory(u32, gfx_hal::memory::Properties),
+    /// Allocation would exceed budget of the heap and eviction didn't release enough memory.
+    BudgetExceeded(usize, u64),
 }
 
 impl std::fmt::Display for HeapsError {
@@ -26,6 +50,11 @@ impl std::fmt::Display for HeapsError {
                 "Memory type among ({}) with properties ({:?}) not found",
                 e, e2
             ),
+            HeapsError::BudgetExceeded(heap, size) => write!(
+                f,
+                "Allocation of {} bytes exceeds budget of the heap {}",
+                size, heap
+            ),
         }
     }
 }
@@ -52,13 +81,121 @@ pub struct HeapsConfig {
 
     /// Config for dynamic sub-allocator.
     pub dynamic: Option<DynamicConfig>,
+
+    /// Config for buddy sub-allocator.
+    pub buddy: Option<BuddyConfig>,
+
+    /// Config for tlsf sub-allocator.
+    pub tlsf: Option<TlsfConfig>,
+
+    /// Config for ring sub-allocator.
+    pub ring: Option<RingConfig>,
+}
+
+/// Soft limit of memory allocated from a heap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MemoryBudget {
+    /// Limit in bytes.
+    Absolute(u64),
+
+    /// Limit as fraction of the heap size.
+    Fraction(f32),
+}
+
+/// Callback invoked when allocation exceeds heap budget.
+/// Receives heaps to free memory blocks to, device,
+/// index of the heap and number of bytes to release.
+/// Returns `true` if memory was released and allocation should be retried.
+pub type EvictionCallback<B> =
+    dyn FnMut(&mut Heaps<B>, &<B as gfx_hal::Backend>::Device, usize, u64) -> bool + Send + Sync;
+
+/// Allocator of `MemoryBlock`s.
+/// Implemented by `Heaps` and by front ends that serve blocks on its behalf.
+pub trait BlockAllocator<B: gfx_hal::Backend> {
+    /// Allocate memory block
+    /// from one of memory types specified by `mask`,
+    /// for intended `usage`,
+    /// with `size`
+    /// and `align` requirements.
+    fn allocate(
+        &mut self,
+        device: &B::Device,
+        mask: u32,
+        usage: impl MemoryUsage,
+        size: u64,
+        align: u64,
+    ) -> Result<MemoryBlock<B>, HeapsError>;
+
+    /// Free memory block.
+    fn free(&mut self, device: &B::Device, block: MemoryBlock<B>);
+}
+
+impl<B> BlockAllocator<B> for Heaps<B>
+where
+    B: gfx_hal::Backend,
+{
+    fn allocate(
+        &mut self,
+        device: &B::Device,
+        mask: u32,
+        usage: impl MemoryUsage,
+        size: u64,
+        align: u64,
+    ) -> Result<MemoryBlock<B>, HeapsError> {
+        Heaps::allocate(self, device, mask, usage, size, align)
+    }
+
+    fn free(&mut self, device: &B::Device, block: MemoryBlock<B>) {
+        Heaps::free(self, device, block)
+    }
 }
 
 /// Heaps available on particular physical device.
-#[derive(Debug)]
 pub struct Heaps<B: gfx_hal::Backend> {
     types: Vec<MemoryType<B>>,
     heaps: Vec<MemoryHeap>,
+    tags: HashMap<Option<&'static str>, TagStats>,
+    eviction: Option<Box<EvictionCallback<B>>>,
+    next_id: u64,
+    trace: Option<TraceRecorder>,
+    spilled: SpillStats,
+    leaks: Option<LeakTracker>,
+    caching: Arc<AtomicBool>,
+}
+
+/// Blocks allocated outside of preferred memory type.
+#[derive(Clone, Copy, Debug, Default)]
+struct SpillStats {
+    count: u64,
+    bytes: u64,
+}
+
+/// Utilization by allocation tag.
+#[derive(Clone, Copy, Debug, Default)]
+struct TagStats {
+    block_bytes: u64,
+    requested_bytes: u64,
+    count: u64,
+}
+
+impl<B> std::fmt::Debug for Heaps<B>
+where
+    B: gfx_hal::Backend,
+{
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("Heaps")
+            .field("types", &self.types)
+            .field("heaps", &self.heaps)
+            .field("tags", &self.tags)
+            .field("eviction", &self.eviction.as_ref().map(|_| ".."))
+            .field("next_id", &self.next_id)
+            .field("trace", &self.trace)
+            .field("spilled", &self.spilled)
+            .field("leaks", &self.leaks)
+            .field("caching", &self.caching)
+            .finish()
+    }
 }
 
 impl<B> Heaps<B>
@@ -95,6 +232,118 @@ where
                 })
                 .collect(),
             heaps,
+            tags: HashMap::new(),
+            eviction: None,
+            next_id: 0,
+            trace: None,
+            spilled: SpillStats::default(),
+            leaks: None,
+            caching: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Set soft budget for the heap.
+    /// Budget limits memory allocated from device, i.e. new chunks of sub-allocators
+    /// and dedicated allocations, while sub-allocations from existing chunks always fit.
+    /// Device allocations that would exceed the budget invoke eviction callback
+    /// and fail with `HeapsError::BudgetExceeded` if not enough memory was released.
+    pub fn set_budget(&mut self, heap_index: usize, budget: Option<MemoryBudget>) {
+        self.heaps[heap_index].set_budget(budget);
+        self.update_caching();
+    }
+
+    /// Get budget of the heap in bytes.
+    pub fn budget(&self, heap_index: usize) -> Option<u64> {
+        self.heaps[heap_index].budget()
+    }
+
+    /// Set callback to invoke when allocation exceeds heap budget.
+    /// See `EvictionCallback` for details.
+    pub fn set_eviction_callback(
+        &mut self,
+        callback: impl FnMut(&mut Heaps<B>, &B::Device, usize, u64) -> bool + Send + Sync + 'static,
+    ) {
+        self.eviction = Some(Box::new(callback));
+    }
+
+    /// Remove eviction callback.
+    pub fn clear_eviction_callback(&mut self) {
+        self.eviction = None;
+    }
+
+    /// Start recording allocation trace.
+    /// Every following `allocate` and `free` call is recorded until `take_trace` is called.
+    /// Restarts recording if it is already in progress.
+    pub fn start_trace(&mut self) {
+        self.trace = Some(TraceRecorder::new());
+        self.update_caching();
+    }
+
+    /// Stop recording and take allocation trace.
+    /// Returns `None` if recording wasn't started.
+    pub fn take_trace(&mut self) -> Option<AllocationTrace> {
+        let trace = self.trace.take().map(TraceRecorder::finish);
+        self.update_caching();
+        trace
+    }
+
+    /// Enable or disable leak tracking.
+    /// While enabled every allocated block records backtrace of the allocation
+    /// and blocks left allocated are reported by `dispose`.
+    /// Blocks allocated before tracking was enabled are not reported.
+    pub fn track_leaks(&mut self, enable: bool) {
+        match (enable, self.leaks.is_some()) {
+            (true, false) => self.leaks = Some(LeakTracker::default()),
+            (false, true) => self.leaks = None,
+            _ => {}
+        }
+        self.update_caching();
+    }
+
+    /// Get blocks left allocated since leak tracking was enabled, grouped by call site.
+    /// Returns `None` if leak tracking is disabled.
+    pub fn leak_report(&self) -> Option<LeakReport> {
+        self.leaks.as_ref().map(LeakTracker::report)
+    }
+
+    /// Allow `BlockCache`s to serve blocks only while allocations
+    /// don't need to be checked against budget, traced or tracked for leaks.
+    fn update_caching(&self) {
+        let caching = self.trace.is_none()
+            && self.leaks.is_none()
+            && self.heaps.iter().all(|heap| heap.budget().is_none());
+        self.caching.store(caching, Ordering::Relaxed);
+    }
+
+    /// Get total number of bytes allocated from device.
+    pub(crate) fn allocated(&self) -> u64 {
+        self.heaps.iter().map(MemoryHeap::used).sum()
+    }
+
+    /// Ensure that device allocation of `size` bytes fits in the budget of the heap.
+    /// Invokes eviction callback until it does.
+    fn fit_budget(
+        &mut self,
+        device: &B::Device,
+        heap_index: usize,
+        size: u64,
+    ) -> Result<(), HeapsError> {
+        log::debug!(
+            "Device allocation of {} bytes exceeds budget of the heap {} by {} bytes",
+            size,
+            heap_index,
+            self.heaps[heap_index].budget_deficit(size)
+        );
+
+        let mut evict = HeapEviction {
+            heaps: self,
+            device,
+            heap_index,
+        };
+        if heap::fit_budget(&mut evict, size) {
+            Ok(())
+        } else {
+            Err(HeapsError::BudgetExceeded(heap_index, size))
         }
     }
 
@@ -113,40 +362,63 @@ where
     ) -> Result<MemoryBlock<B>, HeapsError> {
         debug_assert!(fits_u32(self.types.len()));
 
-        let (memory_index, _, _) = {
-            let suitable_types = self
-                .types
-                .iter()
-                .enumerate()
-                .filter(|(index, _)| (mask & (1u32 << index)) != 0)
-                .filter_map(|(index, mt)| {
-                    if mt.properties().contains(usage.properties_required()) {
-                        let fitness = usage.memory_fitness(mt.properties());
-                        Some((index, mt, fitness))
-                    } else {
-                        None
-                    }
-                })
-                .collect::<smallvec::SmallVec<[_; 64]>>();
+        let suitable_types =
+            suitable_types(self.types.iter().map(MemoryType::properties), mask, &usage);
+
+        if suitable_types.is_empty() {
+            return Err(HeapsError::NoSuitableMemory(
+                mask,
+                usage.properties_required(),
+            ));
+        }
 
-            if suitable_types.is_empty() {
-                return Err(HeapsError::NoSuitableMemory(
-                    mask,
-                    usage.properties_required(),
-                ));
+        let mut last_error = None;
+        for &(memory_index, _) in &suitable_types {
+            let heap_index = self.types[memory_index as usize].heap_index();
+            if self.heaps[heap_index].available() <= size + align {
+                continue;
             }
 
-            suitable_types
-                .into_iter()
-                .filter(|(_, mt, _)| self.heaps[mt.heap_index()].available() > size + align)
-                .max_by_key(|&(_, _, fitness)| fitness)
-                .ok_or_else(|| {
-                    log::error!("All suitable heaps are exhausted. {:#?}", self);
-                    gfx_hal::device::OutOfMemory::Device
-                })?
-        };
+            let mut block =
+                match self.allocate_from(device, mask, memory_index, &usage, size, align) {
+                    Ok(block) => block,
+                    Err(error) if spills(&error) => {
+                        log::debug!(
+                            "Allocation of {} bytes from type {} failed: {}",
+                            size,
+                            memory_index,
+                            error
+                        );
+                        last_error = Some(error);
+                        continue;
+                    }
+                    Err(error) => return Err(error),
+                };
+
+            if let Some(preferred) = spilled_from(&suitable_types, memory_index) {
+                log::warn!(
+                    "Allocation of {} bytes spilled from type {} to type {}",
+                    size,
+                    preferred,
+                    memory_index
+                );
+                block.spill = Some(Box::new(Spill {
+                    preferred,
+                    usage: TracedUsage::capture(
+                        &usage,
+                        self.types.iter().map(MemoryType::properties),
+                    ),
+                }));
+                self.spilled.count += 1;
+                self.spilled.bytes += block.requested;
+            }
+            return Ok(block);
+        }
 
-        self.allocate_from(device, memory_index as u32, usage, size, align)
+        Err(last_error.unwrap_or_else(|| {
+            log::error!("All suitable heaps are exhausted. {:#?}", self);
+            gfx_hal::device::OutOfMemory::Device.into()
+        }))
     }
 
     /// Allocate memory block
@@ -154,9 +426,11 @@ where
     /// for intended `usage`,
     /// with `size`
     /// and `align` requirements.
+    /// Allocation is recorded into trace with `mask` it was requested with.
     fn allocate_from(
         &mut self,
         device: &B::Device,
+        mask: u32,
         memory_index: u32,
         usage: impl MemoryUsage,
         size: u64,
@@ -171,20 +445,99 @@ where
         );
         assert!(fits_usize(memory_index));
 
-        let ref mut memory_type = self.types[memory_index as usize];
-        let ref mut memory_heap = self.heaps[memory_type.heap_index()];
+        let block = self.alloc_in_budget(device, memory_index, size, |memory_type| {
+            memory_type.alloc(device, &usage, size, align)
+        })?;
+
+        let block = MemoryBlock {
+            block,
+            memory_index,
+            align,
+            requested: size,
+            tag: usage.tag(),
+            id: self.next_id,
+            spill: None,
+        };
+        self.next_id += 1;
+        self.track_allocated(&block, mask, &usage);
+        Ok(block)
+    }
+
+    /// Allocate block from `memory_index` with `alloc`
+    /// keeping memory allocated from device within the budget of the heap.
+    fn alloc_in_budget(
+        &mut self,
+        device: &B::Device,
+        memory_index: u32,
+        size: u64,
+        mut alloc: impl FnMut(
+            &mut MemoryType<B>,
+        )
+            -> Result<(BlockFlavor<B>, u64), gfx_hal::device::AllocationError>,
+    ) -> Result<BlockFlavor<B>, HeapsError> {
+        let heap_index = self.types[memory_index as usize].heap_index();
+
+        // Budget limits memory allocated from device,
+        // so it is checked once allocator reports that it allocated new memory object.
+        let mut retained = false;
+        loop {
+            let memory_type = &mut self.types[memory_index as usize];
+            let memory_heap = &mut self.heaps[heap_index];
+
+            if memory_heap.available() < size {
+                return Err(gfx_hal::device::OutOfMemory::Device.into());
+            }
+
+            let (block, allocated) = alloc(memory_type)?;
+            memory_heap.allocated(allocated, block.size());
+            if allocated == 0 || retained || memory_heap.budget_deficit(0) == 0 {
+                return Ok(block);
+            }
+
+            // Return memory object that exceeds the budget before evicting.
+            let block_size = block.size();
+            let freed = memory_type.free(device, block);
+            memory_heap.freed(freed, block_size);
+            if freed < allocated {
+                // Allocator keeps the memory, so retry will reuse it.
+                retained = true;
+                continue;
+            }
+
+            self.fit_budget(device, heap_index, allocated)?;
+        }
+    }
 
-        if memory_heap.available() < size {
-            return Err(gfx_hal::device::OutOfMemory::Device.into());
+    /// Account newly allocated block in tags, trace and leak tracker.
+    fn track_allocated(&mut self, block: &MemoryBlock<B>, mask: u32, usage: &impl MemoryUsage) {
+        self.tag_allocated(block);
+        if let Some(trace) = &mut self.trace {
+            let traced = TracedUsage::capture(usage, self.types.iter().map(MemoryType::properties));
+            trace.allocated(block.id, mask, traced, block.requested, block.align);
         }
+        if let Some(leaks) = &mut self.leaks {
+            leaks.allocated(block.id, block.requested, block.tag);
+        }
+    }
 
-        let (block, allocated) = memory_type.alloc(device, usage, size, align)?;
-        memory_heap.allocated(allocated, block.size());
+    fn tag_allocated(&mut self, block: &MemoryBlock<B>) {
+        let stats = self.tags.entry(block.tag).or_default();
+        stats.block_bytes += block.size();
+        stats.requested_bytes += block.requested;
+        stats.count += 1;
+    }
 
-        Ok(MemoryBlock {
-            block,
-            memory_index,
-        })
+    fn tag_freed(&mut self, block: &MemoryBlock<B>) {
+        let stats = self
+            .tags
+            .get_mut(&block.tag)
+            .expect("Block must be accounted");
+        stats.block_bytes -= block.size();
+        stats.requested_bytes -= block.requested;
+        stats.count -= 1;
+        if stats.count == 0 {
+            self.tags.remove(&block.tag);
+        }
     }
 
     /// Free memory block.
@@ -192,6 +545,17 @@ where
     /// Memory block must be allocated from this heap.
     pub fn free(&mut self, device: &B::Device, block: MemoryBlock<B>) {
         // trace!("Free block '{:#?}'", block);
+        self.tag_freed(&block);
+        if let Some(trace) = &mut self.trace {
+            trace.freed(block.id);
+        }
+        if let Some(leaks) = &mut self.leaks {
+            leaks.freed(block.id);
+        }
+        if block.spill.is_some() {
+            self.spilled.count -= 1;
+            self.spilled.bytes -= block.requested;
+        }
         let memory_index = block.memory_index;
         debug_assert!(fits_usize(memory_index));
         let size = block.size();
@@ -202,10 +566,195 @@ where
         memory_heap.freed(freed, size);
     }
 
+    /// Set epoch of following allocations served by ring allocators.
+    /// Epochs are monotonically increasing counters,
+    /// like `Frame::index` or `FenceEpoch::epoch` of the submitting queue.
+    pub fn set_ring_epoch(&mut self, epoch: u64) {
+        for memory_type in &mut self.types {
+            memory_type.set_ring_epoch(epoch);
+        }
+    }
+
+    /// Retire ring allocations of epochs less than `complete`,
+    /// like `Frames::complete_upper_bound`.
+    /// Memory of retired epochs is reused by following ring allocations
+    /// whether their blocks were freed or not.
+    ///
+    /// Device must not access blocks of retired epochs anymore.
+    pub fn retire_ring_epochs(&mut self, device: &B::Device, complete: u64) {
+        for memory_type in &mut self.types {
+            let freed = memory_type.retire_ring_epochs(device, complete);
+            self.heaps[memory_type.heap_index()].freed(freed, 0);
+        }
+    }
+
+    /// Move live blocks out of sparsely used chunks so that those chunks can be released.
+    ///
+    /// Every block from `blocks` that resides in a chunk picked for evacuation
+    /// is replaced with newly allocated block of the same memory type.
+    /// `relocate` is called with old and new block before replacement,
+    /// so that caller can record commands to copy the content
+    /// and rebind resources to the new block.
+    ///
+    /// Old blocks are kept alive in returned `Defragmentation`
+    /// which must be passed to `complete_defragmentation`
+    /// once recorded copy operations are complete.
+    pub fn defragment<'a>(
+        &mut self,
+        device: &B::Device,
+        blocks: impl IntoIterator<Item = &'a mut MemoryBlock<B>>,
+        mut relocate: impl FnMut(&MemoryBlock<B>, &MemoryBlock<B>),
+    ) -> Defragmentation<B> {
+        let picked: usize = self
+            .types
+            .iter_mut()
+            .map(MemoryType::begin_defragmentation)
+            .sum();
+
+        let mut evacuated = Vec::new();
+        if picked == 0 {
+            return Defragmentation { evacuated };
+        }
+
+        for block in blocks {
+            let memory_index = block.memory_index;
+            let memory_type = &self.types[memory_index as usize];
+            if !memory_type.is_evacuating(&block.block) {
+                continue;
+            }
+            let properties = memory_type.properties();
+
+            let size = block.size();
+            let align = block.align;
+            let new_block = match self.alloc_in_budget(device, memory_index, size, |memory_type| {
+                memory_type.alloc_relocation(device, size, align)
+            }) {
+                Ok(new_block) => new_block,
+                Err(error) => {
+                    log::warn!("Defragmentation is incomplete: {}", error);
+                    break;
+                }
+            };
+
+            let new_block = MemoryBlock {
+                block: new_block,
+                memory_index,
+                align,
+                requested: block.requested,
+                tag: block.tag,
+                id: self.next_id,
+                spill: block.spill.take(),
+            };
+            self.next_id += 1;
+
+            // Relocation is traced as allocation of a new block
+            // followed by free of the evacuated block in `complete_defragmentation`.
+            let usage = TracedUsage::relocation(properties);
+            self.track_allocated(&new_block, 1 << memory_index, &usage);
+
+            relocate(&*block, &new_block);
+            evacuated.push(std::mem::replace(block, new_block));
+        }
+
+        log::trace!("{} blocks relocated", evacuated.len());
+        Defragmentation { evacuated }
+    }
+
+    /// Move spilled blocks back to their preferred memory types where space allows.
+    ///
+    /// Every spilled block from `blocks` is replaced with newly allocated block
+    /// of the memory type it would get if preferred type wasn't exhausted.
+    /// Blocks that still don't fit are left in place.
+    /// `relocate` is called with old and new block before replacement,
+    /// so that caller can record commands to copy the content
+    /// and rebind resources to the new block.
+    ///
+    /// Old blocks are kept alive in returned `Defragmentation`
+    /// which must be passed to `complete_defragmentation`
+    /// once recorded copy operations are complete.
+    pub fn migrate_spilled<'a>(
+        &mut self,
+        device: &B::Device,
+        blocks: impl IntoIterator<Item = &'a mut MemoryBlock<B>>,
+        mut relocate: impl FnMut(&MemoryBlock<B>, &MemoryBlock<B>),
+    ) -> Defragmentation<B> {
+        let mut evacuated = Vec::new();
+        for block in blocks {
+            let spill = match block.spill.take() {
+                Some(spill) => spill,
+                None => continue,
+            };
+
+            let result = match block.tag {
+                Some(tag) => self.allocate_from(
+                    device,
+                    1 << spill.preferred,
+                    spill.preferred,
+                    Tagged::new(&spill.usage, tag),
+                    block.requested,
+                    block.align,
+                ),
+                None => self.allocate_from(
+                    device,
+                    1 << spill.preferred,
+                    spill.preferred,
+                    &spill.usage,
+                    block.requested,
+                    block.align,
+                ),
+            };
+
+            let new_block = match result {
+                Ok(new_block) => new_block,
+                Err(error) => {
+                    log::trace!("Spilled block {} can't be migrated: {}", block.id, error);
+                    block.spill = Some(spill);
+                    continue;
+                }
+            };
+
+            self.spilled.count -= 1;
+            self.spilled.bytes -= block.requested;
+
+            // Migration is traced as allocation of a new block
+            // followed by free of the evacuated block in `complete_defragmentation`.
+            relocate(&*block, &new_block);
+            evacuated.push(std::mem::replace(block, new_block));
+        }
+
+        log::trace!("{} spilled blocks migrated", evacuated.len());
+        Defragmentation { evacuated }
+    }
+
+    /// Complete defragmentation.
+    /// Frees evacuated blocks and releases chunks that became unused.
+    ///
+    /// Device must not access evacuated blocks anymore.
+    pub fn complete_defragmentation(
+        &mut self,
+        device: &B::Device,
+        defragmentation: Defragmentation<B>,
+    ) {
+        for block in defragmentation.evacuated {
+            self.free(device, block);
+        }
+
+        for memory_type in &mut self.types {
+            memory_type.end_defragmentation();
+        }
+    }
+
     /// Dispose of allocator.
     /// Cleanup allocators before dropping.
     /// Will panic if memory instances are left allocated.
+    ///
+    /// If leak tracking is enabled and blocks are left allocated
+    /// leak report is logged before allocators are disposed.
     pub fn dispose(self, device: &B::Device) {
+        if let Some(report) = self.leak_report().filter(|report| !report.is_empty()) {
+            log::error!("Heaps disposed with live blocks. {}", report);
+        }
+
         for mt in self.types {
             mt.dispose(device)
         }
@@ -213,9 +762,29 @@ where
 
     /// Get memory utilization.
     pub fn utilization(&self) -> TotalMemoryUtilization {
+        let mut tags = self
+            .tags
+            .iter()
+            .map(|(tag, stats)| MemoryTagUtilization {
+                block_bytes: stats.block_bytes,
+                requested_bytes: stats.requested_bytes,
+                tag: tag.map(String::from),
+                count: stats.count,
+            })
+            .collect::<Vec<_>>();
+        tags.sort_by_key(|tag| std::cmp::Reverse(tag.block_bytes));
+
         TotalMemoryUtilization {
             heaps: self.heaps.iter().map(MemoryHeap::utilization).collect(),
             types: self.types.iter().map(MemoryType::utilization).collect(),
+            allocators: self
+                .types
+                .iter()
+                .map(MemoryType::allocators_utilization)
+                .collect(),
+            tags,
+            spilled_blocks: self.spilled.count,
+            spilled_bytes: self.spilled.bytes,
         }
     }
 }
@@ -225,6 +794,18 @@ where
 pub struct MemoryBlock<B: gfx_hal::Backend> {
     block: BlockFlavor<B>,
     memory_index: u32,
+    align: u64,
+    requested: u64,
+    tag: Option<&'static str>,
+    id: u64,
+    spill: Option<Box<Spill>>,
+}
+
+/// Origin of the block allocated outside of preferred memory type.
+#[derive(Debug)]
+struct Spill {
+    preferred: u32,
+    usage: TracedUsage,
 }
 
 impl<B> MemoryBlock<B>
@@ -235,6 +816,83 @@ where
     pub fn memory_type(&self) -> u32 {
         self.memory_index
     }
+
+    /// Get tag the block was allocated with.
+    pub fn tag(&self) -> Option<&'static str> {
+        self.tag
+    }
+
+    /// Get memory type id the block would be allocated from
+    /// if it didn't spill because that type was exhausted.
+    /// Returns `None` if block resides in preferred memory type.
+    pub fn spilled_from(&self) -> Option<u32> {
+        self.spill.as_ref().map(|spill| spill.preferred)
+    }
+}
+
+/// Eviction of blocks from the heap through eviction callback.
+struct HeapEviction<'a, B: gfx_hal::Backend> {
+    heaps: &'a mut Heaps<B>,
+    device: &'a B::Device,
+    heap_index: usize,
+}
+
+impl<B> Evict for HeapEviction<'_, B>
+where
+    B: gfx_hal::Backend,
+{
+    fn deficit(&self, size: u64) -> u64 {
+        self.heaps.heaps[self.heap_index].budget_deficit(size)
+    }
+
+    fn evict(&mut self, deficit: u64) -> Option<bool> {
+        let mut eviction = self.heaps.eviction.take()?;
+        let retry = eviction(self.heaps, self.device, self.heap_index, deficit);
+        if self.heaps.eviction.is_none() {
+            self.heaps.eviction = Some(eviction);
+        }
+        Some(retry)
+    }
+}
+
+/// Check if allocation failure should be retried on less fit memory type.
+fn spills(error: &HeapsError) -> bool {
+    match error {
+        HeapsError::AllocationError(gfx_hal::device::AllocationError::OutOfMemory(
+            gfx_hal::device::OutOfMemory::Device,
+        ))
+        | HeapsError::BudgetExceeded(_, _) => true,
+        _ => false,
+    }
+}
+
+/// Blocks evacuated by `Heaps::defragment` or `Heaps::migrate_spilled`.
+/// Must be passed to `Heaps::complete_defragmentation`
+/// after copy operations recorded by the caller are complete.
+#[derive(Debug)]
+#[must_use = "Evacuated blocks must be passed to `Heaps::complete_defragmentation`"]
+pub struct Defragmentation<B: gfx_hal::Backend> {
+    evacuated: Vec<MemoryBlock<B>>,
+}
+
+impl<B> Defragmentation<B>
+where
+    B: gfx_hal::Backend,
+{
+    /// Get number of relocated blocks.
+    pub fn len(&self) -> usize {
+        self.evacuated.len()
+    }
+
+    /// Check if no blocks were relocated.
+    pub fn is_empty(&self) -> bool {
+        self.evacuated.is_empty()
+    }
+
+    /// Get number of bytes to copy.
+    pub fn bytes(&self) -> u64 {
+        self.evacuated.iter().map(|block| block.size()).sum()
+    }
 }
 
 #[derive(Debug)]
@@ -242,6 +900,9 @@ enum BlockFlavor<B: gfx_hal::Backend> {
     Dedicated(DedicatedBlock<B>),
     Linear(LinearBlock<B>),
     Dynamic(DynamicBlock<B>),
+    Buddy(BuddyBlock<B>),
+    Tlsf(TlsfBlock<B>),
+    Ring(RingBlock<B>),
     // Chunk(ChunkBlock<B>),
 }
 
@@ -252,6 +913,9 @@ macro_rules! any_block {
             Dedicated($block) => $expr,
             Linear($block) => $expr,
             Dynamic($block) => $expr,
+            Buddy($block) => $expr,
+            Tlsf($block) => $expr,
+            Ring($block) => $expr,
             // Chunk($block) => $expr,
         }
     }};
@@ -261,6 +925,9 @@ macro_rules! any_block {
             Dedicated($block) => $expr,
             Linear($block) => $expr,
             Dynamic($block) => $expr,
+            Buddy($block) => $expr,
+            Tlsf($block) => $expr,
+            Ring($block) => $expr,
             // Chunk($block) => $expr,
         }
     }};
@@ -270,6 +937,9 @@ macro_rules! any_block {
             Dedicated($block) => $expr,
             Linear($block) => $expr,
             Dynamic($block) => $expr,
+            Buddy($block) => $expr,
+            Tlsf($block) => $expr,
+            Ring($block) => $expr,
             // Chunk($block) => $expr,
         }
     }};
@@ -279,6 +949,19 @@ impl<B> BlockFlavor<B>
 where
     B: gfx_hal::Backend,
 {
+    #[inline]
+    fn kind(&self) -> Kind {
+        use self::BlockFlavor::*;
+        match self {
+            Dedicated(_) => Kind::Dedicated,
+            Linear(_) => Kind::Linear,
+            Dynamic(_) => Kind::Dynamic,
+            Buddy(_) => Kind::Buddy,
+            Tlsf(_) => Kind::Tlsf,
+            Ring(_) => Kind::Ring,
+        }
+    }
+
     #[inline]
     fn size(&self) -> u64 {
         use self::BlockFlavor::*;
@@ -286,6 +969,9 @@ where
             Dedicated(block) => block.size(),
             Linear(block) => block.size(),
             Dynamic(block) => block.size(),
+            Buddy(block) => block.size(),
+            Tlsf(block) => block.size(),
+            Ring(block) => block.size(),
             // Chunk(block) => block.size(),
         }
     }
@@ -322,3 +1008,144 @@ where
         any_block!(&mut self.block => block.unmap(device))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Heaps, HeapsConfig, MemoryBlock, MemoryBudget};
+    use crate::{
+        allocator::DynamicConfig,
+        block::Block,
+        fake,
+        trace::TraceEvent,
+        usage::{Data, Tagged},
+    };
+    use gfx_hal::memory::Properties;
+    use std::collections::HashSet;
+
+    fn heaps() -> Heaps<fake::Backend> {
+        let config = HeapsConfig {
+            linear: None,
+            dynamic: Some(DynamicConfig {
+                block_size_granularity: 256,
+                max_chunk_size: 4096,
+                min_device_allocation: 4096,
+            }),
+            buddy: None,
+            tlsf: None,
+            ring: None,
+        };
+        unsafe { Heaps::new(vec![(Properties::DEVICE_LOCAL, 0, config)], vec![1 << 20]) }
+    }
+
+    /// Allocate blocks filling several chunks and keep every eighth,
+    /// so that all chunks become sparse.
+    fn sparse_blocks(
+        heaps: &mut Heaps<fake::Backend>,
+        device: &fake::Device,
+    ) -> Vec<MemoryBlock<fake::Backend>> {
+        let blocks = (0..64)
+            .map(|_| heaps.allocate(device, 1, Data, 256, 1).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut kept = Vec::new();
+        for (index, block) in blocks.into_iter().enumerate() {
+            if index % 8 == 0 {
+                kept.push(block);
+            } else {
+                heaps.free(device, block);
+            }
+        }
+        kept
+    }
+
+    #[test]
+    fn relocation_is_traced_under_own_id() {
+        let device = fake::Device::new();
+        let mut heaps = heaps();
+        heaps.start_trace();
+        heaps.track_leaks(true);
+
+        let mut blocks = sparse_blocks(&mut heaps, &device);
+        let old_ids = blocks.iter().map(|block| block.id).collect::<HashSet<_>>();
+
+        let mut relocated = 0;
+        let defragmentation = heaps.defragment(&device, blocks.iter_mut(), |_, _| relocated += 1);
+        assert!(relocated > 0);
+        assert!(blocks.iter().any(|block| !old_ids.contains(&block.id)));
+        heaps.complete_defragmentation(&device, defragmentation);
+
+        for block in blocks {
+            heaps.free(&device, block);
+        }
+        assert!(heaps.leak_report().unwrap().is_empty());
+
+        let mut live = HashSet::new();
+        for event in heaps.take_trace().unwrap().events {
+            match event {
+                TraceEvent::Allocate { id, .. } => assert!(live.insert(id)),
+                TraceEvent::Free { id, .. } => assert!(live.remove(&id)),
+            }
+        }
+        assert!(live.is_empty());
+
+        heaps.dispose(&device);
+        assert_eq!(device.allocations(), 0);
+    }
+
+    #[test]
+    fn relocation_respects_budget() {
+        let device = fake::Device::new();
+        let mut heaps = heaps();
+
+        let mut blocks = sparse_blocks(&mut heaps, &device);
+        let allocated = heaps.allocated();
+        heaps.set_budget(0, Some(MemoryBudget::Absolute(allocated)));
+
+        let defragmentation = heaps.defragment(&device, blocks.iter_mut(), |_, _| {});
+        assert!(heaps.allocated() <= allocated);
+        heaps.complete_defragmentation(&device, defragmentation);
+
+        for block in blocks {
+            heaps.free(&device, block);
+        }
+        heaps.dispose(&device);
+    }
+
+    #[test]
+    #[should_panic(expected = "is still used")]
+    fn tracked_leaks_do_not_skip_dispose() {
+        let device = fake::Device::new();
+        let mut heaps = heaps();
+        heaps.track_leaks(true);
+
+        let block = heaps.allocate(&device, 1, Data, 256, 1).unwrap();
+        std::mem::forget(block);
+        heaps.dispose(&device);
+    }
+
+    #[test]
+    fn tag_utilization_counts_block_and_requested_bytes() {
+        let device = fake::Device::new();
+        let mut heaps = heaps();
+
+        let tagged = heaps
+            .allocate(&device, 1, Tagged::new(Data, "mesh"), 100, 1)
+            .unwrap();
+        let untagged = heaps.allocate(&device, 1, Data, 256, 1).unwrap();
+
+        let tags = heaps.utilization().tags;
+        assert_eq!(tags.len(), 2);
+        let mesh = tags
+            .iter()
+            .find(|tag| tag.tag.as_ref().map(String::as_str) == Some("mesh"))
+            .unwrap();
+        assert_eq!(mesh.count, 1);
+        assert_eq!(mesh.block_bytes, tagged.size());
+        assert_eq!(mesh.requested_bytes, 100);
+
+        heaps.free(&device, tagged);
+        heaps.free(&device, untagged);
+        assert!(heaps.utilization().tags.is_empty());
+        heaps.dispose(&device);
+    }
+}
@@ -0,0 +1,175 @@
+//! Leak tracking of memory blocks.
+//! See `Heaps::track_leaks`.
+
+use {backtrace::Backtrace, std::collections::HashMap};
+
+/// Leaked blocks allocated from the same call site.
+#[derive(Clone, Debug)]
+pub struct LeakSite {
+    /// Number of leaked blocks.
+    pub count: u64,
+
+    /// Number of bytes requested by leaked blocks.
+    pub bytes: u64,
+
+    /// Tags leaked blocks were allocated with.
+    pub tags: Vec<Option<&'static str>>,
+
+    /// Resolved backtrace of the first leaked block.
+    pub backtrace: String,
+}
+
+/// Blocks left allocated, grouped by call site.
+#[derive(Clone, Debug, Default)]
+pub struct LeakReport {
+    /// Call sites of leaked blocks.
+    /// Sorted from most bytes leaked.
+    pub sites: Vec<LeakSite>,
+}
+
+impl LeakReport {
+    /// Check if there are no leaked blocks.
+    pub fn is_empty(&self) -> bool {
+        self.sites.is_empty()
+    }
+
+    /// Get total number of leaked blocks.
+    pub fn count(&self) -> u64 {
+        self.sites.iter().map(|site| site.count).sum()
+    }
+
+    /// Get total number of bytes requested by leaked blocks.
+    pub fn bytes(&self) -> u64 {
+        self.sites.iter().map(|site| site.bytes).sum()
+    }
+}
+
+impl std::fmt::Display for LeakReport {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            fmt,
+            "{} memory blocks leaked ({} bytes) from {} call sites",
+            self.count(),
+            self.bytes(),
+            self.sites.len()
+        )?;
+        for (index, site) in self.sites.iter().enumerate() {
+            let tags = site
+                .tags
+                .iter()
+                .map(|tag| tag.unwrap_or("<untagged>"))
+                .collect::<Vec<_>>();
+            writeln!(
+                fmt,
+                "Site {}: {} blocks, {} bytes, tags: [{}]",
+                index,
+                site.count,
+                site.bytes,
+                tags.join(", ")
+            )?;
+            writeln!(fmt, "{}", site.backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct LiveBlock {
+    size: u64,
+    tag: Option<&'static str>,
+    backtrace: Backtrace,
+}
+
+/// Records allocation site of every live block.
+#[derive(Debug, Default)]
+pub(crate) struct LeakTracker {
+    live: HashMap<u64, LiveBlock>,
+}
+
+impl LeakTracker {
+    pub(crate) fn allocated(&mut self, id: u64, size: u64, tag: Option<&'static str>) {
+        self.live.insert(
+            id,
+            LiveBlock {
+                size,
+                tag,
+                backtrace: Backtrace::new_unresolved(),
+            },
+        );
+    }
+
+    pub(crate) fn freed(&mut self, id: u64) {
+        self.live.remove(&id);
+    }
+
+    pub(crate) fn report(&self) -> LeakReport {
+        let mut live = self.live.iter().collect::<Vec<_>>();
+        live.sort_by_key(|&(id, _)| *id);
+
+        let mut sites: Vec<(Vec<usize>, &Backtrace, LeakSite)> = Vec::new();
+        for (_, block) in live {
+            let ips = block
+                .backtrace
+                .frames()
+                .iter()
+                .map(|frame| frame.ip() as usize)
+                .collect::<Vec<_>>();
+
+            match sites.iter_mut().find(|(site_ips, _, _)| *site_ips == ips) {
+                Some((_, _, site)) => {
+                    site.count += 1;
+                    site.bytes += block.size;
+                    if !site.tags.contains(&block.tag) {
+                        site.tags.push(block.tag);
+                    }
+                }
+                None => sites.push((
+                    ips,
+                    &block.backtrace,
+                    LeakSite {
+                        count: 1,
+                        bytes: block.size,
+                        tags: vec![block.tag],
+                        backtrace: String::new(),
+                    },
+                )),
+            }
+        }
+
+        let mut sites = sites
+            .into_iter()
+            .map(|(_, backtrace, mut site)| {
+                let mut backtrace = backtrace.clone();
+                backtrace.resolve();
+                site.backtrace = format!("{:?}", backtrace);
+                site
+            })
+            .collect::<Vec<_>>();
+        sites.sort_by_key(|site| std::cmp::Reverse(site.bytes));
+
+        LeakReport { sites }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LeakTracker;
+
+    #[test]
+    fn leaks_grouped_by_call_site() {
+        let mut tracker = LeakTracker::default();
+        for id in 0..3 {
+            tracker.allocated(id, 16, Some("loop"));
+        }
+        tracker.allocated(3, 8, None);
+        tracker.freed(0);
+
+        let report = tracker.report();
+        assert_eq!(report.sites.len(), 2);
+        assert_eq!(report.count(), 3);
+        assert_eq!(report.bytes(), 40);
+        assert_eq!(report.sites[0].count, 2);
+        assert_eq!(report.sites[0].tags, vec![Some("loop")]);
+        assert_eq!(report.sites[1].tags, vec![None]);
+    }
+}
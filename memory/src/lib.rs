@@ -14,18 +14,30 @@
 mod allocator;
 mod block;
 mod heaps;
+mod leak;
 mod mapping;
 mod memory;
+mod trace;
 mod usage;
 mod util;
 mod utilization;
+mod virtual_allocator;
+
+#[cfg(any(test, feature = "fake"))]
+pub mod fake;
 
 pub use crate::{
     allocator::*,
     block::Block,
-    heaps::{Heaps, HeapsConfig, HeapsError, MemoryBlock},
-    mapping::{write::Write, Coherent, MappedRange, MaybeCoherent, NonCoherent},
+    heaps::{
+        BlockAllocator, BlockCache, BlockCacheConfig, Defragmentation, EvictionCallback, Heaps,
+        HeapsConfig, HeapsError, MemoryBlock, MemoryBudget,
+    },
+    leak::{LeakReport, LeakSite},
+    mapping::{write::Write, Coherent, FlushBatch, MappedRange, MaybeCoherent, NonCoherent},
     memory::Memory,
+    trace::{AllocationTrace, ReplayReport, TraceEvent, TracedUsage},
     usage::*,
     utilization::*,
+    virtual_allocator::{VirtualAllocator, VirtualBlock, VirtualStats},
 };
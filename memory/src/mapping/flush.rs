@@ -0,0 +1,184 @@
+use {
+    super::{mapped_slice_mut, mapped_sub_range, MappedRange, NonCoherent},
+    crate::util::{aligned, fits_usize},
+    gfx_hal::{device::Device as _, Backend},
+    std::{mem::size_of_val, ops::Range},
+};
+
+/// Batch of host writes to non-coherent mapped range.
+/// Tracks sub-ranges written through it
+/// and flushes them with single call when finished or dropped.
+/// Use [`flush`](#method.flush) to handle flush errors,
+/// those are only logged on drop.
+///
+/// Flushed ranges are rounded to `non_coherent_atom_size`
+/// and may cover neighbouring bytes of the same memory object.
+#[derive(Debug)]
+pub struct FlushBatch<'a, 'b, B: Backend> {
+    mapping: &'b mut MappedRange<'a, B, NonCoherent>,
+    device: &'b B::Device,
+    atom: u64,
+    dirty: Vec<Range<u64>>,
+}
+
+impl<'a, B> MappedRange<'a, B, NonCoherent>
+where
+    B: Backend,
+{
+    /// Start batch of writes that will be flushed at once.
+    /// `non_coherent_atom_size` is the device limit with the same name.
+    pub fn flush_batch<'b>(
+        &'b mut self,
+        device: &'b B::Device,
+        non_coherent_atom_size: u64,
+    ) -> FlushBatch<'a, 'b, B> {
+        assert!(
+            non_coherent_atom_size.is_power_of_two(),
+            "Non-coherent atom size must be power of two"
+        );
+        FlushBatch {
+            mapping: self,
+            device,
+            atom: non_coherent_atom_size,
+            dirty: Vec::new(),
+        }
+    }
+}
+
+impl<'a, 'b, B> FlushBatch<'a, 'b, B>
+where
+    B: Backend,
+{
+    /// Fetch writable slice of sub-range and mark it for flushing.
+    /// `range.end - range.start` must be multiple of `size_of::<T>()`.
+    /// `mapping offset + range.start` must be multiple of `align_of::<T>()`.
+    ///
+    /// # Safety
+    ///
+    /// * Caller must ensure that device won't write to or read from the memory region.
+    /// * Returned slice should not be read.
+    pub unsafe fn write<T>(
+        &mut self,
+        range: Range<u64>,
+    ) -> Result<&mut [T], gfx_hal::device::MapError>
+    where
+        T: Copy,
+    {
+        assert!(
+            range.start < range.end,
+            "Memory mapping region must have valid size"
+        );
+        assert!(
+            fits_usize(range.end - range.start),
+            "Range length must fit in usize"
+        );
+
+        let (ptr, range) = mapped_sub_range(self.mapping.ptr, self.mapping.range.clone(), range)
+            .ok_or_else(|| gfx_hal::device::MapError::OutOfBounds)?;
+
+        let size = (range.end - range.start) as usize;
+        let slice = mapped_slice_mut::<T>(ptr, size);
+        self.dirty.push(range);
+        Ok(slice)
+    }
+
+    /// Copy `data` to the mapping at `offset` and mark written range for flushing.
+    ///
+    /// # Safety
+    ///
+    /// * Caller must ensure that device won't write to or read from the memory region.
+    pub unsafe fn copy<T>(
+        &mut self,
+        offset: u64,
+        data: &[T],
+    ) -> Result<(), gfx_hal::device::MapError>
+    where
+        T: Copy,
+    {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let size = size_of_val(data) as u64;
+        self.write::<T>(offset..offset + size)?
+            .copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Get ranges that will be flushed, in memory object space.
+    pub fn dirty_ranges(&self) -> Vec<Range<u64>> {
+        flush_ranges(&self.dirty, self.atom, self.mapping.memory.size())
+    }
+
+    /// Flush all written ranges.
+    pub fn flush(mut self) -> Result<(), gfx_hal::device::OutOfMemory> {
+        self.flush_dirty()
+    }
+
+    fn flush_dirty(&mut self) -> Result<(), gfx_hal::device::OutOfMemory> {
+        if self.dirty.is_empty() {
+            return Ok(());
+        }
+
+        let ranges = self.dirty_ranges();
+        self.dirty.clear();
+        let memory = self.mapping.memory.raw();
+        log::trace!("Flush {} mapped ranges: {:?}", ranges.len(), ranges);
+        unsafe {
+            self.device
+                .flush_mapped_memory_ranges(ranges.into_iter().map(|range| (memory, range)))
+        }
+    }
+}
+
+impl<'a, 'b, B> Drop for FlushBatch<'a, 'b, B>
+where
+    B: Backend,
+{
+    fn drop(&mut self) {
+        if let Err(error) = self.flush_dirty() {
+            log::error!("Failed to flush mapped ranges on drop: {:?}", error);
+        }
+    }
+}
+
+/// Round `dirty` ranges to `atom` within memory object of `memory_size`,
+/// sort and merge overlapping and adjacent ones.
+fn flush_ranges(dirty: &[Range<u64>], atom: u64, memory_size: u64) -> Vec<Range<u64>> {
+    let mut rounded = dirty
+        .iter()
+        .map(|range| {
+            let start = range.start - range.start % atom;
+            let end = aligned(range.end, atom).min(memory_size);
+            start..end
+        })
+        .collect::<Vec<_>>();
+    rounded.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(rounded.len());
+    for range in rounded {
+        match merged.last_mut() {
+            Some(last) if last.end >= range.start => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::flush_ranges;
+
+    #[test]
+    fn flush_ranges_rounded_and_merged() {
+        let dirty = [200..210, 0..4, 60..70, 130..140, 250..251];
+        assert_eq!(flush_ranges(&dirty, 64, 256), vec![0..256]);
+        assert_eq!(
+            flush_ranges(&[10..20, 300..310], 64, 320),
+            vec![0..64, 256..320]
+        );
+        assert_eq!(
+            flush_ranges(&[10..20, 90..100], 32, 100),
+            vec![0..32, 64..100]
+        );
+    }
+}
@@ -1,3 +1,4 @@
+mod flush;
 mod range;
 pub(crate) mod write;
 
@@ -7,6 +8,7 @@ use {
     std::{ops::Range, ptr::NonNull},
 };
 
+pub use self::flush::FlushBatch;
 pub(crate) use self::range::{
     mapped_fitting_range, mapped_slice, mapped_slice_mut, mapped_sub_range,
 };
@@ -142,7 +144,7 @@ where
 
         let size = (range.end - range.start) as usize;
 
-        if self.coherent.0 {
+        if !self.coherent.0 {
             device
                 .invalidate_mapped_memory_ranges(Some((self.memory.raw(), self.range.clone())))?;
         }
@@ -76,7 +76,7 @@ pub(crate) unsafe fn mapped_slice_mut<'a, T>(ptr: NonNull<u8>, size: usize) -> &
         "Range offset must be multiple of element alignment"
     );
     assert!(usize::max_value() - size >= ptr.as_ptr() as usize);
-    from_raw_parts_mut(ptr.as_ptr() as *mut T, size)
+    from_raw_parts_mut(ptr.as_ptr() as *mut T, size / size_of::<T>())
 }
 
 /// # Safety
@@ -97,5 +97,50 @@ pub(crate) unsafe fn mapped_slice<'a, T>(ptr: NonNull<u8>, size: usize) -> &'a [
         "Range offset must be multiple of element alignment"
     );
     assert!(usize::max_value() - size >= ptr.as_ptr() as usize);
-    from_raw_parts(ptr.as_ptr() as *const T, size)
+    from_raw_parts(ptr.as_ptr() as *const T, size / size_of::<T>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mapped_slice, mapped_slice_mut, mapped_sub_range};
+    use std::ptr::NonNull;
+
+    #[test]
+    fn slice_length_is_in_elements() {
+        let mut data = [0u64; 4];
+        let ptr = NonNull::new(data.as_mut_ptr() as *mut u8).unwrap();
+
+        unsafe {
+            assert_eq!(mapped_slice::<u8>(ptr, 32).len(), 32);
+            assert_eq!(mapped_slice::<u32>(ptr, 32).len(), 8);
+            assert_eq!(mapped_slice::<u64>(ptr, 16).len(), 2);
+
+            let slice = mapped_slice_mut::<u32>(ptr, 32);
+            assert_eq!(slice.len(), 8);
+            slice[0] = 42;
+        }
+        assert_eq!(data[0].to_ne_bytes()[..4], 42u32.to_ne_bytes());
+    }
+
+    #[test]
+    #[should_panic(expected = "Range length must be multiple of element size")]
+    fn partial_element_is_rejected() {
+        let mut data = [0u32; 2];
+        let ptr = NonNull::new(data.as_mut_ptr() as *mut u8).unwrap();
+        unsafe {
+            mapped_slice::<u32>(ptr, 6);
+        }
+    }
+
+    #[test]
+    fn sub_range_is_offset_from_mapping() {
+        let mut data = [0u8; 64];
+        let ptr = NonNull::new(data.as_mut_ptr()).unwrap();
+
+        let (sub, range) = mapped_sub_range(ptr, 128..192, 16..32).unwrap();
+        assert_eq!(range, 144..160);
+        assert_eq!(sub.as_ptr() as usize - ptr.as_ptr() as usize, 16);
+
+        assert!(mapped_sub_range(ptr, 128..192, 60..70).is_none());
+    }
 }
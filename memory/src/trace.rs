@@ -0,0 +1,349 @@
+//! Allocation trace recording and replay.
+//! See `Heaps::start_trace` and `Heaps::replay`.
+
+use {
+    crate::{
+        allocator::Kind,
+        heaps::{Heaps, MemoryBlock},
+        usage::MemoryUsage,
+        utilization::TotalMemoryUtilization,
+    },
+    gfx_hal::memory::Properties,
+    std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    },
+};
+
+const KINDS: [Kind; 6] = [
+    Kind::Dedicated,
+    Kind::Dynamic,
+    Kind::Linear,
+    Kind::Buddy,
+    Kind::Tlsf,
+    Kind::Ring,
+];
+
+/// Snapshot of the `MemoryUsage` of traced allocation.
+/// Holds fitness values so that replay picks memory types and allocators
+/// the same way original usage would.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TracedUsage {
+    /// Properties required by the usage.
+    pub properties_required: Properties,
+
+    /// Fitness of memory properties that satisfy required properties.
+    pub memory_fitness: Vec<(Properties, u32)>,
+
+    /// Fitness of allocator kinds.
+    pub allocator_fitness: Vec<(Kind, u32)>,
+}
+
+impl TracedUsage {
+    pub(crate) fn capture(
+        usage: &impl MemoryUsage,
+        properties: impl IntoIterator<Item = Properties>,
+    ) -> Self {
+        let properties_required = usage.properties_required();
+        let mut memory_fitness: Vec<(Properties, u32)> = Vec::new();
+        for properties in properties {
+            if properties.contains(properties_required)
+                && memory_fitness.iter().all(|&(p, _)| p != properties)
+            {
+                memory_fitness.push((properties, usage.memory_fitness(properties)));
+            }
+        }
+
+        TracedUsage {
+            properties_required,
+            memory_fitness,
+            allocator_fitness: KINDS
+                .iter()
+                .map(|&kind| (kind, usage.allocator_fitness(kind)))
+                .collect(),
+        }
+    }
+
+    /// Usage of the block relocated by defragmentation.
+    /// Replay allocates it from dynamic allocator of memory type with the same `properties`.
+    pub(crate) fn relocation(properties: Properties) -> Self {
+        TracedUsage {
+            properties_required: properties,
+            memory_fitness: vec![(properties, 1)],
+            allocator_fitness: KINDS
+                .iter()
+                .map(|&kind| (kind, (kind == Kind::Dynamic) as u32))
+                .collect(),
+        }
+    }
+}
+
+impl MemoryUsage for TracedUsage {
+    fn properties_required(&self) -> Properties {
+        self.properties_required
+    }
+
+    fn memory_fitness(&self, properties: Properties) -> u32 {
+        assert!(properties.contains(self.properties_required));
+        self.memory_fitness
+            .iter()
+            .find(|&&(p, _)| p == properties)
+            .map_or(0, |&(_, fitness)| fitness)
+    }
+
+    fn allocator_fitness(&self, kind: Kind) -> u32 {
+        self.allocator_fitness
+            .iter()
+            .find(|&&(k, _)| k == kind)
+            .map_or(0, |&(_, fitness)| fitness)
+    }
+}
+
+/// Allocation event recorded by `Heaps`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TraceEvent {
+    /// Memory block was allocated.
+    Allocate {
+        /// Id of the block.
+        id: u64,
+        /// Mask of memory types allowed for the allocation.
+        mask: u32,
+        /// Intended usage.
+        usage: TracedUsage,
+        /// Requested size.
+        size: u64,
+        /// Requested alignment.
+        align: u64,
+        /// Time since start of the recording.
+        time: Duration,
+    },
+
+    /// Memory block was freed.
+    Free {
+        /// Id of the block.
+        id: u64,
+        /// Time since start of the recording.
+        time: Duration,
+    },
+}
+
+/// Sequence of allocation events recorded by `Heaps`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AllocationTrace {
+    /// Recorded events in order of occurrence.
+    pub events: Vec<TraceEvent>,
+}
+
+#[derive(Debug)]
+pub(crate) struct TraceRecorder {
+    start: Instant,
+    events: Vec<TraceEvent>,
+}
+
+impl TraceRecorder {
+    pub(crate) fn new() -> Self {
+        TraceRecorder {
+            start: Instant::now(),
+            events: Vec::new(),
+        }
+    }
+
+    pub(crate) fn allocated(
+        &mut self,
+        id: u64,
+        mask: u32,
+        usage: TracedUsage,
+        size: u64,
+        align: u64,
+    ) {
+        let time = self.start.elapsed();
+        self.events.push(TraceEvent::Allocate {
+            id,
+            mask,
+            usage,
+            size,
+            align,
+            time,
+        });
+    }
+
+    pub(crate) fn freed(&mut self, id: u64) {
+        let time = self.start.elapsed();
+        self.events.push(TraceEvent::Free { id, time });
+    }
+
+    pub(crate) fn finish(self) -> AllocationTrace {
+        AllocationTrace {
+            events: self.events,
+        }
+    }
+}
+
+/// Result of `Heaps::replay`.
+#[derive(Clone, Debug)]
+pub struct ReplayReport {
+    /// Number of replayed allocations.
+    pub allocations: u64,
+
+    /// Number of allocations that failed during replay.
+    pub failed: u64,
+
+    /// Number of blocks left allocated by the end of the trace.
+    pub unfreed: u64,
+
+    /// Number of times memory was allocated from device.
+    pub device_allocations: u64,
+
+    /// Peak number of bytes allocated from device.
+    pub peak_used: u64,
+
+    /// Memory utilization at the moment of peak usage.
+    pub peak_utilization: TotalMemoryUtilization,
+
+    /// Memory utilization after the last event, before unfreed blocks are released.
+    pub final_utilization: TotalMemoryUtilization,
+}
+
+impl std::fmt::Display for ReplayReport {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(fmt, "Allocations: {}", self.allocations)?;
+        writeln!(fmt, "Failed allocations: {}", self.failed)?;
+        writeln!(fmt, "Unfreed blocks: {}", self.unfreed)?;
+        writeln!(fmt, "Device allocations: {}", self.device_allocations)?;
+        writeln!(fmt, "Peak usage: {} bytes", self.peak_used)?;
+        writeln!(fmt, "Utilization at peak:")?;
+        writeln!(fmt, "{}", self.peak_utilization)?;
+        writeln!(fmt, "Utilization at the end:")?;
+        write!(fmt, "{}", self.final_utilization)
+    }
+}
+
+impl<B> Heaps<B>
+where
+    B: gfx_hal::Backend,
+{
+    /// Replay allocation trace.
+    /// Allocates and frees blocks in the order of the trace
+    /// and reports memory usage observed along the way.
+    ///
+    /// Blocks left allocated by the end of the trace are freed before return.
+    /// Free events for blocks unknown to the trace are ignored.
+    pub fn replay(&mut self, device: &B::Device, trace: &AllocationTrace) -> ReplayReport {
+        let mut blocks: HashMap<u64, MemoryBlock<B>> = HashMap::new();
+        let mut allocations = 0;
+        let mut failed = 0;
+        let mut device_allocations = 0;
+        let mut peak_used = self.allocated();
+        let mut peak_utilization = self.utilization();
+
+        for event in &trace.events {
+            match event {
+                TraceEvent::Allocate {
+                    id,
+                    mask,
+                    usage,
+                    size,
+                    align,
+                    ..
+                } => {
+                    allocations += 1;
+                    let before = self.allocated();
+                    match self.allocate(device, *mask, usage, *size, *align) {
+                        Ok(block) => {
+                            if let Some(block) = blocks.insert(*id, block) {
+                                log::warn!("Block {} is allocated twice in the trace", id);
+                                self.free(device, block);
+                            }
+                        }
+                        Err(error) => {
+                            log::warn!("Replayed allocation {} failed: {}", id, error);
+                            failed += 1;
+                        }
+                    }
+                    let after = self.allocated();
+                    if after > before {
+                        device_allocations += 1;
+                    }
+                    if after > peak_used {
+                        peak_used = after;
+                        peak_utilization = self.utilization();
+                    }
+                }
+                TraceEvent::Free { id, .. } => {
+                    if let Some(block) = blocks.remove(id) {
+                        self.free(device, block);
+                    }
+                }
+            }
+        }
+
+        let final_utilization = self.utilization();
+        let mut unfreed = blocks.into_iter().collect::<Vec<_>>();
+        unfreed.sort_by_key(|&(id, _)| id);
+        let unfreed_count = unfreed.len() as u64;
+        for (_, block) in unfreed {
+            self.free(device, block);
+        }
+
+        ReplayReport {
+            allocations,
+            failed,
+            unfreed: unfreed_count,
+            device_allocations,
+            peak_used,
+            peak_utilization,
+            final_utilization,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::TracedUsage,
+        crate::{allocator::Kind, usage::*},
+        gfx_hal::memory::Properties,
+    };
+
+    #[test]
+    fn traced_usage_matches_original() {
+        let properties = [
+            Properties::DEVICE_LOCAL,
+            Properties::CPU_VISIBLE | Properties::COHERENT,
+            Properties::DEVICE_LOCAL | Properties::CPU_VISIBLE | Properties::COHERENT,
+        ];
+
+        for usage in &[
+            MemoryUsageValue::Data,
+            MemoryUsageValue::Dynamic,
+            MemoryUsageValue::Upload,
+            MemoryUsageValue::Download,
+            MemoryUsageValue::Transient,
+        ] {
+            let traced = TracedUsage::capture(usage, properties.iter().cloned());
+            assert_eq!(traced.properties_required(), usage.properties_required());
+            for &p in properties
+                .iter()
+                .filter(|p| p.contains(usage.properties_required()))
+            {
+                assert_eq!(traced.memory_fitness(p), usage.memory_fitness(p));
+            }
+            for &kind in &[
+                Kind::Dedicated,
+                Kind::Dynamic,
+                Kind::Linear,
+                Kind::Buddy,
+                Kind::Tlsf,
+                Kind::Ring,
+            ] {
+                assert_eq!(
+                    traced.allocator_fitness(kind),
+                    usage.allocator_fitness(kind)
+                );
+            }
+        }
+    }
+}
@@ -17,6 +17,14 @@ pub trait MemoryUsage: std::fmt::Debug {
 
     /// Get comparable fitness value for memory allocator.
     fn allocator_fitness(&self, kind: Kind) -> u32;
+
+    /// Get tag to account allocations with.
+    /// See [`Tagged`].
+    ///
+    /// [`Tagged`]: struct.Tagged.html
+    fn tag(&self) -> Option<&'static str> {
+        None
+    }
 }
 
 impl<T> MemoryUsage for T
@@ -33,6 +41,9 @@ where
     fn allocator_fitness(&self, kind: Kind) -> u32 {
         (&**self).allocator_fitness(kind)
     }
+    fn tag(&self) -> Option<&'static str> {
+        (&**self).tag()
+    }
 }
 
 /// Full speed GPU access.
@@ -58,7 +69,10 @@ impl MemoryUsage for Data {
     fn allocator_fitness(&self, kind: Kind) -> u32 {
         match kind {
             Kind::Dedicated => 1,
-            Kind::Dynamic => 2,
+            Kind::Buddy => 2,
+            Kind::Tlsf => 3,
+            Kind::Ring => 0,
+            Kind::Dynamic => 4,
             Kind::Linear => 0,
         }
     }
@@ -89,7 +103,10 @@ impl MemoryUsage for Dynamic {
     fn allocator_fitness(&self, kind: Kind) -> u32 {
         match kind {
             Kind::Dedicated => 1,
-            Kind::Dynamic => 2,
+            Kind::Buddy => 2,
+            Kind::Tlsf => 3,
+            Kind::Ring => 0,
+            Kind::Dynamic => 4,
             Kind::Linear => 0,
         }
     }
@@ -119,8 +136,11 @@ impl MemoryUsage for Upload {
     fn allocator_fitness(&self, kind: Kind) -> u32 {
         match kind {
             Kind::Dedicated => 0,
-            Kind::Dynamic => 1,
-            Kind::Linear => 2,
+            Kind::Buddy => 1,
+            Kind::Tlsf => 2,
+            Kind::Ring => 0,
+            Kind::Dynamic => 3,
+            Kind::Linear => 4,
         }
     }
 }
@@ -149,8 +169,44 @@ impl MemoryUsage for Download {
     fn allocator_fitness(&self, kind: Kind) -> u32 {
         match kind {
             Kind::Dedicated => 0,
-            Kind::Dynamic => 1,
-            Kind::Linear => 2,
+            Kind::Buddy => 1,
+            Kind::Tlsf => 2,
+            Kind::Ring => 0,
+            Kind::Dynamic => 3,
+            Kind::Linear => 4,
+        }
+    }
+}
+
+/// CPU to GPU data flow that lives for a single frame.
+/// Used for per-frame uniforms and streaming vertex data
+/// written once and read by commands of the frame.
+/// Host access is guaranteed.
+/// Prefers ring allocator which reclaims memory when the frame is complete.
+/// See [`Heaps::retire_ring_epochs`].
+///
+/// [`Heaps::retire_ring_epochs`]: struct.Heaps.html#method.retire_ring_epochs
+#[derive(Clone, Copy, Debug)]
+pub struct Transient;
+
+impl MemoryUsage for Transient {
+    fn properties_required(&self) -> gfx_hal::memory::Properties {
+        gfx_hal::memory::Properties::CPU_VISIBLE
+    }
+
+    #[inline]
+    fn memory_fitness(&self, properties: gfx_hal::memory::Properties) -> u32 {
+        Dynamic.memory_fitness(properties)
+    }
+
+    fn allocator_fitness(&self, kind: Kind) -> u32 {
+        match kind {
+            Kind::Dedicated => 0,
+            Kind::Buddy => 1,
+            Kind::Tlsf => 2,
+            Kind::Dynamic => 3,
+            Kind::Linear => 4,
+            Kind::Ring => 5,
         }
     }
 }
@@ -177,6 +233,11 @@ pub enum MemoryUsageValue {
     ///
     /// [`Download`]: struct.Download.html
     Download,
+
+    /// See [`Transient`]
+    ///
+    /// [`Transient`]: struct.Transient.html
+    Transient,
 }
 
 /// Memory usage trait.
@@ -187,6 +248,7 @@ impl MemoryUsage for MemoryUsageValue {
             MemoryUsageValue::Dynamic => Dynamic.properties_required(),
             MemoryUsageValue::Upload => Upload.properties_required(),
             MemoryUsageValue::Download => Download.properties_required(),
+            MemoryUsageValue::Transient => Transient.properties_required(),
         }
     }
 
@@ -196,6 +258,7 @@ impl MemoryUsage for MemoryUsageValue {
             MemoryUsageValue::Dynamic => Dynamic.memory_fitness(properties),
             MemoryUsageValue::Upload => Upload.memory_fitness(properties),
             MemoryUsageValue::Download => Download.memory_fitness(properties),
+            MemoryUsageValue::Transient => Transient.memory_fitness(properties),
         }
     }
 
@@ -205,6 +268,45 @@ impl MemoryUsage for MemoryUsageValue {
             MemoryUsageValue::Dynamic => Dynamic.allocator_fitness(kind),
             MemoryUsageValue::Upload => Upload.allocator_fitness(kind),
             MemoryUsageValue::Download => Download.allocator_fitness(kind),
+            MemoryUsageValue::Transient => Transient.allocator_fitness(kind),
         }
     }
 }
+
+/// Memory usage with a tag attached.
+/// Allocations are accounted per tag in [`TotalMemoryUtilization`].
+///
+/// [`TotalMemoryUtilization`]: struct.TotalMemoryUtilization.html
+#[derive(Clone, Copy, Debug)]
+pub struct Tagged<U> {
+    usage: U,
+    tag: &'static str,
+}
+
+impl<U> Tagged<U> {
+    /// Attach `tag` to the `usage`.
+    pub fn new(usage: U, tag: &'static str) -> Self {
+        Tagged { usage, tag }
+    }
+}
+
+impl<U> MemoryUsage for Tagged<U>
+where
+    U: MemoryUsage,
+{
+    fn properties_required(&self) -> gfx_hal::memory::Properties {
+        self.usage.properties_required()
+    }
+
+    fn memory_fitness(&self, properties: gfx_hal::memory::Properties) -> u32 {
+        self.usage.memory_fitness(properties)
+    }
+
+    fn allocator_fitness(&self, kind: Kind) -> u32 {
+        self.usage.allocator_fitness(kind)
+    }
+
+    fn tag(&self) -> Option<&'static str> {
+        Some(self.tag)
+    }
+}
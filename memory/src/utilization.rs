@@ -1,10 +1,12 @@
 use {
+    crate::allocator::Kind,
     colorful::{core::color_string::CString, Color, Colorful as _},
     gfx_hal::memory::Properties,
 };
 
 /// Memory utilization stats.
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MemoryUtilization {
     /// Total number of bytes allocated.
     pub used: u64,
@@ -12,8 +14,23 @@ pub struct MemoryUtilization {
     pub effective: u64,
 }
 
+impl MemoryUtilization {
+    /// Account `used` bytes allocated from device and block of `effective` bytes.
+    pub(crate) fn allocated(&mut self, used: u64, effective: u64) {
+        self.used += used;
+        self.effective += effective;
+    }
+
+    /// Account `used` bytes returned to device and freed block of `effective` bytes.
+    pub(crate) fn freed(&mut self, used: u64, effective: u64) {
+        self.used -= used;
+        self.effective -= effective;
+    }
+}
+
 /// Memory utilization of one heap.
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MemoryHeapUtilization {
     /// Utilization.
     pub utilization: MemoryUtilization,
@@ -24,6 +41,7 @@ pub struct MemoryHeapUtilization {
 
 /// Memory utilization of one type.
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MemoryTypeUtilization {
     /// Utilization.
     pub utilization: MemoryUtilization,
@@ -35,14 +53,97 @@ pub struct MemoryTypeUtilization {
     pub heap_index: usize,
 }
 
+/// Fragmentation of one sub-allocator.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AllocatorUtilization {
+    /// Kind of the allocator.
+    pub kind: Kind,
+
+    /// Number of memory objects allocated from device.
+    pub device_allocations: u64,
+
+    /// Largest contiguous free range within memory objects of the allocator.
+    pub largest_free_range: u64,
+
+    /// Chunks per block size. Filled only for `Kind::Dynamic`.
+    pub size_classes: Vec<SizeClassUtilization>,
+
+    /// Occupancy of lines. Filled only for `Kind::Linear`.
+    pub lines: Vec<LineUtilization>,
+}
+
+/// Chunks of one block size in `DynamicAllocator`.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SizeClassUtilization {
+    /// Size of blocks.
+    pub block_size: u64,
+
+    /// Number of chunks.
+    pub chunks: u64,
+
+    /// Total number of blocks in chunks.
+    pub blocks: u64,
+
+    /// Number of free blocks in chunks.
+    pub free_blocks: u64,
+}
+
+/// Occupancy of one line in `LinearAllocator`.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LineUtilization {
+    /// Size of the line.
+    pub size: u64,
+
+    /// Number of bytes consumed from the line, including alignment padding.
+    pub used: u64,
+
+    /// Number of bytes occupied by live blocks.
+    pub live: u64,
+}
+
+/// Memory utilization of allocations with one tag.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryTagUtilization {
+    /// Number of bytes occupied by blocks, including size rounding.
+    pub block_bytes: u64,
+
+    /// Number of bytes requested by allocations.
+    pub requested_bytes: u64,
+
+    /// Tag of allocations. `None` for untagged allocations.
+    pub tag: Option<String>,
+
+    /// Number of live allocations.
+    pub count: u64,
+}
+
 /// Total memory utilization.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TotalMemoryUtilization {
     /// Utilization by types.
     pub types: Vec<MemoryTypeUtilization>,
 
+    /// Fragmentation of sub-allocators by types.
+    /// Indexed the same way as `types`.
+    pub allocators: Vec<Vec<AllocatorUtilization>>,
+
     /// Utilization by heaps.
     pub heaps: Vec<MemoryHeapUtilization>,
+
+    /// Utilization by allocation tags.
+    /// Sorted from most used.
+    pub tags: Vec<MemoryTagUtilization>,
+
+    /// Number of blocks allocated outside of preferred memory type.
+    pub spilled_blocks: u64,
+
+    /// Number of bytes requested by spilled blocks.
+    pub spilled_bytes: u64,
 }
 
 impl std::fmt::Display for TotalMemoryUtilization {
@@ -79,7 +180,12 @@ impl std::fmt::Display for TotalMemoryUtilization {
                 line
             )?;
 
-            for ty in self.types.iter().filter(|ty| ty.heap_index == index) {
+            for (ty, allocators) in self
+                .types
+                .iter()
+                .zip(&self.allocators)
+                .filter(|(ty, _)| ty.heap_index == index)
+            {
                 let properties = ty.properties;
                 let MemoryUtilization { used, effective } = ty.utilization;
                 let usage_basis_points = used * 10000 / size;
@@ -97,9 +203,54 @@ impl std::fmt::Display for TotalMemoryUtilization {
                     format_basis_points_inverted(effective_basis_points),
                     properties,
                 )?;
+
+                for allocator in allocators
+                    .iter()
+                    .filter(|allocator| allocator.device_allocations > 0)
+                {
+                    writeln!(
+                        fmt,
+                        "         {:>10} {:>6} objects, largest free range: {}",
+                        format!("{:?}", allocator.kind).cyan(),
+                        allocator.device_allocations,
+                        format!("{:.1}MB", allocator.largest_free_range as f64 / MB as f64),
+                    )?;
+                }
             }
         }
 
+        if !self.tags.is_empty() {
+            writeln!(fmt, "Tags:")?;
+        }
+        for tag in &self.tags {
+            let requested_basis_points = if tag.block_bytes > 0 {
+                tag.requested_bytes * 10000 / tag.block_bytes
+            } else {
+                10000
+            };
+
+            writeln!(
+                fmt,
+                "{:>24} {:>8} blocks {:>10} {{ requested:{} }}",
+                tag.tag
+                    .as_ref()
+                    .map_or("<untagged>", String::as_str)
+                    .magenta(),
+                tag.count,
+                format!("{:.1}MB", tag.block_bytes as f64 / MB as f64),
+                format_basis_points_inverted(requested_basis_points),
+            )?;
+        }
+
+        if self.spilled_blocks > 0 {
+            writeln!(
+                fmt,
+                "Spilled: {} blocks {}",
+                self.spilled_blocks,
+                format!("{:.1}MB", self.spilled_bytes as f64 / MB as f64).red(),
+            )?;
+        }
+
         Ok(())
     }
 }
@@ -135,3 +286,25 @@ fn format_basis_points_inverted(basis_points: u64) -> CString {
         s.red()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryUtilization;
+
+    #[test]
+    fn freeing_restores_utilization() {
+        let mut utilization = MemoryUtilization {
+            used: 0,
+            effective: 0,
+        };
+        utilization.allocated(1024, 256);
+        utilization.allocated(0, 128);
+        assert_eq!((utilization.used, utilization.effective), (1024, 384));
+
+        utilization.freed(0, 256);
+        assert_eq!((utilization.used, utilization.effective), (1024, 128));
+
+        utilization.freed(1024, 128);
+        assert_eq!((utilization.used, utilization.effective), (0, 0));
+    }
+}
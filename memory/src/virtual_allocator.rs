@@ -0,0 +1,319 @@
+//! Device-independent sub-allocation of abstract address space.
+
+use {
+    crate::{allocator::Tlsf, util::fits_u32},
+    std::ops::Range,
+};
+
+/// Range allocated from `VirtualAllocator`.
+#[derive(Debug)]
+pub struct VirtualBlock {
+    region_index: u32,
+    range: Range<u64>,
+    relevant: relevant::Relevant,
+}
+
+impl VirtualBlock {
+    /// Get range of the block.
+    /// Size of the range can be greater than requested.
+    pub fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    /// Get offset of the block.
+    pub fn offset(&self) -> u64 {
+        self.range.start
+    }
+
+    /// Get size of the block.
+    pub fn size(&self) -> u64 {
+        self.range.end - self.range.start
+    }
+
+    fn dispose(self) {
+        self.relevant.dispose();
+    }
+}
+
+/// Stats of the `VirtualAllocator`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VirtualStats {
+    /// Size of the address space.
+    pub size: u64,
+
+    /// Number of bytes in allocated blocks.
+    pub used: u64,
+
+    /// Number of allocated blocks.
+    pub allocations: u64,
+
+    /// Number of free ranges.
+    pub free_ranges: u64,
+
+    /// Size of the biggest free range.
+    pub largest_free_range: u64,
+}
+
+/// Allocator of ranges in abstract address space, like offsets in a big `Buffer`.
+/// Doesn't allocate any memory by itself and doesn't require device.
+///
+/// Uses the same Two-Level Segregated Fit bookkeeping as `TlsfAllocator`.
+/// Block sizes are rounded up to granularity.
+/// Freed ranges are merged with free neighbours.
+#[derive(Debug)]
+pub struct VirtualAllocator {
+    size: u64,
+    tlsf: Tlsf,
+    used: u64,
+    allocations: u64,
+}
+
+impl VirtualAllocator {
+    /// Create allocator of `size` bytes of address space.
+    /// Sizes and offsets of blocks are multiples of `granularity`.
+    pub fn new(size: u64, granularity: u64) -> Self {
+        assert!(
+            granularity.is_power_of_two(),
+            "Allocation granularity must be power of two"
+        );
+        assert!(size > 0, "Address space must be non-empty");
+        assert_eq!(
+            size % granularity,
+            0,
+            "Address space size must be multiple of allocation granularity"
+        );
+
+        let mut tlsf = Tlsf::new(granularity);
+        tlsf.add_chunk(0, size);
+        VirtualAllocator {
+            size,
+            tlsf,
+            used: 0,
+            allocations: 0,
+        }
+    }
+
+    /// Allocate block of `size` bytes aligned to `align`.
+    /// Returns `None` if no free range can hold the block.
+    pub fn alloc(&mut self, size: u64, align: u64) -> Option<VirtualBlock> {
+        assert!(align.is_power_of_two(), "Alignment must be power of two");
+        if size > self.size {
+            return None;
+        }
+
+        let region_index = self.tlsf.alloc(size, align)?;
+        assert!(fits_u32(region_index));
+        let (_, range) = self.tlsf.region(region_index);
+        self.used += range.end - range.start;
+        self.allocations += 1;
+
+        Some(VirtualBlock {
+            region_index: region_index as u32,
+            range,
+            relevant: relevant::Relevant,
+        })
+    }
+
+    /// Free block allocated from this allocator.
+    pub fn free(&mut self, block: VirtualBlock) {
+        self.used -= block.size();
+        self.allocations -= 1;
+        if self.tlsf.free(block.region_index as usize).is_some() {
+            // Whole space is free again.
+            self.tlsf.add_chunk(0, self.size);
+        }
+        block.dispose();
+    }
+
+    /// Resize block in place keeping its offset.
+    /// Returns `false` if block can't grow because following range is occupied,
+    /// in which case caller may allocate new block and move the content.
+    pub fn resize(&mut self, block: &mut VirtualBlock, size: u64) -> bool {
+        let region_index = block.region_index as usize;
+        if size > self.size || !self.tlsf.resize(region_index, size) {
+            return false;
+        }
+
+        let (_, range) = self.tlsf.region(region_index);
+        debug_assert_eq!(range.start, block.range.start);
+        self.used = self.used - block.size() + (range.end - range.start);
+        block.range = range;
+        true
+    }
+
+    /// Get allocator stats.
+    pub fn stats(&self) -> VirtualStats {
+        VirtualStats {
+            size: self.size,
+            used: self.used,
+            allocations: self.allocations,
+            free_ranges: self.tlsf.free_sizes().count() as u64,
+            largest_free_range: self.tlsf.largest_free(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{VirtualAllocator, VirtualBlock};
+    use rand::{seq::SliceRandom as _, Rng as _};
+
+    const SIZE: u64 = 1 << 20;
+
+    /// Free gaps between blocks sorted by offset.
+    fn gaps(ranges: &[std::ops::Range<u64>]) -> Vec<u64> {
+        let mut end = 0;
+        let mut gaps = Vec::new();
+        for range in ranges.iter().chain(Some(&(SIZE..SIZE))) {
+            if range.start > end {
+                gaps.push(range.start - end);
+            }
+            end = range.end;
+        }
+        gaps
+    }
+
+    fn check(allocator: &VirtualAllocator, live: &[(VirtualBlock, u64, u64)]) {
+        let mut ranges = live
+            .iter()
+            .map(|(block, size, align)| {
+                assert_eq!(block.offset() % align, 0);
+                assert!(block.size() >= *size);
+                assert!(block.range().end <= SIZE);
+                block.range()
+            })
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|range| range.start);
+        for pair in ranges.windows(2) {
+            assert!(pair[0].end <= pair[1].start, "{:?} overlap", pair);
+        }
+
+        let stats = allocator.stats();
+        let used: u64 = ranges.iter().map(|range| range.end - range.start).sum();
+        assert_eq!(stats.used, used);
+        assert_eq!(stats.allocations, live.len() as u64);
+
+        // Free neighbours are always merged,
+        // so every gap between blocks is exactly one free range.
+        let gaps = gaps(&ranges);
+        assert_eq!(stats.free_ranges, gaps.len() as u64);
+        assert_eq!(
+            stats.largest_free_range,
+            gaps.into_iter().max().unwrap_or(0)
+        );
+    }
+
+    /// Allocation may fail only if no free range can hold the block
+    /// with alignment padding and size class rounding.
+    fn check_failure(allocator: &VirtualAllocator, size: u64, align: u64, granularity: u64) {
+        let size = (size + granularity - 1) / granularity * granularity;
+        let search = size + align.saturating_sub(granularity);
+        assert!(
+            allocator.stats().largest_free_range < search + search / 16 + granularity,
+            "Allocation of {} bytes aligned to {} failed with {:?}",
+            size,
+            align,
+            allocator.stats()
+        );
+    }
+
+    #[test]
+    fn resize_in_place() {
+        let mut allocator = VirtualAllocator::new(4096, 64);
+        let mut a = allocator.alloc(100, 64).unwrap();
+        let b = allocator.alloc(100, 64).unwrap();
+        assert_eq!(a.range(), 0..128);
+        assert_eq!(b.range(), 128..256);
+
+        // `b` follows `a`.
+        assert!(!allocator.resize(&mut a, 200));
+        assert!(allocator.resize(&mut a, 64));
+        assert_eq!(a.range(), 0..64);
+        assert!(allocator.resize(&mut a, 128));
+        assert_eq!(a.range(), 0..128);
+
+        allocator.free(b);
+        assert!(allocator.resize(&mut a, 4096));
+        assert_eq!(allocator.stats().free_ranges, 0);
+        assert!(allocator.alloc(1, 1).is_none());
+
+        allocator.free(a);
+        let stats = allocator.stats();
+        assert_eq!(stats.used, 0);
+        assert_eq!(stats.largest_free_range, 4096);
+    }
+
+    #[test]
+    fn huge_requests_fail() {
+        let mut allocator = VirtualAllocator::new(SIZE, 256);
+        assert!(allocator.alloc(u64::max_value(), 1).is_none());
+        assert!(allocator.alloc(SIZE, 1 << 63).is_none());
+        assert!(allocator.alloc(1, 1 << 63).is_none());
+
+        let mut block = allocator.alloc(1, 1).unwrap();
+        assert!(!allocator.resize(&mut block, u64::max_value()));
+        assert_eq!(block.range(), 0..256);
+        allocator.free(block);
+        assert_eq!(allocator.stats().largest_free_range, SIZE);
+    }
+
+    #[test]
+    fn random_blocks_are_aligned_and_disjoint() {
+        for &granularity in &[1, 16, 256] {
+            random_blocks(granularity);
+        }
+    }
+
+    fn random_blocks(granularity: u64) {
+        let mut rng = rand::thread_rng();
+        let mut allocator = VirtualAllocator::new(SIZE, granularity);
+        let mut live: Vec<(VirtualBlock, u64, u64)> = Vec::new();
+
+        for step in 0..20000 {
+            match rng.gen_range(0, 10) {
+                0..=4 => {
+                    let size = rng.gen_range(1, 16384);
+                    let align = 1 << rng.gen_range(0, 12);
+                    match allocator.alloc(size, align) {
+                        Some(block) => live.push((block, size, align)),
+                        None => check_failure(&allocator, size, align, granularity),
+                    }
+                }
+                5..=7 if !live.is_empty() => {
+                    live.shuffle(&mut rng);
+                    let (block, _, _) = live.pop().unwrap();
+                    allocator.free(block);
+                }
+                8..=9 if !live.is_empty() => {
+                    let index = rng.gen_range(0, live.len());
+                    let (ref mut block, ref mut size, _) = live[index];
+                    let offset = block.offset();
+                    let new_size = rng.gen_range(1, 32768);
+                    if allocator.resize(block, new_size) {
+                        *size = new_size;
+                    }
+                    assert_eq!(block.offset(), offset);
+                }
+                _ => {}
+            }
+
+            if step % 100 == 0 {
+                check(&allocator, &live);
+            }
+        }
+        check(&allocator, &live);
+
+        for (block, _, _) in live {
+            allocator.free(block);
+        }
+
+        // All ranges are merged back.
+        let stats = allocator.stats();
+        assert_eq!(stats.used, 0);
+        assert_eq!(stats.free_ranges, 1);
+        assert_eq!(stats.largest_free_range, SIZE);
+        let whole = allocator.alloc(SIZE, 1).unwrap();
+        allocator.free(whole);
+    }
+}
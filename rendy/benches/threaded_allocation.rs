@@ -0,0 +1,94 @@
+//!
+//! Measures throughput of small buffer creation from many threads
+//! with and without per-thread block caches.
+//!
+//! Run with `cargo bench --bench threaded_allocation`.
+//!
+
+use {
+    rendy::{
+        factory::{Config, Factory},
+        hal::{buffer::Usage, Backend},
+        init::AnyRendy,
+        memory::{BlockCacheConfig, Dynamic},
+        resource::BufferInfo,
+    },
+    std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    },
+};
+
+const THREADS: usize = 8;
+const ITERATIONS: usize = 200;
+const BUFFERS: usize = 64;
+const BUFFER_SIZE: u64 = 256;
+
+fn run<B: Backend>(factory: &Arc<Factory<B>>) -> Duration {
+    let start = Instant::now();
+
+    let threads = (0..THREADS)
+        .map(|_| {
+            let factory = factory.clone();
+            std::thread::spawn(move || {
+                let mut buffers = Vec::with_capacity(BUFFERS);
+                for _ in 0..ITERATIONS {
+                    for _ in 0..BUFFERS {
+                        let buffer = factory
+                            .create_relevant_buffer(
+                                BufferInfo {
+                                    size: BUFFER_SIZE,
+                                    usage: Usage::UNIFORM,
+                                },
+                                Dynamic,
+                            )
+                            .unwrap();
+                        buffers.push(buffer);
+                    }
+
+                    for buffer in buffers.drain(..) {
+                        unsafe {
+                            factory.destroy_relevant_buffer(buffer);
+                        }
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let count = (THREADS * ITERATIONS * BUFFERS) as f64;
+    println!(
+        "{:>10}: {} buffers in {:?} ({:.0} buffers/s)",
+        name,
+        count,
+        elapsed,
+        count / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    env_logger::Builder::from_default_env()
+        .filter_module("threaded_allocation", log::LevelFilter::Trace)
+        .init();
+
+    let config: Config = Default::default();
+    let rendy = AnyRendy::init_auto(&config).unwrap();
+
+    rendy::with_any_rendy!((rendy) (factory, _families) => {
+        let mut factory = Arc::new(factory);
+        report("shared", run(&factory));
+
+        Arc::get_mut(&mut factory)
+            .unwrap()
+            .set_block_cache(Some(BlockCacheConfig::default()));
+        report("cached", run(&factory));
+    });
+}
@@ -0,0 +1,57 @@
+//!
+//! Replays memory allocation trace recorded with `Factory::take_memory_trace`
+//! against `Heaps` built from each of given `SavedHeapsConfig`
+//! and prints utilization, peak usage and number of device allocations.
+//!
+//! Usage: `memory_replay <trace.ron> <heaps-config.ron>...`
+//!
+//! Memory is allocated from the fake device of `rendy-memory`,
+//! so replay doesn't touch GPU and results don't depend on the machine.
+//! Memory types and heaps are taken from the heaps config.
+//!
+
+use {
+    rendy::{
+        factory::{HeapsConfigure as _, SavedHeapsConfig},
+        hal::adapter::MemoryProperties,
+        memory::{fake, AllocationTrace, Heaps},
+    },
+    std::fs::File,
+};
+
+fn main() {
+    env_logger::Builder::from_default_env()
+        .filter_module("memory_replay", log::LevelFilter::Trace)
+        .init();
+
+    let mut args = std::env::args().skip(1);
+    let trace_path = args
+        .next()
+        .expect("Usage: memory_replay <trace.ron> <heaps-config.ron>...");
+    let trace: AllocationTrace =
+        ron::de::from_reader(File::open(&trace_path).expect("Failed to open trace file"))
+            .expect("Failed to parse trace");
+
+    log::info!("Loaded {} events from {}", trace.events.len(), trace_path);
+
+    // Saved config ignores properties of the device.
+    let properties = MemoryProperties {
+        memory_types: Vec::new(),
+        memory_heaps: Vec::new(),
+    };
+
+    for config_path in args {
+        let heaps_config: SavedHeapsConfig = ron::de::from_reader(
+            File::open(&config_path).expect("Failed to open heaps config file"),
+        )
+        .expect("Failed to parse heaps config");
+
+        let (types, heaps) = heaps_config.configure(&properties);
+        let device = fake::Device::new();
+        let mut heaps = unsafe { Heaps::<fake::Backend>::new(types, heaps) };
+        let report = heaps.replay(&device, &trace);
+        heaps.dispose(&device);
+
+        println!("{}:\n{}\n", config_path, report);
+    }
+}
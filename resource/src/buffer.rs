@@ -5,7 +5,7 @@ pub use rendy_core::hal::buffer::*;
 use {
     crate::{
         core::{device_owned, Device, DeviceId},
-        memory::{Block, Heaps, MappedRange, MemoryBlock, MemoryUsage},
+        memory::{Block, BlockAllocator, MappedRange, MemoryBlock, MemoryUsage},
         CreationError,
     },
     relevant::Relevant,
@@ -55,7 +55,7 @@ where
     /// Otherwise usage of hal methods must be always valid.
     pub unsafe fn create(
         device: &Device<B>,
-        heaps: &mut Heaps<B>,
+        heaps: &mut impl BlockAllocator<B>,
         info: BufferInfo,
         memory_usage: impl MemoryUsage,
     ) -> Result<Self, BufferCreationError> {
@@ -91,7 +91,7 @@ where
 
     /// Dispose of buffer resource.
     /// Deallocate memory block.
-    pub unsafe fn dispose(self, device: &Device<B>, heaps: &mut Heaps<B>) {
+    pub unsafe fn dispose(self, device: &Device<B>, heaps: &mut impl BlockAllocator<B>) {
         self.assert_device_owner(device);
         device.destroy_buffer(self.raw);
         heaps.free(device, self.block);
@@ -147,4 +147,11 @@ where
     pub fn size(&self) -> u64 {
         self.info().size
     }
+
+    /// Set name of the buffer shown by debugging tools.
+    /// This is no-op if backend doesn't support object names.
+    pub fn set_name(&mut self, device: &Device<B>, name: &str) {
+        self.assert_device_owner(device);
+        unsafe { device.set_buffer_name(&mut self.raw, name) }
+    }
 }
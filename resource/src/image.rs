@@ -6,7 +6,7 @@ use {
     crate::{
         core::{device_owned, Device, DeviceId},
         escape::Handle,
-        memory::{Block, Heaps, MemoryBlock, MemoryUsage},
+        memory::{Block, BlockAllocator, MemoryBlock, MemoryUsage},
         CreationError,
     },
     relevant::Relevant,
@@ -68,7 +68,7 @@ where
     /// Otherwise usage of hal methods must be always valid.
     pub unsafe fn create(
         device: &Device<B>,
-        heaps: &mut Heaps<B>,
+        heaps: &mut impl BlockAllocator<B>,
         info: ImageInfo,
         memory_usage: impl MemoryUsage,
     ) -> Result<Self, ImageCreationError> {
@@ -128,7 +128,7 @@ where
     }
 
     /// Destroy image resource.
-    pub unsafe fn dispose(self, device: &Device<B>, heaps: &mut Heaps<B>) {
+    pub unsafe fn dispose(self, device: &Device<B>, heaps: &mut impl BlockAllocator<B>) {
         self.assert_device_owner(device);
         device.destroy_image(self.raw);
         self.block.map(|block| heaps.free(device, block));
@@ -190,6 +190,13 @@ where
     pub fn layers(&self) -> u16 {
         self.info.kind.num_layers()
     }
+
+    /// Set name of the image shown by debugging tools.
+    /// This is no-op if backend doesn't support object names.
+    pub fn set_name(&mut self, device: &Device<B>, name: &str) {
+        self.assert_device_owner(device);
+        unsafe { device.set_image_name(&mut self.raw, name) }
+    }
 }
 
 /// Image view info
@@ -211,6 +218,7 @@ pub struct ImageView<B: Backend> {
     raw: B::ImageView,
     image: Handle<Image<B>>,
     info: ImageViewInfo,
+    name: Option<String>,
     relevant: Relevant,
 }
 
@@ -258,6 +266,7 @@ where
             raw: view,
             image,
             info,
+            name: None,
             relevant: Relevant,
         })
     }
@@ -288,6 +297,24 @@ where
     pub fn image(&self) -> &Handle<Image<B>> {
         &self.image
     }
+
+    /// Set name of the image view.
+    ///
+    /// Backend has no way to name image views,
+    /// so the name is only kept in the wrapper and returned by [`name`].
+    /// Name the underlying image to see it in debugging tools.
+    ///
+    /// [`name`]: #method.name
+    pub fn set_name(&mut self, name: &str) {
+        self.name = Some(name.to_owned());
+    }
+
+    /// Get name of the image view set by [`set_name`].
+    ///
+    /// [`set_name`]: #method.set_name
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(String::as_str)
+    }
 }
 
 fn match_kind(kind: Kind, view_kind: ViewKind, view_caps: ViewCapabilities) -> bool {